@@ -0,0 +1,140 @@
+//! Compares the conversions in `cef::pixels` with the plain per-pixel loop that
+//! `examples/embedded` uses, on a full 1080p frame and on typical dirty rects.
+
+use cef::{pixels, values::Rect};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+const WIDTH: i32 = 1920;
+const HEIGHT: i32 = 1080;
+
+fn frame() -> Vec<u8> {
+    (0..WIDTH * HEIGHT * 4).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+/// A caret blink, a scrolled text column and a small animation.
+fn dirty_rects() -> Vec<Rect> {
+    vec![
+        Rect::new(410, 300, 2, 18),
+        Rect::new(200, 120, 760, 840),
+        Rect::new(1500, 40, 96, 96),
+    ]
+}
+
+fn naive_bgra_to_rgba(src: &[u8], dst: &mut [u8], width: i32, rects: &[Rect]) {
+    for rect in rects {
+        for row in rect.y..rect.y + rect.height {
+            for col in rect.x..rect.x + rect.width {
+                let i = (row * width + col) as usize * 4;
+                dst[i] = src[i + 2];
+                dst[i + 1] = src[i + 1];
+                dst[i + 2] = src[i];
+                dst[i + 3] = src[i + 3];
+            }
+        }
+    }
+}
+
+fn naive_bgra_to_rgb(src: &[u8], dst: &mut [u8], width: i32, rects: &[Rect]) {
+    for rect in rects {
+        for row in rect.y..rect.y + rect.height {
+            for col in rect.x..rect.x + rect.width {
+                let i = (row * width + col) as usize;
+                dst[i * 3] = src[i * 4 + 2];
+                dst[i * 3 + 1] = src[i * 4 + 1];
+                dst[i * 3 + 2] = src[i * 4];
+            }
+        }
+    }
+}
+
+fn naive_unpremultiply(src: &[u8], dst: &mut [u8], width: i32, rects: &[Rect]) {
+    for rect in rects {
+        for row in rect.y..rect.y + rect.height {
+            for col in rect.x..rect.x + rect.width {
+                let i = (row * width + col) as usize * 4;
+                let alpha = src[i + 3] as u32;
+                let channel = |value: u8| {
+                    if alpha == 0 {
+                        0
+                    } else {
+                        ((value as u32 * 255 + alpha / 2) / alpha).min(255) as u8
+                    }
+                };
+                dst[i] = channel(src[i + 2]);
+                dst[i + 1] = channel(src[i + 1]);
+                dst[i + 2] = channel(src[i]);
+                dst[i + 3] = src[i + 3];
+            }
+        }
+    }
+}
+
+fn naive_luma(src: &[u8], dst: &mut [u8], width: i32, rects: &[Rect]) {
+    for rect in rects {
+        for row in rect.y..rect.y + rect.height {
+            for col in rect.x..rect.x + rect.width {
+                let i = (row * width + col) as usize;
+                let (b, g, r) = (src[i * 4] as u32, src[i * 4 + 1] as u32, src[i * 4 + 2] as u32);
+                dst[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+            }
+        }
+    }
+}
+
+fn bench_conversions(c: &mut Criterion) {
+    let src = frame();
+    let full = [Rect::new(0, 0, WIDTH, HEIGHT)];
+    let dirty = dirty_rects();
+    let simd = format!("{:?}", pixels::SimdLevel::detect());
+
+    for (name, rects) in &[("full", &full[..]), ("dirty", &dirty[..])] {
+        let pixel_count: i32 = rects.iter().map(|rect| rect.width * rect.height).sum();
+        let mut group = c.benchmark_group(format!("bgra_to_rgba/{}", name));
+        group.throughput(Throughput::Elements(pixel_count as u64));
+        let mut dst = vec![0; src.len()];
+        group.bench_function("naive", |b| {
+            b.iter(|| naive_bgra_to_rgba(black_box(&src), &mut dst, WIDTH, rects))
+        });
+        group.bench_function(&simd, |b| {
+            b.iter(|| pixels::bgra_to_rgba(black_box(&src), &mut dst, WIDTH, rects))
+        });
+        group.finish();
+
+        let mut group = c.benchmark_group(format!("bgra_to_rgb/{}", name));
+        group.throughput(Throughput::Elements(pixel_count as u64));
+        let mut dst = vec![0; src.len() / 4 * 3];
+        group.bench_function("naive", |b| {
+            b.iter(|| naive_bgra_to_rgb(black_box(&src), &mut dst, WIDTH, rects))
+        });
+        group.bench_function(&simd, |b| {
+            b.iter(|| pixels::bgra_to_rgb(black_box(&src), &mut dst, WIDTH, rects))
+        });
+        group.finish();
+
+        let mut group = c.benchmark_group(format!("unpremultiply/{}", name));
+        group.throughput(Throughput::Elements(pixel_count as u64));
+        let mut dst = vec![0; src.len()];
+        group.bench_function("naive", |b| {
+            b.iter(|| naive_unpremultiply(black_box(&src), &mut dst, WIDTH, rects))
+        });
+        group.bench_function(&simd, |b| {
+            b.iter(|| pixels::bgra_to_rgba_unpremultiplied(black_box(&src), &mut dst, WIDTH, rects))
+        });
+        group.finish();
+
+        let mut group = c.benchmark_group(format!("bgra_to_i420/{}", name));
+        group.throughput(Throughput::Elements(pixel_count as u64));
+        let (luma_size, chroma_size) = pixels::i420_plane_sizes(WIDTH, HEIGHT);
+        let (mut y, mut u, mut v) = (vec![0; luma_size], vec![0; chroma_size], vec![0; chroma_size]);
+        group.bench_function("naive luma", |b| {
+            b.iter(|| naive_luma(black_box(&src), &mut y, WIDTH, rects))
+        });
+        group.bench_function(&format!("{} luma+chroma", simd), |b| {
+            b.iter(|| pixels::bgra_to_i420(black_box(&src), WIDTH, rects, &mut y, &mut u, &mut v))
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_conversions);
+criterion_main!(benches);
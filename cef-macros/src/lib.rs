@@ -0,0 +1,487 @@
+//! Procedural macros for the `cef` crate. Use them through the re-exports in `cef`.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, AttributeArgs, Error, Fields, FnArg, GenericArgument,
+    ImplItem, Item, ItemImpl, ItemStruct, Lit, Meta, NestedMeta, Pat, PathArguments, ReturnType,
+    Type, Visibility,
+};
+
+/// Exposes a struct's public fields, or an `impl` block's public methods, to
+/// JavaScript. See `cef::js_class` for details.
+#[proc_macro_attribute]
+pub fn js_class(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as AttributeArgs);
+    let item = parse_macro_input!(item as Item);
+    let result = match item {
+        Item::Struct(item) => expand_struct(args, item),
+        Item::Impl(item) => expand_impl(args, item),
+        item => Err(Error::new(
+            item.span(),
+            "js_class can only be applied to structs and impl blocks",
+        )),
+    };
+    result.unwrap_or_else(|error| error.to_compile_error()).into()
+}
+
+/// Options given through `#[js(...)]` on fields and methods, or through the
+/// attribute arguments of `js_class` itself.
+#[derive(Default)]
+struct Options {
+    name: Option<String>,
+    skip: bool,
+    readonly: bool,
+}
+
+impl Options {
+    fn parse_nested(&mut self, nested: &[NestedMeta]) -> Result<(), Error> {
+        for meta in nested {
+            match meta {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => self.skip = true,
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("readonly") => {
+                    self.readonly = true
+                }
+                NestedMeta::Meta(Meta::NameValue(pair)) if pair.path.is_ident("name") => {
+                    match &pair.lit {
+                        Lit::Str(name) => self.name = Some(name.value()),
+                        lit => return Err(Error::new(lit.span(), "expected a string")),
+                    }
+                }
+                meta => return Err(Error::new(meta.span(), "unknown js option")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses and removes the `#[js(...)]` attributes.
+    fn take(attrs: &mut Vec<syn::Attribute>) -> Result<Options, Error> {
+        let mut options = Options::default();
+        let mut error = None;
+        attrs.retain(|attr| {
+            if !attr.path.is_ident("js") {
+                return true;
+            }
+            let parsed = attr.parse_meta().and_then(|meta| match meta {
+                Meta::List(list) => {
+                    let nested: Vec<NestedMeta> = list.nested.into_iter().collect();
+                    options.parse_nested(&nested)
+                }
+                meta => Err(Error::new(meta.span(), "expected #[js(...)]")),
+            });
+            if let Err(e) = parsed {
+                error.get_or_insert(e);
+            }
+            false
+        });
+        match error {
+            Some(error) => Err(error),
+            None => Ok(options),
+        }
+    }
+}
+
+fn expand_struct(args: AttributeArgs, mut item: ItemStruct) -> Result<TokenStream2, Error> {
+    let mut options = Options::default();
+    options.parse_nested(&args)?;
+    if !item.generics.params.is_empty() {
+        return Err(Error::new(item.generics.span(), "js_class structs can't be generic"));
+    }
+    let ident = &item.ident;
+    let class_name = options.name.unwrap_or_else(|| ident.to_string());
+
+    if let Fields::Unnamed(unnamed) = &mut item.fields {
+        for field in unnamed.unnamed.iter_mut() {
+            let options = Options::take(&mut field.attrs)?;
+            if !options.skip && is_public(&field.vis) {
+                return Err(Error::new(
+                    field.span(),
+                    "js_class can't expose tuple struct fields; name them or use #[js(skip)]",
+                ));
+            }
+        }
+    }
+
+    let mut fields = Vec::new();
+    if let Fields::Named(named) = &mut item.fields {
+        for field in named.named.iter_mut() {
+            let options = Options::take(&mut field.attrs)?;
+            if options.skip || !is_public(&field.vis) {
+                continue;
+            }
+            let field_ident = field.ident.clone().expect("named field");
+            let name = options.name.unwrap_or_else(|| field_ident.to_string());
+            let ts_type = ts_type(&field.ty);
+            let ty = &field.ty;
+            let set = if options.readonly {
+                quote!(None)
+            } else {
+                quote! {
+                    Some(|this: &mut #ident, value: &::cef::v8context::V8Value| {
+                        this.#field_ident = ::cef::v8context::from_v8_value::<#ty>(value)?;
+                        Ok(())
+                    })
+                }
+            };
+            fields.push(quote! {
+                ::cef::js_class::JsField {
+                    name: #name,
+                    ts_type: #ts_type,
+                    get: |this: &#ident| ::cef::v8context::to_v8_value(&this.#field_ident),
+                    set: #set,
+                }
+            });
+        }
+    }
+
+    Ok(quote! {
+        #item
+
+        impl ::cef::js_class::JsFields for #ident {
+            const CLASS_NAME: &'static str = #class_name;
+            fn js_fields() -> ::std::vec::Vec<::cef::js_class::JsField<Self>> {
+                vec![#(#fields),*]
+            }
+        }
+    })
+}
+
+fn expand_impl(args: AttributeArgs, mut item: ItemImpl) -> Result<TokenStream2, Error> {
+    if !args.is_empty() {
+        return Err(Error::new(Span::call_site(), "js_class takes no arguments on impl blocks"));
+    }
+    if item.trait_.is_some() || !item.generics.params.is_empty() {
+        return Err(Error::new(
+            item.span(),
+            "js_class can only be applied to inherent impl blocks without generics",
+        ));
+    }
+    let self_ty = item.self_ty.clone();
+
+    let mut methods = Vec::new();
+    for impl_item in item.items.iter_mut() {
+        let method = match impl_item {
+            ImplItem::Method(method) => method,
+            _ => continue,
+        };
+        let options = Options::take(&mut method.attrs)?;
+        let has_receiver = match method.sig.inputs.first() {
+            Some(FnArg::Receiver(receiver)) => receiver.reference.is_some(),
+            _ => false,
+        };
+        if options.skip || !is_public(&method.vis) || !has_receiver {
+            continue;
+        }
+        if method.sig.asyncness.is_some() || !method.sig.generics.params.is_empty() {
+            return Err(Error::new(
+                method.sig.span(),
+                "js_class methods can't be async or generic; use #[js(skip)]",
+            ));
+        }
+        let method_ident = &method.sig.ident;
+        let name = options.name.unwrap_or_else(|| method_ident.to_string());
+
+        let mut params = Vec::new();
+        let mut conversions = Vec::new();
+        let mut call_args = Vec::new();
+        for (index, input) in method.sig.inputs.iter().skip(1).enumerate() {
+            let typed = match input {
+                FnArg::Typed(typed) => typed,
+                FnArg::Receiver(_) => unreachable!("receiver is always first"),
+            };
+            let param_name = match &*typed.pat {
+                Pat::Ident(ident) => ident.ident.to_string(),
+                _ => format!("arg{}", index),
+            };
+            let ts = ts_type(&typed.ty);
+            params.push(quote!((#param_name, #ts)));
+
+            let arg = syn::Ident::new(&format!("__arg{}", index), Span::call_site());
+            let (owned, borrowed) = owned_type(&typed.ty);
+            conversions.push(quote! {
+                let #arg: #owned = ::cef::js_class::convert_argument(args, #index, #name, #param_name)?;
+            });
+            call_args.push(if borrowed { quote!(&#arg) } else { quote!(#arg) });
+        }
+
+        let (ts_return, unwrap_result) = match &method.sig.output {
+            ReturnType::Default => ("void".to_owned(), quote!()),
+            ReturnType::Type(_, ty) => match result_ok_type(ty) {
+                Some(ok) => (
+                    ts_type(ok),
+                    quote!(let result = result.map_err(|error| error.to_string())?;),
+                ),
+                None => (ts_type(ty), quote!()),
+            },
+        };
+
+        methods.push(quote! {
+            ::cef::js_class::JsMethod {
+                name: #name,
+                params: &[#(#params),*],
+                ts_return: #ts_return,
+                call: |this: &mut #self_ty, args: &[::cef::v8context::V8Value]| {
+                    #(#conversions)*
+                    let result = this.#method_ident(#(#call_args),*);
+                    #unwrap_result
+                    ::cef::v8context::to_v8_value(&result).map_err(|error| error.to_string())
+                },
+            }
+        });
+    }
+
+    Ok(quote! {
+        #item
+
+        impl ::cef::js_class::JsMethods for #self_ty {
+            fn js_methods() -> ::std::vec::Vec<::cef::js_class::JsMethod<Self>> {
+                vec![#(#methods),*]
+            }
+        }
+    })
+}
+
+fn is_public(vis: &Visibility) -> bool {
+    matches!(vis, Visibility::Public(_))
+}
+
+/// Returns the last path segment of `ty` and its type arguments.
+fn last_segment(ty: &Type) -> Option<(String, Vec<&Type>)> {
+    let path = match ty {
+        Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+    let segment = path.segments.last()?;
+    let args = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    Some((segment.ident.to_string(), args))
+}
+
+fn result_ok_type(ty: &Type) -> Option<&Type> {
+    match last_segment(ty) {
+        Some((name, args)) if name == "Result" => args.first().copied(),
+        _ => None,
+    }
+}
+
+/// Returns the type an argument is deserialized into, and whether a reference to
+/// it has to be passed to the method.
+fn owned_type(ty: &Type) -> (TokenStream2, bool) {
+    match ty {
+        Type::Reference(reference) => match &*reference.elem {
+            Type::Path(path) if path.path.is_ident("str") => (quote!(::std::string::String), true),
+            Type::Slice(slice) => {
+                let elem = &slice.elem;
+                (quote!(::std::vec::Vec<#elem>), true)
+            }
+            elem => (quote!(#elem), true),
+        },
+        ty => (quote!(#ty), false),
+    }
+}
+
+/// Maps a Rust type onto the TypeScript type it is converted to. Unknown types
+/// are assumed to have a declaration of the same name.
+fn ts_type(ty: &Type) -> String {
+    match ty {
+        Type::Reference(reference) => ts_type(&reference.elem),
+        Type::Paren(paren) => ts_type(&paren.elem),
+        Type::Group(group) => ts_type(&group.elem),
+        Type::Tuple(tuple) if tuple.elems.is_empty() => "null".to_owned(),
+        Type::Tuple(tuple) => {
+            let elems: Vec<String> = tuple.elems.iter().map(ts_type).collect();
+            format!("[{}]", elems.join(", "))
+        }
+        Type::Slice(slice) => format!("{}[]", ts_element(&slice.elem)),
+        Type::Array(array) => format!("{}[]", ts_element(&array.elem)),
+        Type::Path(_) => {
+            let (name, args) = match last_segment(ty) {
+                Some(segment) => segment,
+                None => return "any".to_owned(),
+            };
+            match (name.as_str(), args.as_slice()) {
+                ("i8", _) | ("i16", _) | ("i32", _) | ("i64", _) | ("i128", _) | ("isize", _)
+                | ("u8", _) | ("u16", _) | ("u32", _) | ("u64", _) | ("u128", _)
+                | ("usize", _) | ("f32", _) | ("f64", _) => "number".to_owned(),
+                ("bool", _) => "boolean".to_owned(),
+                ("String", _) | ("str", _) | ("char", _) => "string".to_owned(),
+                ("V8Date", _) => "Date".to_owned(),
+                ("Option", [inner]) => format!("{} | null", ts_type(inner)),
+                ("Box", [inner]) | ("Rc", [inner]) | ("Arc", [inner]) | ("Cow", [inner]) => {
+                    ts_type(inner)
+                }
+                ("Vec", [inner]) | ("VecDeque", [inner]) | ("HashSet", [inner])
+                | ("BTreeSet", [inner]) => format!("{}[]", ts_element(inner)),
+                ("HashMap", [key, value]) | ("BTreeMap", [key, value]) => {
+                    let key = if ts_type(key) == "number" { "number" } else { "string" };
+                    format!("Record<{}, {}>", key, ts_type(value))
+                }
+                ("Result", [ok, ..]) => ts_type(ok),
+                (name, _) => name.to_owned(),
+            }
+        }
+        _ => "any".to_owned(),
+    }
+}
+
+/// Like [ts_type], but parenthesizes union types so they can be used as array
+/// elements.
+fn ts_element(ty: &Type) -> String {
+    let ts = ts_type(ty);
+    if ts.contains('|') {
+        format!("({})", ts)
+    } else {
+        ts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::{parse::Parser, parse_quote, punctuated::Punctuated, Token};
+
+    fn ts(ty: &str) -> String {
+        ts_type(&syn::parse_str(ty).unwrap())
+    }
+
+    /// Expands `item` with the attribute arguments `args` and returns the output
+    /// without whitespace, or the error message.
+    fn expand(args: TokenStream2, item: Item) -> Result<String, String> {
+        let args: Vec<NestedMeta> = Punctuated::<NestedMeta, Token![,]>::parse_terminated
+            .parse2(args)
+            .unwrap()
+            .into_iter()
+            .collect();
+        let expanded = match item {
+            Item::Struct(item) => expand_struct(args, item),
+            Item::Impl(item) => expand_impl(args, item),
+            _ => unreachable!(),
+        };
+        expanded
+            .map(|tokens| tokens.to_string().chars().filter(|c| !c.is_whitespace()).collect())
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn typescript_types() {
+        assert_eq!(ts("i32"), "number");
+        assert_eq!(ts("f64"), "number");
+        assert_eq!(ts("bool"), "boolean");
+        assert_eq!(ts("&str"), "string");
+        assert_eq!(ts("std::string::String"), "string");
+        assert_eq!(ts("()"), "null");
+        assert_eq!(ts("(u8, String)"), "[number, string]");
+        assert_eq!(ts("Option<String>"), "string | null");
+        assert_eq!(ts("Vec<Option<i32>>"), "(number | null)[]");
+        assert_eq!(ts("&[bool]"), "boolean[]");
+        assert_eq!(ts("[u8; 4]"), "number[]");
+        assert_eq!(ts("Arc<Vec<String>>"), "string[]");
+        assert_eq!(ts("HashMap<String, u32>"), "Record<string, number>");
+        assert_eq!(ts("BTreeMap<u32, Settings>"), "Record<number, Settings>");
+        assert_eq!(ts("Result<V8Date, String>"), "Date");
+        assert_eq!(ts("Settings"), "Settings");
+        assert_eq!(ts("fn()"), "any");
+    }
+
+    #[test]
+    fn owned_argument_types() {
+        let owned = |ty: Type| {
+            let (owned, borrowed) = owned_type(&ty);
+            (owned.to_string().replace(' ', ""), borrowed)
+        };
+        assert_eq!(owned(parse_quote!(&str)), ("::std::string::String".to_string(), true));
+        assert_eq!(owned(parse_quote!(&[u8])), ("::std::vec::Vec<u8>".to_string(), true));
+        assert_eq!(owned(parse_quote!(&Settings)), ("Settings".to_string(), true));
+        assert_eq!(owned(parse_quote!(Option<i32>)), ("Option<i32>".to_string(), false));
+    }
+
+    #[test]
+    fn struct_expansion() {
+        let expanded = expand(
+            quote!(name = "CounterState"),
+            parse_quote! {
+                pub struct Counter {
+                    pub count: i32,
+                    #[js(readonly, name = "title")]
+                    pub label: String,
+                    #[js(skip)]
+                    pub secret: u64,
+                    private: bool,
+                }
+            },
+        )
+        .unwrap();
+        assert!(expanded.contains(r#"constCLASS_NAME:&'staticstr="CounterState";"#));
+        assert!(expanded.contains(r#"name:"count",ts_type:"number""#));
+        assert!(expanded.contains(r#"name:"title",ts_type:"string",get:|this:&Counter|::cef::v8context::to_v8_value(&this.label),set:None"#));
+        assert!(expanded.contains("this.count=::cef::v8context::from_v8_value::<i32>(value)?;"));
+        assert!(!expanded.contains(r#"name:"secret""#));
+        assert!(!expanded.contains(r#""private""#));
+        assert!(!expanded.contains("#[js"));
+    }
+
+    #[test]
+    fn impl_expansion() {
+        let expanded = expand(
+            quote!(),
+            parse_quote! {
+                impl Counter {
+                    pub fn increment(&mut self, by: i32) -> i32 { 0 }
+                    #[js(name = "reset")]
+                    pub fn reset_to(&mut self, value: Option<i32>, label: &str) -> Result<(), String> { Ok(()) }
+                    pub fn new() -> Counter { Counter }
+                    fn private(&self) {}
+                }
+            },
+        )
+        .unwrap();
+        assert!(expanded.contains(r#"name:"increment",params:&[("by","number")],ts_return:"number""#));
+        assert!(expanded.contains(r#"let__arg0:i32=::cef::js_class::convert_argument(args,0usize,"increment","by")?;"#));
+        assert!(expanded.contains(r#"name:"reset",params:&[("value","number|null"),("label","string")],ts_return:"null""#));
+        assert!(expanded.contains(r#"let__arg1:::std::string::String=::cef::js_class::convert_argument(args,1usize,"reset","label")?;"#));
+        assert!(expanded.contains("this.reset_to(__arg0,&__arg1);letresult=result.map_err(|error|error.to_string())?;"));
+        assert!(!expanded.contains(r#""new""#));
+        assert!(!expanded.contains(r#""private""#));
+    }
+
+    #[test]
+    fn expansion_errors() {
+        let error = |args: TokenStream2, item: Item| expand(args, item).unwrap_err();
+        assert_eq!(
+            error(quote!(), parse_quote!(pub struct Point(pub f64, #[js(skip)] pub f64);)),
+            "js_class can't expose tuple struct fields; name them or use #[js(skip)]"
+        );
+        assert_eq!(
+            error(quote!(), parse_quote!(pub struct Point(f64, pub f64);)),
+            "js_class can't expose tuple struct fields; name them or use #[js(skip)]"
+        );
+        assert_eq!(error(quote!(), parse_quote!(pub struct Wrapper<T> { pub inner: T })), "js_class structs can't be generic");
+        assert_eq!(error(quote!(frozen), parse_quote!(pub struct Counter {})), "unknown js option");
+        assert_eq!(error(quote!(name = 1), parse_quote!(pub struct Counter {})), "expected a string");
+        assert_eq!(
+            error(quote!(), parse_quote!(pub struct Counter { #[js = "x"] pub count: i32 })),
+            "expected #[js(...)]"
+        );
+        assert_eq!(error(quote!(name = "X"), parse_quote!(impl Counter {})), "js_class takes no arguments on impl blocks");
+        assert_eq!(
+            error(quote!(), parse_quote!(impl Default for Counter {})),
+            "js_class can only be applied to inherent impl blocks without generics"
+        );
+        assert_eq!(
+            error(quote!(), parse_quote!(impl Counter { pub async fn load(&self) {} })),
+            "js_class methods can't be async or generic; use #[js(skip)]"
+        );
+    }
+}
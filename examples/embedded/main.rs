@@ -9,12 +9,9 @@ compile_error!("At least one renderer feature must be enabled! Enable winit-blit
 
 use cef::color::Color;
 use cef::browser_host::PaintElementType;
-use cef::client::render_handler::{CursorType, CustomCursorInfo};
+use cef::client::render_handler::CursorType;
 use cef::client::render_handler::ScreenInfo;
 use cef::drag::DragOperation;
-use cef::events::KeyEvent;
-use cef::events::WindowsKeyCode;
-use cef::events::{PointerType, TouchEvent, TouchEventType};
 use cef::values::{Point, Rect};
 use cef::{
     app::{App, AppCallbacks},
@@ -27,9 +24,9 @@ use cef::{
         Client, ClientCallbacks,
     },
     command_line::CommandLine,
-    events::{EventFlags, MouseButtonType, MouseEvent},
     settings::{LogSeverity, Settings},
     window::{RawWindow, WindowInfo},
+    winit::{Cursor, InputTranslator},
 };
 use cef_sys::cef_cursor_handle_t;
 use parking_lot::Mutex;
@@ -38,13 +35,9 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
-use winit::event::{Touch, TouchPhase};
 use winit::{
-    dpi::{LogicalPosition, PhysicalPosition, PhysicalSize},
-    event::{
-        ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, StartCause,
-        VirtualKeyCode, WindowEvent,
-    },
+    dpi::{LogicalPosition, PhysicalPosition},
+    event::{Event, StartCause, WindowEvent},
     event_loop::{ControlFlow, EventLoop, EventLoopProxy},
     window::{CursorIcon, Window, WindowBuilder, CustomCursorIcon, RgbaBuffer},
 };
@@ -216,118 +209,21 @@ impl<R: Renderer> RenderHandlerCallbacks for RenderHandlerCallbacksImpl<R> {
         unimplemented!()
     }
     fn on_cursor_change(&self, _browser: Browser, _cursor: cef_cursor_handle_t, type_: CursorType) {
-        // this is a good website for testing cursor changes
-        // http://html5advent2011.digitpaint.nl/3/index.html
-        let winit_cursor = match type_ {
-            CursorType::MiddlePanning
-            | CursorType::EastPanning
-            | CursorType::NorthPanning
-            | CursorType::NorthEastPanning
-            | CursorType::NorthWestPanning
-            | CursorType::SouthPanning
-            | CursorType::SouthEastPanning
-            | CursorType::SouthWestPanning
-            | CursorType::WestPanning
-            | CursorType::MiddlePanningVertical
-            | CursorType::MiddlePanningHorizontal
-            | CursorType::DndNone
-            | CursorType::DndMove
-            | CursorType::DndCopy
-            | CursorType::DndLink
-            | CursorType::Pointer => Some(CursorIcon::Default),
-            CursorType::Cross => Some(CursorIcon::Crosshair),
-            CursorType::Hand => Some(CursorIcon::Hand),
-            CursorType::IBeam => Some(CursorIcon::Text),
-            CursorType::Wait => Some(CursorIcon::Wait),
-            CursorType::Help => Some(CursorIcon::Help),
-            CursorType::EastResize => Some(CursorIcon::EResize),
-            CursorType::NorthResize => Some(CursorIcon::NResize),
-            CursorType::NorthEastResize => Some(CursorIcon::NeResize),
-            CursorType::NorthWestResize => Some(CursorIcon::NwResize),
-            CursorType::SouthResize => Some(CursorIcon::SResize),
-            CursorType::SouthEastResize => Some(CursorIcon::SeResize),
-            CursorType::SouthWestResize => Some(CursorIcon::SwResize),
-            CursorType::WestResize => Some(CursorIcon::WResize),
-            CursorType::NorthSouthResize => Some(CursorIcon::NsResize),
-            CursorType::EastWestResize => Some(CursorIcon::EwResize),
-            CursorType::NorthEastSouthWestResize => Some(CursorIcon::NeswResize),
-            CursorType::NorthWestSouthEastResize => Some(CursorIcon::NwseResize),
-            CursorType::ColumnResize => Some(CursorIcon::ColResize),
-            CursorType::RowResize => Some(CursorIcon::RowResize),
-            CursorType::Move => Some(CursorIcon::Move),
-            CursorType::VerticalText => Some(CursorIcon::VerticalText),
-            CursorType::Cell => Some(CursorIcon::Cell),
-            CursorType::ContextMenu => Some(CursorIcon::ContextMenu),
-            CursorType::Alias => Some(CursorIcon::Alias),
-            CursorType::Progress => Some(CursorIcon::Progress),
-            CursorType::NoDrop => Some(CursorIcon::NoDrop),
-            CursorType::Copy => Some(CursorIcon::Copy),
-            CursorType::None => None,
-            CursorType::NotAllowed => Some(CursorIcon::NotAllowed),
-            CursorType::ZoomIn => Some(CursorIcon::ZoomIn),
-            CursorType::ZoomOut => Some(CursorIcon::ZoomOut),
-            CursorType::Grab => Some(CursorIcon::Grab),
-            CursorType::Grabbing => Some(CursorIcon::Grabbing),
-            CursorType::Custom(custom_cursor) => {
-                let CustomCursorInfo {
-                    hotspot,
-                    image_scale_factor,
-                    buffer,
-                    size,
-                } = custom_cursor;
-                let hot_spot = PhysicalPosition::new(hotspot.x as u32, hotspot.y as u32);
-                let size = PhysicalSize::new(size.width as u32, size.height as u32);
-                let mut buffer = buffer.to_owned();
-
-                // convert from BGRA to RGBA
-                for pixel in buffer.chunks_mut(4) {
-                    let (l, r) = pixel.split_at_mut(2);
-                    std::mem::swap(&mut l[0], &mut r[0]);
-                }
-                let source_scale_factor = image_scale_factor as f64;
-
-                Some(CursorIcon::Custom(
-                    CustomCursorIcon::from_rgba_fn(move |_, requested_scale_factor| {
-                        let scaled_size = size
-                            .to_logical::<u32>(source_scale_factor)
-                            .to_physical::<u32>(requested_scale_factor);
-                        let scaled_hotspot = hot_spot
-                            .to_logical::<u32>(source_scale_factor)
-                            .to_physical::<u32>(requested_scale_factor);
-                        let buffer = if requested_scale_factor != source_scale_factor {
-                            let mut resizer = resize::new(
-                                size.width as usize,
-                                size.height as usize,
-                                scaled_size.width as usize,
-                                scaled_size.height as usize,
-                                resize::Pixel::RGBA,
-                                if requested_scale_factor < source_scale_factor {
-                                    resize::Type::Lanczos3
-                                } else {
-                                    resize::Type::Catrom
-                                },
-                            );
-                            let mut out = vec![0; (scaled_size.width * scaled_size.height * 4) as usize];
-                            resizer.resize(&buffer, &mut out);
-                            out
-                        } else {
-                            buffer.clone()
-                        };
-                        let rgba_buffer = RgbaBuffer::from_rgba(buffer, scaled_size);
-                        Ok((rgba_buffer, scaled_hotspot))
-                    })
-                ))
-            }
-        };
         let renderer = self.renderer.lock();
         let window = renderer.window();
 
-        match winit_cursor {
-            Some(cursor) => {
-                window.set_cursor_icon(cursor);
+        match cef::winit::cursor(type_) {
+            Cursor::Custom(custom_cursor) => {
+                window.set_cursor_icon(CursorIcon::Custom(CustomCursorIcon::from_rgba_fn(
+                    move |_, requested_scale_factor| {
+                        let scaled = custom_cursor.scaled(requested_scale_factor);
+                        let rgba_buffer = RgbaBuffer::from_rgba(scaled.rgba().to_vec(), scaled.size());
+                        Ok((rgba_buffer, scaled.hotspot()))
+                    },
+                )));
                 window.set_cursor_visible(true);
             }
-            None => window.set_cursor_visible(false),
+            _ => cef::winit::set_cursor(window, type_),
         }
     }
     fn update_drag_cursor(&self, _browser: Browser, _operation: DragOperation) {}
@@ -394,6 +290,7 @@ fn main() {
 
             let width = renderer.window().inner_size().width;
             let height = renderer.window().inner_size().height;
+            let scale_factor = renderer.window().scale_factor();
 
             let window_info = WindowInfo {
                 windowless_rendering_enabled: true,
@@ -429,11 +326,7 @@ fn main() {
 
             println!("initialize done");
 
-            let mut mouse_event = MouseEvent {
-                x: 0,
-                y: 0,
-                modifiers: EventFlags::empty(),
-            };
+            let mut input = InputTranslator::new(scale_factor);
             let poll_duration = Duration::new(1, 0) / 30;
             let mut poll_instant = Instant::now() + poll_duration;
             let mut scheduled_work_queue = vec![poll_instant];
@@ -460,140 +353,9 @@ fn main() {
                         window_id: _,
                     } => match event {
                         WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                        WindowEvent::Resized(_) => {
-                            browser.get_host().was_resized();
-                        }
-                        WindowEvent::CursorMoved {
-                            position,
-                            modifiers,
-                            ..
-                        } => {
-                            mouse_event
-                                .modifiers
-                                .set(EventFlags::SHIFT_DOWN, modifiers.shift());
-                            mouse_event
-                                .modifiers
-                                .set(EventFlags::CONTROL_DOWN, modifiers.ctrl());
-                            mouse_event
-                                .modifiers
-                                .set(EventFlags::ALT_DOWN, modifiers.alt());
-                            mouse_event.x = position.x.round() as _;
-                            mouse_event.y = position.y.round() as _;
-                            browser
-                                .get_host()
-                                .send_mouse_move_event(&mouse_event, false);
+                        event => {
+                            input.handle_event(&browser.get_host(), &event);
                         }
-                        WindowEvent::MouseWheel { delta, .. } => {
-                            let (delta_x, delta_y) = match delta {
-                                MouseScrollDelta::LineDelta(x, y) => (20 * x as i32, 20 * y as i32),
-                                MouseScrollDelta::PixelDelta(delta) => (delta.x as _, delta.y as _),
-                            };
-                            browser.get_host().send_mouse_wheel_event(
-                                &mouse_event,
-                                delta_x,
-                                delta_y,
-                            );
-                        }
-                        WindowEvent::MouseInput {
-                            state,
-                            button,
-                            modifiers,
-                            ..
-                        } => {
-                            mouse_event
-                                .modifiers
-                                .set(EventFlags::SHIFT_DOWN, modifiers.shift());
-                            mouse_event
-                                .modifiers
-                                .set(EventFlags::CONTROL_DOWN, modifiers.ctrl());
-                            mouse_event
-                                .modifiers
-                                .set(EventFlags::ALT_DOWN, modifiers.alt());
-                            let button = match button {
-                                MouseButton::Left => Some(MouseButtonType::Left),
-                                MouseButton::Middle => Some(MouseButtonType::Middle),
-                                MouseButton::Right => Some(MouseButtonType::Right),
-                                _ => None,
-                            };
-                            let released = match state {
-                                ElementState::Pressed => false,
-                                ElementState::Released => true,
-                            };
-                            if let Some(button) = button {
-                                match button {
-                                    MouseButtonType::Left => mouse_event
-                                        .modifiers
-                                        .set(EventFlags::LEFT_MOUSE_BUTTON, !released),
-                                    MouseButtonType::Middle => mouse_event
-                                        .modifiers
-                                        .set(EventFlags::MIDDLE_MOUSE_BUTTON, !released),
-                                    MouseButtonType::Right => mouse_event
-                                        .modifiers
-                                        .set(EventFlags::RIGHT_MOUSE_BUTTON, !released),
-                                }
-                                browser.get_host().send_mouse_click_event(
-                                    &mouse_event,
-                                    button,
-                                    released,
-                                    1,
-                                );
-                            }
-                        }
-                        WindowEvent::Touch(Touch {
-                            phase,
-                            location,
-                            force,
-                            id,
-                            ..
-                        }) => browser.get_host().send_touch_event(&TouchEvent {
-                            touch_id: id as i32,
-                            x: location.x as f32,
-                            y: location.y as f32,
-                            radius_x: 1.0,
-                            radius_y: 1.0,
-                            rotation_angle: 0.0,
-                            pressure: force.map(|f| f.normalized() as f32).unwrap_or(1.0),
-                            event_type: match phase {
-                                TouchPhase::Started => TouchEventType::Pressed,
-                                TouchPhase::Moved => TouchEventType::Moved,
-                                TouchPhase::Ended => TouchEventType::Released,
-                                TouchPhase::Cancelled => TouchEventType::Cancelled,
-                            },
-                            modifiers: mouse_event.modifiers,
-                            pointer_type: PointerType::Touch,
-                        }),
-                        WindowEvent::KeyboardInput{input: KeyboardInput {state, virtual_keycode, scancode, modifiers, ..}, ..} => {
-                            mouse_event.modifiers.set(EventFlags::SHIFT_DOWN, modifiers.shift());
-                            mouse_event.modifiers.set(EventFlags::CONTROL_DOWN, modifiers.ctrl());
-                            mouse_event.modifiers.set(EventFlags::ALT_DOWN, modifiers.alt());
-                            if let Some(keycode) = virtual_keycode.and_then(winit_keycode_to_windows_keycode) {
-                                browser.get_host().send_key_event(
-                                    match state {
-                                        ElementState::Pressed => KeyEvent::KeyDown {
-                                            modifiers: mouse_event.modifiers,
-                                            windows_key_code: keycode,
-                                            native_key_code: scancode as _,
-                                            is_system_key: false,
-                                            focus_on_editable_field: false,
-                                        },
-                                        ElementState::Released => KeyEvent::KeyUp {
-                                            modifiers: mouse_event.modifiers,
-                                            windows_key_code: keycode,
-                                            native_key_code: scancode as _,
-                                            is_system_key: false,
-                                            focus_on_editable_field: false,
-                                        },
-                                    }
-                                );
-                            }
-                        },
-                        WindowEvent::ReceivedCharacter(char) => {
-                            browser.get_host().send_key_event(KeyEvent::Char {
-                                modifiers: mouse_event.modifiers,
-                                char,
-                            });
-                        }
-                        _ => (),
                     },
                     Event::UserEvent(event) => match event {
                         CefEvent::ScheduleWork(instant) => {
@@ -625,148 +387,3 @@ fn main() {
         }
     }
 }
-
-fn winit_keycode_to_windows_keycode(winit_keycode: VirtualKeyCode) -> Option<WindowsKeyCode> {
-    match winit_keycode {
-        VirtualKeyCode::Key1 => Some(WindowsKeyCode::Key1),
-        VirtualKeyCode::Key2 => Some(WindowsKeyCode::Key2),
-        VirtualKeyCode::Key3 => Some(WindowsKeyCode::Key3),
-        VirtualKeyCode::Key4 => Some(WindowsKeyCode::Key4),
-        VirtualKeyCode::Key5 => Some(WindowsKeyCode::Key5),
-        VirtualKeyCode::Key6 => Some(WindowsKeyCode::Key6),
-        VirtualKeyCode::Key7 => Some(WindowsKeyCode::Key7),
-        VirtualKeyCode::Key8 => Some(WindowsKeyCode::Key8),
-        VirtualKeyCode::Key9 => Some(WindowsKeyCode::Key9),
-        VirtualKeyCode::Key0 => Some(WindowsKeyCode::Key0),
-        VirtualKeyCode::A => Some(WindowsKeyCode::A),
-        VirtualKeyCode::B => Some(WindowsKeyCode::B),
-        VirtualKeyCode::C => Some(WindowsKeyCode::C),
-        VirtualKeyCode::D => Some(WindowsKeyCode::D),
-        VirtualKeyCode::E => Some(WindowsKeyCode::E),
-        VirtualKeyCode::F => Some(WindowsKeyCode::F),
-        VirtualKeyCode::G => Some(WindowsKeyCode::G),
-        VirtualKeyCode::H => Some(WindowsKeyCode::H),
-        VirtualKeyCode::I => Some(WindowsKeyCode::I),
-        VirtualKeyCode::J => Some(WindowsKeyCode::J),
-        VirtualKeyCode::K => Some(WindowsKeyCode::K),
-        VirtualKeyCode::L => Some(WindowsKeyCode::L),
-        VirtualKeyCode::M => Some(WindowsKeyCode::M),
-        VirtualKeyCode::N => Some(WindowsKeyCode::N),
-        VirtualKeyCode::O => Some(WindowsKeyCode::O),
-        VirtualKeyCode::P => Some(WindowsKeyCode::P),
-        VirtualKeyCode::Q => Some(WindowsKeyCode::Q),
-        VirtualKeyCode::R => Some(WindowsKeyCode::R),
-        VirtualKeyCode::S => Some(WindowsKeyCode::S),
-        VirtualKeyCode::T => Some(WindowsKeyCode::T),
-        VirtualKeyCode::U => Some(WindowsKeyCode::U),
-        VirtualKeyCode::V => Some(WindowsKeyCode::V),
-        VirtualKeyCode::W => Some(WindowsKeyCode::W),
-        VirtualKeyCode::X => Some(WindowsKeyCode::X),
-        VirtualKeyCode::Y => Some(WindowsKeyCode::Y),
-        VirtualKeyCode::Z => Some(WindowsKeyCode::Z),
-        VirtualKeyCode::Escape => Some(WindowsKeyCode::Escape),
-        VirtualKeyCode::F1 => Some(WindowsKeyCode::F1),
-        VirtualKeyCode::F2 => Some(WindowsKeyCode::F2),
-        VirtualKeyCode::F3 => Some(WindowsKeyCode::F3),
-        VirtualKeyCode::F4 => Some(WindowsKeyCode::F4),
-        VirtualKeyCode::F5 => Some(WindowsKeyCode::F5),
-        VirtualKeyCode::F6 => Some(WindowsKeyCode::F6),
-        VirtualKeyCode::F7 => Some(WindowsKeyCode::F7),
-        VirtualKeyCode::F8 => Some(WindowsKeyCode::F8),
-        VirtualKeyCode::F9 => Some(WindowsKeyCode::F9),
-        VirtualKeyCode::F10 => Some(WindowsKeyCode::F10),
-        VirtualKeyCode::F11 => Some(WindowsKeyCode::F11),
-        VirtualKeyCode::F12 => Some(WindowsKeyCode::F12),
-        VirtualKeyCode::F13 => Some(WindowsKeyCode::F13),
-        VirtualKeyCode::F14 => Some(WindowsKeyCode::F14),
-        VirtualKeyCode::F15 => Some(WindowsKeyCode::F15),
-        VirtualKeyCode::F16 => Some(WindowsKeyCode::F16),
-        VirtualKeyCode::F17 => Some(WindowsKeyCode::F17),
-        VirtualKeyCode::F18 => Some(WindowsKeyCode::F18),
-        VirtualKeyCode::F19 => Some(WindowsKeyCode::F19),
-        VirtualKeyCode::F20 => Some(WindowsKeyCode::F20),
-        VirtualKeyCode::F21 => Some(WindowsKeyCode::F21),
-        VirtualKeyCode::F22 => Some(WindowsKeyCode::F22),
-        VirtualKeyCode::F23 => Some(WindowsKeyCode::F23),
-        VirtualKeyCode::F24 => Some(WindowsKeyCode::F24),
-        VirtualKeyCode::Snapshot => Some(WindowsKeyCode::Snapshot),
-        VirtualKeyCode::Scroll => Some(WindowsKeyCode::Scroll),
-        VirtualKeyCode::Pause => Some(WindowsKeyCode::Pause),
-        VirtualKeyCode::Insert => Some(WindowsKeyCode::Insert),
-        VirtualKeyCode::Home => Some(WindowsKeyCode::Home),
-        VirtualKeyCode::Delete => Some(WindowsKeyCode::Delete),
-        VirtualKeyCode::End => Some(WindowsKeyCode::End),
-        VirtualKeyCode::PageDown => Some(WindowsKeyCode::Next),
-        VirtualKeyCode::PageUp => Some(WindowsKeyCode::Prior),
-        VirtualKeyCode::Left => Some(WindowsKeyCode::Left),
-        VirtualKeyCode::Up => Some(WindowsKeyCode::Up),
-        VirtualKeyCode::Right => Some(WindowsKeyCode::Right),
-        VirtualKeyCode::Down => Some(WindowsKeyCode::Down),
-        VirtualKeyCode::Back => Some(WindowsKeyCode::Back),
-        VirtualKeyCode::Return => Some(WindowsKeyCode::Return),
-        VirtualKeyCode::Space => Some(WindowsKeyCode::Space),
-        VirtualKeyCode::Numlock => Some(WindowsKeyCode::Numlock),
-        VirtualKeyCode::Numpad0 => Some(WindowsKeyCode::Numpad0),
-        VirtualKeyCode::Numpad1 => Some(WindowsKeyCode::Numpad1),
-        VirtualKeyCode::Numpad2 => Some(WindowsKeyCode::Numpad2),
-        VirtualKeyCode::Numpad3 => Some(WindowsKeyCode::Numpad3),
-        VirtualKeyCode::Numpad4 => Some(WindowsKeyCode::Numpad4),
-        VirtualKeyCode::Numpad5 => Some(WindowsKeyCode::Numpad5),
-        VirtualKeyCode::Numpad6 => Some(WindowsKeyCode::Numpad6),
-        VirtualKeyCode::Numpad7 => Some(WindowsKeyCode::Numpad7),
-        VirtualKeyCode::Numpad8 => Some(WindowsKeyCode::Numpad8),
-        VirtualKeyCode::Numpad9 => Some(WindowsKeyCode::Numpad9),
-        VirtualKeyCode::Add => Some(WindowsKeyCode::Add),
-        VirtualKeyCode::Apps => Some(WindowsKeyCode::Apps),
-        VirtualKeyCode::Capital => Some(WindowsKeyCode::Capital),
-        VirtualKeyCode::Comma => Some(WindowsKeyCode::OemComma),
-        VirtualKeyCode::Convert => Some(WindowsKeyCode::Convert),
-        VirtualKeyCode::Decimal => Some(WindowsKeyCode::Decimal),
-        VirtualKeyCode::Divide => Some(WindowsKeyCode::Divide),
-        VirtualKeyCode::Equals => Some(WindowsKeyCode::OemPlus),
-        VirtualKeyCode::Kana => Some(WindowsKeyCode::Kana),
-        VirtualKeyCode::Kanji => Some(WindowsKeyCode::Kanji),
-        VirtualKeyCode::LAlt => Some(WindowsKeyCode::LMenu),
-        VirtualKeyCode::LControl => Some(WindowsKeyCode::LControl),
-        VirtualKeyCode::LShift => Some(WindowsKeyCode::LShift),
-        VirtualKeyCode::LWin => Some(WindowsKeyCode::LWin),
-        VirtualKeyCode::Mail => Some(WindowsKeyCode::LaunchMail),
-        VirtualKeyCode::MediaSelect => Some(WindowsKeyCode::LaunchMediaSelect),
-        VirtualKeyCode::MediaStop => Some(WindowsKeyCode::MediaStop),
-        VirtualKeyCode::Minus => Some(WindowsKeyCode::OemMinus),
-        VirtualKeyCode::Multiply => Some(WindowsKeyCode::Multiply),
-        VirtualKeyCode::Mute => Some(WindowsKeyCode::VolumeMute),
-        VirtualKeyCode::NavigateForward => Some(WindowsKeyCode::BrowserForward),
-        VirtualKeyCode::NavigateBackward => Some(WindowsKeyCode::BrowserBack),
-        VirtualKeyCode::NextTrack => Some(WindowsKeyCode::MediaNextTrack),
-        VirtualKeyCode::NoConvert => Some(WindowsKeyCode::NonConvert),
-        VirtualKeyCode::OEM102 => Some(WindowsKeyCode::Oem102),
-        VirtualKeyCode::Period => Some(WindowsKeyCode::OemPeriod),
-        VirtualKeyCode::PlayPause => Some(WindowsKeyCode::MediaPlayPause),
-        VirtualKeyCode::PrevTrack => Some(WindowsKeyCode::MediaPrevTrack),
-        VirtualKeyCode::RAlt => Some(WindowsKeyCode::RMenu),
-        VirtualKeyCode::RControl => Some(WindowsKeyCode::RControl),
-        VirtualKeyCode::RShift => Some(WindowsKeyCode::RShift),
-        VirtualKeyCode::RWin => Some(WindowsKeyCode::RWin),
-        VirtualKeyCode::Sleep => Some(WindowsKeyCode::Sleep),
-        VirtualKeyCode::Subtract => Some(WindowsKeyCode::Subtract),
-        VirtualKeyCode::Tab => Some(WindowsKeyCode::Tab),
-        VirtualKeyCode::VolumeDown => Some(WindowsKeyCode::VolumeDown),
-        VirtualKeyCode::VolumeUp => Some(WindowsKeyCode::VolumeUp),
-        VirtualKeyCode::WebFavorites => Some(WindowsKeyCode::BrowserFavorites),
-        VirtualKeyCode::WebForward => Some(WindowsKeyCode::BrowserForward),
-        VirtualKeyCode::WebHome => Some(WindowsKeyCode::BrowserHome),
-        VirtualKeyCode::WebRefresh => Some(WindowsKeyCode::BrowserRefresh),
-        VirtualKeyCode::WebSearch => Some(WindowsKeyCode::BrowserSearch),
-        VirtualKeyCode::WebStop => Some(WindowsKeyCode::BrowserStop),
-        // These key-codes should be unmapped by reversing MapVirtualKeyA.
-        // VirtualKeyCode::Apostrophe => Some(WindowsKeyCode::Apostrophe),
-        // VirtualKeyCode::Backslash => Some(WindowsKeyCode::Backslash),
-        // VirtualKeyCode::Grave => Some(WindowsKeyCode::Grave),
-        // VirtualKeyCode::LBracket => Some(WindowsKeyCode::LBracket),
-        // VirtualKeyCode::RBracket => Some(WindowsKeyCode::RBracket),
-        // VirtualKeyCode::Semicolon => Some(WindowsKeyCode::Semicolon),
-        // VirtualKeyCode::Slash => Some(WindowsKeyCode::Slash),
-        _ => None,
-    }
-}
@@ -2,7 +2,7 @@ use crate::{
     browser_process_handler::{BrowserProcessHandler},
     command_line::CommandLine,
     refcounted::{RefCountedPtr, Wrapper},
-    render_process_handler::{RenderProcessHandler},
+    render_process_handler::{DefaultRenderProcessHandler, RenderProcessHandler},
     resource_bundle_handler::{ResourceBundleHandler},
     scheme_registrar::SchemeRegistrar,
     string::CefString,
@@ -49,7 +49,8 @@ pub trait AppCallbacks: 'static + Send + Sync {
         None
     }
     /// Return the handler for functionality specific to the render process. This
-    /// function is called on the render process main thread.
+    /// function is called on the render process main thread. If no handler is
+    /// returned a default one is used, so that [Frame::eval_js] keeps working.
     fn get_render_process_handler(&self) -> Option<RenderProcessHandler> {
         None
     }
@@ -121,7 +122,10 @@ cef_callback_impl! {
             self.0.get_browser_process_handler().map(|cef| cef.into_raw()).unwrap_or(null_mut())
         }
         fn get_render_process_handler(&self) -> *mut cef_render_process_handler_t {
-            self.0.get_render_process_handler().map(|cef| cef.into_raw()).unwrap_or(null_mut())
+            self.0
+                .get_render_process_handler()
+                .unwrap_or_else(|| RenderProcessHandler::new(DefaultRenderProcessHandler))
+                .into_raw()
         }
     }
 }
@@ -0,0 +1,577 @@
+//! Serving web content that is compiled into the executable or packed into a zip
+//! archive.
+//!
+//! [Assets] is a table of files keyed by their path, e.g. `js/app.js`, that is
+//! registered as the scheme handler factory of a custom scheme. Like
+//! [StaticFiles](crate::static_files::StaticFiles) it guesses MIME types from file
+//! extensions, serves `index.html` for directories and can fall back to a single
+//! page application's entry point. A file can also be included only as a
+//! precompressed variant, e.g. `js/app.js.br` or `js/app.js.gz`, to keep the
+//! executable small. CEF doesn't decode the `Content-Encoding` of custom scheme
+//! responses, so the variant is decompressed for every response that sends it,
+//! up to the end of the requested range. Its decompressed length is only
+//! computed once.
+//!
+//! ```ignore
+//! let assets = Assets::new()
+//!     .file("index.html", &include_bytes!("../ui/dist/index.html")[..])
+//!     .file("js/app.js.br", &include_bytes!("../ui/dist/js/app.js.br")[..])
+//!     .spa_fallback("index.html");
+//! // Or load the same files from a zip archive:
+//! let assets = Assets::from_zip_file(Path::new("ui.zip"))?.spa_fallback("index.html");
+//!
+//! SchemeHandlerFactory::new(assets).register(SchemeName::Custom { name: "app".into() });
+//! // app://ui/js/app.js now serves js/app.js.br, decompressed.
+//! ```
+
+use crate::{
+    browser::Browser,
+    callback::Callback,
+    frame::Frame,
+    load_handler::ErrorCode,
+    request::Request,
+    response::Response,
+    router::{reason_phrase, url_path},
+    scheme::SchemeHandlerFactoryCallbacks,
+    static_files::{
+        self, directory_url, not_modified, parse_range, path_segments, percent_decode, skip_position,
+        strip_path_prefix,
+    },
+    url_request::{ResourceHandler, ResourceHandlerCallbacks, ResourceReadHandler, ResourceSkipCallback},
+    zip_reader::ZipReader,
+};
+use parking_lot::Mutex;
+use std::{
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::Hasher,
+    io::{self, Read},
+    path::Path,
+    sync::Arc,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    fn decoder<'a>(self, data: &'a [u8]) -> Box<dyn Read + 'a> {
+        match self {
+            Encoding::Brotli => Box::new(brotli_decompressor::Decompressor::new(data, 4096)),
+            Encoding::Gzip => Box::new(flate2::read::GzDecoder::new(data)),
+        }
+    }
+    /// Decompresses the first `len` bytes of `data`.
+    fn decompress(self, data: &[u8], len: u64) -> io::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        self.decoder(data).take(len).read_to_end(&mut decompressed)?;
+        Ok(decompressed)
+    }
+    /// Returns the length of `data` decompressed, without keeping it.
+    fn decompressed_len(self, data: &[u8]) -> io::Result<u64> {
+        io::copy(&mut self.decoder(data), &mut io::sink())
+    }
+}
+
+/// The precompressed variants of a file, by encoding and file suffix, in order
+/// of preference.
+const ENCODINGS: &[(Encoding, &str)] = &[(Encoding::Brotli, ".br"), (Encoding::Gzip, ".gz")];
+
+#[derive(Clone)]
+enum Data {
+    Static(&'static [u8]),
+    Shared(Arc<[u8]>),
+}
+
+impl AsRef<[u8]> for Data {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Data::Static(data) => data,
+            Data::Shared(data) => data,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Asset {
+    data: Data,
+    etag: String,
+    /// The length of the file decompressed from this variant, once known.
+    decompressed_len: Arc<Mutex<Option<u64>>>,
+}
+
+impl Asset {
+    /// Returns the length of the file decompressed from this variant, which is
+    /// computed by the first request.
+    fn decompressed_len(&self, encoding: Encoding) -> io::Result<u64> {
+        let mut decompressed_len = self.decompressed_len.lock();
+        if let Some(len) = *decompressed_len {
+            return Ok(len);
+        }
+        let len = encoding.decompressed_len(self.data.as_ref())?;
+        *decompressed_len = Some(len);
+        Ok(len)
+    }
+}
+
+/// A table of files that is served through a custom scheme. Cloning it is cheap
+/// and shares the files.
+#[derive(Clone)]
+pub struct Assets {
+    files: Arc<HashMap<String, Asset>>,
+    prefix: String,
+    index_file: Option<String>,
+    spa_fallback: Option<String>,
+    cache_control: String,
+}
+
+impl Default for Assets {
+    fn default() -> Assets {
+        Assets::new()
+    }
+}
+
+impl Assets {
+    pub fn new() -> Assets {
+        Assets {
+            files: Arc::new(HashMap::new()),
+            prefix: String::new(),
+            index_file: Some("index.html".to_string()),
+            spa_fallback: None,
+            cache_control: "no-cache".to_string(),
+        }
+    }
+    /// Create a table from `(path, data)` pairs, e.g. a generated list of
+    /// `include_bytes!` calls.
+    pub fn from_files<'a>(files: impl IntoIterator<Item = (&'a str, &'static [u8])>) -> Assets {
+        files.into_iter().fold(Assets::new(), |assets, (path, data)| assets.file(path, data))
+    }
+    /// Create a table from the files of a zip archive, which are decompressed
+    /// into memory.
+    pub fn from_zip(reader: &mut ZipReader) -> io::Result<Assets> {
+        let mut assets = Assets::new();
+        let mut more = reader.move_to_first_file();
+        while more {
+            let path = reader.file_name();
+            if !path.ends_with('/') {
+                let mut data = Vec::with_capacity(reader.file_size() as usize);
+                reader.open_file(None)?.read_to_end(&mut data)?;
+                assets = assets.file(&path, data);
+            }
+            more = reader.move_to_next_file();
+        }
+        Ok(assets)
+    }
+    /// Create a table from the files of a zip archive in memory.
+    pub fn from_zip_data(data: &[u8]) -> io::Result<Assets> {
+        let mut reader = ZipReader::from_data(data)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a zip archive"))?;
+        Assets::from_zip(&mut reader)
+    }
+    /// Create a table from the files of the zip archive at `path`.
+    pub fn from_zip_file(path: &Path) -> io::Result<Assets> {
+        let mut reader = ZipReader::from_file(path).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a zip archive", path.display()))
+        })?;
+        Assets::from_zip(&mut reader)
+    }
+
+    /// Add the file at `path`, relative to the root of the scheme. Pass a
+    /// `&'static [u8]` to serve data compiled into the executable without
+    /// copying it.
+    pub fn file(mut self, path: &str, data: impl Into<Cow<'static, [u8]>>) -> Self {
+        let data = match data.into() {
+            Cow::Borrowed(data) => Data::Static(data),
+            Cow::Owned(data) => Data::Shared(data.into()),
+        };
+        let mut hasher = DefaultHasher::new();
+        hasher.write(data.as_ref());
+        let etag = format!("\"{:x}-{:x}\"", data.as_ref().len(), hasher.finish());
+        let path = path.trim_start_matches('/').to_string();
+        let asset = Asset {
+            data,
+            etag,
+            decompressed_len: Default::default(),
+        };
+        Arc::make_mut(&mut self.files).insert(path, asset);
+        self
+    }
+    /// Only serve URLs whose path starts with `prefix`, which is removed before
+    /// the path is looked up.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_end_matches('/').to_string();
+        self
+    }
+    /// Set the file served for directories, or None to answer them with `404 Not
+    /// Found`. Defaults to `index.html`.
+    pub fn index_file(mut self, index_file: Option<&str>) -> Self {
+        self.index_file = index_file.map(String::from);
+        self
+    }
+    /// Serve the file at `path` for paths that don't exist and have no file
+    /// extension, so that a single page application can route them on the client.
+    pub fn spa_fallback(mut self, path: &str) -> Self {
+        self.spa_fallback = Some(path.trim_start_matches('/').to_string());
+        self
+    }
+    /// Set the `Cache-Control` header of served files. Defaults to `no-cache`.
+    pub fn cache_control(mut self, cache_control: &str) -> Self {
+        self.cache_control = cache_control.to_string();
+        self
+    }
+
+    /// Returns the contents of the file at `path`.
+    pub fn get(&self, path: &str) -> Option<&[u8]> {
+        self.files.get(path.trim_start_matches('/')).map(|asset| asset.data.as_ref())
+    }
+    /// Returns the paths of all files.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+    pub fn resource_handler(&self) -> ResourceHandler {
+        ResourceHandler::new(AssetHandler {
+            assets: self.clone(),
+            response: None,
+        })
+    }
+
+    /// Returns true if the file at `path` or a precompressed variant exists.
+    fn exists(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+            || ENCODINGS.iter().any(|(_, suffix)| self.files.contains_key(&format!("{}{}", path, suffix)))
+    }
+
+    /// Map the path of a URL to the path of a file, or to the response if there
+    /// is none.
+    fn resolve(&self, url: &str) -> Result<String, AssetResponse> {
+        let path = match strip_path_prefix(url_path(url), &self.prefix) {
+            Some(path) => percent_decode(path),
+            None => return Err(AssetResponse::error(404)),
+        };
+        let mut key = match path_segments(&path) {
+            Some(segments) => segments.join("/"),
+            None => return Err(AssetResponse::error(404)),
+        };
+        let directory = key.is_empty() || path.ends_with('/');
+        if let Some(index_file) = &self.index_file {
+            let index = if key.is_empty() {
+                index_file.clone()
+            } else {
+                format!("{}/{}", key, index_file)
+            };
+            if directory {
+                key = index;
+            } else if !self.exists(&key) && self.exists(&index) {
+                let mut response = AssetResponse::error(301);
+                response.headers.insert("Location".to_string(), vec![directory_url(url)]);
+                return Err(response);
+            }
+        }
+        if self.exists(&key) {
+            return Ok(key);
+        }
+        let has_extension = key.rsplit('/').next().map_or(false, |name| name.contains('.'));
+        match &self.spa_fallback {
+            Some(spa_fallback) if !directory && !has_extension && self.exists(spa_fallback) => Ok(spa_fallback.clone()),
+            _ => Err(AssetResponse::error(404)),
+        }
+    }
+
+    /// Pick the variant of the file at `path` to serve and the encoding it has
+    /// to be decompressed from. The file itself is preferred, since
+    /// decompressing a variant costs time on every response.
+    fn select(&self, path: &str) -> Option<(&Asset, Option<Encoding>)> {
+        self.files.get(path).map(|asset| (asset, None)).or_else(|| {
+            ENCODINGS.iter().find_map(|&(encoding, suffix)| {
+                self.files.get(&format!("{}{}", path, suffix)).map(|asset| (asset, Some(encoding)))
+            })
+        })
+    }
+
+    fn respond(&self, request: &Request) -> AssetResponse {
+        let method = request.get_method();
+        let head = method.eq_ignore_ascii_case("HEAD");
+        if !head && !method.eq_ignore_ascii_case("GET") {
+            let mut response = AssetResponse::error(405);
+            response.headers.insert("Allow".to_string(), vec!["GET, HEAD".to_string()]);
+            return response;
+        }
+        let path = match self.resolve(&request.get_url()) {
+            Ok(path) => path,
+            Err(response) => return response,
+        };
+        let (asset, encoding) = match self.select(&path) {
+            Some(selected) => selected,
+            None => return AssetResponse::error(404),
+        };
+
+        let mut response = AssetResponse {
+            status: 200,
+            mime_type: static_files::mime_type(Path::new(&path)),
+            headers: HashMap::new(),
+            data: None,
+            start: 0,
+            offset: 0,
+            end: 0,
+        };
+        response.headers.insert("Accept-Ranges".to_string(), vec!["bytes".to_string()]);
+        response.headers.insert("Cache-Control".to_string(), vec![self.cache_control.clone()]);
+        response.headers.insert("ETag".to_string(), vec![asset.etag.clone()]);
+
+        if not_modified(request, &asset.etag, None) {
+            response.status = 304;
+            return response;
+        }
+        let length = match encoding {
+            Some(encoding) => match asset.decompressed_len(encoding) {
+                Ok(length) => length,
+                Err(err) => {
+                    log::warn!("failed to decompress {}: {}", path, err);
+                    return AssetResponse::error(500);
+                }
+            },
+            None => asset.data.as_ref().len() as u64,
+        };
+        response.end = length;
+        if let Some(range) = request.get_header_by_name("Range") {
+            match parse_range(&range, length) {
+                Some(Ok((first, last))) => {
+                    response.status = 206;
+                    response.start = first;
+                    response.offset = first;
+                    response.end = last + 1;
+                    response.headers.insert(
+                        "Content-Range".to_string(),
+                        vec![format!("bytes {}-{}/{}", first, last, length)],
+                    );
+                }
+                Some(Err(())) => {
+                    let mut response = AssetResponse::error(416);
+                    response.headers.insert("Content-Range".to_string(), vec![format!("bytes */{}", length)]);
+                    return response;
+                }
+                None => (),
+            }
+        }
+        if head {
+            response.offset = response.end;
+            return response;
+        }
+        response.data = match encoding {
+            // The bytes after the range aren't needed.
+            Some(encoding) => match encoding.decompress(asset.data.as_ref(), response.end) {
+                Ok(data) => Some(Data::Shared(data.into())),
+                Err(err) => {
+                    log::warn!("failed to decompress {}: {}", path, err);
+                    return AssetResponse::error(500);
+                }
+            },
+            None => Some(asset.data.clone()),
+        };
+        response
+    }
+}
+
+impl SchemeHandlerFactoryCallbacks for Assets {
+    fn create(
+        &self,
+        _browser: Browser,
+        _frame: Frame,
+        _scheme_name: &str,
+        _request: Request,
+    ) -> Option<ResourceHandler> {
+        Some(self.resource_handler())
+    }
+}
+
+struct AssetResponse {
+    status: i32,
+    mime_type: &'static str,
+    headers: HashMap<String, Vec<String>>,
+    data: Option<Data>,
+    /// The position of the first byte of the body.
+    start: u64,
+    /// The position of the next byte to send.
+    offset: u64,
+    /// The position after the last byte of the body.
+    end: u64,
+}
+
+impl AssetResponse {
+    fn error(status: i32) -> AssetResponse {
+        AssetResponse {
+            status,
+            mime_type: "text/plain",
+            headers: HashMap::new(),
+            data: None,
+            start: 0,
+            offset: 0,
+            end: 0,
+        }
+    }
+}
+
+struct AssetHandler {
+    assets: Assets,
+    response: Option<AssetResponse>,
+}
+
+impl ResourceHandlerCallbacks for AssetHandler {
+    fn open(&mut self, request: Request, handle_request: &mut bool, _callback: Callback) -> bool {
+        self.response = Some(self.assets.respond(&request));
+        *handle_request = true;
+        true
+    }
+    fn get_response_headers(
+        &self,
+        response: Response,
+        response_length: &mut Option<u64>,
+        _redirect_url: &mut String,
+    ) {
+        let asset_response = match &self.response {
+            Some(asset_response) => asset_response,
+            None => return,
+        };
+        response.set_header_map(&asset_response.headers);
+        response.set_status(asset_response.status);
+        response.set_status_text(reason_phrase(asset_response.status));
+        response.set_mime_type(asset_response.mime_type);
+        if static_files::is_text(asset_response.mime_type) {
+            response.set_charset("utf-8");
+        }
+        *response_length = Some(asset_response.end - asset_response.start);
+    }
+    /// See [skip_position](crate::static_files::skip_position).
+    fn skip(&mut self, bytes_to_skip: u64, bytes_skipped: &mut u64, _callback: ResourceSkipCallback) -> Result<(), ErrorCode> {
+        let asset_response = match &mut self.response {
+            Some(asset_response) => asset_response,
+            None => return Err(ErrorCode::Failed),
+        };
+        if asset_response.data.is_some() {
+            asset_response.offset = skip_position(asset_response.offset, asset_response.end, bytes_to_skip)?;
+        }
+        *bytes_skipped = bytes_to_skip;
+        Ok(())
+    }
+    fn read(&mut self, mut handler: ResourceReadHandler) -> Option<ResourceReadHandler> {
+        let asset_response = match &mut self.response {
+            Some(asset_response) => asset_response,
+            None => {
+                handler.set_error(ErrorCode::Failed);
+                return Some(handler);
+            }
+        };
+        let data = match &asset_response.data {
+            Some(data) => &data.as_ref()[asset_response.offset.min(asset_response.end) as usize..asset_response.end as usize],
+            None => &[],
+        };
+        let buffer = handler.as_buffer_ref();
+        let len = buffer.len().min(data.len());
+        buffer[..len].copy_from_slice(&data[..len]);
+        asset_response.offset += len as u64;
+        handler.set_bytes_read(len as i32);
+        Some(handler)
+    }
+    fn cancel(&mut self) {
+        self.response = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn assets() -> Assets {
+        Assets::new()
+            .file("index.html", &b"index"[..])
+            .file("docs/index.html", &b"docs"[..])
+            .file("js/app.js.gz", &b"gzip"[..])
+            .file("js/app.js.br", &b"brotli"[..])
+            .file("css/app.css", &b"css"[..])
+            .file("css/app.css.gz", &b"gzip"[..])
+    }
+
+    /// Returns the resolved path, or the status and `Location` header of the
+    /// response.
+    fn resolve(assets: &Assets, url: &str) -> Result<String, (i32, Option<String>)> {
+        assets.resolve(url).map_err(|response| {
+            let location = response.headers.get("Location").map(|location| location[0].clone());
+            (response.status, location)
+        })
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn resolving() {
+        let assets = assets();
+        assert_eq!(resolve(&assets, "app://ui/"), Ok("index.html".to_string()));
+        assert_eq!(resolve(&assets, "app://ui/css/app.css?v=2"), Ok("css/app.css".to_string()));
+        assert_eq!(resolve(&assets, "app://ui/css/app%2Ecss"), Ok("css/app.css".to_string()));
+        // Files that only exist as precompressed variants.
+        assert_eq!(resolve(&assets, "app://ui/js/app.js"), Ok("js/app.js".to_string()));
+        assert_eq!(resolve(&assets, "app://ui/docs/"), Ok("docs/index.html".to_string()));
+        assert_eq!(
+            resolve(&assets, "app://ui/docs?page=1"),
+            Err((301, Some("app://ui/docs/?page=1".to_string())))
+        );
+        assert_eq!(resolve(&assets, "app://ui/js/"), Err((404, None)));
+        assert_eq!(resolve(&assets, "app://ui/missing.js"), Err((404, None)));
+        assert_eq!(resolve(&assets, "app://ui/js/../index.html"), Err((404, None)));
+        assert_eq!(resolve(&assets, "app://ui/js/%2E%2E/index.html"), Err((404, None)));
+    }
+
+    #[test]
+    fn resolving_with_options() {
+        let assets = assets().spa_fallback("/index.html");
+        assert_eq!(resolve(&assets, "app://ui/settings/profile"), Ok("index.html".to_string()));
+        // Files and directories that don't exist aren't routes.
+        assert_eq!(resolve(&assets, "app://ui/missing.js"), Err((404, None)));
+        assert_eq!(resolve(&assets, "app://ui/settings/"), Err((404, None)));
+
+        let assets = assets.prefix("/static/").index_file(None);
+        assert_eq!(resolve(&assets, "app://ui/static/css/app.css"), Ok("css/app.css".to_string()));
+        assert_eq!(resolve(&assets, "app://ui/css/app.css"), Err((404, None)));
+        assert_eq!(resolve(&assets, "app://ui/staticcss/app.css"), Err((404, None)));
+        assert_eq!(resolve(&assets, "app://ui/static/docs/"), Err((404, None)));
+        assert_eq!(resolve(&assets, "app://ui/static/docs"), Ok("index.html".to_string()));
+    }
+
+    #[test]
+    fn selecting() {
+        let assets = assets();
+        let select = |path| assets.select(path).map(|(asset, encoding)| (asset.data.as_ref(), encoding));
+        assert_eq!(select("css/app.css"), Some((&b"css"[..], None)));
+        assert_eq!(select("js/app.js"), Some((&b"brotli"[..], Some(Encoding::Brotli))));
+        assert_eq!(select("js/app.js.gz"), Some((&b"gzip"[..], None)));
+        assert_eq!(select("missing.js"), None);
+        let assets = Assets::new().file("app.js.gz", &b"gzip"[..]);
+        assert_eq!(
+            assets.select("app.js").map(|(asset, encoding)| (asset.data.as_ref(), encoding)),
+            Some((&b"gzip"[..], Some(Encoding::Gzip)))
+        );
+    }
+
+    #[test]
+    fn decompressing() {
+        let text = b"hello, hello, hello, world".repeat(100);
+        let assets = Assets::new().file("a.txt.gz", gzip(&text));
+        let (asset, encoding) = assets.select("a.txt").unwrap();
+        let encoding = encoding.unwrap();
+        assert_eq!(asset.decompressed_len(encoding).unwrap(), text.len() as u64);
+        assert_eq!(*asset.decompressed_len.lock(), Some(text.len() as u64));
+        assert_eq!(encoding.decompress(asset.data.as_ref(), 12).unwrap(), &text[..12]);
+        assert_eq!(encoding.decompress(asset.data.as_ref(), u64::max_value()).unwrap(), text);
+
+        let corrupt = Assets::new().file("a.txt.gz", &b"not gzip"[..]);
+        let (asset, encoding) = corrupt.select("a.txt").unwrap();
+        assert!(asset.decompressed_len(encoding.unwrap()).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use cef_sys::cef_drag_operations_mask_t;
 use crate::{
     browser::{Browser, BrowserSettings, State},
+    browser_registry::BrowserKind,
     client::Client,
     devtools_message_observer::DevToolsMessageObserver,
     registration::Registration,
@@ -11,25 +12,33 @@ use crate::{
     image::Image,
     ime::CompositionUnderline,
     navigation::NavigationEntry,
+    oneshot,
     printing::PDFPrintSettings,
     refcounted::{RefCountedPtr, Wrapper},
     request_context::RequestContext,
     send_protector::SendProtectorMut,
     string::{CefString, CefStringList},
+    task::{TaskRunner, ThreadId},
     values::{DictionaryValue, Point, Range, Size, StoredValue},
     window::{RawWindow, WindowInfo},
 };
 use cef_sys::{
-    cef_browser_host_create_browser_sync, cef_browser_host_t,
+    cef_browser_host_create_browser, cef_browser_host_create_browser_sync, cef_browser_host_t,
     cef_download_image_callback_t, cef_image_t, cef_navigation_entry_t,
     cef_navigation_entry_visitor_t, cef_paint_element_type_t, cef_pdf_print_callback_t,
     cef_string_t,
 };
 use parking_lot::Mutex;
 use std::{
-    collections::HashMap,
+    cell::Cell,
+    collections::{HashMap, VecDeque},
+    future::Future,
     iter::FromIterator,
+    pin::Pin,
     ptr::{null, null_mut},
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+    time::Duration,
 };
 
 /// Paint element types.
@@ -46,6 +55,111 @@ impl PaintElementType {
     }
 }
 
+/// A pending [BrowserHost::create_browser] call.
+struct PendingCreation {
+    id: u64,
+    sender: oneshot::Sender<Browser>,
+}
+
+/// The pending [BrowserHost::create_browser] calls, keyed by the address of their
+/// client. CEF creates browsers in the order they were requested, so the queue for
+/// a client is in creation order.
+#[derive(Default)]
+struct PendingCreations(HashMap<usize, VecDeque<PendingCreation>>);
+
+impl PendingCreations {
+    fn push(&mut self, client_key: usize, creation: PendingCreation) {
+        self.0.entry(client_key).or_default().push_back(creation);
+    }
+    /// Removes the call with `id`, which failed or timed out.
+    fn remove(&mut self, client_key: usize, id: u64) -> Option<PendingCreation> {
+        let queue = self.0.get_mut(&client_key)?;
+        let creation = queue
+            .iter()
+            .position(|creation| creation.id == id)
+            .and_then(|index| queue.remove(index));
+        if queue.is_empty() {
+            self.0.remove(&client_key);
+        }
+        creation
+    }
+    /// Removes the oldest call for the client, which is the one a browser that
+    /// has just been created belongs to.
+    fn pop(&mut self, client_key: usize) -> Option<PendingCreation> {
+        let queue = self.0.get_mut(&client_key)?;
+        let creation = queue.pop_front();
+        if queue.is_empty() {
+            self.0.remove(&client_key);
+        }
+        creation
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref PENDING_CREATIONS: Mutex<PendingCreations> =
+        Mutex::new(PendingCreations::default());
+}
+static NEXT_CREATION_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static SYNC_CREATION_DEPTH: Cell<u32> = Cell::new(0);
+}
+
+/// Resolves to the browser created by [BrowserHost::create_browser], or to None if
+/// it couldn't be created.
+pub struct CreateBrowserFuture {
+    client_key: usize,
+    id: u64,
+    receiver: oneshot::Receiver<Browser>,
+}
+
+impl CreateBrowserFuture {
+    /// Resolves the future to None if the browser wasn't created within `timeout`.
+    /// CEF doesn't report every failure to create a browser, so without a timeout
+    /// the future may never resolve. The timeout should leave enough time for
+    /// creation to finish, since a browser created after it is handed to the next
+    /// pending call for the same client.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        let (client_key, id) = (self.client_key, self.id);
+        TaskRunner::post_delayed_task_on(
+            ThreadId::UI,
+            move || {
+                // Dropping the sender resolves the future to None.
+                let creation = PENDING_CREATIONS.lock().remove(client_key, id);
+                drop(creation);
+            },
+            timeout.as_millis() as i64,
+        );
+        self
+    }
+}
+
+impl Future for CreateBrowserFuture {
+    type Output = Option<Browser>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.receiver).poll(cx).map(Result::ok)
+    }
+}
+
+/// Hands `browser` to the oldest pending [BrowserHost::create_browser] call for its
+/// client. Popups and browsers created by [BrowserHost::create_browser_sync] are
+/// ignored. Called from [LifeSpanHandlerCallbacks::on_after_created].
+pub(crate) fn on_after_created(browser: &Browser) {
+    if browser.is_popup() || SYNC_CREATION_DEPTH.with(Cell::get) > 0 {
+        return;
+    }
+    let client = match browser.get_host().get_client() {
+        Some(client) => client,
+        None => return,
+    };
+    let client_key = client.as_ptr() as usize;
+    let creation = PENDING_CREATIONS.lock().pop(client_key);
+    if let Some(creation) = creation {
+        creation.sender.send(browser.clone());
+    }
+}
+
 ref_counted_ptr! {
     /// Structure used to represent the browser process aspects of a browser window.
     /// The functions of this structure can only be called in the browser process.
@@ -55,41 +169,58 @@ ref_counted_ptr! {
 }
 
 impl BrowserHost {
-    // TODO: HOW CAN WE MAKE THIS SAFE?
-    // We've run into memory issues when using this function and `BrowserHost::invalidate`. It's
-    // difficult to guarantee that everything was initialized properly before `invalidate` gets
-    // called, and while we don't know how to do that it seems safer to just disable this function.
-    // /// Create a new browser window using the window parameters specified by
-    // /// `window_info`. All values will be copied internally and the actual window will
-    // /// be created on the UI thread. If `request_context` is None the global request
-    // /// context will be used. This function can be called on any browser process
-    // /// thread and will not block. The optional `extra_info` parameter provides an
-    // /// opportunity to specify extra information specific to the created browser that
-    // /// will be passed to [RenderProcessHandlerCallbacks::on_browser_created] in the
-    // /// render process.
-    // pub fn create_browser(
-    //     window_info: &WindowInfo,
-    //     client: Client,
-    //     url: &str,
-    //     settings: &BrowserSettings,
-    //     extra_info: Option<&HashMap<String, StoredValue>>,
-    //     request_context: Option<&RequestContext>,
-    // ) -> bool {
-    //     let extra_info = extra_info.map(DictionaryValue::from);
+    /// Create a new browser window using the window parameters specified by
+    /// `window_info`. All values will be copied internally and the actual window will
+    /// be created on the UI thread. If `request_context` is None the global request
+    /// context will be used. This function can be called on any browser process
+    /// thread and will not block. The optional `extra_info` parameter provides an
+    /// opportunity to specify extra information specific to the created browser that
+    /// will be passed to [RenderProcessHandlerCallbacks::on_browser_created] in the
+    /// render process.
+    ///
+    /// The returned future resolves to the new browser once
+    /// [LifeSpanHandlerCallbacks::on_after_created] has been called for it, so the
+    /// browser and its host can't be used before creation has finished. It resolves
+    /// to None if the browser couldn't be created. CEF doesn't report all failures,
+    /// so use [CreateBrowserFuture::with_timeout] to not wait forever. The browser is
+    /// created even if the future is dropped.
+    pub fn create_browser(
+        window_info: &WindowInfo,
+        client: Client,
+        url: &str,
+        settings: &BrowserSettings,
+        extra_info: Option<&HashMap<String, StoredValue>>,
+        request_context: Option<RequestContext>,
+    ) -> CreateBrowserFuture {
+        let extra_info = extra_info.map(DictionaryValue::from);
+        let (sender, receiver) = oneshot::channel();
+        let client_key = client.as_ptr() as usize;
+        let id = NEXT_CREATION_ID.fetch_add(1, Ordering::Relaxed);
+        PENDING_CREATIONS.lock().push(client_key, PendingCreation { id, sender });
 
-    //     unsafe {
-    //         cef_browser_host_create_browser(
-    //             &window_info.into_raw(),
-    //             client.into_raw(),
-    //             CefString::new(url).as_ptr(),
-    //             &settings.into_raw(),
-    //             extra_info.map(|ei| ei.as_ptr()).unwrap_or_else(null_mut),
-    //             request_context
-    //                 .map(|rc| rc.as_ptr())
-    //                 .unwrap_or_else(null_mut),
-    //         ) != 0
-    //     }
-    // }
+        let created = unsafe {
+            cef_browser_host_create_browser(
+                &window_info.into_raw(),
+                client.into_raw(),
+                CefString::new(url).as_ptr(),
+                &settings.into_raw(),
+                extra_info.map(DictionaryValue::into_raw).unwrap_or_else(null_mut),
+                request_context
+                    .map(|rc| rc.into_raw())
+                    .unwrap_or_else(null_mut),
+            ) != 0
+        };
+        if !created {
+            // Dropping the sender resolves the future to None.
+            let creation = PENDING_CREATIONS.lock().remove(client_key, id);
+            drop(creation);
+        }
+        CreateBrowserFuture {
+            client_key,
+            id,
+            receiver,
+        }
+    }
     /// Create a new browser window using the window parameters specified by
     /// `windowInfo`. If `request_context` is None the global request context will be
     /// used. This function can only be called on the browser process UI thread. The
@@ -106,18 +237,23 @@ impl BrowserHost {
     ) -> Browser {
         let extra_info = extra_info.map(DictionaryValue::from);
 
-        unsafe {
+        // The browser is returned directly, so it must not be handed to a pending
+        // `create_browser` call for the same client.
+        SYNC_CREATION_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        let browser = unsafe {
             Browser::from_ptr_unchecked(cef_browser_host_create_browser_sync(
                 &window_info.into_raw(),
                 client.into_raw(),
                 CefString::new(url).as_ptr(),
                 &settings.into_raw(),
-                extra_info.map(|ei| ei.as_ptr()).unwrap_or_else(null_mut),
+                extra_info.map(DictionaryValue::into_raw).unwrap_or_else(null_mut),
                 request_context
                     .map(|rc| rc.into_raw())
                     .unwrap_or_else(null_mut),
             ))
-        }
+        };
+        SYNC_CREATION_DEPTH.with(|depth| depth.set(depth.get() - 1));
+        browser
     }
     /// Returns the hosted browser object.
     pub fn get_browser(&self) -> Browser {
@@ -306,6 +442,22 @@ impl BrowserHost {
             }
         }
     }
+    /// Like [download_image](BrowserHost::download_image), but returns a future
+    /// that resolves to the resulting HTTP status code and image. The status code is
+    /// 0 if the download never completed.
+    pub fn download_image_async(
+        &self,
+        image_url: &str,
+        is_favicon: bool,
+        max_image_size: u32,
+        bypass_cache: bool,
+    ) -> impl Future<Output = (u16, Option<Image>)> {
+        let (sender, receiver) = oneshot::channel();
+        self.download_image(image_url, is_favicon, max_image_size, bypass_cache, move |_, status, image| {
+            sender.send((status, image))
+        });
+        async move { receiver.await.unwrap_or((0, None)) }
+    }
     /// Print the current browser contents.
     pub fn print(&self) {
         if let Some(print) = self.0.print {
@@ -339,6 +491,13 @@ impl BrowserHost {
             }
         }
     }
+    /// Like [print_to_pdf](BrowserHost::print_to_pdf), but returns a future that
+    /// resolves to true if printing completed successfully.
+    pub fn print_to_pdf_async(&self, path: &str, settings: &PDFPrintSettings) -> impl Future<Output = bool> {
+        let (sender, receiver) = oneshot::channel();
+        self.print_to_pdf(path, settings, move |_, success| sender.send(success));
+        async move { receiver.await.unwrap_or(false) }
+    }
     /// Search for `searchText`. `identifier` must be a unique ID and these IDs
     /// must strictly increase so that newer requests always have greater IDs than
     /// older requests. If `identifier` is zero or less than the previous ID value
@@ -392,6 +551,14 @@ impl BrowserHost {
         inspect_element_at: Point,
     ) {
         if let Some(show_dev_tools) = self.0.show_dev_tools {
+            if !self.has_dev_tools() {
+                crate::browser_registry::expect_child(
+                    &self.get_browser(),
+                    BrowserKind::DevTools,
+                    client.as_ref(),
+                    "",
+                );
+            }
             let client = client
                 .map(Client::into_raw)
                 .unwrap_or_else(null_mut);
@@ -410,6 +577,10 @@ impl BrowserHost {
     /// Explicitly close the associated DevTools browser, if any.
     pub fn close_dev_tools(&self) {
         if let Some(close_dev_tools) = self.0.close_dev_tools {
+            // DevTools that are still being created won't be.
+            if !self.has_dev_tools() {
+                crate::browser_registry::cancel_child(&self.get_browser(), BrowserKind::DevTools);
+            }
             unsafe {
                 close_dev_tools(self.0.as_ptr());
             }
@@ -908,13 +1079,12 @@ impl BrowserHost {
             }
         }
     }
-    /// Returns the current visible navigation entry for this browser. This
-    /// function can only be called on the UI thread.
-    pub fn get_visible_navigation_entry(&self) -> NavigationEntry {
+    /// Returns the current visible navigation entry for this browser, or None if
+    /// nothing has been navigated to yet, as with a popup that was just created.
+    /// This function can only be called on the UI thread.
+    pub fn get_visible_navigation_entry(&self) -> Option<NavigationEntry> {
         let get_visible_navigation_entry = self.0.get_visible_navigation_entry.unwrap();
-        unsafe {
-            NavigationEntry::from_ptr_unchecked(get_visible_navigation_entry(self.0.as_ptr()))
-        }
+        unsafe { NavigationEntry::from_ptr(get_visible_navigation_entry(self.0.as_ptr())) }
     }
     /// Set accessibility state for all frames. If `accessibility_state` is [State::Default]
     /// then accessibility will be disabled by default and the state may be further
@@ -1151,3 +1321,52 @@ cef_callback_impl! {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn creation(id: u64) -> PendingCreation {
+        PendingCreation {
+            id,
+            sender: oneshot::channel().0,
+        }
+    }
+
+    fn ids(pending: &PendingCreations, client_key: usize) -> Vec<u64> {
+        pending
+            .0
+            .get(&client_key)
+            .map(|queue| queue.iter().map(|creation| creation.id).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn creations_are_handed_out_in_order() {
+        let mut pending = PendingCreations::default();
+        pending.push(1, creation(10));
+        pending.push(2, creation(20));
+        pending.push(1, creation(11));
+        assert_eq!(pending.pop(1).map(|creation| creation.id), Some(10));
+        assert_eq!(pending.pop(1).map(|creation| creation.id), Some(11));
+        assert!(pending.pop(1).is_none());
+        assert!(!pending.0.contains_key(&1));
+        assert_eq!(ids(&pending, 2), vec![20]);
+    }
+
+    #[test]
+    fn removed_creations_are_skipped() {
+        let mut pending = PendingCreations::default();
+        pending.push(1, creation(10));
+        pending.push(1, creation(11));
+        pending.push(1, creation(12));
+        // A call that timed out no longer receives the next browser.
+        assert_eq!(pending.remove(1, 10).map(|creation| creation.id), Some(10));
+        assert!(pending.remove(1, 10).is_none());
+        assert!(pending.remove(2, 11).is_none());
+        assert_eq!(ids(&pending, 1), vec![11, 12]);
+        assert_eq!(pending.pop(1).map(|creation| creation.id), Some(11));
+        assert_eq!(pending.remove(1, 12).map(|creation| creation.id), Some(12));
+        assert!(pending.0.is_empty());
+    }
+}
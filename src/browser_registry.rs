@@ -0,0 +1,295 @@
+//! A registry of all live browsers in the browser process.
+//!
+//! Browsers are registered when [LifeSpanHandlerCallbacks::on_after_created] is
+//! called and removed when [LifeSpanHandlerCallbacks::on_before_close] is called,
+//! regardless of whether the client provides its own [LifeSpanHandler]. Popups
+//! and DevTools browsers record the browser that opened them.
+//!
+//! ```ignore
+//! browser_registry::subscribe(|event| {
+//!     if let BrowserEvent::LastBrowserClosed = event {
+//!         let _ = cef::quit_message_loop();
+//!     }
+//! });
+//! ```
+//!
+//! [LifeSpanHandler]: crate::client::life_span_handler::LifeSpanHandler
+
+use crate::{browser::Browser, client::Client, window::RawWindow};
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// How a browser was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BrowserKind {
+    /// Created with [BrowserHost::create_browser] or
+    /// [BrowserHost::create_browser_sync].
+    ///
+    /// [BrowserHost::create_browser]: crate::browser_host::BrowserHost::create_browser
+    /// [BrowserHost::create_browser_sync]: crate::browser_host::BrowserHost::create_browser_sync
+    Normal,
+    /// A popup opened by the page of another browser.
+    Popup,
+    /// A DevTools browser opened with [BrowserHost::show_dev_tools].
+    ///
+    /// [BrowserHost::show_dev_tools]: crate::browser_host::BrowserHost::show_dev_tools
+    DevTools,
+}
+
+/// A browser tracked by the registry.
+#[derive(Clone)]
+pub struct BrowserInfo {
+    /// The browser itself.
+    pub browser: Browser,
+    /// The browser's identifier, as returned by [Browser::get_identifier].
+    pub id: i32,
+    /// How the browser was created.
+    pub kind: BrowserKind,
+    /// The identifier of the browser that opened this popup or DevTools browser,
+    /// if known.
+    pub opener_id: Option<i32>,
+}
+
+/// A change in the set of live browsers.
+pub enum BrowserEvent<'a> {
+    /// A browser was created. Delivered after
+    /// [LifeSpanHandlerCallbacks::on_after_created].
+    Created(&'a BrowserInfo),
+    /// A browser received a request to close. Delivered after
+    /// [LifeSpanHandlerCallbacks::do_close], which isn't called for every browser.
+    Closing(&'a BrowserInfo),
+    /// A browser is about to be destroyed and has been removed from the registry.
+    /// Delivered after [LifeSpanHandlerCallbacks::on_before_close]; only the
+    /// identifier of the browser may be used from here on.
+    Closed(&'a BrowserInfo),
+    /// The last live browser was closed. Delivered after the final
+    /// [BrowserEvent::Closed], this is the point where an application usually
+    /// quits its message loop.
+    LastBrowserClosed,
+}
+
+/// Identifies a callback registered with [subscribe].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+type Subscriber = Arc<dyn Fn(&BrowserEvent) + Send + Sync>;
+
+/// A popup or DevTools browser that was allowed but hasn't been created yet.
+struct PendingChild {
+    opener_id: i32,
+    kind: BrowserKind,
+    /// The address of the client the child is created with.
+    client_key: usize,
+    /// The URL the child is opened with, empty for DevTools browsers.
+    target_url: String,
+}
+
+impl PendingChild {
+    fn matches_url(&self, url: &str) -> bool {
+        match self.kind {
+            BrowserKind::DevTools => url.starts_with("devtools://"),
+            _ => !url.is_empty() && url == self.target_url,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    browsers: HashMap<i32, BrowserInfo>,
+    /// Popups and DevTools browsers that were allowed but haven't been created yet,
+    /// in the order they were requested.
+    pending_children: VecDeque<PendingChild>,
+    subscribers: Vec<(SubscriptionId, Subscriber)>,
+}
+
+impl Registry {
+    /// Removes the pending child that a popup with the given client and URL was
+    /// created for. CEF doesn't tell which request a popup belongs to, so the child
+    /// is picked by its client and URL, falling back to the oldest request made
+    /// with the same client and then to the oldest request overall.
+    fn take_pending_child(&mut self, client_key: usize, url: &str) -> Option<PendingChild> {
+        let children = &self.pending_children;
+        let index = children
+            .iter()
+            .position(|child| child.client_key == client_key && child.matches_url(url))
+            .or_else(|| children.iter().position(|child| child.matches_url(url)))
+            .or_else(|| children.iter().position(|child| child.client_key == client_key))
+            .or_else(|| if children.is_empty() { None } else { Some(0) })?;
+        self.pending_children.remove(index)
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: Mutex<Registry> = Mutex::new(Registry::default());
+}
+static NEXT_SUBSCRIPTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Returns the live browser with the identifier `id`.
+pub fn get(id: i32) -> Option<Browser> {
+    REGISTRY.lock().browsers.get(&id).map(|info| info.browser.clone())
+}
+
+/// Returns the registry entry of the live browser with the identifier `id`.
+pub fn get_info(id: i32) -> Option<BrowserInfo> {
+    REGISTRY.lock().browsers.get(&id).cloned()
+}
+
+/// Returns the live browser hosted in `window`. Browsers using off-screen
+/// rendering have no window and are never found.
+pub fn find_by_window_handle(window: &RawWindow) -> Option<Browser> {
+    let handle = window.to_cef_handle();
+    all().into_iter().map(|info| info.browser).find(|browser| {
+        browser
+            .get_host()
+            .get_window_handle()
+            .map_or(false, |window| window.to_cef_handle() == handle)
+    })
+}
+
+/// Returns all live browsers, ordered by identifier.
+pub fn all() -> Vec<BrowserInfo> {
+    let mut browsers: Vec<BrowserInfo> = REGISTRY.lock().browsers.values().cloned().collect();
+    browsers.sort_by_key(|info| info.id);
+    browsers
+}
+
+/// Returns the live popups and DevTools browsers opened by the browser with the
+/// identifier `opener_id`, ordered by identifier.
+pub fn children(opener_id: i32) -> Vec<BrowserInfo> {
+    let mut browsers: Vec<BrowserInfo> = all();
+    browsers.retain(|info| info.opener_id == Some(opener_id));
+    browsers
+}
+
+/// Returns the number of live browsers.
+pub fn count() -> usize {
+    REGISTRY.lock().browsers.len()
+}
+
+/// Registers a callback for [BrowserEvent]s. Events are delivered on the browser
+/// process UI thread, without the registry being locked.
+pub fn subscribe(callback: impl Fn(&BrowserEvent) + Send + Sync + 'static) -> SubscriptionId {
+    let id = SubscriptionId(NEXT_SUBSCRIPTION_ID.fetch_add(1, Ordering::Relaxed));
+    REGISTRY.lock().subscribers.push((id, Arc::new(callback)));
+    id
+}
+
+/// Removes a callback registered with [subscribe]. Returns false if no callback is
+/// registered under `id`.
+pub fn unsubscribe(id: SubscriptionId) -> bool {
+    let mut registry = REGISTRY.lock();
+    let len = registry.subscribers.len();
+    registry.subscribers.retain(|(subscription_id, _)| *subscription_id != id);
+    registry.subscribers.len() != len
+}
+
+fn notify(event: BrowserEvent) {
+    let subscribers: Vec<Subscriber> = REGISTRY
+        .lock()
+        .subscribers
+        .iter()
+        .map(|(_, subscriber)| subscriber.clone())
+        .collect();
+    for subscriber in subscribers {
+        subscriber(&event);
+    }
+}
+
+/// Records that `opener` is about to open a browser of `kind`. Called when a popup
+/// is allowed in [LifeSpanHandlerCallbacks::on_before_popup] and when DevTools are
+/// opened.
+pub(crate) fn expect_child(opener: &Browser, kind: BrowserKind, client: Option<&Client>, target_url: &str) {
+    let client_key = match client {
+        Some(client) => client.as_ptr() as usize,
+        None => opener
+            .get_host()
+            .get_client()
+            .map_or(0, |client| client.as_ptr() as usize),
+    };
+    REGISTRY.lock().pending_children.push_back(PendingChild {
+        opener_id: opener.get_identifier(),
+        kind,
+        client_key,
+        target_url: target_url.to_owned(),
+    });
+}
+
+/// Forgets the latest child of `kind` recorded with [expect_child] for `opener`,
+/// as its creation was cancelled.
+pub(crate) fn cancel_child(opener: &Browser, kind: BrowserKind) {
+    let opener_id = opener.get_identifier();
+    let mut registry = REGISTRY.lock();
+    let index = registry
+        .pending_children
+        .iter()
+        .rposition(|child| child.opener_id == opener_id && child.kind == kind);
+    if let Some(index) = index {
+        registry.pending_children.remove(index);
+    }
+}
+
+pub(crate) fn on_after_created(browser: &Browser) {
+    let id = browser.get_identifier();
+    // Query CEF before taking the lock so that no CEF call runs while it is held.
+    let popup = if browser.is_popup() {
+        let host = browser.get_host();
+        let client_key = host.get_client().map_or(0, |client| client.as_ptr() as usize);
+        let url = host
+            .get_visible_navigation_entry()
+            .map(|entry| entry.get_url())
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| browser.get_main_frame().get_url());
+        Some((client_key, url))
+    } else {
+        None
+    };
+    let info = {
+        let mut registry = REGISTRY.lock();
+        let (kind, opener_id) = if let Some((client_key, url)) = popup {
+            match registry.take_pending_child(client_key, &url) {
+                Some(child) => (child.kind, Some(child.opener_id)),
+                None => (BrowserKind::Popup, None),
+            }
+        } else {
+            (BrowserKind::Normal, None)
+        };
+        let info = BrowserInfo {
+            browser: browser.clone(),
+            id,
+            kind,
+            opener_id,
+        };
+        registry.browsers.insert(id, info.clone());
+        info
+    };
+    notify(BrowserEvent::Created(&info));
+}
+
+pub(crate) fn on_do_close(browser: &Browser) {
+    if let Some(info) = get_info(browser.get_identifier()) {
+        notify(BrowserEvent::Closing(&info));
+    }
+}
+
+pub(crate) fn on_before_close(browser: &Browser) {
+    let id = browser.get_identifier();
+    let (info, last) = {
+        let mut registry = REGISTRY.lock();
+        // Popups that haven't been created yet are cancelled with their opener.
+        registry.pending_children.retain(|child| child.opener_id != id);
+        let info = registry.browsers.remove(&id);
+        (info, registry.browsers.is_empty())
+    };
+    if let Some(info) = info {
+        notify(BrowserEvent::Closed(&info));
+        if last {
+            notify(BrowserEvent::LastBrowserClosed);
+        }
+    }
+}
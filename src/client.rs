@@ -39,7 +39,7 @@ use self::{
     focus_handler::FocusHandler,
     js_dialog_handler::JsDialogHandler,
     keyboard_handler::KeyboardHandler,
-    life_span_handler::LifeSpanHandler,
+    life_span_handler::{DefaultLifeSpanHandler, LifeSpanHandler},
     render_handler::RenderHandler,
     request_handler::RequestHandler,
 };
@@ -186,7 +186,10 @@ cef_callback_impl! {
             self.0.get_keyboard_handler().map(|cef| cef.into_raw()).unwrap_or(null_mut())
         }
         fn get_life_span_handler(&self) -> *mut cef_life_span_handler_t {
-            self.0.get_life_span_handler().map(|cef| cef.into_raw()).unwrap_or(null_mut())
+            self.0
+                .get_life_span_handler()
+                .unwrap_or_else(|| LifeSpanHandler::new(DefaultLifeSpanHandler))
+                .into_raw()
         }
         fn get_load_handler(&self) -> *mut cef_load_handler_t {
             self.0.get_load_handler().map(|cef| cef.into_raw()).unwrap_or(null_mut())
@@ -205,6 +208,9 @@ cef_callback_impl! {
             message       : ProcessMessage: *mut cef_process_message_t
         ) -> std::os::raw::c_int {
             assert_eq!(ProcessId::Renderer, source_process);
+            if crate::eval::on_browser_message_received(&message) {
+                return 1;
+            }
             self.0.on_process_message_received(browser, frame, message) as std::os::raw::c_int
         }
     }
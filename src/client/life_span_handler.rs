@@ -1,5 +1,6 @@
 use crate::{
     browser::{Browser, BrowserSettings},
+    browser_registry::BrowserKind,
     client::{
         Client,
         request_handler::WindowOpenDisposition,
@@ -70,7 +71,6 @@ pub trait LifeSpanHandlerCallbacks: 'static + Send + Sync {
         extra_info: &mut DictionaryValue, // *mut *mut _cef_dictionary_value_t,
         no_javascript_access: &mut bool // *mut c_int
     ) -> bool {
-        println!("popup window");
         false
     }
     /// Called after a new browser is created. This callback will be the first
@@ -220,6 +220,12 @@ impl PopupFeatures {
     }
 }
 
+/// Used when a client doesn't provide a life span handler, so that the crate can
+/// still track browser creation.
+pub(crate) struct DefaultLifeSpanHandler;
+
+impl LifeSpanHandlerCallbacks for DefaultLifeSpanHandler {}
+
 struct LifeSpanHandlerWrapper(Box<dyn LifeSpanHandlerCallbacks>);
 
 impl Wrapper for LifeSpanHandlerWrapper {
@@ -261,7 +267,7 @@ cef_callback_impl!{
             let mut settings_rust = unsafe{ BrowserSettings::from_raw(&*settings) };
             let mut no_javascript_access_rust = *no_javascript_access != 0;
             let ret = self.0.on_before_popup(
-                browser,
+                browser.clone(),
                 frame,
                 target_url
                     .map(String::from)
@@ -283,16 +289,31 @@ cef_callback_impl!{
             *window_info_ref = window_info_rust.into_raw();
             *settings_ref = settings_rust.into_raw();
             *no_javascript_access = no_javascript_access_rust as _;
+            if ret == 0 {
+                crate::browser_registry::expect_child(
+                    &browser,
+                    BrowserKind::Popup,
+                    Some(&*client),
+                    &target_url.map(String::from).unwrap_or_default(),
+                );
+            }
             ret
         }
         fn on_after_created(&self, browser: Browser: *mut cef_browser_t) {
-            self.0.on_after_created(browser);
+            self.0.on_after_created(browser.clone());
+            crate::browser_registry::on_after_created(&browser);
+            crate::browser_host::on_after_created(&browser);
         }
         fn do_close(&self, browser: Browser: *mut cef_browser_t) -> std::os::raw::c_int {
-            self.0.do_close(browser) as _
+            let ret = self.0.do_close(browser.clone()) as _;
+            crate::browser_registry::on_do_close(&browser);
+            ret
         }
         fn on_before_close(&self, browser: Browser: *mut cef_browser_t) {
             self.0.on_before_close(browser.clone());
+            crate::rpc::cancel_for_browser(browser.get_identifier());
+            crate::eval::cancel_for_browser(browser.get_identifier(), "browser was closed");
+            crate::browser_registry::on_before_close(&browser);
             unsafe{ browser.poison(); }
         }
     }
@@ -425,6 +425,158 @@ pub trait RenderHandlerCallbacks: 'static + Send + Sync {
     }
 }
 
+/// Observes the paints of a browser without taking over its render handler. Wrap
+/// the render handler callbacks and the observer in an [ObservedRenderHandler] to
+/// install it.
+pub trait PaintObserver: 'static + Send + Sync {
+    /// Called after [RenderHandlerCallbacks::on_paint] with the same arguments.
+    fn on_paint(
+        &self,
+        browser: &Browser,
+        type_: PaintElementType,
+        dirty_rects: &[Rect],
+        buffer: &[u8],
+        width: i32,
+        height: i32,
+    );
+    /// Called after [RenderHandlerCallbacks::on_popup_show].
+    fn on_popup_show(&self, _browser: &Browser, _show: bool) {}
+    /// Called after [RenderHandlerCallbacks::on_popup_size].
+    fn on_popup_size(&self, _browser: &Browser, _rect: Rect) {}
+}
+
+impl<A: PaintObserver, B: PaintObserver> PaintObserver for (A, B) {
+    fn on_paint(
+        &self,
+        browser: &Browser,
+        type_: PaintElementType,
+        dirty_rects: &[Rect],
+        buffer: &[u8],
+        width: i32,
+        height: i32,
+    ) {
+        self.0.on_paint(browser, type_, dirty_rects, buffer, width, height);
+        self.1.on_paint(browser, type_, dirty_rects, buffer, width, height);
+    }
+    fn on_popup_show(&self, browser: &Browser, show: bool) {
+        self.0.on_popup_show(browser, show);
+        self.1.on_popup_show(browser, show);
+    }
+    fn on_popup_size(&self, browser: &Browser, rect: Rect) {
+        self.0.on_popup_size(browser, rect);
+        self.1.on_popup_size(browser, rect);
+    }
+}
+
+/// Render handler callbacks that forward everything to `C` and additionally
+/// report paints to the observer `O`.
+pub struct ObservedRenderHandler<C: RenderHandlerCallbacks, O: PaintObserver> {
+    callbacks: C,
+    observer: O,
+}
+
+impl<C: RenderHandlerCallbacks, O: PaintObserver> ObservedRenderHandler<C, O> {
+    pub fn new(callbacks: C, observer: O) -> Self {
+        ObservedRenderHandler { callbacks, observer }
+    }
+    pub fn callbacks(&self) -> &C {
+        &self.callbacks
+    }
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+}
+
+impl<C: RenderHandlerCallbacks, O: PaintObserver> RenderHandlerCallbacks for ObservedRenderHandler<C, O> {
+    fn get_accessibility_handler(&self) -> Option<AccessibilityHandler> {
+        self.callbacks.get_accessibility_handler()
+    }
+    fn get_root_screen_rect(&self, browser: Browser) -> Option<Rect> {
+        self.callbacks.get_root_screen_rect(browser)
+    }
+    fn get_view_rect(&self, browser: Browser) -> Rect {
+        self.callbacks.get_view_rect(browser)
+    }
+    fn get_screen_point(&self, browser: Browser, view_point: Point) -> Option<Point> {
+        self.callbacks.get_screen_point(browser, view_point)
+    }
+    fn get_screen_info(&self, browser: Browser) -> Option<ScreenInfo> {
+        self.callbacks.get_screen_info(browser)
+    }
+    fn on_popup_show(&self, browser: Browser, show: bool) {
+        self.callbacks.on_popup_show(browser.clone(), show);
+        self.observer.on_popup_show(&browser, show);
+    }
+    fn on_popup_size(&self, browser: Browser, rect: Rect) {
+        self.callbacks.on_popup_size(browser.clone(), rect);
+        self.observer.on_popup_size(&browser, rect);
+    }
+    fn on_paint(
+        &self,
+        browser: Browser,
+        type_: PaintElementType,
+        dirty_rects: &[Rect],
+        buffer: &[u8],
+        width: i32,
+        height: i32,
+    ) {
+        self.callbacks.on_paint(browser.clone(), type_, dirty_rects, buffer, width, height);
+        self.observer.on_paint(&browser, type_, dirty_rects, buffer, width, height);
+    }
+    fn on_accelerated_paint(
+        &self,
+        browser: Browser,
+        type_: PaintElementType,
+        dirty_rects: &[Rect],
+        shared_handle: *mut c_void,
+    ) {
+        self.callbacks.on_accelerated_paint(browser, type_, dirty_rects, shared_handle);
+    }
+    fn on_cursor_change(&self, browser: Browser, cursor: CursorHandle, type_: CursorType<'_>) {
+        self.callbacks.on_cursor_change(browser, cursor, type_);
+    }
+    fn start_dragging(
+        &self,
+        browser: Browser,
+        drag_data: DragData,
+        allowed_ops: DragOperation,
+        drag_start: Point,
+    ) -> bool {
+        self.callbacks.start_dragging(browser, drag_data, allowed_ops, drag_start)
+    }
+    fn update_drag_cursor(&self, browser: Browser, operation: DragOperation) {
+        self.callbacks.update_drag_cursor(browser, operation);
+    }
+    fn on_scroll_offset_changed(&self, browser: Browser, x: f64, y: f64) {
+        self.callbacks.on_scroll_offset_changed(browser, x, y);
+    }
+    fn on_ime_composition_range_changed(
+        &self,
+        browser: Browser,
+        selected_range: Range,
+        character_bounds_count: usize,
+        character_bounds: Rect,
+    ) {
+        self.callbacks.on_ime_composition_range_changed(
+            browser,
+            selected_range,
+            character_bounds_count,
+            character_bounds,
+        );
+    }
+    fn on_text_selection_changed(
+        &self,
+        browser: Browser,
+        selected_text: Option<&str>,
+        selected_range: Range,
+    ) {
+        self.callbacks.on_text_selection_changed(browser, selected_text, selected_range);
+    }
+    fn on_virtual_keyboard_requested(&self, browser: Browser, input_mode: TextInputMode) {
+        self.callbacks.on_virtual_keyboard_requested(browser, input_mode);
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct PopupFeatures {
     pub x: Option<i32>,
@@ -393,7 +393,10 @@ cef_callback_impl!{
             browser: Browser: *mut cef_browser_t,
             status: TerminationStatus: cef_termination_status_t::Type
         ) {
+            let browser_id = browser.get_identifier();
             self.0.on_render_process_terminated(browser, status);
+            crate::rpc::cancel_for_browser(browser_id);
+            crate::eval::cancel_for_browser(browser_id, "render process terminated");
         }
         fn on_document_available_in_main_frame(
             &self,
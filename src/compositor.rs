@@ -0,0 +1,442 @@
+//! Merging off-screen paints into a single backing store.
+//!
+//! With windowless rendering, [RenderHandlerCallbacks::on_paint] delivers the view
+//! and the popup widget (e.g. an open `<select>`) as separate BGRA buffers, and
+//! only the `dirty_rects` of each buffer are guaranteed to have changed. A
+//! [Compositor] keeps the backing store of the view, overlays the popup at the
+//! rectangle given by [RenderHandlerCallbacks::on_popup_size], and collects the
+//! regions that changed since they were last read, so that only those have to be
+//! uploaded to a texture or framebuffer:
+//!
+//! ```ignore
+//! impl RenderHandlerCallbacks for MyView {
+//!     fn on_paint(&self, _browser: Browser, type_: PaintElementType, dirty_rects: &[Rect], buffer: &[u8], width: i32, height: i32) {
+//!         let mut compositor = self.compositor.lock();
+//!         compositor.on_paint(type_, dirty_rects, buffer, width, height);
+//!         for rect in compositor.take_damage() {
+//!             self.texture.upload(rect, compositor.buffer(), compositor.width());
+//!         }
+//!     }
+//!     fn on_popup_show(&self, _browser: Browser, show: bool) {
+//!         self.compositor.lock().on_popup_show(show);
+//!     }
+//!     fn on_popup_size(&self, _browser: Browser, rect: Rect) {
+//!         self.compositor.lock().on_popup_size(rect);
+//!     }
+//!     // ...
+//! }
+//! ```
+//!
+//! [RenderHandlerCallbacks::on_paint]: crate::client::render_handler::RenderHandlerCallbacks::on_paint
+//! [RenderHandlerCallbacks::on_popup_size]: crate::client::render_handler::RenderHandlerCallbacks::on_popup_size
+
+use crate::{browser_host::PaintElementType, values::Rect};
+
+/// The number of separate damage rectangles kept before they are merged into their
+/// bounding box.
+pub const MAX_DAMAGE_RECTS: usize = 16;
+
+#[derive(Default)]
+struct Popup {
+    visible: bool,
+    /// The popup rectangle in view coordinates, as given by CEF.
+    rect: Option<Rect>,
+    pixels: Vec<u8>,
+    width: i32,
+    height: i32,
+}
+
+/// The backing store of an off-screen view and its popup widget.
+///
+/// All pixels are BGRA with premultiplied alpha, as delivered to
+/// [RenderHandlerCallbacks::on_paint], and coordinates are in physical pixels
+/// unless stated otherwise.
+///
+/// [RenderHandlerCallbacks::on_paint]: crate::client::render_handler::RenderHandlerCallbacks::on_paint
+#[derive(Default)]
+pub struct Compositor {
+    width: i32,
+    height: i32,
+    device_scale_factor: f32,
+    /// The view without the popup.
+    view: Vec<u8>,
+    /// The view with the popup on top.
+    output: Vec<u8>,
+    popup: Popup,
+    damage: Vec<Rect>,
+}
+
+impl Compositor {
+    pub fn new() -> Compositor {
+        Compositor {
+            device_scale_factor: 1.0,
+            ..Compositor::default()
+        }
+    }
+    /// Set the ratio between physical and logical pixels, which is used to place
+    /// the popup. It should match [ScreenInfo::device_scale_factor].
+    ///
+    /// [ScreenInfo::device_scale_factor]: crate::client::render_handler::ScreenInfo::device_scale_factor
+    pub fn set_device_scale_factor(&mut self, device_scale_factor: f32) {
+        if self.device_scale_factor != device_scale_factor {
+            let old = self.popup_rect();
+            self.device_scale_factor = device_scale_factor;
+            self.popup_moved(old);
+        }
+    }
+
+    /// Returns the width of the backing store.
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+    /// Returns the height of the backing store.
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+    /// Returns the number of bytes per row of [buffer](Compositor::buffer).
+    pub fn stride(&self) -> usize {
+        self.width as usize * 4
+    }
+    /// Returns the composited pixels.
+    pub fn buffer(&self) -> &[u8] {
+        &self.output
+    }
+    /// Returns the pixels of the view without the popup.
+    pub fn view_buffer(&self) -> &[u8] {
+        &self.view
+    }
+    /// Returns the rectangle covered by the popup, or None if no popup is shown.
+    pub fn popup_rect(&self) -> Option<Rect> {
+        if !self.popup.visible {
+            return None;
+        }
+        let rect = self.popup.rect?;
+        let scale = self.device_scale_factor;
+        let mut rect = Rect {
+            x: (rect.x as f32 * scale).round() as i32,
+            y: (rect.y as f32 * scale).round() as i32,
+            width: (rect.width as f32 * scale).round() as i32,
+            height: (rect.height as f32 * scale).round() as i32,
+        };
+        // Keep the popup inside the view, like upstream cefclient does.
+        if rect.x + rect.width > self.width {
+            rect.x = self.width - rect.width;
+        }
+        if rect.y + rect.height > self.height {
+            rect.y = self.height - rect.height;
+        }
+        rect.x = rect.x.max(0);
+        rect.y = rect.y.max(0);
+        Some(rect)
+    }
+
+    /// Returns the regions that changed since [take_damage](Compositor::take_damage)
+    /// was last called.
+    pub fn damage(&self) -> &[Rect] {
+        &self.damage
+    }
+    /// Returns the bounding box of [damage](Compositor::damage), or None if nothing
+    /// changed.
+    pub fn damage_bounds(&self) -> Option<Rect> {
+        let bounds = self
+            .damage
+            .iter()
+            .fold(Rect::new(0, 0, 0, 0), |bounds, rect| bounds.union(rect));
+        if bounds.is_empty() {
+            None
+        } else {
+            Some(bounds)
+        }
+    }
+    /// Returns the regions that changed since the last call and resets them.
+    pub fn take_damage(&mut self) -> Vec<Rect> {
+        std::mem::take(&mut self.damage)
+    }
+    /// Mark the whole backing store as changed, e.g. after the texture it is
+    /// uploaded to was lost.
+    pub fn invalidate(&mut self) {
+        self.damage.clear();
+        let full = Rect::new(0, 0, self.width, self.height);
+        self.add_damage(full);
+    }
+
+    /// Apply a paint. Forward the arguments of
+    /// [RenderHandlerCallbacks::on_paint] to this.
+    ///
+    /// [RenderHandlerCallbacks::on_paint]: crate::client::render_handler::RenderHandlerCallbacks::on_paint
+    pub fn on_paint(
+        &mut self,
+        type_: PaintElementType,
+        dirty_rects: &[Rect],
+        buffer: &[u8],
+        width: i32,
+        height: i32,
+    ) {
+        if width <= 0 || height <= 0 || buffer.len() < width as usize * height as usize * 4 {
+            return;
+        }
+        match type_ {
+            PaintElementType::View => self.paint_view(dirty_rects, buffer, width, height),
+            PaintElementType::Popup => self.paint_popup(dirty_rects, buffer, width, height),
+        }
+    }
+    /// Show or hide the popup. Forward the argument of
+    /// [RenderHandlerCallbacks::on_popup_show] to this.
+    ///
+    /// [RenderHandlerCallbacks::on_popup_show]: crate::client::render_handler::RenderHandlerCallbacks::on_popup_show
+    pub fn on_popup_show(&mut self, show: bool) {
+        if show {
+            // Nothing is drawn until the popup is painted.
+            self.popup.visible = true;
+            return;
+        }
+        let old = self.popup_rect();
+        self.popup = Popup::default();
+        if let Some(old) = old {
+            self.compose(old);
+        }
+    }
+    /// Move or resize the popup. Forward the argument of
+    /// [RenderHandlerCallbacks::on_popup_size], which is in logical view
+    /// coordinates, to this.
+    ///
+    /// [RenderHandlerCallbacks::on_popup_size]: crate::client::render_handler::RenderHandlerCallbacks::on_popup_size
+    pub fn on_popup_size(&mut self, rect: Rect) {
+        let old = self.popup_rect();
+        self.popup.rect = Some(rect);
+        self.popup_moved(old);
+    }
+
+    fn paint_view(&mut self, dirty_rects: &[Rect], buffer: &[u8], width: i32, height: i32) {
+        let full = Rect::new(0, 0, width, height);
+        if width != self.width || height != self.height {
+            self.width = width;
+            self.height = height;
+            let len = width as usize * height as usize * 4;
+            self.view = buffer[..len].to_vec();
+            self.output = vec![0; len];
+            self.damage.clear();
+            self.compose(full);
+            return;
+        }
+        for rect in dirty_rects {
+            let rect = rect.intersect(&full);
+            if rect.is_empty() {
+                continue;
+            }
+            copy_rect(buffer, width, &mut self.view, width, rect, rect.x, rect.y);
+            self.compose(rect);
+        }
+    }
+
+    fn paint_popup(&mut self, dirty_rects: &[Rect], buffer: &[u8], width: i32, height: i32) {
+        let full = Rect::new(0, 0, width, height);
+        let resized = width != self.popup.width || height != self.popup.height;
+        if resized {
+            self.popup.width = width;
+            self.popup.height = height;
+            self.popup.pixels = buffer[..width as usize * height as usize * 4].to_vec();
+        } else {
+            for rect in dirty_rects {
+                let rect = rect.intersect(&full);
+                if !rect.is_empty() {
+                    copy_rect(buffer, width, &mut self.popup.pixels, width, rect, rect.x, rect.y);
+                }
+            }
+        }
+        if let Some(popup_rect) = self.popup_rect() {
+            if resized {
+                self.compose(popup_rect);
+            } else {
+                for rect in dirty_rects {
+                    self.compose(rect.intersect(&full).offset(popup_rect.x, popup_rect.y));
+                }
+            }
+        }
+    }
+
+    /// Recomposes the old and the new popup rectangle after the popup changed its
+    /// place.
+    fn popup_moved(&mut self, old: Option<Rect>) {
+        let new = self.popup_rect();
+        if old == new {
+            return;
+        }
+        if let Some(old) = old {
+            self.compose(old);
+        }
+        if let Some(new) = new {
+            self.compose(new);
+        }
+    }
+
+    /// Rebuilds `rect` of the output from the view and the popup, and records it as
+    /// damaged.
+    fn compose(&mut self, rect: Rect) {
+        let rect = rect.intersect(&Rect::new(0, 0, self.width, self.height));
+        if rect.is_empty() {
+            return;
+        }
+        copy_rect(&self.view, self.width, &mut self.output, self.width, rect, rect.x, rect.y);
+        if let Some(popup_rect) = self.popup_rect() {
+            let painted = Rect::new(popup_rect.x, popup_rect.y, self.popup.width, self.popup.height);
+            let overlap = rect.intersect(&popup_rect).intersect(&painted);
+            if !overlap.is_empty() {
+                copy_rect(
+                    &self.popup.pixels,
+                    self.popup.width,
+                    &mut self.output,
+                    self.width,
+                    overlap.offset(-popup_rect.x, -popup_rect.y),
+                    overlap.x,
+                    overlap.y,
+                );
+            }
+        }
+        self.add_damage(rect);
+    }
+
+    fn add_damage(&mut self, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+        // Merge overlapping rectangles, so that no pixel is uploaded twice.
+        let mut rect = rect;
+        while let Some(index) = self
+            .damage
+            .iter()
+            .position(|damaged| !damaged.intersect(&rect).is_empty())
+        {
+            rect = rect.union(&self.damage.swap_remove(index));
+        }
+        self.damage.push(rect);
+        if self.damage.len() > MAX_DAMAGE_RECTS {
+            let bounds = self.damage_bounds().unwrap();
+            self.damage.clear();
+            self.damage.push(bounds);
+        }
+    }
+}
+
+/// Copies `rect` of `src` to (`x`, `y`) of `dst`. Both buffers hold 4 bytes per
+/// pixel and are the given number of pixels wide.
+fn copy_rect(src: &[u8], src_width: i32, dst: &mut [u8], dst_width: i32, rect: Rect, x: i32, y: i32) {
+    let len = rect.width as usize * 4;
+    for row in 0..rect.height as usize {
+        let src_start = ((rect.y as usize + row) * src_width as usize + rect.x as usize) * 4;
+        let dst_start = ((y as usize + row) * dst_width as usize + x as usize) * 4;
+        dst[dst_start..dst_start + len].copy_from_slice(&src[src_start..src_start + len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `width`×`height` BGRA buffer whose pixels all have the value `value`.
+    fn solid(width: i32, height: i32, value: u8) -> Vec<u8> {
+        vec![value; width as usize * height as usize * 4]
+    }
+
+    fn pixel(compositor: &Compositor, x: i32, y: i32) -> u8 {
+        compositor.buffer()[(y * compositor.width() + x) as usize * 4]
+    }
+
+    #[test]
+    fn rect_intersect() {
+        let a = Rect::new(0, 0, 10, 10);
+        assert_eq!(a.intersect(&Rect::new(5, 5, 10, 10)), Rect::new(5, 5, 5, 5));
+        assert_eq!(a.intersect(&Rect::new(2, 3, 4, 5)), Rect::new(2, 3, 4, 5));
+        assert!(a.intersect(&Rect::new(10, 0, 5, 5)).is_empty());
+        assert!(a.intersect(&Rect::new(20, 20, 5, 5)).is_empty());
+    }
+
+    #[test]
+    fn rect_union() {
+        let a = Rect::new(0, 0, 10, 10);
+        assert_eq!(a.union(&Rect::new(20, 5, 5, 10)), Rect::new(0, 0, 25, 15));
+        assert_eq!(a.union(&Rect::new(50, 50, 0, 0)), a);
+        assert_eq!(Rect::new(50, 50, 0, 3).union(&a), a);
+        assert_eq!(a.offset(3, -2), Rect::new(3, -2, 10, 10));
+    }
+
+    #[test]
+    fn resize_damages_everything() {
+        let mut compositor = Compositor::new();
+        compositor.on_paint(PaintElementType::View, &[], &solid(8, 4, 1), 8, 4);
+        assert_eq!(compositor.take_damage(), vec![Rect::new(0, 0, 8, 4)]);
+        assert!(compositor.damage_bounds().is_none());
+    }
+
+    #[test]
+    fn dirty_rects_are_copied_and_merged() {
+        let mut compositor = Compositor::new();
+        compositor.on_paint(PaintElementType::View, &[], &solid(8, 8, 1), 8, 8);
+        compositor.take_damage();
+
+        let dirty = [Rect::new(0, 0, 2, 2), Rect::new(1, 1, 2, 2), Rect::new(6, 6, 4, 4)];
+        compositor.on_paint(PaintElementType::View, &dirty, &solid(8, 8, 2), 8, 8);
+        let mut damage = compositor.take_damage();
+        damage.sort_by_key(|rect| rect.x);
+        // Overlapping rectangles are merged and rectangles are clipped to the view.
+        assert_eq!(damage, vec![Rect::new(0, 0, 3, 3), Rect::new(6, 6, 2, 2)]);
+        assert_eq!(pixel(&compositor, 2, 2), 2);
+        assert_eq!(pixel(&compositor, 3, 3), 1);
+        assert_eq!(pixel(&compositor, 7, 7), 2);
+    }
+
+    #[test]
+    fn damage_collapses_to_bounds() {
+        let mut compositor = Compositor::new();
+        compositor.on_paint(PaintElementType::View, &[], &solid(64, 64, 0), 64, 64);
+        compositor.take_damage();
+
+        let dirty: Vec<Rect> = (0..MAX_DAMAGE_RECTS as i32 + 1)
+            .map(|i| Rect::new(i * 3, i * 3, 1, 1))
+            .collect();
+        compositor.on_paint(PaintElementType::View, &dirty, &solid(64, 64, 1), 64, 64);
+        let last = MAX_DAMAGE_RECTS as i32 * 3;
+        assert_eq!(compositor.take_damage(), vec![Rect::new(0, 0, last + 1, last + 1)]);
+    }
+
+    #[test]
+    fn popup_is_scaled_clamped_and_removed() {
+        let mut compositor = Compositor::new();
+        compositor.set_device_scale_factor(2.0);
+        compositor.on_paint(PaintElementType::View, &[], &solid(20, 20, 1), 20, 20);
+        compositor.on_popup_show(true);
+        // 8×8 physical pixels at (14, 14) would end at 22, so the popup is moved
+        // back into the view.
+        compositor.on_popup_size(Rect::new(7, 7, 4, 4));
+        assert_eq!(compositor.popup_rect(), Some(Rect::new(12, 12, 8, 8)));
+        compositor.take_damage();
+
+        compositor.on_paint(PaintElementType::Popup, &[], &solid(8, 8, 9), 8, 8);
+        assert_eq!(compositor.take_damage(), vec![Rect::new(12, 12, 8, 8)]);
+        assert_eq!(pixel(&compositor, 12, 12), 9);
+        assert_eq!(pixel(&compositor, 11, 11), 1);
+        assert_eq!(compositor.view_buffer()[(12 * 20 + 12) * 4], 1);
+
+        compositor.on_popup_show(false);
+        assert_eq!(compositor.popup_rect(), None);
+        assert_eq!(compositor.take_damage(), vec![Rect::new(12, 12, 8, 8)]);
+        assert_eq!(pixel(&compositor, 12, 12), 1);
+    }
+
+    #[test]
+    fn moving_the_popup_damages_both_places() {
+        let mut compositor = Compositor::new();
+        compositor.on_paint(PaintElementType::View, &[], &solid(20, 20, 1), 20, 20);
+        compositor.on_popup_show(true);
+        compositor.on_popup_size(Rect::new(0, 0, 4, 4));
+        compositor.on_paint(PaintElementType::Popup, &[], &solid(4, 4, 9), 4, 4);
+        compositor.take_damage();
+
+        compositor.on_popup_size(Rect::new(10, 10, 4, 4));
+        let mut damage = compositor.take_damage();
+        damage.sort_by_key(|rect| rect.x);
+        assert_eq!(damage, vec![Rect::new(0, 0, 4, 4), Rect::new(10, 10, 4, 4)]);
+        assert_eq!(pixel(&compositor, 0, 0), 1);
+        assert_eq!(pixel(&compositor, 10, 10), 9);
+    }
+}
@@ -11,8 +11,10 @@ use chrono::{DateTime, Utc};
 
 use crate::{
     callback::CompletionCallback,
+    oneshot,
     string::CefString,
 };
+use std::future::Future;
 
 /// Cookie priority values.
 #[repr(C)]
@@ -280,6 +282,104 @@ impl CookieManager {
             ) != 0
         }
     }
+    /// Collect all cookies, ordered by longest path, then by earliest creation date.
+    /// The future resolves to None if cookies cannot be accessed.
+    pub fn visit_all_cookies_async(&self) -> impl Future<Output = Option<Vec<Cookie>>> {
+        let (visitor, receiver) = collect_cookies();
+        let accessible = self.visit_all_cookies(visitor);
+        async move {
+            match accessible {
+                true => receiver.await.ok(),
+                false => None,
+            }
+        }
+    }
+    /// Collect the cookies that would be sent to `url`, ordered by longest path,
+    /// then by earliest creation date. If `include_http_only` is `true` HTTP-only
+    /// cookies will also be included. The future resolves to None if cookies cannot
+    /// be accessed.
+    pub fn visit_url_cookies_async(
+        &self,
+        url: &str,
+        include_http_only: bool,
+    ) -> impl Future<Output = Option<Vec<Cookie>>> {
+        let (visitor, receiver) = collect_cookies();
+        let accessible = self.visit_url_cookies(url, include_http_only, visitor);
+        async move {
+            match accessible {
+                true => receiver.await.ok(),
+                false => None,
+            }
+        }
+    }
+    /// Like [set_cookie](CookieManager::set_cookie), but returns a future that
+    /// resolves to true once the cookie has been set, or to false if it couldn't be.
+    pub fn set_cookie_async(&self, url: &str, cookie: &Cookie) -> impl Future<Output = bool> {
+        let (sender, receiver) = oneshot::channel();
+        let accepted = self.set_cookie(url, cookie, move |success| sender.send(success));
+        async move { accepted && receiver.await.unwrap_or(false) }
+    }
+    /// Like [delete_cookies](CookieManager::delete_cookies), but returns a future
+    /// that resolves to the number of deleted cookies, or to None if they couldn't
+    /// be deleted.
+    pub fn delete_cookies_async(
+        &self,
+        url: &str,
+        cookie_name: &str,
+    ) -> impl Future<Output = Option<usize>> {
+        let (sender, receiver) = oneshot::channel();
+        let accepted = self.delete_cookies(url, cookie_name, move |deleted| sender.send(deleted));
+        async move {
+            match accepted {
+                true => receiver.await.ok(),
+                false => None,
+            }
+        }
+    }
+    /// Flush the backing store (if any) to disk. The future resolves to true once
+    /// the flush is complete, or to false if cookies cannot be accessed.
+    pub fn flush_store_async(&self) -> impl Future<Output = bool> {
+        let (sender, receiver) = oneshot::channel();
+        let accepted = self.flush_store(move || sender.send(()));
+        async move { accepted && receiver.await.is_ok() }
+    }
+}
+
+/// Collects the cookies passed to a [CookieVisitor] and sends them after the last
+/// one, or once CEF releases the visitor if there were none.
+struct CookieCollector {
+    cookies: Vec<Cookie>,
+    sender: Option<oneshot::Sender<Vec<Cookie>>>,
+}
+
+impl CookieCollector {
+    fn finish(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            sender.send(std::mem::replace(&mut self.cookies, Vec::new()));
+        }
+    }
+}
+
+impl Drop for CookieCollector {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn collect_cookies() -> (impl CookieVisitorFn, oneshot::Receiver<Vec<Cookie>>) {
+    let (sender, receiver) = oneshot::channel();
+    let mut collector = CookieCollector {
+        cookies: Vec::new(),
+        sender: Some(sender),
+    };
+    let visitor = move |visit: CookieVisit| {
+        collector.cookies.push(visit.cookie);
+        if visit.index + 1 >= visit.len {
+            collector.finish();
+        }
+        true
+    };
+    (visitor, receiver)
 }
 
 impl CookieVisitor {
@@ -314,7 +414,7 @@ cef_callback_impl!{
         ) -> c_int {
             let cookie = unsafe{ Cookie::new(cookie) };
             let mut delete_cookie_rs = *delete_cookie != 0;
-            let ret = self.0.lock().take().unwrap()(CookieVisit {
+            let ret = self.0.lock().as_mut().unwrap()(CookieVisit {
                 cookie,
                 index: count as usize,
                 len: total as usize,
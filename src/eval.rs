@@ -0,0 +1,259 @@
+//! Evaluating JavaScript in a frame from the browser process.
+//!
+//! [Frame::eval_js] sends the script to the render process hosting the frame,
+//! where it is run with [V8Context::eval]. The completion value is converted
+//! into a [StoredValue] with [from_v8_value] and sent back. The messages are handled
+//! by the client and render process handler wrappers before they reach user
+//! callbacks, so nothing needs to be wired up by hand.
+//!
+//! Pending evaluations fail when their browser closes or its render process
+//! terminates. A frame that navigates away or is detached before the script runs
+//! never replies, so use [EvalJsFuture::with_timeout] when that matters.
+
+use crate::{
+    frame::Frame,
+    helper_traits::DeepClone,
+    oneshot,
+    process::ProcessMessage,
+    task::{TaskRunner, ThreadId},
+    v8context::{from_v8_value, JsError, JsErrorKind, JsStackFrame, V8Context},
+    values::{ListValue, StoredValue, Value},
+};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU32, Ordering},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+const REQUEST_MESSAGE: &str = "cef.eval.request";
+const RESPONSE_MESSAGE: &str = "cef.eval.response";
+
+struct PendingEval {
+    browser_id: i32,
+    sender: oneshot::Sender<Result<StoredValue, JsError>>,
+}
+
+lazy_static::lazy_static! {
+    static ref PENDING: Mutex<HashMap<u32, PendingEval>> = Mutex::new(HashMap::new());
+}
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// A future resolving to the completion value of a script started with
+/// [Frame::eval_js].
+///
+/// If the browser closes or its render process terminates before the script
+/// completes, the future resolves to a [JsError] without a source position.
+pub struct EvalJsFuture {
+    id: u32,
+    receiver: Option<oneshot::Receiver<Result<StoredValue, JsError>>>,
+}
+
+impl EvalJsFuture {
+    /// Fails the evaluation with a [JsError] if no reply arrived within `timeout`.
+    /// A reply arriving later is ignored. The timeout runs on the browser process
+    /// UI thread.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        if self.receiver.is_some() {
+            let id = self.id;
+            TaskRunner::post_delayed_task_on(
+                ThreadId::UI,
+                move || complete(id, Err(JsError::new("script timed out"))),
+                timeout.as_millis() as i64,
+            );
+        }
+        self
+    }
+}
+
+impl Future for EvalJsFuture {
+    type Output = Result<StoredValue, JsError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match &mut self.receiver {
+            Some(receiver) => Pin::new(receiver).poll(cx).map(|result| {
+                result.unwrap_or_else(|_| {
+                    Err(JsError::new("frame was released before the script completed"))
+                })
+            }),
+            None => Poll::Ready(Err(JsError::new("frame is no longer valid"))),
+        }
+    }
+}
+
+pub(crate) fn eval_js(frame: &Frame, code: &str, script_url: &str, start_line: i32) -> EvalJsFuture {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    if !frame.is_valid() {
+        return EvalJsFuture { id, receiver: None };
+    }
+    let (sender, receiver) = oneshot::channel();
+    PENDING.lock().insert(
+        id,
+        PendingEval {
+            browser_id: frame.get_browser().get_identifier(),
+            sender,
+        },
+    );
+
+    let message = ProcessMessage::new(REQUEST_MESSAGE);
+    let args = message.get_argument_list();
+    args.set_int(0, id as i32);
+    args.set_string(1, code);
+    args.set_string(2, script_url);
+    args.set_int(3, start_line);
+    frame.send_process_message(message);
+    EvalJsFuture {
+        id,
+        receiver: Some(receiver),
+    }
+}
+
+fn complete(id: u32, result: Result<StoredValue, JsError>) {
+    let pending = PENDING.lock().remove(&id);
+    if let Some(pending) = pending {
+        pending.sender.send(result);
+    }
+}
+
+/// Fails all evaluations pending in frames of the browser with `browser_id` with
+/// `reason`.
+pub(crate) fn cancel_for_browser(browser_id: i32, reason: &str) {
+    let cancelled: Vec<PendingEval> = {
+        let mut pending = PENDING.lock();
+        let ids: Vec<u32> = pending
+            .iter()
+            .filter(|(_, eval)| eval.browser_id == browser_id)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.into_iter().filter_map(|id| pending.remove(&id)).collect()
+    };
+    for eval in cancelled {
+        eval.sender.send(Err(JsError::new(reason)));
+    }
+}
+
+/// Handles an eval reply in the browser process. Returns true if `message` was one.
+pub(crate) fn on_browser_message_received(message: &ProcessMessage) -> bool {
+    if message.get_name().as_ref().map(String::as_str) != Some(RESPONSE_MESSAGE) {
+        return false;
+    }
+    let args = message.get_argument_list();
+    let id = args.get_int(0).unwrap_or_default() as u32;
+    let result = if args.get_bool(1).unwrap_or(false) {
+        // The value is owned by the message, which is destroyed once the
+        // callback returns.
+        Ok(args
+            .get(2)
+            .map(|value| value.deep_clone())
+            .unwrap_or(StoredValue::Null))
+    } else {
+        Err(JsError {
+            message: args.get_string(2).unwrap_or_default(),
+            script_resource_name: args.get_string(3).unwrap_or_default(),
+            source_line: args.get_string(4).unwrap_or_default(),
+            line_number: args.get_int(5).unwrap_or_default(),
+            start_column: args.get_int(6).unwrap_or_default(),
+            end_column: args.get_int(7).unwrap_or_default(),
+            kind: JsErrorKind::from_class_name(&args.get_string(8).unwrap_or_default()),
+            stack: args.get_list(9).map(|list| read_stack(&list)).unwrap_or_default(),
+            ..JsError::default()
+        })
+    };
+    complete(id, result);
+    true
+}
+
+/// Handles an eval request in the render process. Returns true if `message` was one.
+pub(crate) fn on_render_message_received(frame: &Frame, message: &ProcessMessage) -> bool {
+    if message.get_name().as_ref().map(String::as_str) != Some(REQUEST_MESSAGE) {
+        return false;
+    }
+    let args = message.get_argument_list();
+    let id = args.get_int(0).unwrap_or_default();
+    let code = args.get_string(1).unwrap_or_default();
+    let script_url = args.get_string(2).unwrap_or_default();
+    let start_line = args.get_int(3).unwrap_or_default();
+
+    let result = if frame.is_valid() {
+        evaluate(&frame.get_v8context(), &code, &script_url, start_line)
+    } else {
+        Err(JsError::new("frame is no longer valid"))
+    };
+
+    let reply = ProcessMessage::new(RESPONSE_MESSAGE);
+    let reply_args = reply.get_argument_list();
+    reply_args.set_int(0, id);
+    let value = result.and_then(|value| {
+        Value::try_from(value).map_err(|_| JsError::new("unable to store result"))
+    });
+    match value {
+        Ok(value) => {
+            reply_args.set_bool(1, true);
+            reply_args.set_value_inner(2, value);
+        }
+        Err(error) => {
+            reply_args.set_bool(1, false);
+            reply_args.set_string(2, &error.message);
+            reply_args.set_string(3, &error.script_resource_name);
+            reply_args.set_string(4, &error.source_line);
+            reply_args.set_int(5, error.line_number);
+            reply_args.set_int(6, error.start_column);
+            reply_args.set_int(7, error.end_column);
+            reply_args.set_string(8, error.kind.class_name());
+            reply_args.set_list(9, write_stack(&error.stack));
+        }
+    }
+    frame.send_process_message(reply);
+    true
+}
+
+/// Stores each frame as a list of its fields, in declaration order.
+fn write_stack(stack: &[JsStackFrame]) -> ListValue {
+    let list = ListValue::new();
+    for (index, frame) in stack.iter().enumerate() {
+        let fields = ListValue::new();
+        fields.set_string(0, &frame.script_name);
+        fields.set_string(1, &frame.function_name);
+        fields.set_int(2, frame.line_number);
+        fields.set_int(3, frame.column);
+        fields.set_bool(4, frame.is_eval);
+        fields.set_bool(5, frame.is_constructor);
+        list.set_list(index, fields);
+    }
+    list
+}
+
+fn read_stack(list: &ListValue) -> Vec<JsStackFrame> {
+    (0..list.len())
+        .filter_map(|index| list.get_list(index))
+        .map(|fields| JsStackFrame {
+            script_name: fields.get_string(0).unwrap_or_default(),
+            function_name: fields.get_string(1).unwrap_or_default(),
+            line_number: fields.get_int(2).unwrap_or_default(),
+            column: fields.get_int(3).unwrap_or_default(),
+            is_eval: fields.get_bool(4).unwrap_or_default(),
+            is_constructor: fields.get_bool(5).unwrap_or_default(),
+        })
+        .collect()
+}
+
+fn evaluate(
+    context: &V8Context,
+    code: &str,
+    script_url: &str,
+    start_line: i32,
+) -> Result<StoredValue, JsError> {
+    if !context.is_valid() {
+        return Err(JsError::new("frame has no valid V8 context"));
+    }
+    context
+        .execute_in_context(|| {
+            let value = context.eval(code, script_url, start_line)?;
+            from_v8_value::<StoredValue>(&value).map_err(|error| JsError::new(&error.to_string()))
+        })
+        .unwrap_or_else(|| Err(JsError::new("unable to enter the V8 context")))
+}
@@ -57,6 +57,9 @@ pub enum KeyEvent {
     Char {
         modifiers: EventFlags,
         char: char,
+        /// The native key code of the key that produced the character.
+        native_key_code: i32,
+        is_system_key: bool,
     }
 }
 
@@ -81,10 +84,12 @@ impl KeyEvent {
                 focus_on_editable_field: focus_on_editable_field as _,
                 ..unsafe{ mem::zeroed() }
             },
-            KeyEvent::Char{modifiers, char} => cef_key_event_t {
+            KeyEvent::Char{modifiers, char, native_key_code, is_system_key} => cef_key_event_t {
                 type_: cef_key_event_type_t::KEYEVENT_CHAR,
                 modifiers: modifiers.bits() as _,
                 windows_key_code: char as _,
+                native_key_code,
+                is_system_key: is_system_key as _,
                 character: char as _,
                 ..unsafe{ mem::zeroed() }
             }
@@ -119,6 +124,8 @@ impl From<cef_key_event_t> for KeyEvent {
             cef_key_event_type_t::KEYEVENT_CHAR => KeyEvent::Char {
                 modifiers: EventFlags::from_bits_truncate(event.modifiers as _),
                 char: std::char::from_u32(event.windows_key_code as u32).unwrap_or('\0'),
+                native_key_code: event.native_key_code,
+                is_system_key: event.is_system_key != 0,
             },
             _ => panic!("invalid event"),
         }
@@ -233,7 +240,7 @@ impl Into<cef_touch_event_t> for TouchEvent {
 
 #[repr(transparent)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct WindowsKeyCode(i32);
+pub struct WindowsKeyCode(pub(crate) i32);
 
 #[allow(non_upper_case_globals)]
 impl WindowsKeyCode {
@@ -6,8 +6,11 @@ use crate::{
     url_request::{URLRequest, URLRequestClient},
     v8context::V8Context,
     process::{ProcessId, ProcessMessage},
+    eval::EvalJsFuture,
+    oneshot,
 };
 use cef_sys::{cef_frame_t, cef_string_userfree_utf16_free};
+use std::future::Future;
 
 ref_counted_ptr! {
     /// Structure used to represent a frame in the browser window. When used in the
@@ -109,6 +112,20 @@ impl Frame {
             }
         }
     }
+    /// Retrieve this frame's HTML source. The future resolves to None if the source
+    /// couldn't be retrieved.
+    pub fn get_source_async(&self) -> impl Future<Output = Option<String>> {
+        let (visitor, receiver) = collect_string();
+        self.get_source(visitor);
+        async move { receiver.await.ok().flatten() }
+    }
+    /// Retrieve this frame's display text. The future resolves to None if the text
+    /// couldn't be retrieved.
+    pub fn get_text_async(&self) -> impl Future<Output = Option<String>> {
+        let (visitor, receiver) = collect_string();
+        self.get_text(visitor);
+        async move { receiver.await.ok().flatten() }
+    }
     /// Load the request represented by the |request| object.
     pub fn load_request(&self, request: Request) {
         if let Some(load_request) = self.0.load_request {
@@ -142,6 +159,16 @@ impl Frame {
             }
         }
     }
+    /// Execute a string of JavaScript code in this frame's V8 context and return
+    /// a future resolving to the completion value of the script, converted into a
+    /// [StoredValue] with [from_v8_value](crate::v8context::from_v8_value). If the
+    /// script throws, the future resolves to a [JsError] carrying the exception
+    /// message and position. The future only resolves on its own if the frame
+    /// replies or the browser goes away, see [EvalJsFuture::with_timeout]. This
+    /// function can only be called from the browser process.
+    pub fn eval_js(&self, code: &str) -> EvalJsFuture {
+        crate::eval::eval_js(self, code, "", 0)
+    }
     /// Returns true if this is the main (top-level) frame.
     pub fn is_main(&self) -> bool {
         if let Some(is_main) = self.0.is_main {
@@ -284,3 +311,30 @@ impl Frame {
         }
     }
 }
+
+/// Collects the strings passed to a [StringVisitor] and sends them once CEF releases
+/// the visitor, or None if it was never called.
+struct StringCollector {
+    text: Option<String>,
+    sender: Option<oneshot::Sender<Option<String>>>,
+}
+
+impl Drop for StringCollector {
+    fn drop(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            sender.send(self.text.take());
+        }
+    }
+}
+
+fn collect_string() -> (StringVisitor, oneshot::Receiver<Option<String>>) {
+    let (sender, receiver) = oneshot::channel();
+    let mut collector = StringCollector {
+        text: None,
+        sender: Some(sender),
+    };
+    let visitor = StringVisitor::new(move |string: &str| {
+        collector.text.get_or_insert_with(String::new).push_str(string);
+    });
+    (visitor, receiver)
+}
@@ -0,0 +1,466 @@
+//! Driving windowless rendering with external begin frames.
+//!
+//! When [WindowInfo::external_begin_frame_enabled] is set, a windowless browser
+//! only produces a frame when [BrowserHost::send_external_begin_frame] is called.
+//! A [FrameScheduler] issues those begin frames from a fixed-rate timer, from vsync
+//! signals that the application reports, or one at a time, and reports the paint
+//! that completes each frame. Install it as a [PaintObserver] next to the render
+//! handler:
+//!
+//! ```ignore
+//! let scheduler = FrameScheduler::new();
+//! let render_handler = RenderHandler::new(ObservedRenderHandler::new(view.clone(), scheduler.clone()));
+//! // Create the browser with external_begin_frame_enabled, then:
+//! scheduler.attach(browser.get_host());
+//!
+//! // Step frames deterministically in a screenshot test...
+//! let frame = scheduler.render_frame().await.unwrap();
+//! assert_eq!(frame.size, (800, 600));
+//! let png = view.screenshot_png();
+//!
+//! // ...or animate at 60 frames per second.
+//! scheduler.run_fixed_rate(60);
+//! ```
+//!
+//! CEF doesn't say which begin frame a paint belongs to, and a begin frame with
+//! nothing to paint produces no paint at all. The scheduler therefore keeps at
+//! most one begin frame waiting for its paint: a begin frame requested meanwhile
+//! is sent once the previous one was painted or timed out, and the timer and vsync
+//! sources skip a frame instead. Paints while no begin frame is waiting, such as
+//! the one [BrowserHost::invalidate] may make right away with the current contents,
+//! aren't reported.
+//!
+//! [WindowInfo::external_begin_frame_enabled]: crate::window::WindowInfo::external_begin_frame_enabled
+
+use crate::{
+    browser::Browser,
+    browser_host::{BrowserHost, PaintElementType},
+    client::render_handler::PaintObserver,
+    oneshot,
+    task::{TaskRunner, ThreadId},
+    values::Rect,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How long to wait for the paint of a begin frame before concluding that the
+/// frame had nothing to paint.
+pub const DEFAULT_PAINT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The paint that completed a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FramePaint {
+    /// The number of the begin frame, counting from 1.
+    pub frame: u64,
+    /// The regions of the view that were painted.
+    pub dirty_rects: Vec<Rect>,
+    /// The size of the painted view in physical pixels.
+    pub size: (i32, i32),
+    /// The time between sending the begin frame and the paint.
+    pub latency: Duration,
+}
+
+/// What drives the begin frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSource {
+    /// Begin frames are only sent by [FrameScheduler::begin_frame] and
+    /// [FrameScheduler::render_frame].
+    OnDemand,
+    /// A timer on the UI thread sends begin frames at this many frames per second.
+    FixedRate(u32),
+    /// [FrameScheduler::on_vsync] sends a begin frame per call. The scheduler has
+    /// no access to the display's vsync itself.
+    Vsync,
+}
+
+struct InFlight {
+    frame: u64,
+    sent: Instant,
+    sender: oneshot::Sender<FramePaint>,
+}
+
+/// A begin frame waiting to be sent.
+struct Request {
+    invalidate: bool,
+    sender: oneshot::Sender<FramePaint>,
+}
+
+type FrameCallback = Arc<dyn Fn(&FramePaint) + Send + Sync>;
+
+struct State {
+    host: Option<BrowserHost>,
+    source: FrameSource,
+    /// Incremented whenever the source changes, which stops a running timer.
+    generation: u64,
+    frames_sent: u64,
+    in_flight: Option<InFlight>,
+    queued: VecDeque<Request>,
+    paint_timeout: Duration,
+    on_frame: Option<FrameCallback>,
+}
+
+impl State {
+    /// Returns true if no begin frame is waiting for its paint or to be sent.
+    fn is_idle(&self) -> bool {
+        self.in_flight.is_none() && self.queued.is_empty()
+    }
+    /// Returns the request if its begin frame can be sent now, or queues it behind
+    /// the frame waiting for its paint.
+    fn take_request(&mut self, request: Request) -> Option<Request> {
+        if self.in_flight.is_some() {
+            self.queued.push_back(request);
+            None
+        } else {
+            Some(request)
+        }
+    }
+    /// Marks a begin frame as sent and returns its number.
+    fn start_frame(&mut self, sender: oneshot::Sender<FramePaint>, sent: Instant) -> u64 {
+        self.frames_sent += 1;
+        let frame = self.frames_sent;
+        self.in_flight = Some(InFlight { frame, sent, sender });
+        frame
+    }
+    /// Stops waiting for the paint of `frame` once its paint timeout expired.
+    /// Returns true if the frame was still waiting, in which case it had nothing
+    /// to paint and the next queued frame can be sent.
+    fn time_out(&mut self, frame: u64) -> bool {
+        let timed_out = self.in_flight.as_ref().map(|in_flight| in_flight.frame) == Some(frame);
+        if timed_out {
+            // Dropping the sender resolves the future to None.
+            self.in_flight = None;
+        }
+        timed_out
+    }
+}
+
+/// Returns the time of the tick after the one at `previous`, and the delay until
+/// it in whole milliseconds, rounded up so that the tick doesn't run early. Ticks
+/// are scheduled from the ideal time rather than from `now`, so that the rate
+/// doesn't drift, and ticks that were missed are skipped.
+fn next_tick(previous: Instant, interval: Duration, now: Instant) -> (Instant, i64) {
+    let mut next = previous + interval;
+    while next <= now {
+        next += interval;
+    }
+    let delay = next - now;
+    let mut delay_ms = delay.as_millis() as i64;
+    if delay.subsec_nanos() % 1_000_000 != 0 {
+        delay_ms += 1;
+    }
+    (next, delay_ms)
+}
+
+/// Sends external begin frames to a windowless browser and reports the paints
+/// that complete them. Cloning it returns another handle to the same scheduler.
+#[derive(Clone)]
+pub struct FrameScheduler(Arc<Mutex<State>>);
+
+impl Default for FrameScheduler {
+    fn default() -> FrameScheduler {
+        FrameScheduler::new()
+    }
+}
+
+impl FrameScheduler {
+    pub fn new() -> FrameScheduler {
+        FrameScheduler(Arc::new(Mutex::new(State {
+            host: None,
+            source: FrameSource::OnDemand,
+            generation: 0,
+            frames_sent: 0,
+            in_flight: None,
+            queued: VecDeque::new(),
+            paint_timeout: DEFAULT_PAINT_TIMEOUT,
+            on_frame: None,
+        })))
+    }
+    /// Set how long [begin_frame](FrameScheduler::begin_frame) waits for a paint
+    /// before resolving to None. Defaults to [DEFAULT_PAINT_TIMEOUT]. Begin frames
+    /// requested meanwhile wait as well, so keep it below the frame interval of a
+    /// [fixed rate](FrameScheduler::run_fixed_rate).
+    pub fn paint_timeout(self, paint_timeout: Duration) -> Self {
+        self.0.lock().paint_timeout = paint_timeout;
+        self
+    }
+    /// Set the browser to send begin frames to. It must have been created with
+    /// [WindowInfo::external_begin_frame_enabled] set.
+    ///
+    /// [WindowInfo::external_begin_frame_enabled]: crate::window::WindowInfo::external_begin_frame_enabled
+    pub fn attach(&self, host: BrowserHost) {
+        self.0.lock().host = Some(host);
+    }
+    /// Stop sending begin frames to the browser, e.g. before it closes.
+    pub fn detach(&self) {
+        self.set_source(FrameSource::OnDemand);
+        let mut state = self.0.lock();
+        state.host = None;
+        // Dropping the senders resolves the futures to None.
+        state.queued.clear();
+    }
+    /// Call `callback` for the paint that completes each frame, whatever sent the
+    /// begin frame. It is called on the browser process UI thread.
+    pub fn on_frame(&self, callback: impl Fn(&FramePaint) + Send + Sync + 'static) {
+        self.0.lock().on_frame = Some(Arc::new(callback));
+    }
+    /// Returns what currently drives the begin frames.
+    pub fn source(&self) -> FrameSource {
+        self.0.lock().source
+    }
+    /// Returns the number of begin frames sent so far.
+    pub fn frames_sent(&self) -> u64 {
+        self.0.lock().frames_sent
+    }
+
+    /// Send begin frames at `frames_per_second` from a timer on the browser
+    /// process UI thread, until another source is chosen. A tick while the previous
+    /// frame still waits for its paint is skipped.
+    pub fn run_fixed_rate(&self, frames_per_second: u32) {
+        let generation = self.set_source(FrameSource::FixedRate(frames_per_second.max(1)));
+        let interval = Duration::from_secs(1) / frames_per_second.max(1);
+        self.tick(generation, Instant::now(), interval);
+    }
+    /// Send a begin frame whenever [on_vsync](FrameScheduler::on_vsync) is called.
+    /// The application has to call it from whatever vsync signal it has, e.g. the
+    /// redraw event of a window presenting with vsync; without one, use
+    /// [run_fixed_rate](FrameScheduler::run_fixed_rate) instead.
+    pub fn run_on_vsync(&self) {
+        self.set_source(FrameSource::Vsync);
+    }
+    /// Only send begin frames on request. This stops a running timer.
+    pub fn run_on_demand(&self) {
+        self.set_source(FrameSource::OnDemand);
+    }
+    /// Report a vsync. Sends a begin frame if the scheduler runs on vsync and the
+    /// previous frame isn't still waiting for its paint. May be called on any
+    /// thread.
+    pub fn on_vsync(&self) {
+        if self.source() == FrameSource::Vsync && self.is_idle() {
+            drop(self.begin_frame());
+        }
+    }
+
+    /// Send a single begin frame, now or once the previous one was painted. The
+    /// returned future resolves to the paint that completes the frame, or to None
+    /// if nothing was painted within the [paint timeout](FrameScheduler::paint_timeout)
+    /// or no browser is attached. May be called on any thread.
+    pub fn begin_frame(&self) -> impl Future<Output = Option<FramePaint>> {
+        self.send(false)
+    }
+    /// Render exactly one frame: invalidate the whole view and send a begin frame,
+    /// so that the frame is painted even if nothing changed. Use this to
+    /// step frames in screenshot tests. May be called on any thread.
+    pub fn render_frame(&self) -> impl Future<Output = Option<FramePaint>> {
+        self.send(true)
+    }
+
+    fn is_idle(&self) -> bool {
+        self.0.lock().is_idle()
+    }
+
+    fn set_source(&self, source: FrameSource) -> u64 {
+        let mut state = self.0.lock();
+        state.source = source;
+        state.generation += 1;
+        state.generation
+    }
+
+    fn tick(&self, generation: u64, next: Instant, interval: Duration) {
+        if self.0.lock().generation != generation {
+            return;
+        }
+        if self.is_idle() {
+            drop(self.begin_frame());
+        }
+        let (next, delay_ms) = next_tick(next, interval, Instant::now());
+        let scheduler = self.clone();
+        TaskRunner::post_delayed_task_on(
+            ThreadId::UI,
+            move || scheduler.tick(generation, next, interval),
+            delay_ms,
+        );
+    }
+
+    fn send(&self, invalidate: bool) -> impl Future<Output = Option<FramePaint>> {
+        let (sender, receiver) = oneshot::channel();
+        let request = Request { invalidate, sender };
+        if TaskRunner::currently_on(ThreadId::UI) {
+            self.send_on_ui_thread(request);
+        } else {
+            let scheduler = self.clone();
+            TaskRunner::post_task_on(ThreadId::UI, move || scheduler.send_on_ui_thread(request));
+        }
+        async move { receiver.await.ok() }
+    }
+
+    fn send_on_ui_thread(&self, request: Request) {
+        let (host, request) = {
+            let mut state = self.0.lock();
+            let host = match &state.host {
+                Some(host) => host.clone(),
+                // Dropping the sender resolves the future to None.
+                None => return,
+            };
+            match state.take_request(request) {
+                Some(request) => (host, request),
+                None => return,
+            }
+        };
+        // This may paint the current contents right away, which is ignored since no
+        // frame is in flight yet.
+        if request.invalidate {
+            host.invalidate(PaintElementType::View);
+        }
+        let (frame, timeout) = {
+            let mut state = self.0.lock();
+            let frame = state.start_frame(request.sender, Instant::now());
+            (frame, state.paint_timeout)
+        };
+        host.send_external_begin_frame();
+
+        let scheduler = self.clone();
+        TaskRunner::post_delayed_task_on(
+            ThreadId::UI,
+            move || {
+                let timed_out = scheduler.0.lock().time_out(frame);
+                if timed_out {
+                    scheduler.send_queued();
+                }
+            },
+            timeout.as_millis() as i64,
+        );
+    }
+
+    /// Sends the next requested begin frame, once the previous one is done.
+    fn send_queued(&self) {
+        let request = self.0.lock().queued.pop_front();
+        if let Some(request) = request {
+            self.send_on_ui_thread(request);
+        }
+    }
+}
+
+impl PaintObserver for FrameScheduler {
+    fn on_paint(
+        &self,
+        _browser: &Browser,
+        type_: PaintElementType,
+        dirty_rects: &[Rect],
+        _buffer: &[u8],
+        width: i32,
+        height: i32,
+    ) {
+        if type_ != PaintElementType::View {
+            return;
+        }
+        let (in_flight, on_frame) = {
+            let mut state = self.0.lock();
+            match state.in_flight.take() {
+                Some(in_flight) => (in_flight, state.on_frame.clone()),
+                None => return,
+            }
+        };
+        let paint = FramePaint {
+            frame: in_flight.frame,
+            dirty_rects: dirty_rects.to_vec(),
+            size: (width, height),
+            latency: in_flight.sent.elapsed(),
+        };
+        if let Some(on_frame) = on_frame {
+            on_frame(&paint);
+        }
+        in_flight.sender.send(paint);
+        self.send_queued();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> State {
+        State {
+            host: None,
+            source: FrameSource::OnDemand,
+            generation: 0,
+            frames_sent: 0,
+            in_flight: None,
+            queued: VecDeque::new(),
+            paint_timeout: DEFAULT_PAINT_TIMEOUT,
+            on_frame: None,
+        }
+    }
+
+    fn new_request(invalidate: bool) -> Request {
+        Request {
+            invalidate,
+            sender: oneshot::channel().0,
+        }
+    }
+
+    #[test]
+    fn ticks_keep_their_rate() {
+        let start = Instant::now();
+        let interval = Duration::from_secs(1) / 60;
+        // A tick that ran late doesn't delay the next one.
+        let (next, delay_ms) = next_tick(start, interval, start + Duration::from_millis(5));
+        assert_eq!(next, start + interval);
+        assert_eq!(delay_ms, 12);
+        // A delay of whole milliseconds isn't rounded up.
+        let (next, delay_ms) = next_tick(start, Duration::from_millis(10), start + Duration::from_millis(4));
+        assert_eq!(next, start + Duration::from_millis(10));
+        assert_eq!(delay_ms, 6);
+        // Missed ticks are skipped.
+        let (next, delay_ms) = next_tick(start, interval, start + interval * 3 + Duration::from_millis(1));
+        assert_eq!(next, start + interval * 4);
+        assert_eq!(delay_ms, 16);
+        let (next, _) = next_tick(start, interval, start + interval);
+        assert_eq!(next, start + interval * 2);
+    }
+
+    #[test]
+    fn one_frame_waits_for_its_paint() {
+        let mut state = state();
+        assert!(state.is_idle());
+        let request = state.take_request(new_request(false)).unwrap();
+        assert_eq!(state.start_frame(request.sender, Instant::now()), 1);
+        assert!(!state.is_idle());
+
+        // Requests made meanwhile are queued in order.
+        assert!(state.take_request(new_request(true)).is_none());
+        assert!(state.take_request(new_request(false)).is_none());
+        assert_eq!(state.queued.len(), 2);
+        assert!(state.queued[0].invalidate);
+
+        // The frame is painted, so its timeout does nothing.
+        let in_flight = state.in_flight.take().unwrap();
+        assert_eq!(in_flight.frame, 1);
+        assert!(!state.time_out(1));
+
+        let request = state.queued.pop_front().unwrap();
+        let request = state.take_request(request).unwrap();
+        assert_eq!(state.start_frame(request.sender, Instant::now()), 2);
+        assert_eq!(state.frames_sent, 2);
+    }
+
+    #[test]
+    fn unpainted_frames_time_out() {
+        let mut state = state();
+        state.start_frame(oneshot::channel().0, Instant::now());
+        state.in_flight = None;
+        let frame = state.start_frame(oneshot::channel().0, Instant::now());
+        // The timeout of an earlier frame doesn't end the current one.
+        assert!(!state.time_out(frame - 1));
+        assert!(state.in_flight.is_some());
+        assert!(state.time_out(frame));
+        assert!(state.in_flight.is_none());
+        assert!(state.is_idle());
+        assert!(!state.time_out(frame));
+    }
+
+}
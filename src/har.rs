@@ -0,0 +1,907 @@
+//! Recording network traffic in the [HAR 1.2](http://www.softwareishard.com/blog/har-12-spec/) format.
+//!
+//! A [HarRecorder] records the requests it sees through its
+//! [ResourceRequestHandlerCallbacks]. Install it as the [RequestHandlerCallbacks]
+//! of a browser's client to record that browser, or as the
+//! [RequestContextHandlerCallbacks] of a [RequestContext] to record every browser
+//! that uses the context:
+//!
+//! ```ignore
+//! let recorder = HarRecorder::new().record_bodies(true);
+//! let context = RequestContext::new(&RequestContextSettings::default(), Some(RequestContextHandler::new(recorder.clone())));
+//! // ... browse ...
+//! recorder.save(Path::new("network.har"))?;
+//! ```
+//!
+//! Requests that another handler returns a [ResourceRequestHandler] for are not
+//! seen by the recorder, and headers that the network stack adds after
+//! [ResourceRequestHandlerCallbacks::on_before_resource_load], like cookies from
+//! the cookie store, are not recorded. Timings are measured from the handler's
+//! callbacks, so only `wait` and `receive` are known.
+//!
+//! [RequestContext]: crate::request_context::RequestContext
+
+use crate::{
+    browser::Browser,
+    client::request_handler::RequestHandlerCallbacks,
+    frame::Frame,
+    request::{PostDataElementType, Request, ResourceType},
+    request_context::RequestContextHandlerCallbacks,
+    resource_request_handler::{ResourceRequestHandler, ResourceRequestHandlerCallbacks},
+    response::Response,
+    static_files::{is_text, percent_decode},
+    url_request::{
+        FilterOutput, RequestCallback, ResponseFilter, ResponseFilterCallbacks, ResponseFilterStatus,
+        URLRequestStatus,
+    },
+    ReturnValue,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
+
+/// The default for [HarRecorder::max_body_size].
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+/// The default for [HarRecorder::max_pending].
+pub const DEFAULT_MAX_PENDING: usize = 1000;
+
+/// Records requests and responses and writes them out as a HAR log. Clones
+/// share the same log.
+#[derive(Clone)]
+pub struct HarRecorder {
+    record_bodies: bool,
+    max_body_size: usize,
+    max_pending: usize,
+    log: Arc<Mutex<Log>>,
+}
+
+#[derive(Default)]
+struct Log {
+    pages: Vec<Page>,
+    /// The current page of each browser.
+    browser_pages: HashMap<i32, usize>,
+    /// Requests that haven't completed yet, by request identifier.
+    pending: HashMap<u64, Entry>,
+    entries: Vec<Entry>,
+}
+
+#[derive(Clone)]
+struct Page {
+    id: String,
+    title: String,
+    started: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+struct Entry {
+    page: Option<usize>,
+    started: DateTime<Utc>,
+    start: Instant,
+    response_time: Option<Instant>,
+    end: Option<Instant>,
+    method: String,
+    url: String,
+    request_headers: Vec<(String, String)>,
+    post_data: Option<PostData>,
+    status: i32,
+    status_text: String,
+    response_headers: Vec<(String, String)>,
+    mime_type: String,
+    redirect_url: String,
+    received_size: i64,
+    body: Option<Arc<Mutex<Body>>>,
+    error: Option<String>,
+}
+
+#[derive(Clone)]
+struct PostData {
+    mime_type: String,
+    text: String,
+    files: Vec<String>,
+    size: i64,
+}
+
+#[derive(Default)]
+struct Body {
+    data: Vec<u8>,
+    size: usize,
+}
+
+impl Default for HarRecorder {
+    fn default() -> HarRecorder {
+        HarRecorder::new()
+    }
+}
+
+impl HarRecorder {
+    pub fn new() -> HarRecorder {
+        HarRecorder {
+            record_bodies: false,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_pending: DEFAULT_MAX_PENDING,
+            log: Arc::new(Mutex::new(Log::default())),
+        }
+    }
+    /// Record response bodies. This installs a [ResponseFilter] on every
+    /// response. Disabled by default.
+    pub fn record_bodies(mut self, record_bodies: bool) -> Self {
+        self.record_bodies = record_bodies;
+        self
+    }
+    /// Set the number of bytes of each response body that are recorded. Longer
+    /// bodies are left out of the log, but their size is still recorded.
+    /// Defaults to [DEFAULT_MAX_BODY_SIZE].
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+    /// Set the number of requests that can be loading at the same time. CEF
+    /// doesn't report the completion of every request, e.g. when its browser is
+    /// closed, so once there are more, the oldest is logged as incomplete.
+    /// Defaults to [DEFAULT_MAX_PENDING].
+    pub fn max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending.max(1);
+        self
+    }
+
+    /// Returns a handler that records a request. Use this from your own
+    /// [RequestHandlerCallbacks::get_resource_request_handler] for the requests
+    /// that you don't handle otherwise.
+    pub fn resource_request_handler(&self) -> ResourceRequestHandler {
+        ResourceRequestHandler::new(RecordingHandler(self.clone()))
+    }
+    /// Returns the number of completed requests.
+    pub fn entry_count(&self) -> usize {
+        self.log.lock().entries.len()
+    }
+    /// Forget all recorded requests and pages.
+    pub fn clear(&self) {
+        let mut log = self.log.lock();
+        log.pages.clear();
+        log.browser_pages.clear();
+        log.entries.clear();
+        for entry in log.pending.values_mut() {
+            entry.page = None;
+        }
+    }
+    /// Returns the HAR log of the completed requests as JSON. Requests that are
+    /// still loading are left out.
+    pub fn to_har(&self) -> String {
+        let (pages, entries) = {
+            let log = self.log.lock();
+            (log.pages.clone(), log.entries.clone())
+        };
+        to_json(&pages, entries)
+    }
+    /// Write the HAR log to `writer`.
+    pub fn write_har<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.to_har().as_bytes())
+    }
+    /// Write the HAR log to the file at `path`.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_har(&mut writer)?;
+        writer.flush()
+    }
+}
+
+impl RequestHandlerCallbacks for HarRecorder {
+    fn get_resource_request_handler(
+        &self,
+        _browser: Browser,
+        _frame: Frame,
+        _request: Request,
+        _is_navigation: bool,
+        _is_download: bool,
+        _request_initiator: &str,
+        _disable_default_handling: &mut bool,
+    ) -> Option<ResourceRequestHandler> {
+        Some(self.resource_request_handler())
+    }
+}
+
+impl RequestContextHandlerCallbacks for HarRecorder {
+    fn get_resource_request_handler(
+        &self,
+        _browser: Option<Browser>,
+        _frame: Option<Frame>,
+        _request: Request,
+        _is_navigation: bool,
+        _is_download: bool,
+        _request_initiator: &str,
+        _disable_default_handling: &mut bool,
+    ) -> Option<ResourceRequestHandler> {
+        Some(self.resource_request_handler())
+    }
+}
+
+struct RecordingHandler(HarRecorder);
+
+impl ResourceRequestHandlerCallbacks for RecordingHandler {
+    fn on_before_resource_load(
+        &self,
+        browser: Option<Browser>,
+        _frame: Option<Frame>,
+        request: Request,
+        _callback: RequestCallback,
+    ) -> ReturnValue {
+        let url = request.get_url();
+        let started = Utc::now();
+        let mut log = self.0.log.lock();
+        let identifier = request.get_identifier();
+        let redirected = log.pending.remove(&identifier);
+        let browser_id = browser.map(|browser| browser.get_identifier());
+        if let (Some(browser_id), ResourceType::MainFrame, None) = (browser_id, request.get_resource_type(), &redirected) {
+            let page = log.pages.len();
+            log.pages.push(Page {
+                id: format!("page_{}", page + 1),
+                title: url.clone(),
+                started,
+            });
+            log.browser_pages.insert(browser_id, page);
+        }
+        let page = match redirected {
+            Some(redirected) => redirected.page,
+            None => browser_id.and_then(|browser_id| log.browser_pages.get(&browser_id).copied()),
+        };
+        let request_headers = sorted_headers(request.get_header_map());
+        let post_data = request.get_post_data().map(|post_data| {
+            let mut data = Vec::new();
+            let mut files = Vec::new();
+            for element in post_data.get_elements() {
+                match element.get_type() {
+                    PostDataElementType::Bytes => data.extend_from_slice(&element.get_bytes()),
+                    PostDataElementType::File => files.push(element.get_file()),
+                    PostDataElementType::Empty => (),
+                }
+            }
+            PostData {
+                mime_type: header(&request_headers, "Content-Type").unwrap_or_default().to_string(),
+                text: String::from_utf8_lossy(&data).into_owned(),
+                size: if files.is_empty() { data.len() as i64 } else { -1 },
+                files,
+            }
+        });
+        log.pending.insert(identifier, Entry {
+            page,
+            started,
+            start: Instant::now(),
+            response_time: None,
+            end: None,
+            method: request.get_method(),
+            url,
+            request_headers,
+            post_data,
+            status: 0,
+            status_text: String::new(),
+            response_headers: Vec::new(),
+            mime_type: String::new(),
+            redirect_url: String::new(),
+            received_size: -1,
+            body: None,
+            error: None,
+        });
+        if log.pending.len() > self.0.max_pending {
+            log.evict_oldest_pending();
+        }
+        ReturnValue::Continue
+    }
+    fn on_resource_redirect(
+        &self,
+        _browser: Option<Browser>,
+        _frame: Option<Frame>,
+        request: Request,
+        response: Response,
+        new_url: &mut String,
+    ) {
+        // Each hop of a redirect gets an entry of its own. The request keeps its
+        // identifier, and its entry is replaced when it is loaded again.
+        let mut log = self.0.log.lock();
+        let identifier = request.get_identifier();
+        if let Some(mut entry) = log.pending.remove(&identifier) {
+            let now = Instant::now();
+            let next = Entry {
+                started: Utc::now(),
+                start: now,
+                url: new_url.clone(),
+                ..entry.request()
+            };
+            entry.set_response(&response);
+            entry.response_time = Some(now);
+            entry.end = Some(now);
+            entry.redirect_url = new_url.clone();
+            log.entries.push(entry);
+            log.pending.insert(identifier, next);
+        }
+    }
+    fn on_resource_response(
+        &self,
+        _browser: Option<Browser>,
+        _frame: Option<Frame>,
+        request: Request,
+        response: Response,
+    ) {
+        if let Some(entry) = self.0.log.lock().pending.get_mut(&request.get_identifier()) {
+            entry.set_response(&response);
+            entry.response_time = Some(Instant::now());
+        }
+    }
+    fn get_resource_response_filter(
+        &self,
+        _browser: Option<Browser>,
+        _frame: Option<Frame>,
+        request: Request,
+        _response: Response,
+    ) -> Option<ResponseFilter> {
+        if !self.0.record_bodies {
+            return None;
+        }
+        let body = Arc::new(Mutex::new(Body::default()));
+        self.0.log.lock().pending.get_mut(&request.get_identifier())?.body = Some(body.clone());
+        Some(ResponseFilter::new(RecordingFilter {
+            body,
+            max_body_size: self.0.max_body_size,
+            output: Mutex::new(FilterOutput::default()),
+        }))
+    }
+    fn on_resource_load_complete(
+        &self,
+        _browser: Option<Browser>,
+        _frame: Option<Frame>,
+        request: Request,
+        response: Response,
+        status: URLRequestStatus,
+        received_content_length: i64,
+    ) {
+        let mut log = self.0.log.lock();
+        if let Some(mut entry) = log.pending.remove(&request.get_identifier()) {
+            let now = Instant::now();
+            if entry.response_time.is_none() {
+                entry.set_response(&response);
+            }
+            entry.end = Some(now);
+            entry.received_size = received_content_length;
+            entry.error = match status {
+                URLRequestStatus::Success => None,
+                URLRequestStatus::Canceled => Some("canceled".to_string()),
+                _ => Some(format!("{:?}", response.get_error())),
+            };
+            log.entries.push(entry);
+        }
+    }
+}
+
+/// Copies the response body through unchanged while recording it.
+struct RecordingFilter {
+    body: Arc<Mutex<Body>>,
+    max_body_size: usize,
+    output: Mutex<FilterOutput>,
+}
+
+impl ResponseFilterCallbacks for RecordingFilter {
+    fn init_filter(&self) -> bool {
+        true
+    }
+    fn filter(
+        &self,
+        data_in: &[u8],
+        data_in_read: &mut usize,
+        data_out: &mut [u8],
+        data_out_written: &mut usize,
+    ) -> ResponseFilterStatus {
+        let mut output = self.output.lock();
+        *data_in_read = data_in.len();
+        if !output.at_end(data_in) {
+            output.push(data_in);
+            let mut body = self.body.lock();
+            body.size += data_in.len();
+            if body.size <= self.max_body_size {
+                body.data.extend_from_slice(data_in);
+            } else {
+                body.data = Vec::new();
+            }
+        }
+        output.write(data_out, data_out_written)
+    }
+}
+
+impl Entry {
+    /// Returns a copy of the request without response.
+    fn request(&self) -> Entry {
+        Entry {
+            page: self.page,
+            started: self.started,
+            start: self.start,
+            response_time: None,
+            end: None,
+            method: self.method.clone(),
+            url: self.url.clone(),
+            request_headers: self.request_headers.clone(),
+            post_data: self.post_data.clone(),
+            status: 0,
+            status_text: String::new(),
+            response_headers: Vec::new(),
+            mime_type: String::new(),
+            redirect_url: String::new(),
+            received_size: -1,
+            body: None,
+            error: None,
+        }
+    }
+
+    fn set_response(&mut self, response: &Response) {
+        self.status = response.get_status();
+        self.status_text = response.get_status_text();
+        self.response_headers = sorted_headers(response.get_header_map());
+        let mime_type = response.get_mime_type();
+        let charset = response.get_charset();
+        self.mime_type = if charset.is_empty() || mime_type.is_empty() {
+            mime_type
+        } else {
+            format!("{}; charset={}", mime_type, charset)
+        };
+        if let Some(location) = header(&self.response_headers, "Location") {
+            self.redirect_url = location.to_string();
+        }
+    }
+
+    fn write_json(&self, pages: &[Page], json: &mut String) {
+        let millis = |from: Instant, to: Option<Instant>| to.map_or(0.0, |to| to.duration_since(from).as_secs_f64() * 1000.0);
+        let wait = millis(self.start, self.response_time.or(self.end));
+        let receive = self.response_time.map_or(0.0, |response_time| millis(response_time, self.end));
+        json.push('{');
+        if let Some(page) = self.page.and_then(|page| pages.get(page)) {
+            let _ = write!(json, r#""pageref":{},"#, json_string(&page.id));
+        }
+        let _ = write!(
+            json,
+            r#""startedDateTime":{},"time":{:.3},"#,
+            json_string(&iso_date(self.started)),
+            wait + receive,
+        );
+
+        let _ = write!(
+            json,
+            r#""request":{{"method":{},"url":{},"httpVersion":"","cookies":"#,
+            json_string(&self.method),
+            json_string(&self.url),
+        );
+        write_cookies(header(&self.request_headers, "Cookie").map(parse_cookie_header).unwrap_or_default(), json);
+        json.push_str(r#","headers":"#);
+        write_pairs(&self.request_headers, json);
+        json.push_str(r#","queryString":"#);
+        write_pairs(&query_string(&self.url), json);
+        if let Some(post_data) = &self.post_data {
+            let _ = write!(
+                json,
+                r#","postData":{{"mimeType":{},"text":{}"#,
+                json_string(&post_data.mime_type),
+                json_string(&post_data.text),
+            );
+            if !post_data.files.is_empty() {
+                json.push_str(r#","params":["#);
+                for (i, file) in post_data.files.iter().enumerate() {
+                    if i > 0 {
+                        json.push(',');
+                    }
+                    let _ = write!(json, r#"{{"name":"","fileName":{}}}"#, json_string(file));
+                }
+                json.push(']');
+            }
+            json.push('}');
+        }
+        let _ = write!(
+            json,
+            r#","headersSize":-1,"bodySize":{}}},"#,
+            self.post_data.as_ref().map_or(0, |post_data| post_data.size),
+        );
+
+        let _ = write!(
+            json,
+            r#""response":{{"status":{},"statusText":{},"httpVersion":"","cookies":"#,
+            self.status,
+            json_string(&self.status_text),
+        );
+        write_cookies(
+            self.response_headers
+                .iter()
+                .filter(|(name, _)| name.eq_ignore_ascii_case("Set-Cookie"))
+                .filter_map(|(_, value)| parse_set_cookie(value))
+                .collect(),
+            json,
+        );
+        json.push_str(r#","headers":"#);
+        write_pairs(&self.response_headers, json);
+        let body = self.body.as_ref().map(|body| body.lock());
+        let size = body.as_ref().map_or(self.received_size.max(0), |body| body.size as i64);
+        let _ = write!(json, r#","content":{{"size":{},"mimeType":{}"#, size, json_string(&self.mime_type));
+        if let Some(body) = &body {
+            if body.data.len() < body.size {
+                json.push_str(r#","comment":"body not recorded""#);
+            } else {
+                match std::str::from_utf8(&body.data) {
+                    Ok(text) if is_text(&self.mime_type) || self.mime_type.contains("javascript") || self.mime_type.contains("xml") => {
+                        let _ = write!(json, r#","text":{}"#, json_string(text));
+                    }
+                    _ => {
+                        let _ = write!(json, r#","text":"{}","encoding":"base64""#, base64(&body.data));
+                    }
+                }
+            }
+        }
+        let _ = write!(
+            json,
+            r#"}},"redirectURL":{},"headersSize":-1,"bodySize":{}"#,
+            json_string(&self.redirect_url),
+            self.received_size,
+        );
+        if let Some(error) = &self.error {
+            let _ = write!(json, r#","_error":{}"#, json_string(error));
+        }
+        let _ = write!(
+            json,
+            r#"}},"cache":{{}},"timings":{{"blocked":-1,"dns":-1,"connect":-1,"send":0,"wait":{:.3},"receive":{:.3},"ssl":-1}}}}"#,
+            wait,
+            receive,
+        );
+    }
+}
+
+impl Log {
+    /// Move the request that has been loading the longest to the entries as
+    /// incomplete.
+    fn evict_oldest_pending(&mut self) {
+        let oldest = self.pending.iter().min_by_key(|(_, entry)| entry.start).map(|(&identifier, _)| identifier);
+        if let Some(mut entry) = oldest.and_then(|identifier| self.pending.remove(&identifier)) {
+            entry.error = Some("incomplete".to_string());
+            self.entries.push(entry);
+        }
+    }
+}
+
+/// Serializes the log. It takes a snapshot of the pages and entries, so that
+/// the log isn't locked while the JSON is built.
+fn to_json(pages: &[Page], mut entries: Vec<Entry>) -> String {
+    let mut json = String::new();
+    let _ = write!(
+        json,
+        r#"{{"log":{{"version":"1.2","creator":{{"name":{},"version":{}}},"pages":["#,
+        json_string(env!("CARGO_PKG_NAME")),
+        json_string(env!("CARGO_PKG_VERSION")),
+    );
+    for (i, page) in pages.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        let _ = write!(
+            json,
+            r#"{{"startedDateTime":{},"id":{},"title":{},"pageTimings":{{"onContentLoad":-1,"onLoad":-1}}}}"#,
+            json_string(&iso_date(page.started)),
+            json_string(&page.id),
+            json_string(&page.title),
+        );
+    }
+    json.push_str(r#"],"entries":["#);
+    entries.sort_by_key(|entry| entry.start);
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        entry.write_json(pages, &mut json);
+    }
+    json.push_str("]}}");
+    json
+}
+
+fn sorted_headers(header_map: HashMap<String, Vec<String>>) -> Vec<(String, String)> {
+    let mut headers: Vec<_> = header_map
+        .into_iter()
+        .flat_map(|(name, values)| values.into_iter().map(move |value| (name.clone(), value)))
+        .collect();
+    headers.sort_by_key(|(name, _)| name.to_ascii_lowercase());
+    headers
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn query_string(url: &str) -> Vec<(String, String)> {
+    let query = match url.find('?') {
+        Some(start) => &url[start + 1..],
+        None => return Vec::new(),
+    };
+    let query = query.split('#').next().unwrap_or_default();
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut pair = pair.splitn(2, '=');
+            let name = pair.next().unwrap_or_default().replace('+', " ");
+            let value = pair.next().unwrap_or_default().replace('+', " ");
+            (percent_decode(&name), percent_decode(&value))
+        })
+        .collect()
+}
+
+struct Cookie {
+    name: String,
+    value: String,
+    attributes: Vec<(&'static str, String)>,
+}
+
+fn parse_cookie_header(value: &str) -> Vec<Cookie> {
+    value
+        .split(';')
+        .filter_map(|pair| {
+            let mut pair = pair.trim().splitn(2, '=');
+            let name = pair.next()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+            Some(Cookie {
+                name: name.to_string(),
+                value: pair.next().unwrap_or_default().trim().to_string(),
+                attributes: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+fn parse_set_cookie(value: &str) -> Option<Cookie> {
+    let mut parts = value.split(';');
+    let mut cookie = parse_cookie_header(parts.next()?).pop()?;
+    for attribute in parts {
+        let mut attribute = attribute.trim().splitn(2, '=');
+        let name = attribute.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let value = attribute.next().unwrap_or_default().trim().to_string();
+        match name.as_str() {
+            "path" => cookie.attributes.push(("path", value)),
+            "domain" => cookie.attributes.push(("domain", value)),
+            "expires" => {
+                if let Some(expires) = parse_cookie_date(&value) {
+                    cookie.attributes.push(("expires", iso_date(expires)));
+                }
+            }
+            "httponly" => cookie.attributes.push(("httpOnly", String::new())),
+            "secure" => cookie.attributes.push(("secure", String::new())),
+            _ => (),
+        }
+    }
+    Some(cookie)
+}
+
+/// Parses the date of an `Expires` attribute, which is usually an HTTP date but
+/// can also use dashes between the day, month and year, e.g. `Wed, 21-Oct-2015
+/// 07:28:00 GMT`, with a two digit year in old cookies.
+fn parse_cookie_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc2822(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+    // A two digit year would also parse as a four digit one.
+    ["%a, %d-%b-%y %H:%M:%S GMT", "%a, %d-%b-%Y %H:%M:%S GMT"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|date| DateTime::from_utc(date, Utc))
+}
+
+fn write_cookies(cookies: Vec<Cookie>, json: &mut String) {
+    json.push('[');
+    for (i, cookie) in cookies.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        let _ = write!(json, r#"{{"name":{},"value":{}"#, json_string(&cookie.name), json_string(&cookie.value));
+        for (name, value) in &cookie.attributes {
+            match *name {
+                "httpOnly" | "secure" => {
+                    let _ = write!(json, r#","{}":true"#, name);
+                }
+                _ => {
+                    let _ = write!(json, r#","{}":{}"#, name, json_string(value));
+                }
+            }
+        }
+        json.push('}');
+    }
+    json.push(']');
+}
+
+fn write_pairs(pairs: &[(String, String)], json: &mut String) {
+    json.push('[');
+    for (i, (name, value)) in pairs.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        let _ = write!(json, r#"{{"name":{},"value":{}}}"#, json_string(name), json_string(value));
+    }
+    json.push(']');
+}
+
+/// Formats `time` as an ISO 8601 date with milliseconds, as HAR requires.
+fn iso_date(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len() / 3 * 4 + 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let n = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_strings() {
+        assert_eq!(json_string(""), r#""""#);
+        assert_eq!(json_string("a\"b\\c"), r#""a\"b\\c""#);
+        assert_eq!(json_string("\n\r\t\u{1}\u{1f}"), r#""\n\r\t\u0001\u001f""#);
+        assert_eq!(json_string("é/\u{7f}"), "\"é/\u{7f}\"");
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+
+    #[test]
+    fn query_strings() {
+        let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect()
+        };
+        assert_eq!(query_string("https://example.com/"), pairs(&[]));
+        assert_eq!(query_string("https://example.com/?"), pairs(&[]));
+        assert_eq!(query_string("https://example.com/?a=1&b&&c=x=y"), pairs(&[("a", "1"), ("b", ""), ("c", "x=y")]));
+        assert_eq!(query_string("https://example.com/?q=a+b%26c%3D#d=e"), pairs(&[("q", "a b&c=")]));
+    }
+
+    #[test]
+    fn set_cookies() {
+        let cookie = parse_set_cookie("id=a3fWa; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Path=/; Secure; HttpOnly").unwrap();
+        assert_eq!((cookie.name.as_str(), cookie.value.as_str()), ("id", "a3fWa"));
+        assert_eq!(
+            cookie.attributes,
+            vec![
+                ("expires", "2015-10-21T07:28:00.000Z".to_string()),
+                ("path", "/".to_string()),
+                ("secure", String::new()),
+                ("httpOnly", String::new()),
+            ]
+        );
+        let cookie = parse_set_cookie("id=1; expires=Wed, 21-Oct-2015 07:28:00 GMT; domain=example.com").unwrap();
+        assert_eq!(
+            cookie.attributes,
+            vec![("expires", "2015-10-21T07:28:00.000Z".to_string()), ("domain", "example.com".to_string())]
+        );
+        let cookie = parse_set_cookie("id=1; Expires=Wed, 21-Oct-15 07:28:00 GMT; Max-Age=10").unwrap();
+        assert_eq!(cookie.attributes, vec![("expires", "2015-10-21T07:28:00.000Z".to_string())]);
+        assert!(parse_set_cookie("id=1; Expires=soon").unwrap().attributes.is_empty());
+        assert!(parse_set_cookie("=1; Path=/").is_none());
+    }
+
+    /// Feeds `chunks` through a filter recording at most `max_body_size` bytes,
+    /// like CEF does, and returns the filter and its output.
+    fn filter_chunks(chunks: &[&[u8]], max_body_size: usize) -> (RecordingFilter, Vec<u8>) {
+        let filter = RecordingFilter {
+            body: Arc::new(Mutex::new(Body::default())),
+            max_body_size,
+            output: Mutex::new(FilterOutput::default()),
+        };
+        let mut filtered = Vec::new();
+        let mut data_out = [0; 3];
+        let mut status = ResponseFilterStatus::NeedMoreData;
+        // CEF keeps calling with an empty input until the filter is done.
+        for chunk in chunks.iter().chain(&[&b""[..]; 10]) {
+            let (mut read, mut written) = (0, 0);
+            status = filter.filter(chunk, &mut read, &mut data_out, &mut written);
+            assert_eq!(read, chunk.len());
+            filtered.extend_from_slice(&data_out[..written]);
+            if status == ResponseFilterStatus::Done {
+                break;
+            }
+        }
+        assert_eq!(status, ResponseFilterStatus::Done);
+        (filter, filtered)
+    }
+
+    /// Returns the HAR content object of a text response with `body`.
+    fn content_json(body: Arc<Mutex<Body>>) -> String {
+        let entry = Entry {
+            page: None,
+            started: Utc::now(),
+            start: Instant::now(),
+            response_time: None,
+            end: None,
+            method: "GET".to_string(),
+            url: "https://example.com/".to_string(),
+            request_headers: Vec::new(),
+            post_data: None,
+            status: 200,
+            status_text: "OK".to_string(),
+            response_headers: Vec::new(),
+            mime_type: "text/plain".to_string(),
+            redirect_url: String::new(),
+            received_size: -1,
+            body: Some(body),
+            error: None,
+        };
+        let mut json = String::new();
+        entry.write_json(&[], &mut json);
+        let start = json.find(r#""content":"#).unwrap();
+        let end = start + json[start..].find('}').unwrap() + 1;
+        json[start..end].to_string()
+    }
+
+    #[test]
+    fn recording_filter() {
+        let (filter, filtered) = filter_chunks(&[b"hello ", b"world"], 16);
+        assert_eq!(filtered, b"hello world");
+        assert_eq!(filter.body.lock().data, b"hello world");
+        assert_eq!(
+            content_json(filter.body),
+            r#""content":{"size":11,"mimeType":"text/plain","text":"hello world"}"#
+        );
+    }
+
+    #[test]
+    fn recording_filter_over_max_body_size() {
+        // The body is still passed through, but not recorded.
+        let (filter, filtered) = filter_chunks(&[b"hello ", b"world, ", b"hello"], 16);
+        assert_eq!(filtered, b"hello world, hello");
+        assert_eq!(filter.body.lock().size, 18);
+        assert!(filter.body.lock().data.is_empty());
+        assert_eq!(
+            content_json(filter.body),
+            r#""content":{"size":18,"mimeType":"text/plain","comment":"body not recorded"}"#
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//! Windowless browsers for server-side rendering and tests.
+//!
+//! A [HeadlessView] is a ready-made [RenderHandler] with a fixed-size view that
+//! keeps the latest painted frame as an RGBA buffer. [HeadlessBrowser] creates a
+//! browser rendering into one:
+//!
+//! ```ignore
+//! let settings = headless::settings();
+//! // Add headless::append_switches to App::on_before_command_line_processing.
+//! let context = Context::initialize(settings, Some(app), None)?;
+//!
+//! // The browser is created and painted by the message loop, so the future must
+//! // be driven by it rather than blocked on.
+//! TaskRunner::spawn_on(ThreadId::UI, async {
+//!     let options = HeadlessOptions::default();
+//!     if let Some(browser) = HeadlessBrowser::create("https://example.com", &options).await {
+//!         browser.view().next_frame().await;
+//!         std::fs::write("page.png", browser.screenshot_png().unwrap()).unwrap();
+//!     }
+//!     cef::quit_message_loop().unwrap();
+//! });
+//! context.run_message_loop();
+//! ```
+//!
+//! [RenderHandler]: crate::client::render_handler::RenderHandler
+
+use crate::{
+    browser::{Browser, BrowserSettings, State},
+    browser_host::{BrowserHost, PaintElementType},
+    client::{
+        render_handler::{CursorHandle, CursorType, RenderHandler, RenderHandlerCallbacks, ScreenInfo},
+        Client, ClientCallbacks,
+    },
+    color::Color,
+    command_line::CommandLine,
+    compositor::Compositor,
+    drag::DragOperation,
+    image::{AlphaType, ColorType, Image},
+    oneshot, pixels,
+    settings::Settings,
+    values::{Point, Rect},
+    window::WindowInfo,
+};
+use parking_lot::Mutex;
+use std::{future::Future, os::raw::c_void, sync::Arc};
+
+/// Returns [Settings] with windowless rendering enabled. Combine them with
+/// [append_switches] on machines without a GPU.
+pub fn settings() -> Settings {
+    Settings::new().windowless_rendering_enabled(true)
+}
+
+/// Appends the command line switches that make CEF render in software, for
+/// machines without a GPU. Call this from
+/// [AppCallbacks::on_before_command_line_processing] for the browser process.
+///
+/// [AppCallbacks::on_before_command_line_processing]: crate::app::AppCallbacks::on_before_command_line_processing
+pub fn append_switches(command_line: &CommandLine) {
+    for switch in &["disable-gpu", "disable-gpu-compositing", "disable-gpu-vsync"] {
+        command_line.append_switch(switch);
+    }
+}
+
+/// How a headless browser renders.
+#[derive(Debug, Clone)]
+pub struct HeadlessOptions {
+    /// The view width in logical pixels.
+    pub width: i32,
+    /// The view height in logical pixels.
+    pub height: i32,
+    /// The ratio between physical and logical pixels. Frames are
+    /// `width * device_scale_factor` pixels wide.
+    pub device_scale_factor: f32,
+    /// The maximum rate at which frames are painted.
+    pub frame_rate: i32,
+    /// The color painted behind transparent pages. A transparent color keeps the
+    /// page transparent.
+    pub background_color: Color,
+    /// Only paint when a begin frame is sent, e.g. by a
+    /// [FrameScheduler](crate::frame_scheduler::FrameScheduler).
+    pub external_begin_frame: bool,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> HeadlessOptions {
+        HeadlessOptions {
+            width: 1280,
+            height: 720,
+            device_scale_factor: 1.0,
+            frame_rate: 30,
+            background_color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+            external_begin_frame: false,
+        }
+    }
+}
+
+impl HeadlessOptions {
+    /// Returns [WindowInfo] for a windowless browser.
+    pub fn window_info(&self) -> WindowInfo {
+        WindowInfo {
+            width: self.width,
+            height: self.height,
+            windowless_rendering_enabled: true,
+            external_begin_frame_enabled: self.external_begin_frame,
+            ..WindowInfo::new()
+        }
+    }
+    /// Returns [BrowserSettings] for a windowless browser, with WebGL disabled
+    /// since it needs a GPU.
+    pub fn browser_settings(&self) -> BrowserSettings {
+        BrowserSettings {
+            windowless_frame_rate: self.frame_rate,
+            webgl: State::Disabled,
+            background_color: self.background_color,
+            ..BrowserSettings::new()
+        }
+    }
+}
+
+/// A frame painted by a headless browser.
+#[derive(Debug, Clone, Default)]
+pub struct HeadlessFrame {
+    /// The width in physical pixels.
+    pub width: i32,
+    /// The height in physical pixels.
+    pub height: i32,
+    /// The pixels as premultiplied RGBA, row by row from the top-left corner.
+    pub data: Vec<u8>,
+    /// Counts the frames painted by the view, starting at 1.
+    pub number: u64,
+}
+
+impl HeadlessFrame {
+    /// Create an [Image] of the frame. Must be called on the browser process UI
+    /// thread.
+    pub fn to_image(&self, scale_factor: f32) -> Option<Image> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let image = Image::new();
+        let added = image.add_bitmap(
+            scale_factor,
+            self.width,
+            self.height,
+            ColorType::Rgba8888,
+            AlphaType::Premultiplied,
+            &self.data,
+        );
+        if added {
+            Some(image)
+        } else {
+            None
+        }
+    }
+}
+
+struct ViewState {
+    width: i32,
+    height: i32,
+    device_scale_factor: f32,
+    compositor: Compositor,
+    frame: HeadlessFrame,
+    waiting: Vec<oneshot::Sender<HeadlessFrame>>,
+}
+
+/// A render handler with a fixed-size view that keeps the latest painted frame.
+/// Return [render_handler](HeadlessView::render_handler) from
+/// [ClientCallbacks::get_render_handler] of a windowless browser. Cloning it
+/// returns another handle to the same view.
+#[derive(Clone)]
+pub struct HeadlessView(Arc<Mutex<ViewState>>);
+
+impl HeadlessView {
+    /// Create a view of `width` x `height` logical pixels.
+    pub fn new(width: i32, height: i32, device_scale_factor: f32) -> HeadlessView {
+        HeadlessView(Arc::new(Mutex::new(ViewState {
+            width,
+            height,
+            device_scale_factor,
+            compositor: {
+                let mut compositor = Compositor::new();
+                compositor.set_device_scale_factor(device_scale_factor);
+                compositor
+            },
+            frame: HeadlessFrame::default(),
+            waiting: Vec::new(),
+        })))
+    }
+    /// Returns a [RenderHandler] painting into this view.
+    pub fn render_handler(&self) -> RenderHandler {
+        RenderHandler::new(self.clone())
+    }
+    /// Change the view size. Call [BrowserHost::was_resized] afterwards to repaint
+    /// the browser.
+    pub fn set_size(&self, width: i32, height: i32) {
+        let mut state = self.0.lock();
+        state.width = width;
+        state.height = height;
+    }
+    /// Returns the view size in logical pixels.
+    pub fn size(&self) -> (i32, i32) {
+        let state = self.0.lock();
+        (state.width, state.height)
+    }
+    /// Returns the ratio between physical and logical pixels.
+    pub fn device_scale_factor(&self) -> f32 {
+        self.0.lock().device_scale_factor
+    }
+    /// Returns the number of frames painted so far.
+    pub fn frame_count(&self) -> u64 {
+        self.0.lock().frame.number
+    }
+    /// Returns a copy of the latest frame, or None if nothing has been painted yet.
+    pub fn latest_frame(&self) -> Option<HeadlessFrame> {
+        let state = self.0.lock();
+        if state.frame.number == 0 {
+            None
+        } else {
+            Some(state.frame.clone())
+        }
+    }
+    /// Returns a future that resolves to the next frame painted into this view. It
+    /// resolves to None if the view is destroyed before another frame is painted.
+    pub fn next_frame(&self) -> impl Future<Output = Option<HeadlessFrame>> {
+        let (sender, receiver) = oneshot::channel();
+        self.0.lock().waiting.push(sender);
+        async move { receiver.await.ok() }
+    }
+    /// Create an [Image] of the latest frame. Must be called on the browser process
+    /// UI thread.
+    pub fn screenshot(&self) -> Option<Image> {
+        let scale_factor = self.device_scale_factor();
+        self.latest_frame()?.to_image(scale_factor)
+    }
+    /// Encode the latest frame as PNG. Must be called on the browser process UI
+    /// thread.
+    pub fn screenshot_png(&self) -> Option<Vec<u8>> {
+        let scale_factor = self.device_scale_factor();
+        let image = self.screenshot()?;
+        image
+            .get_as_png(scale_factor, true)
+            .map(|png| png.data)
+    }
+}
+
+impl RenderHandlerCallbacks for HeadlessView {
+    fn get_view_rect(&self, _browser: Browser) -> Rect {
+        let (width, height) = self.size();
+        Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+    fn get_screen_point(&self, _browser: Browser, view_point: Point) -> Option<Point> {
+        Some(view_point)
+    }
+    fn get_screen_info(&self, browser: Browser) -> Option<ScreenInfo> {
+        let rect = self.get_view_rect(browser);
+        Some(ScreenInfo {
+            device_scale_factor: self.device_scale_factor(),
+            depth: 32,
+            depth_per_component: 8,
+            is_monochrome: false,
+            rect,
+            available_rect: rect,
+        })
+    }
+    fn on_popup_show(&self, _browser: Browser, show: bool) {
+        self.0.lock().compositor.on_popup_show(show);
+    }
+    fn on_popup_size(&self, _browser: Browser, rect: Rect) {
+        self.0.lock().compositor.on_popup_size(rect);
+    }
+    fn on_paint(
+        &self,
+        _browser: Browser,
+        type_: PaintElementType,
+        dirty_rects: &[Rect],
+        buffer: &[u8],
+        width: i32,
+        height: i32,
+    ) {
+        let (frame, waiting) = {
+            let mut state = self.0.lock();
+            let ViewState {
+                compositor, frame, ..
+            } = &mut *state;
+            compositor.on_paint(type_, dirty_rects, buffer, width, height);
+            let damage = compositor.take_damage();
+            if damage.is_empty() {
+                return;
+            }
+            let (width, height) = (compositor.width(), compositor.height());
+            if frame.width != width || frame.height != height {
+                frame.width = width;
+                frame.height = height;
+                frame.data = vec![0; width as usize * height as usize * 4];
+            }
+            pixels::bgra_to_rgba(compositor.buffer(), &mut frame.data, width, &damage);
+            frame.number += 1;
+            if state.waiting.is_empty() {
+                return;
+            }
+            (state.frame.clone(), std::mem::replace(&mut state.waiting, Vec::new()))
+        };
+        for sender in waiting {
+            sender.send(frame.clone());
+        }
+    }
+    fn on_accelerated_paint(
+        &self,
+        _browser: Browser,
+        _type_: PaintElementType,
+        _dirty_rects: &[Rect],
+        _shared_handle: *mut c_void,
+    ) {
+    }
+    fn on_cursor_change(&self, _browser: Browser, _cursor: CursorHandle, _type_: CursorType<'_>) {}
+    fn update_drag_cursor(&self, _browser: Browser, _operation: DragOperation) {}
+}
+
+struct HeadlessClient(HeadlessView);
+
+impl ClientCallbacks for HeadlessClient {
+    fn get_render_handler(&self) -> Option<RenderHandler> {
+        Some(self.0.render_handler())
+    }
+}
+
+/// A windowless browser rendering into a [HeadlessView].
+#[derive(Clone)]
+pub struct HeadlessBrowser {
+    browser: Browser,
+    view: HeadlessView,
+}
+
+impl HeadlessBrowser {
+    /// Create a headless browser loading `url`. Like
+    /// [BrowserHost::create_browser], this can be called on any browser process
+    /// thread, and the future resolves once the browser has been created.
+    pub fn create(url: &str, options: &HeadlessOptions) -> impl Future<Output = Option<HeadlessBrowser>> {
+        let view = HeadlessView::new(options.width, options.height, options.device_scale_factor);
+        let client = Client::new(HeadlessClient(view.clone()));
+        Self::create_with_client(url, options, client, view)
+    }
+    /// Create a headless browser with a custom client, whose
+    /// [ClientCallbacks::get_render_handler] must return
+    /// [view.render_handler()](HeadlessView::render_handler).
+    pub fn create_with_client(
+        url: &str,
+        options: &HeadlessOptions,
+        client: Client,
+        view: HeadlessView,
+    ) -> impl Future<Output = Option<HeadlessBrowser>> {
+        let created = BrowserHost::create_browser(
+            &options.window_info(),
+            client,
+            url,
+            &options.browser_settings(),
+            None,
+            None,
+        );
+        async move {
+            let browser = created.await?;
+            Some(HeadlessBrowser { browser, view })
+        }
+    }
+    /// Returns the browser.
+    pub fn browser(&self) -> &Browser {
+        &self.browser
+    }
+    /// Returns the view the browser renders into.
+    pub fn view(&self) -> &HeadlessView {
+        &self.view
+    }
+    /// Resize the view and repaint the browser.
+    pub fn resize(&self, width: i32, height: i32) {
+        self.view.set_size(width, height);
+        self.browser.get_host().was_resized();
+    }
+    /// Create an [Image] of the latest frame. See [HeadlessView::screenshot].
+    pub fn screenshot(&self) -> Option<Image> {
+        self.view.screenshot()
+    }
+    /// Encode the latest frame as PNG. See [HeadlessView::screenshot_png].
+    pub fn screenshot_png(&self) -> Option<Vec<u8>> {
+        self.view.screenshot_png()
+    }
+    /// Close the browser.
+    pub fn close(&self) {
+        self.browser.get_host().close_browser(true);
+    }
+}
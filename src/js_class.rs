@@ -0,0 +1,333 @@
+//! Runtime support for exposing Rust objects to JavaScript.
+//!
+//! The [js_class](crate::js_class) attribute implements [JsFields] when placed on
+//! a struct and [JsMethods] when placed on one of its `impl` blocks; together they
+//! make the type a [JsClass]:
+//!
+//! ```ignore
+//! #[cef::js_class]
+//! pub struct Counter {
+//!     pub count: i32,
+//!     #[js(readonly)]
+//!     pub label: String,
+//!     #[js(skip)]
+//!     pub secret: u64,
+//! }
+//!
+//! #[cef::js_class]
+//! impl Counter {
+//!     pub fn increment(&mut self, by: i32) -> i32 {
+//!         self.count += by;
+//!         self.count
+//!     }
+//!     #[js(name = "reset")]
+//!     pub fn reset_to(&mut self, value: Option<i32>) -> Result<(), String> {
+//!         self.count = value.unwrap_or(0);
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
+//! Public fields and public methods taking `&self` or `&mut self` are exposed;
+//! arguments, return values and fields are converted with
+//! [to_v8_value](crate::v8context::to_v8_value) and
+//! [from_v8_value](crate::v8context::from_v8_value), so their types must
+//! implement `Serialize` and `Deserialize`. Methods returning `Result` throw the error's `Display` output.
+//!
+//! [new_js_object] creates a V8 object for an instance, and
+//! [typescript_declaration] generates the matching `.d.ts` interface. The
+//! declarations of several classes can simply be concatenated into one file.
+//!
+//! The attribute doesn't write that file itself, as it only sees one item at a
+//! time. A test in the crate defining the classes can keep it up to date:
+//!
+//! ```ignore
+//! #[test]
+//! fn write_typescript_declarations() {
+//!     let declarations = cef::js_class::typescript_declaration::<Counter>()
+//!         + &cef::js_class::typescript_declaration::<Settings>();
+//!     let path = concat!(env!("CARGO_MANIFEST_DIR"), "/web/bindings.d.ts");
+//!     std::fs::write(path, declarations).unwrap();
+//! }
+//! ```
+//!
+//! Tuple struct fields have no names to expose, so making one public without
+//! `#[js(skip)]` is a compile error.
+
+use crate::v8context::{
+    from_v8_value, V8AccessControl, V8Accessor, V8AccessorCallbacks, V8PropertyAttribute, V8Value,
+};
+use crate::values::SerdeError;
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
+use std::{any::Any, fmt::Display, sync::Arc};
+
+/// A field exposed to JavaScript as an accessor property.
+pub struct JsField<T> {
+    /// The property name.
+    pub name: &'static str,
+    /// The TypeScript type of the property.
+    pub ts_type: &'static str,
+    /// Reads the field.
+    pub get: fn(&T) -> Result<V8Value, SerdeError>,
+    /// Writes the field, or None if it is read-only.
+    pub set: Option<JsFieldSetter<T>>,
+}
+
+/// Writes a [JsField].
+pub type JsFieldSetter<T> = fn(&mut T, &V8Value) -> Result<(), SerdeError>;
+
+/// A method exposed to JavaScript as a function property.
+pub struct JsMethod<T> {
+    /// The property name.
+    pub name: &'static str,
+    /// Names and TypeScript types of the parameters.
+    pub params: &'static [(&'static str, &'static str)],
+    /// The TypeScript return type.
+    pub ts_return: &'static str,
+    /// Converts the arguments, calls the method and converts its result.
+    pub call: fn(&mut T, &[V8Value]) -> Result<V8Value, String>,
+}
+
+/// The fields of a type exposed to JavaScript. Implemented by the `js_class`
+/// attribute on a struct.
+pub trait JsFields: Any + Send + Sized {
+    /// The name of the generated TypeScript interface.
+    const CLASS_NAME: &'static str;
+    fn js_fields() -> Vec<JsField<Self>>;
+}
+
+/// The methods of a type exposed to JavaScript. Implemented by the `js_class`
+/// attribute on an `impl` block.
+pub trait JsMethods: Sized {
+    fn js_methods() -> Vec<JsMethod<Self>>;
+}
+
+/// A type that can be exposed to JavaScript with [new_js_object].
+pub trait JsClass: JsFields + JsMethods {}
+
+impl<T: JsFields + JsMethods> JsClass for T {}
+
+/// The user data attached to objects created by [new_js_object].
+struct JsInstance<T>(Arc<Mutex<T>>);
+
+/// Converts the argument at `index` of a call to `method`, treating a missing
+/// argument as `undefined`. Called by the code the `js_class` attribute
+/// generates.
+#[doc(hidden)]
+pub fn convert_argument<T: DeserializeOwned>(args: &[V8Value], index: usize, method: &str, param: &str) -> Result<T, String> {
+    let converted = match args.get(index) {
+        Some(arg) => from_v8_value(arg),
+        None => from_v8_value(&V8Value::undefined()),
+    };
+    converted.map_err(|error| argument_error(method, param, error))
+}
+
+/// The message thrown when an argument can't be converted.
+fn argument_error(method: &str, param: &str, error: impl Display) -> String {
+    format!("{}: argument `{}`: {}", method, param, error)
+}
+
+fn borrow_error<T: JsClass>() -> String {
+    format!("{} is already in use by a call further up the stack", T::CLASS_NAME)
+}
+
+struct FieldAccessor<T: JsClass> {
+    instance: Arc<Mutex<T>>,
+    fields: Vec<JsField<T>>,
+}
+
+impl<T: JsClass> V8AccessorCallbacks for FieldAccessor<T> {
+    fn get(&self, name: &str, _object: &V8Value) -> Result<V8Value, String> {
+        let field = self
+            .fields
+            .iter()
+            .find(|field| field.name == name)
+            .ok_or_else(|| format!("{} has no property {}", T::CLASS_NAME, name))?;
+        let instance = self.instance.try_lock().ok_or_else(borrow_error::<T>)?;
+        (field.get)(&instance).map_err(|error| error.to_string())
+    }
+    fn set(&self, name: &str, _object: &V8Value, value: &V8Value) -> Result<(), String> {
+        let set = self
+            .fields
+            .iter()
+            .find(|field| field.name == name)
+            .and_then(|field| field.set)
+            .ok_or_else(|| format!("{}.{} is read-only", T::CLASS_NAME, name))?;
+        let mut instance = self.instance.try_lock().ok_or_else(borrow_error::<T>)?;
+        set(&mut instance, value).map_err(|error| format!("{}.{}: {}", T::CLASS_NAME, name, error))
+    }
+}
+
+/// Create a V8 object exposing the fields and methods of `value`. Returns the
+/// object and a shared handle to the instance, which is also reachable through
+/// [js_object_instance]. This function should only be called from within the scope
+/// of a [RenderProcessHandlerCallbacks], [V8Handler] or [V8AccessorCallbacks]
+/// callback, or in combination with calling [V8Context::enter] and
+/// [V8Context::exit] on a stored [V8Context] reference.
+pub fn new_js_object<T: JsClass>(value: T) -> (V8Value, Arc<Mutex<T>>) {
+    let instance = Arc::new(Mutex::new(value));
+    let fields = T::js_fields();
+    let field_names: Vec<(&'static str, bool)> = fields
+        .iter()
+        .map(|field| (field.name, field.set.is_none()))
+        .collect();
+
+    let accessor = V8Accessor::new(FieldAccessor {
+        instance: instance.clone(),
+        fields,
+    });
+    let object = V8Value::new_object(Some(accessor), None);
+    object.set_user_data(JsInstance(instance.clone()));
+
+    for (name, readonly) in field_names {
+        let attributes = if readonly {
+            V8PropertyAttribute::READ_ONLY | V8PropertyAttribute::DONT_DELETE
+        } else {
+            V8PropertyAttribute::DONT_DELETE
+        };
+        object.set_value_byaccessor(name, V8AccessControl::empty(), attributes);
+    }
+
+    for method in T::js_methods() {
+        let this = instance.clone();
+        let call = method.call;
+        let function = V8Value::new_function(method.name, move |_, _, args| {
+            let mut this = this.try_lock().ok_or_else(borrow_error::<T>)?;
+            call(&mut this, args)
+        });
+        object.set_value_bykey(
+            method.name,
+            function,
+            V8PropertyAttribute::READ_ONLY | V8PropertyAttribute::DONT_ENUM | V8PropertyAttribute::DONT_DELETE,
+        );
+    }
+
+    (object, instance)
+}
+
+/// Returns the instance behind an object created by [new_js_object], or None if
+/// `object` wasn't created for a `T`.
+pub fn js_object_instance<T: JsClass>(object: &V8Value) -> Option<Arc<Mutex<T>>> {
+    let user_data = object.get_user_data()?;
+    user_data
+        .downcast_ref::<JsInstance<T>>()
+        .map(|instance| instance.0.clone())
+}
+
+/// Generate a TypeScript interface declaration for `T`.
+pub fn typescript_declaration<T: JsClass>() -> String {
+    let mut declaration = format!("interface {} {{\n", T::CLASS_NAME);
+    for field in T::js_fields() {
+        let readonly = if field.set.is_none() { "readonly " } else { "" };
+        declaration += &format!("    {}{}: {};\n", readonly, field.name, field.ts_type);
+    }
+    for method in T::js_methods() {
+        let params: Vec<String> = method
+            .params
+            .iter()
+            .map(|(name, ts_type)| format!("{}: {}", name, ts_type))
+            .collect();
+        declaration += &format!(
+            "    {}({}): {};\n",
+            method.name,
+            params.join(", "),
+            method.ts_return
+        );
+    }
+    declaration += "}\n";
+    declaration
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter {
+        count: i32,
+    }
+
+    impl JsFields for Counter {
+        const CLASS_NAME: &'static str = "Counter";
+        fn js_fields() -> Vec<JsField<Self>> {
+            vec![
+                JsField {
+                    name: "count",
+                    ts_type: "number",
+                    get: |_| unreachable!(),
+                    set: Some(|_, _| unreachable!()),
+                },
+                JsField {
+                    name: "tags",
+                    ts_type: "(string | null)[]",
+                    get: |_| unreachable!(),
+                    set: None,
+                },
+            ]
+        }
+    }
+
+    impl JsMethods for Counter {
+        fn js_methods() -> Vec<JsMethod<Self>> {
+            vec![
+                JsMethod {
+                    name: "increment",
+                    params: &[("by", "number")],
+                    ts_return: "number",
+                    call: |this, _| {
+                        this.count += 1;
+                        Err("not converted".to_string())
+                    },
+                },
+                JsMethod {
+                    name: "reset",
+                    params: &[("value", "number | null"), ("label", "string")],
+                    ts_return: "void",
+                    call: |_, _| unreachable!(),
+                },
+                JsMethod {
+                    name: "clear",
+                    params: &[],
+                    ts_return: "void",
+                    call: |_, _| unreachable!(),
+                },
+            ]
+        }
+    }
+
+    #[test]
+    fn typescript_declarations() {
+        assert_eq!(
+            typescript_declaration::<Counter>(),
+            "interface Counter {\n\
+            \x20   count: number;\n\
+            \x20   readonly tags: (string | null)[];\n\
+            \x20   increment(by: number): number;\n\
+            \x20   reset(value: number | null, label: string): void;\n\
+            \x20   clear(): void;\n\
+            }\n"
+        );
+    }
+
+    #[test]
+    fn argument_errors() {
+        assert_eq!(
+            argument_error("reset", "value", SerdeError::Message("invalid type: string, expected i32".to_string())),
+            "reset: argument `value`: invalid type: string, expected i32"
+        );
+        assert_eq!(argument_error("f", "x", SerdeError::CyclicValue), "f: argument `x`: value contains a cycle");
+    }
+
+    #[test]
+    fn method_errors_propagate() {
+        let mut counter = Counter { count: 0 };
+        let increment = &Counter::js_methods()[0];
+        assert_eq!((increment.call)(&mut counter, &[]).err(), Some("not converted".to_string()));
+        assert_eq!(counter.count, 1);
+    }
+
+    #[test]
+    fn borrow_errors_name_the_class() {
+        assert_eq!(borrow_error::<Counter>(), "Counter is already in use by a call further up the stack");
+    }
+}
@@ -0,0 +1,760 @@
+//! Keyboard code conversions for windowless browsers.
+//!
+//! CEF identifies keys by their [WindowsKeyCode] on every platform, together with
+//! a `native_key_code` whose meaning depends on the platform. This module converts
+//! between the Windows key codes and X11 keysyms, Linux evdev scancodes and USB HID
+//! usages, and builds the key events a physical key press produces in a windowed
+//! browser:
+//!
+//! ```ignore
+//! let key = PhysicalKey::from_evdev(30).unwrap(); // KEY_A
+//! let events = KeyEventBuilder::new(key)
+//!     .modifiers(EventFlags::SHIFT_DOWN)
+//!     .press();
+//! for event in events {
+//!     host.send_key_event(event);
+//! }
+//! ```
+//!
+//! The characters keys produce follow the US keyboard layout. Use
+//! [KeyEventBuilder::char] to supply the character of another layout.
+
+use crate::events::{EventFlags, KeyEvent, WindowsKeyCode as K};
+
+/// Marks keys without a macOS virtual key code.
+const NO_MAC: u16 = 0xFFFF;
+
+struct Key {
+    /// The HID usage page in the upper 16 bits and the usage in the lower 16 bits.
+    usb: u32,
+    evdev: u16,
+    /// The scan code set 1 make code, with 0xE0 in the upper byte for extended
+    /// keys, or 0.
+    windows_scancode: u16,
+    mac: u16,
+    key_code: K,
+    /// The unshifted and the shifted character on a US keyboard.
+    chars: Option<(char, char)>,
+}
+
+const fn key(usb: u32, evdev: u16, windows_scancode: u16, mac: u16, key_code: K, chars: Option<(char, char)>) -> Key {
+    Key {
+        usb,
+        evdev,
+        windows_scancode,
+        mac,
+        key_code,
+        chars,
+    }
+}
+
+/// The physical keys, in the order of their USB HID usages. Where several keys
+/// share a Windows key code, the first one is its canonical key.
+static KEYS: &[Key] = &[
+    key(0x07_0004, 30, 0x001E, 0x00, K::A, Some(('a', 'A'))),
+    key(0x07_0005, 48, 0x0030, 0x0B, K::B, Some(('b', 'B'))),
+    key(0x07_0006, 46, 0x002E, 0x08, K::C, Some(('c', 'C'))),
+    key(0x07_0007, 32, 0x0020, 0x02, K::D, Some(('d', 'D'))),
+    key(0x07_0008, 18, 0x0012, 0x0E, K::E, Some(('e', 'E'))),
+    key(0x07_0009, 33, 0x0021, 0x03, K::F, Some(('f', 'F'))),
+    key(0x07_000A, 34, 0x0022, 0x05, K::G, Some(('g', 'G'))),
+    key(0x07_000B, 35, 0x0023, 0x04, K::H, Some(('h', 'H'))),
+    key(0x07_000C, 23, 0x0017, 0x22, K::I, Some(('i', 'I'))),
+    key(0x07_000D, 36, 0x0024, 0x26, K::J, Some(('j', 'J'))),
+    key(0x07_000E, 37, 0x0025, 0x28, K::K, Some(('k', 'K'))),
+    key(0x07_000F, 38, 0x0026, 0x25, K::L, Some(('l', 'L'))),
+    key(0x07_0010, 50, 0x0032, 0x2E, K::M, Some(('m', 'M'))),
+    key(0x07_0011, 49, 0x0031, 0x2D, K::N, Some(('n', 'N'))),
+    key(0x07_0012, 24, 0x0018, 0x1F, K::O, Some(('o', 'O'))),
+    key(0x07_0013, 25, 0x0019, 0x23, K::P, Some(('p', 'P'))),
+    key(0x07_0014, 16, 0x0010, 0x0C, K::Q, Some(('q', 'Q'))),
+    key(0x07_0015, 19, 0x0013, 0x0F, K::R, Some(('r', 'R'))),
+    key(0x07_0016, 31, 0x001F, 0x01, K::S, Some(('s', 'S'))),
+    key(0x07_0017, 20, 0x0014, 0x11, K::T, Some(('t', 'T'))),
+    key(0x07_0018, 22, 0x0016, 0x20, K::U, Some(('u', 'U'))),
+    key(0x07_0019, 47, 0x002F, 0x09, K::V, Some(('v', 'V'))),
+    key(0x07_001A, 17, 0x0011, 0x0D, K::W, Some(('w', 'W'))),
+    key(0x07_001B, 45, 0x002D, 0x07, K::X, Some(('x', 'X'))),
+    key(0x07_001C, 21, 0x0015, 0x10, K::Y, Some(('y', 'Y'))),
+    key(0x07_001D, 44, 0x002C, 0x06, K::Z, Some(('z', 'Z'))),
+    key(0x07_001E, 2, 0x0002, 0x12, K::Key1, Some(('1', '!'))),
+    key(0x07_001F, 3, 0x0003, 0x13, K::Key2, Some(('2', '@'))),
+    key(0x07_0020, 4, 0x0004, 0x14, K::Key3, Some(('3', '#'))),
+    key(0x07_0021, 5, 0x0005, 0x15, K::Key4, Some(('4', '$'))),
+    key(0x07_0022, 6, 0x0006, 0x17, K::Key5, Some(('5', '%'))),
+    key(0x07_0023, 7, 0x0007, 0x16, K::Key6, Some(('6', '^'))),
+    key(0x07_0024, 8, 0x0008, 0x1A, K::Key7, Some(('7', '&'))),
+    key(0x07_0025, 9, 0x0009, 0x1C, K::Key8, Some(('8', '*'))),
+    key(0x07_0026, 10, 0x000A, 0x19, K::Key9, Some(('9', '('))),
+    key(0x07_0027, 11, 0x000B, 0x1D, K::Key0, Some(('0', ')'))),
+    key(0x07_0028, 28, 0x001C, 0x24, K::Return, Some(('\r', '\r'))),
+    key(0x07_0029, 1, 0x0001, 0x35, K::Escape, Some(('\u{1b}', '\u{1b}'))),
+    key(0x07_002A, 14, 0x000E, 0x33, K::Back, Some(('\u{8}', '\u{8}'))),
+    key(0x07_002B, 15, 0x000F, 0x30, K::Tab, Some(('\t', '\t'))),
+    key(0x07_002C, 57, 0x0039, 0x31, K::Space, Some((' ', ' '))),
+    key(0x07_002D, 12, 0x000C, 0x1B, K::OemMinus, Some(('-', '_'))),
+    key(0x07_002E, 13, 0x000D, 0x18, K::OemPlus, Some(('=', '+'))),
+    key(0x07_002F, 26, 0x001A, 0x21, K::Oem4, Some(('[', '{'))),
+    key(0x07_0030, 27, 0x001B, 0x1E, K::Oem6, Some((']', '}'))),
+    key(0x07_0031, 43, 0x002B, 0x2A, K::Oem5, Some(('\\', '|'))),
+    key(0x07_0033, 39, 0x0027, 0x29, K::Oem1, Some((';', ':'))),
+    key(0x07_0034, 40, 0x0028, 0x27, K::Oem7, Some(('\'', '"'))),
+    key(0x07_0035, 41, 0x0029, 0x32, K::Oem3, Some(('`', '~'))),
+    key(0x07_0036, 51, 0x0033, 0x2B, K::OemComma, Some((',', '<'))),
+    key(0x07_0037, 52, 0x0034, 0x2F, K::OemPeriod, Some(('.', '>'))),
+    key(0x07_0038, 53, 0x0035, 0x2C, K::Oem2, Some(('/', '?'))),
+    key(0x07_0039, 58, 0x003A, 0x39, K::Capital, None),
+    key(0x07_003A, 59, 0x003B, 0x7A, K::F1, None),
+    key(0x07_003B, 60, 0x003C, 0x78, K::F2, None),
+    key(0x07_003C, 61, 0x003D, 0x63, K::F3, None),
+    key(0x07_003D, 62, 0x003E, 0x76, K::F4, None),
+    key(0x07_003E, 63, 0x003F, 0x60, K::F5, None),
+    key(0x07_003F, 64, 0x0040, 0x61, K::F6, None),
+    key(0x07_0040, 65, 0x0041, 0x62, K::F7, None),
+    key(0x07_0041, 66, 0x0042, 0x64, K::F8, None),
+    key(0x07_0042, 67, 0x0043, 0x65, K::F9, None),
+    key(0x07_0043, 68, 0x0044, 0x6D, K::F10, None),
+    key(0x07_0044, 87, 0x0057, 0x67, K::F11, None),
+    key(0x07_0045, 88, 0x0058, 0x6F, K::F12, None),
+    key(0x07_0046, 99, 0xE037, NO_MAC, K::Snapshot, None),
+    key(0x07_0047, 70, 0x0046, NO_MAC, K::Scroll, None),
+    key(0x07_0048, 119, 0x0045, NO_MAC, K::Pause, None),
+    key(0x07_0049, 110, 0xE052, 0x72, K::Insert, None),
+    key(0x07_004A, 102, 0xE047, 0x73, K::Home, None),
+    key(0x07_004B, 104, 0xE049, 0x74, K::Prior, None),
+    key(0x07_004C, 111, 0xE053, 0x75, K::Delete, None),
+    key(0x07_004D, 107, 0xE04F, 0x77, K::End, None),
+    key(0x07_004E, 109, 0xE051, 0x79, K::Next, None),
+    key(0x07_004F, 106, 0xE04D, 0x7C, K::Right, None),
+    key(0x07_0050, 105, 0xE04B, 0x7B, K::Left, None),
+    key(0x07_0051, 108, 0xE050, 0x7D, K::Down, None),
+    key(0x07_0052, 103, 0xE048, 0x7E, K::Up, None),
+    key(0x07_0053, 69, 0xE045, 0x47, K::Numlock, None),
+    key(0x07_0054, 98, 0xE035, 0x4B, K::Divide, Some(('/', '/'))),
+    key(0x07_0055, 55, 0x0037, 0x43, K::Multiply, Some(('*', '*'))),
+    key(0x07_0056, 74, 0x004A, 0x4E, K::Subtract, Some(('-', '-'))),
+    key(0x07_0057, 78, 0x004E, 0x45, K::Add, Some(('+', '+'))),
+    key(0x07_0058, 96, 0xE01C, 0x4C, K::Return, Some(('\r', '\r'))),
+    key(0x07_0059, 79, 0x004F, 0x53, K::Numpad1, Some(('1', '1'))),
+    key(0x07_005A, 80, 0x0050, 0x54, K::Numpad2, Some(('2', '2'))),
+    key(0x07_005B, 81, 0x0051, 0x55, K::Numpad3, Some(('3', '3'))),
+    key(0x07_005C, 75, 0x004B, 0x56, K::Numpad4, Some(('4', '4'))),
+    key(0x07_005D, 76, 0x004C, 0x57, K::Numpad5, Some(('5', '5'))),
+    key(0x07_005E, 77, 0x004D, 0x58, K::Numpad6, Some(('6', '6'))),
+    key(0x07_005F, 71, 0x0047, 0x59, K::Numpad7, Some(('7', '7'))),
+    key(0x07_0060, 72, 0x0048, 0x5B, K::Numpad8, Some(('8', '8'))),
+    key(0x07_0061, 73, 0x0049, 0x5C, K::Numpad9, Some(('9', '9'))),
+    key(0x07_0062, 82, 0x0052, 0x52, K::Numpad0, Some(('0', '0'))),
+    key(0x07_0063, 83, 0x0053, 0x41, K::Decimal, Some(('.', '.'))),
+    key(0x07_0064, 86, 0x0056, 0x0A, K::Oem102, Some(('\\', '|'))),
+    key(0x07_0065, 127, 0xE05D, 0x6E, K::Apps, None),
+    key(0x07_0068, 183, 0x0064, 0x69, K::F13, None),
+    key(0x07_0069, 184, 0x0065, 0x6B, K::F14, None),
+    key(0x07_006A, 185, 0x0066, 0x71, K::F15, None),
+    key(0x07_006B, 186, 0x0067, 0x6A, K::F16, None),
+    key(0x07_006C, 187, 0x0068, 0x40, K::F17, None),
+    key(0x07_006D, 188, 0x0069, 0x4F, K::F18, None),
+    key(0x07_006E, 189, 0x006A, 0x50, K::F19, None),
+    key(0x07_006F, 190, 0x006B, 0x5A, K::F20, None),
+    key(0x07_0070, 191, 0x006C, NO_MAC, K::F21, None),
+    key(0x07_0071, 192, 0x006D, NO_MAC, K::F22, None),
+    key(0x07_0072, 193, 0x006E, NO_MAC, K::F23, None),
+    key(0x07_0073, 194, 0x0076, NO_MAC, K::F24, None),
+    key(0x07_0075, 138, 0xE03B, NO_MAC, K::Help, None),
+    key(0x07_007F, 113, 0xE020, 0x4A, K::VolumeMute, None),
+    key(0x07_0080, 115, 0xE030, 0x48, K::VolumeUp, None),
+    key(0x07_0081, 114, 0xE02E, 0x49, K::VolumeDown, None),
+    key(0x07_0085, 121, 0x007E, 0x5F, K::Separator, Some((',', ','))),
+    key(0x07_0088, 93, 0x0070, NO_MAC, K::Kana, None),
+    key(0x07_008A, 92, 0x0079, NO_MAC, K::Convert, None),
+    key(0x07_008B, 94, 0x007B, NO_MAC, K::NonConvert, None),
+    key(0x07_0091, 123, 0x0071, 0x66, K::Hanja, None),
+    key(0x07_00E0, 29, 0x001D, 0x3B, K::Control, None),
+    key(0x07_00E1, 42, 0x002A, 0x38, K::Shift, None),
+    key(0x07_00E2, 56, 0x0038, 0x3A, K::Menu, None),
+    key(0x07_00E3, 125, 0xE05B, 0x37, K::LWin, None),
+    key(0x07_00E4, 97, 0xE01D, 0x3E, K::Control, None),
+    key(0x07_00E5, 54, 0x0036, 0x3C, K::Shift, None),
+    key(0x07_00E6, 100, 0xE038, 0x3D, K::Menu, None),
+    key(0x07_00E7, 126, 0xE05C, 0x36, K::RWin, None),
+    key(0x01_0082, 142, 0xE05F, NO_MAC, K::Sleep, None),
+    key(0x0C_00B5, 163, 0xE019, NO_MAC, K::MediaNextTrack, None),
+    key(0x0C_00B6, 165, 0xE010, NO_MAC, K::MediaPrevTrack, None),
+    key(0x0C_00B7, 166, 0xE024, NO_MAC, K::MediaStop, None),
+    key(0x0C_00CD, 164, 0xE022, NO_MAC, K::MediaPlayPause, None),
+    key(0x0C_0183, 171, 0xE06D, NO_MAC, K::LaunchMediaSelect, None),
+    key(0x0C_018A, 155, 0xE06C, NO_MAC, K::LaunchMail, None),
+    key(0x0C_0192, 140, 0xE021, NO_MAC, K::LaunchApp2, None),
+    key(0x0C_0194, 144, 0xE06B, NO_MAC, K::LaunchApp1, None),
+    key(0x0C_0221, 217, 0xE065, NO_MAC, K::BrowserSearch, None),
+    key(0x0C_0223, 172, 0xE032, NO_MAC, K::BrowserHome, None),
+    key(0x0C_0224, 158, 0xE06A, NO_MAC, K::BrowserBack, None),
+    key(0x0C_0225, 159, 0xE069, NO_MAC, K::BrowserForward, None),
+    key(0x0C_0226, 128, 0xE068, NO_MAC, K::BrowserStop, None),
+    key(0x0C_0227, 173, 0xE067, NO_MAC, K::BrowserRefresh, None),
+    key(0x0C_022A, 156, 0xE066, NO_MAC, K::BrowserFavorites, None),
+];
+
+/// X11 keysyms and their key codes, besides letters, digits, function keys and
+/// keypad digits, which are mapped by range. Where several keysyms share a key
+/// code, the first one is the one [WindowsKeyCode::to_x11_keysym] returns.
+///
+/// [WindowsKeyCode::to_x11_keysym]: crate::events::WindowsKeyCode::to_x11_keysym
+static KEYSYMS: &[(u32, K)] = &[
+    (0xFF08, K::Back),           // BackSpace
+    (0xFF09, K::Tab),            // Tab
+    (0xFE20, K::Tab),            // ISO_Left_Tab
+    (0xFF0B, K::Clear),          // Clear
+    (0xFF0D, K::Return),         // Return
+    (0xFF8D, K::Return),         // KP_Enter
+    (0xFF13, K::Pause),          // Pause
+    (0xFF14, K::Scroll),         // Scroll_Lock
+    (0xFF1B, K::Escape),         // Escape
+    (0xFFFF, K::Delete),         // Delete
+    (0xFF50, K::Home),           // Home
+    (0xFF51, K::Left),           // Left
+    (0xFF52, K::Up),             // Up
+    (0xFF53, K::Right),          // Right
+    (0xFF54, K::Down),           // Down
+    (0xFF55, K::Prior),          // Prior
+    (0xFF56, K::Next),           // Next
+    (0xFF57, K::End),            // End
+    (0xFF60, K::Select),         // Select
+    (0xFF61, K::Snapshot),       // Print
+    (0xFF62, K::Execute),        // Execute
+    (0xFF63, K::Insert),         // Insert
+    (0xFF67, K::Apps),           // Menu
+    (0xFF6A, K::Help),           // Help
+    (0xFF7F, K::Numlock),        // Num_Lock
+    (0xFF95, K::Home),           // KP_Home
+    (0xFF96, K::Left),           // KP_Left
+    (0xFF97, K::Up),             // KP_Up
+    (0xFF98, K::Right),          // KP_Right
+    (0xFF99, K::Down),           // KP_Down
+    (0xFF9A, K::Prior),          // KP_Prior
+    (0xFF9B, K::Next),           // KP_Next
+    (0xFF9C, K::End),            // KP_End
+    (0xFF9D, K::Clear),          // KP_Begin
+    (0xFF9E, K::Insert),         // KP_Insert
+    (0xFF9F, K::Delete),         // KP_Delete
+    (0xFFAA, K::Multiply),       // KP_Multiply
+    (0xFFAB, K::Add),            // KP_Add
+    (0xFFAC, K::Separator),      // KP_Separator
+    (0xFFAD, K::Subtract),       // KP_Subtract
+    (0xFFAE, K::Decimal),        // KP_Decimal
+    (0xFFAF, K::Divide),         // KP_Divide
+    (0xFFE1, K::Shift),          // Shift_L
+    (0xFFE2, K::Shift),          // Shift_R
+    (0xFFE3, K::Control),        // Control_L
+    (0xFFE4, K::Control),        // Control_R
+    (0xFFE5, K::Capital),        // Caps_Lock
+    (0xFFE9, K::Menu),           // Alt_L
+    (0xFFEA, K::Menu),           // Alt_R
+    (0xFFE7, K::Menu),           // Meta_L
+    (0xFFE8, K::Menu),           // Meta_R
+    (0xFFEB, K::LWin),           // Super_L
+    (0xFFEC, K::RWin),           // Super_R
+    (0xFE03, K::OemAx),          // ISO_Level3_Shift (AltGr)
+    (0xFF21, K::Kanji),          // Kanji
+    (0xFF22, K::NonConvert),     // Muhenkan
+    (0xFF23, K::Convert),        // Henkan
+    (0xFF2D, K::Kana),           // Kana_Lock
+    (0xFF31, K::Hangul),         // Hangul
+    (0xFF34, K::Hanja),          // Hangul_Hanja
+    (0x0020, K::Space),          // space
+    (0x0021, K::Key1),           // exclam
+    (0x0040, K::Key2),           // at
+    (0x0023, K::Key3),           // numbersign
+    (0x0024, K::Key4),           // dollar
+    (0x0025, K::Key5),           // percent
+    (0x005E, K::Key6),           // asciicircum
+    (0x0026, K::Key7),           // ampersand
+    (0x002A, K::Key8),           // asterisk
+    (0x0028, K::Key9),           // parenleft
+    (0x0029, K::Key0),           // parenright
+    (0x003D, K::OemPlus),        // equal
+    (0x002B, K::OemPlus),        // plus
+    (0x002C, K::OemComma),       // comma
+    (0x003C, K::OemComma),       // less
+    (0x002D, K::OemMinus),       // minus
+    (0x005F, K::OemMinus),       // underscore
+    (0x002E, K::OemPeriod),      // period
+    (0x003E, K::OemPeriod),      // greater
+    (0x003B, K::Oem1),           // semicolon
+    (0x003A, K::Oem1),           // colon
+    (0x002F, K::Oem2),           // slash
+    (0x003F, K::Oem2),           // question
+    (0x0060, K::Oem3),           // grave
+    (0x007E, K::Oem3),           // asciitilde
+    (0x005B, K::Oem4),           // bracketleft
+    (0x007B, K::Oem4),           // braceleft
+    (0x005C, K::Oem5),           // backslash
+    (0x007C, K::Oem5),           // bar
+    (0x005D, K::Oem6),           // bracketright
+    (0x007D, K::Oem6),           // braceright
+    (0x0027, K::Oem7),           // apostrophe
+    (0x0022, K::Oem7),           // quotedbl
+    (0x1008_FF26, K::BrowserBack),       // XF86Back
+    (0x1008_FF27, K::BrowserForward),    // XF86Forward
+    (0x1008_FF29, K::BrowserRefresh),    // XF86Refresh
+    (0x1008_FF28, K::BrowserStop),       // XF86Stop
+    (0x1008_FF1B, K::BrowserSearch),     // XF86Search
+    (0x1008_FF30, K::BrowserFavorites),  // XF86Favorites
+    (0x1008_FF18, K::BrowserHome),       // XF86HomePage
+    (0x1008_FF12, K::VolumeMute),        // XF86AudioMute
+    (0x1008_FF11, K::VolumeDown),        // XF86AudioLowerVolume
+    (0x1008_FF13, K::VolumeUp),          // XF86AudioRaiseVolume
+    (0x1008_FF17, K::MediaNextTrack),    // XF86AudioNext
+    (0x1008_FF16, K::MediaPrevTrack),    // XF86AudioPrev
+    (0x1008_FF15, K::MediaStop),         // XF86AudioStop
+    (0x1008_FF14, K::MediaPlayPause),    // XF86AudioPlay
+    (0x1008_FF19, K::LaunchMail),        // XF86Mail
+    (0x1008_FF32, K::LaunchMediaSelect), // XF86AudioMedia
+    (0x1008_FF33, K::LaunchApp1),        // XF86MyComputer
+    (0x1008_FF1D, K::LaunchApp2),        // XF86Calculator
+    (0x1008_FF2F, K::Sleep),             // XF86Sleep
+];
+
+const XK_F1: u32 = 0xFFBE;
+const XK_F24: u32 = 0xFFD5;
+const XK_KP_0: u32 = 0xFFB0;
+const XK_KP_9: u32 = 0xFFB9;
+
+impl K {
+    /// Returns the key code of the X11 `keysym`, if it has one. Both cases of a
+    /// letter map to the letter's key code.
+    pub fn from_x11_keysym(keysym: u32) -> Option<K> {
+        match keysym {
+            0x41..=0x5A => Some(K(keysym as i32)),
+            0x61..=0x7A => Some(K(keysym as i32 - 0x20)),
+            0x30..=0x39 => Some(K(keysym as i32)),
+            XK_F1..=XK_F24 => Some(K(K::F1.0 + (keysym - XK_F1) as i32)),
+            XK_KP_0..=XK_KP_9 => Some(K(K::Numpad0.0 + (keysym - XK_KP_0) as i32)),
+            _ => KEYSYMS
+                .iter()
+                .find(|(sym, _)| *sym == keysym)
+                .map(|(_, key_code)| *key_code),
+        }
+    }
+    /// Returns the X11 keysym of the unshifted key with this key code, if any.
+    pub fn to_x11_keysym(self) -> Option<u32> {
+        match self.0 {
+            0x41..=0x5A => Some(self.0 as u32 + 0x20),
+            0x30..=0x39 => Some(self.0 as u32),
+            code if (K::F1.0..=K::F24.0).contains(&code) => Some(XK_F1 + (code - K::F1.0) as u32),
+            code if (K::Numpad0.0..=K::Numpad9.0).contains(&code) => {
+                Some(XK_KP_0 + (code - K::Numpad0.0) as u32)
+            }
+            // The right modifier keys have keysyms of their own.
+            _ if self == K::RShift => Some(0xFFE2),   // Shift_R
+            _ if self == K::RControl => Some(0xFFE4), // Control_R
+            _ if self == K::RMenu => Some(0xFFEA),    // Alt_R
+            _ => self.located().to_x11_keysym_in_table(),
+        }
+    }
+    fn to_x11_keysym_in_table(self) -> Option<u32> {
+        KEYSYMS
+            .iter()
+            .find(|(_, key_code)| *key_code == self)
+            .map(|(sym, _)| *sym)
+    }
+
+    /// Returns the key code of the key with the Linux evdev scancode `code`, as
+    /// found in `linux/input-event-codes.h`.
+    pub fn from_evdev(code: u16) -> Option<K> {
+        PhysicalKey::from_evdev(code).map(PhysicalKey::windows_key_code)
+    }
+    /// Returns the evdev scancode of the canonical key with this key code.
+    pub fn to_evdev(self) -> Option<u16> {
+        PhysicalKey::from_windows_key_code(self).map(PhysicalKey::evdev)
+    }
+    /// Returns the key code of the key with the USB HID `usage`, given as the usage
+    /// page in the upper 16 bits and the usage ID in the lower 16 bits, e.g.
+    /// `0x07_0004` for the A key.
+    pub fn from_usb_hid(usage: u32) -> Option<K> {
+        PhysicalKey::from_usb_hid(usage).map(PhysicalKey::windows_key_code)
+    }
+    /// Returns the USB HID usage of the canonical key with this key code.
+    pub fn to_usb_hid(self) -> Option<u32> {
+        PhysicalKey::from_windows_key_code(self).map(PhysicalKey::usb_hid)
+    }
+
+    /// Maps the left and right variants of the modifier keys to the key code they
+    /// share, which is what key events carry.
+    pub(crate) fn located(self) -> K {
+        match self {
+            K::LShift | K::RShift => K::Shift,
+            K::LControl | K::RControl => K::Control,
+            K::LMenu | K::RMenu => K::Menu,
+            _ => self,
+        }
+    }
+}
+
+/// A key on the keyboard, independent of the keyboard layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PhysicalKey(u32);
+
+impl PhysicalKey {
+    fn find(predicate: impl Fn(&Key) -> bool) -> Option<PhysicalKey> {
+        KEYS.iter().find(|key| predicate(key)).map(|key| PhysicalKey(key.usb))
+    }
+    fn key(self) -> &'static Key {
+        KEYS.iter().find(|key| key.usb == self.0).unwrap()
+    }
+
+    /// Returns the key with the USB HID `usage`, given as the usage page in the
+    /// upper 16 bits and the usage ID in the lower 16 bits.
+    pub fn from_usb_hid(usage: u32) -> Option<PhysicalKey> {
+        PhysicalKey::find(|key| key.usb == usage)
+    }
+    /// Returns the key with the Linux evdev scancode `code`.
+    pub fn from_evdev(code: u16) -> Option<PhysicalKey> {
+        PhysicalKey::find(|key| key.evdev == code)
+    }
+    /// Returns the key with the X11 `keycode`, which is the evdev scancode plus 8.
+    pub fn from_x11_keycode(keycode: u32) -> Option<PhysicalKey> {
+        if !(8..=0xFFFF + 8).contains(&keycode) {
+            return None;
+        }
+        PhysicalKey::from_evdev((keycode - 8) as u16)
+    }
+    /// Returns the key with the Windows `scancode`, with 0xE0 in the upper byte
+    /// for extended keys.
+    pub fn from_windows_scancode(scancode: u16) -> Option<PhysicalKey> {
+        PhysicalKey::find(|key| key.windows_scancode == scancode && scancode != 0)
+    }
+    /// Returns the key with the macOS virtual key code `keycode`.
+    pub fn from_mac_keycode(keycode: u16) -> Option<PhysicalKey> {
+        PhysicalKey::find(|key| key.mac == keycode && keycode != NO_MAC)
+    }
+    /// Returns the canonical key with `key_code`, e.g. the left shift key for
+    /// [WindowsKeyCode::Shift](K::Shift) and the main Enter key for
+    /// [WindowsKeyCode::Return](K::Return).
+    pub fn from_windows_key_code(key_code: K) -> Option<PhysicalKey> {
+        let usb = match key_code {
+            K::RShift => 0x07_00E5,
+            K::RControl => 0x07_00E4,
+            K::RMenu => 0x07_00E6,
+            _ => return PhysicalKey::find(|key| key.key_code == key_code.located()),
+        };
+        PhysicalKey::from_usb_hid(usb)
+    }
+
+    /// Returns the USB HID usage of the key.
+    pub fn usb_hid(self) -> u32 {
+        self.0
+    }
+    /// Returns the Linux evdev scancode of the key.
+    pub fn evdev(self) -> u16 {
+        self.key().evdev
+    }
+    /// Returns the X11 keycode of the key.
+    pub fn x11_keycode(self) -> u32 {
+        self.evdev() as u32 + 8
+    }
+    /// Returns the Windows scancode of the key, with 0xE0 in the upper byte for
+    /// extended keys.
+    pub fn windows_scancode(self) -> Option<u16> {
+        Some(self.key().windows_scancode).filter(|scancode| *scancode != 0)
+    }
+    /// Returns the macOS virtual key code of the key.
+    pub fn mac_keycode(self) -> Option<u16> {
+        Some(self.key().mac).filter(|keycode| *keycode != NO_MAC)
+    }
+    /// Returns the key code that key events of this key carry.
+    pub fn windows_key_code(self) -> K {
+        self.key().key_code
+    }
+
+    /// Returns the location flags key events of this key carry.
+    pub fn location(self) -> EventFlags {
+        match self.0 {
+            0x07_00E0..=0x07_00E3 => EventFlags::IS_LEFT,
+            0x07_00E4..=0x07_00E7 => EventFlags::IS_RIGHT,
+            0x07_0054..=0x07_0063 | 0x07_0085 => EventFlags::IS_KEY_PAD,
+            _ => EventFlags::empty(),
+        }
+    }
+    fn is_keypad_digit(self) -> bool {
+        (0x07_0059..=0x07_0063).contains(&self.0)
+    }
+
+    /// Returns the `native_key_code` CEF expects with key events of this key on the
+    /// current platform: the X11 keycode on Linux, the macOS virtual key code on
+    /// macOS, and the `lParam` of the `WM_KEYDOWN` message on Windows.
+    pub fn native_key_code(self, key_up: bool) -> i32 {
+        if cfg!(target_os = "windows") {
+            let scancode = self.key().windows_scancode as u32;
+            // The repeat count, the scancode, the extended key flag and the
+            // previous and transition states.
+            let mut lparam = 1 | (scancode & 0xFF) << 16;
+            if scancode & 0xE000 != 0 {
+                lparam |= 1 << 24;
+            }
+            if key_up {
+                lparam |= 0xC000_0000;
+            }
+            lparam as i32
+        } else if cfg!(target_os = "macos") {
+            self.mac_keycode().map(i32::from).unwrap_or(0)
+        } else {
+            self.x11_keycode() as i32
+        }
+    }
+
+    /// Returns the character the key produces on a US keyboard with `modifiers`,
+    /// if any.
+    ///
+    /// Like Chromium does, Ctrl with a letter or one of `[`, `\`, `]`, `^`, `_`
+    /// produces the corresponding control character and no character otherwise,
+    /// Command on macOS produces no character, and the keypad digits only produce
+    /// characters with [EventFlags::NUM_LOCK_ON].
+    pub fn char(self, modifiers: EventFlags) -> Option<char> {
+        let (unshifted, shifted) = self.key().chars?;
+        if self.is_keypad_digit() && !modifiers.contains(EventFlags::NUM_LOCK_ON) {
+            return None;
+        }
+        let shift = modifiers.contains(EventFlags::SHIFT_DOWN);
+        if modifiers.contains(EventFlags::COMMAND_DOWN) && cfg!(target_os = "macos") {
+            return None;
+        }
+        if modifiers.contains(EventFlags::CONTROL_DOWN) && !modifiers.contains(EventFlags::ALT_DOWN) {
+            return control_char(unshifted, shift);
+        }
+        if unshifted.is_ascii_lowercase() {
+            let caps_lock = modifiers.contains(EventFlags::CAPS_LOCK_ON);
+            return Some(if shift != caps_lock { shifted } else { unshifted });
+        }
+        Some(if shift { shifted } else { unshifted })
+    }
+}
+
+fn control_char(unshifted: char, shift: bool) -> Option<char> {
+    let code = match (unshifted, shift) {
+        ('a'..='z', _) => unshifted as u32 - 'a' as u32 + 1,
+        ('[', false) => 0x1B,
+        ('\\', false) => 0x1C,
+        (']', false) => 0x1D,
+        ('6', true) => 0x1E,
+        ('-', true) => 0x1F,
+        ('\r', false) => 0x0A,
+        _ => return None,
+    };
+    std::char::from_u32(code)
+}
+
+/// Windows reports keys pressed with Alt, but without Ctrl, as system keys.
+pub(crate) fn is_system_key(modifiers: EventFlags) -> bool {
+    cfg!(target_os = "windows")
+        && modifiers.contains(EventFlags::ALT_DOWN)
+        && !modifiers.contains(EventFlags::CONTROL_DOWN)
+}
+
+/// Builds the key events that pressing and releasing a [PhysicalKey] produces in
+/// a windowed browser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEventBuilder {
+    key: PhysicalKey,
+    modifiers: EventFlags,
+    char: Option<Option<char>>,
+    focus_on_editable_field: bool,
+}
+
+impl KeyEventBuilder {
+    pub fn new(key: PhysicalKey) -> KeyEventBuilder {
+        KeyEventBuilder {
+            key,
+            modifiers: EventFlags::empty(),
+            char: None,
+            focus_on_editable_field: false,
+        }
+    }
+    /// Set the held modifier keys and mouse buttons and the lock states. The
+    /// location flags of the key are added automatically.
+    pub fn modifiers(mut self, modifiers: EventFlags) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
+    /// Set the character the key produces, or None if it produces none, e.g. for
+    /// a keyboard layout other than US. By default it is [PhysicalKey::char].
+    pub fn char(mut self, char: Option<char>) -> Self {
+        self.char = Some(char);
+        self
+    }
+    pub fn focus_on_editable_field(mut self, focus_on_editable_field: bool) -> Self {
+        self.focus_on_editable_field = focus_on_editable_field;
+        self
+    }
+
+    fn event_modifiers(&self) -> EventFlags {
+        self.modifiers | self.key.location()
+    }
+    /// Returns the key code the events carry. Without Num Lock, the keypad digits
+    /// act as navigation keys.
+    fn windows_key_code(&self) -> K {
+        if self.key.is_keypad_digit() && !self.modifiers.contains(EventFlags::NUM_LOCK_ON) {
+            match self.key.windows_key_code() {
+                K::Numpad0 => K::Insert,
+                K::Numpad1 => K::End,
+                K::Numpad2 => K::Down,
+                K::Numpad3 => K::Next,
+                K::Numpad4 => K::Left,
+                K::Numpad5 => K::Clear,
+                K::Numpad6 => K::Right,
+                K::Numpad7 => K::Home,
+                K::Numpad8 => K::Up,
+                K::Numpad9 => K::Prior,
+                K::Decimal => K::Delete,
+                key_code => key_code,
+            }
+        } else {
+            self.key.windows_key_code()
+        }
+    }
+
+    /// Returns the event of pressing the key.
+    pub fn key_down(&self) -> KeyEvent {
+        KeyEvent::KeyDown {
+            modifiers: self.event_modifiers(),
+            windows_key_code: self.windows_key_code(),
+            native_key_code: self.key.native_key_code(false),
+            is_system_key: is_system_key(self.modifiers),
+            focus_on_editable_field: self.focus_on_editable_field,
+        }
+    }
+    /// Returns the event of the character the key produces, if any.
+    pub fn char_event(&self) -> Option<KeyEvent> {
+        let char = match self.char {
+            Some(char) => char,
+            None => self.key.char(self.modifiers),
+        }?;
+        Some(KeyEvent::Char {
+            modifiers: self.event_modifiers(),
+            char,
+            native_key_code: self.key.native_key_code(false),
+            is_system_key: is_system_key(self.modifiers),
+        })
+    }
+    /// Returns the event of releasing the key.
+    pub fn key_up(&self) -> KeyEvent {
+        KeyEvent::KeyUp {
+            modifiers: self.event_modifiers(),
+            windows_key_code: self.windows_key_code(),
+            native_key_code: self.key.native_key_code(true),
+            is_system_key: is_system_key(self.modifiers),
+            focus_on_editable_field: self.focus_on_editable_field,
+        }
+    }
+    /// Returns the events of pressing the key: the key down event, followed by
+    /// the character event if the key produces a character.
+    pub fn press(&self) -> Vec<KeyEvent> {
+        let mut events = vec![self.key_down()];
+        events.extend(self.char_event());
+        events
+    }
+    /// Returns the events of pressing and releasing the key.
+    pub fn press_and_release(&self) -> Vec<KeyEvent> {
+        let mut events = self.press();
+        events.push(self.key_up());
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn physical_keys_round_trip() {
+        for key in KEYS {
+            let physical = PhysicalKey::from_usb_hid(key.usb).unwrap();
+            assert_eq!(physical.usb_hid(), key.usb);
+            assert_eq!(PhysicalKey::from_evdev(key.evdev), Some(physical), "evdev {}", key.evdev);
+            assert_eq!(PhysicalKey::from_x11_keycode(physical.x11_keycode()), Some(physical));
+            if let Some(scancode) = physical.windows_scancode() {
+                assert_eq!(PhysicalKey::from_windows_scancode(scancode), Some(physical), "scancode {:#x}", scancode);
+            }
+            if let Some(keycode) = physical.mac_keycode() {
+                assert_eq!(PhysicalKey::from_mac_keycode(keycode), Some(physical), "mac keycode {:#x}", keycode);
+            }
+            let canonical = PhysicalKey::from_windows_key_code(key.key_code).unwrap();
+            assert_eq!(canonical.windows_key_code(), key.key_code);
+        }
+    }
+
+    #[test]
+    fn key_codes_round_trip() {
+        for key in KEYS {
+            assert_eq!(K::from_evdev(key.evdev), Some(key.key_code));
+            assert_eq!(K::from_usb_hid(key.usb), Some(key.key_code));
+            let evdev = key.key_code.to_evdev().unwrap();
+            assert_eq!(K::from_evdev(evdev), Some(key.key_code));
+        }
+        assert_eq!(PhysicalKey::from_windows_key_code(K::RShift).map(PhysicalKey::usb_hid), Some(0x07_00E5));
+        assert_eq!(PhysicalKey::from_windows_key_code(K::Return).map(PhysicalKey::evdev), Some(28));
+    }
+
+    #[test]
+    fn keysyms_round_trip() {
+        for key in KEYS {
+            if let Some(keysym) = key.key_code.to_x11_keysym() {
+                assert_eq!(K::from_x11_keysym(keysym), Some(key.key_code), "keysym {:#x}", keysym);
+            }
+        }
+        for &(keysym, key_code) in KEYSYMS {
+            assert_eq!(K::from_x11_keysym(keysym), Some(key_code), "keysym {:#x}", keysym);
+            let canonical = key_code.to_x11_keysym().unwrap();
+            assert_eq!(K::from_x11_keysym(canonical), Some(key_code));
+        }
+        assert_eq!(K::from_x11_keysym(0x61), Some(K::A));
+        assert_eq!(K::from_x11_keysym(0x41), Some(K::A));
+        assert_eq!(K::from_x11_keysym(XK_F1 + 11), Some(K::F12));
+        assert_eq!(K::from_x11_keysym(XK_KP_0 + 7), Some(K::Numpad7));
+        assert_eq!(K::A.to_x11_keysym(), Some(0x61));
+        assert_eq!(K::from_x11_keysym(0x1234_5678), None);
+    }
+
+    #[test]
+    fn shifted_digit_keysyms() {
+        let shifted = [0x29, 0x21, 0x40, 0x23, 0x24, 0x25, 0x5E, 0x26, 0x2A, 0x28];
+        for (digit, keysym) in shifted.iter().enumerate() {
+            assert_eq!(K::from_x11_keysym(*keysym), Some(K(K::Key0.0 + digit as i32)));
+        }
+    }
+
+    #[test]
+    fn modifier_keysyms() {
+        assert_eq!(K::Shift.to_x11_keysym(), Some(0xFFE1));
+        assert_eq!(K::LShift.to_x11_keysym(), Some(0xFFE1));
+        assert_eq!(K::RShift.to_x11_keysym(), Some(0xFFE2));
+        assert_eq!(K::RControl.to_x11_keysym(), Some(0xFFE4));
+        assert_eq!(K::Menu.to_x11_keysym(), Some(0xFFE9));
+        assert_eq!(K::RMenu.to_x11_keysym(), Some(0xFFEA));
+        assert_eq!(K::from_x11_keysym(0xFFE2), Some(K::Shift));
+    }
+
+    #[test]
+    fn key_event_builder() {
+        let key = PhysicalKey::from_evdev(30).unwrap();
+        let events = KeyEventBuilder::new(key).modifiers(EventFlags::SHIFT_DOWN).press_and_release();
+        assert_eq!(events.len(), 3);
+        match events[1] {
+            KeyEvent::Char { char, native_key_code, .. } => {
+                assert_eq!(char, 'A');
+                assert_eq!(native_key_code, key.native_key_code(false));
+            }
+            event => panic!("expected a character, got {:?}", event),
+        }
+        let numpad = PhysicalKey::from_usb_hid(0x07_0059).unwrap();
+        match KeyEventBuilder::new(numpad).key_down() {
+            KeyEvent::KeyDown { windows_key_code, modifiers, .. } => {
+                assert_eq!(windows_key_code, K::End);
+                assert!(modifiers.contains(EventFlags::IS_KEY_PAD));
+            }
+            event => panic!("expected a key down, got {:?}", event),
+        }
+        assert_eq!(KeyEventBuilder::new(numpad).char_event(), None);
+        assert_eq!(key.char(EventFlags::CONTROL_DOWN), Some('\u{1}'));
+    }
+}
@@ -17,8 +17,17 @@ pub mod scheme_registrar;
 pub mod resource_bundle;
 pub mod resource_bundle_handler;
 pub mod browser_process_handler;
+pub mod message_pump;
 pub mod browser;
 pub mod browser_host;
+pub mod browser_registry;
+pub mod compositor;
+pub mod pixels;
+pub mod recorder;
+pub mod frame_scheduler;
+pub mod headless;
+#[cfg(feature = "winit")]
+pub mod winit;
 pub mod frame;
 pub mod load_handler;
 pub mod registration;
@@ -26,6 +35,15 @@ pub mod render_process_handler;
 pub mod dom;
 pub mod v8context;
 pub mod process;
+pub mod rpc;
+pub mod eval;
+pub mod message_router;
+pub mod js_class;
+/// Exposes a struct's public fields, or an `impl` block's public methods, to
+/// JavaScript. Apply it to both the struct and one `impl` block to make the type a
+/// [JsClass](js_class::JsClass); see the [js_class](mod@js_class) module for
+/// details.
+pub use cef_macros::js_class;
 pub mod request;
 pub mod response;
 pub mod url_request;
@@ -35,6 +53,11 @@ pub mod cookie;
 pub mod devtools_message_observer;
 pub mod callback;
 pub mod resource_request_handler;
+pub mod router;
+pub mod static_files;
+pub mod assets;
+pub mod response_rewriter;
+pub mod har;
 pub mod client;
 pub mod image;
 
@@ -48,6 +71,7 @@ pub mod scheme;
 pub mod settings;
 pub mod color;
 pub mod events;
+pub mod keyboard;
 pub mod drag;
 pub mod file_dialog;
 pub mod printing;
@@ -57,10 +81,12 @@ pub mod ime;
 pub mod navigation;
 pub mod extension;
 pub mod stream;
+pub mod zip_reader;
 pub mod ssl;
 pub mod task;
 pub mod logging;
 mod send_protector;
+mod oneshot;
 #[cfg(target_os = "macos")] mod framework_loader_macos;
 #[cfg(target_os = "macos")] pub use framework_loader_macos::load_framework;
 
@@ -1127,11 +1127,18 @@ cef_callback_impl! {
             frame: Frame: *mut cef_frame_t,
             transition_type: TransitionType: cef_transition_type_t,
         ) {
+            let browser_id = browser.get_identifier();
             self.delegate.on_load_start(
                 browser,
-                frame,
+                frame.clone(),
                 transition_type,
             );
+            // The calls sent to the documents that were replaced can't be answered.
+            if frame.is_main() {
+                crate::rpc::cancel_for_browser(browser_id);
+            } else {
+                crate::rpc::cancel_for_frame(&frame);
+            }
         }
         fn load_end(
             &self,
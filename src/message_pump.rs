@@ -0,0 +1,308 @@
+//! Scheduling [Context::do_message_loop_work] calls for
+//! [Settings::external_message_pump], modelled after upstream cefclient's
+//! `MainMessageLoopExternalPump`.
+//!
+//! Forward [BrowserProcessHandlerCallbacks::on_schedule_message_pump_work] to
+//! [MessagePump::schedule], which may be called from any thread, and drive the
+//! pump from the main thread in one of these ways:
+//!
+//! - A plain thread loop: [MessagePump::run] blocks until [MessagePump::quit]
+//!   is called.
+//! - A calloop/mio-style event loop: register a wakeup with
+//!   [MessagePump::set_wakeup] (e.g. a calloop `Ping` or a `mio::Waker`), poll with
+//!   [MessagePump::timeout] as the timeout and call [MessagePump::perform_work]
+//!   whenever the loop wakes up.
+//! - An async runtime: await [MessagePump::run_async] with the runtime's sleep
+//!   function, for example inside a tokio `LocalSet`, since [Context] can't leave
+//!   the main thread:
+//!
+//! ```ignore
+//! let local = tokio::task::LocalSet::new();
+//! local.block_on(&runtime, pump.run_async(&context, tokio::time::sleep));
+//! ```
+//!
+//! Work is done at least every [max_delay](MessagePump::max_delay), as CEF
+//! expects when an external pump is used, and at most at the rate given by
+//! [max_rate](MessagePump::max_rate).
+//!
+//! [Context]: crate::Context
+//! [Context::do_message_loop_work]: crate::Context::do_message_loop_work
+//! [Settings::external_message_pump]: crate::settings::Settings::external_message_pump
+//! [BrowserProcessHandlerCallbacks::on_schedule_message_pump_work]: crate::browser_process_handler::BrowserProcessHandlerCallbacks::on_schedule_message_pump_work
+
+use crate::Context;
+use parking_lot::{Condvar, Mutex};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{self, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+/// The longest time between two calls to [Context::do_message_loop_work], as in
+/// upstream cefclient.
+///
+/// [Context::do_message_loop_work]: crate::Context::do_message_loop_work
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_millis(1000 / 30);
+
+struct State {
+    /// When work should be done next.
+    next_work: Option<Instant>,
+    last_work: Option<Instant>,
+    max_delay: Duration,
+    min_interval: Duration,
+    /// True while [Context::do_message_loop_work] runs.
+    working: bool,
+    /// Set if immediate work was scheduled while working.
+    reentrancy_detected: bool,
+    quit: bool,
+    /// Incremented on every change that a waiting loop has to look at.
+    generation: u64,
+    async_waker: Option<Waker>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+    wakeup: Mutex<Option<Arc<dyn Fn() + Send + Sync>>>,
+}
+
+/// Turns the delays requested through
+/// [BrowserProcessHandlerCallbacks::on_schedule_message_pump_work] into calls to
+/// [Context::do_message_loop_work]. Cloning it returns another handle to the same
+/// pump.
+///
+/// [Context::do_message_loop_work]: crate::Context::do_message_loop_work
+/// [BrowserProcessHandlerCallbacks::on_schedule_message_pump_work]: crate::browser_process_handler::BrowserProcessHandlerCallbacks::on_schedule_message_pump_work
+#[derive(Clone)]
+pub struct MessagePump(Arc<Shared>);
+
+impl Default for MessagePump {
+    fn default() -> MessagePump {
+        MessagePump::new()
+    }
+}
+
+impl MessagePump {
+    pub fn new() -> MessagePump {
+        MessagePump(Arc::new(Shared {
+            state: Mutex::new(State {
+                next_work: Some(Instant::now()),
+                last_work: None,
+                max_delay: DEFAULT_MAX_DELAY,
+                min_interval: Duration::from_millis(0),
+                working: false,
+                reentrancy_detected: false,
+                quit: false,
+                generation: 0,
+                async_waker: None,
+            }),
+            condvar: Condvar::new(),
+            wakeup: Mutex::new(None),
+        }))
+    }
+    /// Set the longest time between two calls to [Context::do_message_loop_work].
+    /// Longer delays requested by CEF are shortened to this. Defaults to
+    /// [DEFAULT_MAX_DELAY].
+    ///
+    /// [Context::do_message_loop_work]: crate::Context::do_message_loop_work
+    pub fn max_delay(self, max_delay: Duration) -> Self {
+        self.0.state.lock().max_delay = max_delay;
+        self
+    }
+    /// Limit the calls to [Context::do_message_loop_work] to `calls_per_second`.
+    /// Work that is requested sooner is postponed. Unlimited by default.
+    ///
+    /// [Context::do_message_loop_work]: crate::Context::do_message_loop_work
+    pub fn max_rate(self, calls_per_second: u32) -> Self {
+        self.0.state.lock().min_interval = match calls_per_second {
+            0 => Duration::from_millis(0),
+            rate => Duration::from_secs(1) / rate,
+        };
+        self
+    }
+    /// Set a function that wakes up the event loop driving this pump. It is called
+    /// from the thread that schedules work whenever the next deadline changes, and
+    /// the event loop should then call [perform_work](MessagePump::perform_work) and
+    /// wait for at most [timeout](MessagePump::timeout).
+    pub fn set_wakeup(&self, wakeup: impl Fn() + Send + Sync + 'static) {
+        *self.0.wakeup.lock() = Some(Arc::new(wakeup));
+    }
+
+    /// Schedule work in `delay_ms` milliseconds, replacing any pending schedule.
+    /// Call this from [BrowserProcessHandlerCallbacks::on_schedule_message_pump_work].
+    /// May be called on any thread.
+    ///
+    /// [BrowserProcessHandlerCallbacks::on_schedule_message_pump_work]: crate::browser_process_handler::BrowserProcessHandlerCallbacks::on_schedule_message_pump_work
+    pub fn schedule(&self, delay_ms: i64) {
+        {
+            let mut state = self.0.state.lock();
+            if delay_ms <= 0 && state.working {
+                // Handled once the current call returns; see perform_work.
+                state.reentrancy_detected = true;
+                return;
+            }
+            let delay = Duration::from_millis(delay_ms.max(0) as u64).min(state.max_delay);
+            state.next_work = Some(Instant::now() + delay);
+        }
+        self.notify();
+    }
+    /// Make [run](MessagePump::run) and [run_async](MessagePump::run_async) return.
+    pub fn quit(&self) {
+        self.0.state.lock().quit = true;
+        self.notify();
+    }
+    /// Returns true if [quit](MessagePump::quit) has been called.
+    pub fn is_quitting(&self) -> bool {
+        self.0.state.lock().quit
+    }
+
+    /// Returns when work should be done next, taking [max_rate](MessagePump::max_rate)
+    /// into account.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let state = self.0.state.lock();
+        Self::deadline(&state)
+    }
+    /// Returns how long an event loop may wait before calling
+    /// [perform_work](MessagePump::perform_work), or None if no work is scheduled.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.next_deadline().map(|deadline| {
+            let now = Instant::now();
+            if deadline > now {
+                deadline - now
+            } else {
+                Duration::from_millis(0)
+            }
+        })
+    }
+    /// Call [Context::do_message_loop_work] if work is due. Returns true if it was
+    /// called. Must be called on the main thread.
+    ///
+    /// [Context::do_message_loop_work]: crate::Context::do_message_loop_work
+    pub fn perform_work(&self, context: &Context) -> bool {
+        let now = Instant::now();
+        {
+            let mut state = self.0.state.lock();
+            match Self::deadline(&state) {
+                Some(deadline) if deadline <= now && !state.working => (),
+                _ => return false,
+            }
+            state.next_work = None;
+            state.working = true;
+            state.reentrancy_detected = false;
+        }
+
+        context.do_message_loop_work();
+
+        let mut state = self.0.state.lock();
+        let now = Instant::now();
+        state.working = false;
+        state.last_work = Some(now);
+        if state.reentrancy_detected {
+            // Immediate work was requested while working; do it as soon as
+            // possible.
+            state.next_work = Some(now);
+        } else if state.next_work.is_none() {
+            // CEF relies on being pumped regularly even if it doesn't ask for it.
+            state.next_work = Some(now + state.max_delay);
+        }
+        true
+    }
+
+    /// Drive the pump until [quit](MessagePump::quit) is called, blocking the
+    /// current thread between calls. Must be called on the main thread.
+    pub fn run(&self, context: &Context) {
+        loop {
+            self.perform_work(context);
+            let mut state = self.0.state.lock();
+            if state.quit {
+                return;
+            }
+            match Self::deadline(&state) {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if deadline > now {
+                        self.0.condvar.wait_for(&mut state, deadline - now);
+                    }
+                }
+                None => self.0.condvar.wait(&mut state),
+            }
+        }
+    }
+    /// Drive the pump from an async runtime until [quit](MessagePump::quit) is
+    /// called. `sleep` is the runtime's timer, e.g. `tokio::time::sleep`. The
+    /// returned future must be polled on the main thread.
+    pub async fn run_async<S, F>(&self, context: &Context, mut sleep: S)
+    where
+        S: FnMut(Duration) -> F,
+        F: Future<Output = ()>,
+    {
+        loop {
+            self.perform_work(context);
+            let generation = {
+                let state = self.0.state.lock();
+                if state.quit {
+                    return;
+                }
+                state.generation
+            };
+            let sleep = self.timeout().map(|timeout| Box::pin(sleep(timeout)));
+            WaitForWork {
+                pump: self,
+                generation,
+                sleep,
+            }
+            .await;
+        }
+    }
+
+    fn deadline(state: &State) -> Option<Instant> {
+        let next_work = state.next_work?;
+        Some(match state.last_work {
+            Some(last_work) => next_work.max(last_work + state.min_interval),
+            None => next_work,
+        })
+    }
+
+    fn notify(&self) {
+        let async_waker = {
+            let mut state = self.0.state.lock();
+            state.generation = state.generation.wrapping_add(1);
+            state.async_waker.take()
+        };
+        self.0.condvar.notify_all();
+        if let Some(waker) = async_waker {
+            waker.wake();
+        }
+        let wakeup = self.0.wakeup.lock().clone();
+        if let Some(wakeup) = wakeup {
+            wakeup();
+        }
+    }
+}
+
+/// Completes when the sleep has elapsed or the schedule has changed.
+struct WaitForWork<'a, F> {
+    pump: &'a MessagePump,
+    generation: u64,
+    sleep: Option<Pin<Box<F>>>,
+}
+
+impl<F: Future<Output = ()>> Future for WaitForWork<'_, F> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<()> {
+        {
+            let mut state = self.pump.0.state.lock();
+            if state.generation != self.generation {
+                return Poll::Ready(());
+            }
+            state.async_waker = Some(cx.waker().clone());
+        }
+        match self.sleep.as_mut() {
+            Some(sleep) => sleep.as_mut().poll(cx),
+            None => Poll::Pending,
+        }
+    }
+}
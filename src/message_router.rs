@@ -0,0 +1,716 @@
+//! Asynchronous queries from JavaScript to the browser process, modelled after
+//! upstream CEF's `CefMessageRouter`.
+//!
+//! The render process side injects two functions into every V8 context of an
+//! allowed origin (names are configurable, these are the defaults):
+//!
+//! ```js
+//! // Returns a request id that can be passed to cefQueryCancel.
+//! var requestId = window.cefQuery({
+//!     request: 'my_request',
+//!     persistent: false,
+//!     onSuccess: function(response) {},
+//!     onFailure: function(errorCode, errorMessage) {}
+//! });
+//! window.cefQueryCancel(requestId);
+//! ```
+//!
+//! Queries are delivered to the [MessageRouterHandler]s registered with the
+//! [MessageRouterBrowserSide], which answer them through a [QueryCallback]. A
+//! non-persistent query is answered exactly once; a persistent query may be
+//! answered with any number of successes until it fails or is cancelled.
+//!
+//! Both sides need to be fed from the matching client and render process
+//! callbacks:
+//!
+//! - [MessageRouterRendererSide::on_context_created],
+//!   [MessageRouterRendererSide::on_context_released] and
+//!   [MessageRouterRendererSide::on_process_message_received] from
+//!   [RenderProcessHandlerCallbacks].
+//! - [MessageRouterBrowserSide::on_process_message_received] from
+//!   [ClientCallbacks::on_process_message_received],
+//!   [MessageRouterBrowserSide::on_before_browse] from
+//!   [RequestHandlerCallbacks::on_before_browse],
+//!   [MessageRouterBrowserSide::on_render_process_terminated] from
+//!   [RequestHandlerCallbacks::on_render_process_terminated] and
+//!   [MessageRouterBrowserSide::on_before_close] from
+//!   [LifeSpanHandlerCallbacks::on_before_close].
+
+use crate::{
+    browser::Browser,
+    frame::Frame,
+    process::ProcessMessage,
+    v8context::{V8Context, V8PropertyAttribute, V8Value},
+};
+use parking_lot::{Mutex, RwLock};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicI32, AtomicI64, AtomicU64, Ordering},
+        Arc, Weak,
+    },
+};
+
+/// Error code passed to `onFailure` when no handler accepted a query.
+pub const UNHANDLED_ERROR_CODE: i32 = -1;
+/// Error code passed to `onFailure` when the origin of the frame isn't allowed
+/// to send queries.
+pub const ORIGIN_NOT_ALLOWED_ERROR_CODE: i32 = -2;
+
+/// Configuration shared by both sides of a message router. The browser and render
+/// process sides must be created with the same configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageRouterConfig {
+    /// Name of the JavaScript function that sends a query. Defaults to `cefQuery`.
+    pub js_query_function: String,
+    /// Name of the JavaScript function that cancels a pending query. Defaults to
+    /// `cefQueryCancel`.
+    pub js_cancel_function: String,
+    /// Origins (`scheme://host[:port]`) whose frames may send queries. An empty
+    /// list allows every origin.
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for MessageRouterConfig {
+    fn default() -> Self {
+        MessageRouterConfig {
+            js_query_function: "cefQuery".to_owned(),
+            js_cancel_function: "cefQueryCancel".to_owned(),
+            allowed_origins: Vec::new(),
+        }
+    }
+}
+
+impl MessageRouterConfig {
+    fn query_message(&self) -> String {
+        format!("{}Msg", self.js_query_function)
+    }
+    fn cancel_message(&self) -> String {
+        format!("{}Msg", self.js_cancel_function)
+    }
+    fn response_message(&self) -> String {
+        format!("{}ResponseMsg", self.js_query_function)
+    }
+    /// Returns true if the document at `url` may send queries.
+    fn is_allowed(&self, url: &str) -> bool {
+        if self.allowed_origins.is_empty() {
+            return true;
+        }
+        let origin = origin_of(url);
+        self.allowed_origins
+            .iter()
+            .any(|allowed| origin_of(allowed) == origin)
+    }
+}
+
+/// Returns the `scheme://host[:port]` part of `url`, lowercased. The port is left
+/// out when it is the scheme's default, so `https://a:443` and `https://a` match.
+fn origin_of(url: &str) -> String {
+    let url = url.trim().to_ascii_lowercase();
+    let (scheme, rest) = match url.find("://") {
+        Some(index) => (&url[..index], &url[index + 3..]),
+        None => return url,
+    };
+    let authority = rest.split(|c| c == '/' || c == '?' || c == '#').next().unwrap_or("");
+    let mut host = authority.rsplit('@').next().unwrap_or("");
+    // The port follows the last colon, unless that colon is inside an IPv6 literal.
+    if let Some(index) = host.rfind(':').filter(|&index| !host[index..].contains(']')) {
+        let port = &host[index + 1..];
+        if port.is_empty() || Some(port) == default_port(scheme) {
+            host = &host[..index];
+        }
+    }
+    format!("{}://{}", scheme, host)
+}
+
+fn default_port(scheme: &str) -> Option<&'static str> {
+    match scheme {
+        "http" | "ws" => Some("80"),
+        "https" | "wss" => Some("443"),
+        "ftp" => Some("21"),
+        _ => None,
+    }
+}
+
+/// Handler for queries sent from JavaScript. The functions of this trait are called
+/// on the browser process UI thread.
+pub trait MessageRouterHandler: Send + Sync + 'static {
+    /// Called when a query arrives. `query_id` uniquely identifies the query for the
+    /// lifetime of the router. Return true to handle the query, in which case
+    /// `callback` must eventually be answered unless the query is cancelled first.
+    /// Return false to pass the query to the next handler.
+    fn on_query(
+        &self,
+        browser: Browser,
+        frame: Frame,
+        query_id: i64,
+        request: &str,
+        persistent: bool,
+        callback: QueryCallback,
+    ) -> bool;
+    /// Called when a query handled by this handler is cancelled, either from
+    /// JavaScript or because the frame navigated, its context was released or the
+    /// browser closed. The query's [QueryCallback] does nothing from now on.
+    fn on_query_canceled(&self, browser: Browser, frame: Frame, query_id: i64) {}
+}
+
+/// Identifies a handler registered with [MessageRouterBrowserSide::add_handler].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HandlerId(u64);
+
+/// Identifies a query as sent by the renderer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct QueryKey {
+    browser_id: i32,
+    frame_id: Option<i64>,
+    context_id: i32,
+    request_id: i32,
+}
+
+struct BrowserQuery {
+    key: QueryKey,
+    browser: Browser,
+    frame: Frame,
+    persistent: bool,
+    handler: Option<Arc<dyn MessageRouterHandler>>,
+}
+
+struct BrowserInner {
+    config: MessageRouterConfig,
+    handlers: RwLock<Vec<(HandlerId, Arc<dyn MessageRouterHandler>)>>,
+    queries: Mutex<HashMap<i64, BrowserQuery>>,
+    next_handler_id: AtomicU64,
+    next_query_id: AtomicI64,
+}
+
+impl BrowserInner {
+    fn send_response(&self, key: &QueryKey, frame: &Frame, result: Result<&str, (i32, &str)>) {
+        if !frame.is_valid() {
+            return;
+        }
+        let message = ProcessMessage::new(&self.config.response_message());
+        let args = message.get_argument_list();
+        args.set_int(0, key.context_id);
+        args.set_int(1, key.request_id);
+        match result {
+            Ok(response) => {
+                args.set_bool(2, true);
+                args.set_string(3, response);
+            }
+            Err((code, error)) => {
+                args.set_bool(2, false);
+                args.set_int(3, code);
+                args.set_string(4, error);
+            }
+        }
+        frame.send_process_message(message);
+    }
+
+    fn cancel_where(&self, filter: impl Fn(&BrowserQuery) -> bool) {
+        let cancelled: Vec<(i64, BrowserQuery)> = {
+            let mut queries = self.queries.lock();
+            let ids: Vec<i64> = queries
+                .iter()
+                .filter(|(_, query)| filter(query))
+                .map(|(id, _)| *id)
+                .collect();
+            ids.into_iter()
+                .filter_map(|id| queries.remove(&id).map(|query| (id, query)))
+                .collect()
+        };
+        for (id, query) in cancelled {
+            if let Some(handler) = query.handler {
+                handler.on_query_canceled(query.browser, query.frame, id);
+            }
+        }
+    }
+}
+
+/// Browser process side of a message router.
+/// Cloning yields another handle to the same handlers and pending queries.
+#[derive(Clone)]
+pub struct MessageRouterBrowserSide(Arc<BrowserInner>);
+
+impl MessageRouterBrowserSide {
+    pub fn new(config: MessageRouterConfig) -> MessageRouterBrowserSide {
+        MessageRouterBrowserSide(Arc::new(BrowserInner {
+            config,
+            handlers: RwLock::new(Vec::new()),
+            queries: Mutex::new(HashMap::new()),
+            next_handler_id: AtomicU64::new(1),
+            next_query_id: AtomicI64::new(1),
+        }))
+    }
+
+    /// Registers a handler. Handlers are asked in order until one of them accepts a
+    /// query; if `first` is true the handler is asked before all others.
+    pub fn add_handler<H: MessageRouterHandler>(&self, handler: H, first: bool) -> HandlerId {
+        let id = HandlerId(self.0.next_handler_id.fetch_add(1, Ordering::Relaxed));
+        let entry: (HandlerId, Arc<dyn MessageRouterHandler>) = (id, Arc::new(handler));
+        let mut handlers = self.0.handlers.write();
+        if first {
+            handlers.insert(0, entry);
+        } else {
+            handlers.push(entry);
+        }
+        id
+    }
+
+    /// Removes a handler and cancels all queries pending with it. Returns false if
+    /// no handler is registered under `id`.
+    pub fn remove_handler(&self, id: HandlerId) -> bool {
+        let handler = {
+            let mut handlers = self.0.handlers.write();
+            match handlers.iter().position(|(handler_id, _)| *handler_id == id) {
+                Some(index) => handlers.remove(index).1,
+                None => return false,
+            }
+        };
+        self.0.cancel_where(|query| {
+            query
+                .handler
+                .as_ref()
+                .map(|query_handler| Arc::ptr_eq(query_handler, &handler))
+                .unwrap_or(false)
+        });
+        true
+    }
+
+    /// Returns the number of queries that haven't been answered or cancelled yet.
+    pub fn pending_query_count(&self) -> usize {
+        self.0.queries.lock().len()
+    }
+
+    /// Cancels all pending queries sent from `browser`.
+    pub fn cancel_pending(&self, browser: &Browser) {
+        let browser_id = browser.get_identifier();
+        self.0.cancel_where(|query| query.key.browser_id == browser_id);
+    }
+
+    /// Cancels the queries of `frame`, or of the whole browser if it's the main
+    /// frame, as the documents that sent them are going away.
+    pub fn on_before_browse(&self, browser: &Browser, frame: &Frame) {
+        if frame.is_main() {
+            self.cancel_pending(browser);
+        } else {
+            let browser_id = browser.get_identifier();
+            let frame_id = frame.get_identifier();
+            self.0.cancel_where(|query| {
+                query.key.browser_id == browser_id && query.key.frame_id == frame_id
+            });
+        }
+    }
+
+    /// Cancels all pending queries of `browser`.
+    pub fn on_render_process_terminated(&self, browser: &Browser) {
+        self.cancel_pending(browser);
+    }
+
+    /// Cancels all pending queries of `browser`.
+    pub fn on_before_close(&self, browser: &Browser) {
+        self.cancel_pending(browser);
+    }
+
+    /// Dispatches router messages. Returns true if `message` belonged to this
+    /// router, in which case it shouldn't be processed any further.
+    pub fn on_process_message_received(
+        &self,
+        browser: &Browser,
+        frame: &Frame,
+        message: &ProcessMessage,
+    ) -> bool {
+        let name = match message.get_name() {
+            Some(name) => name,
+            None => return false,
+        };
+        let args = message.get_argument_list();
+        let key = QueryKey {
+            browser_id: browser.get_identifier(),
+            frame_id: frame.get_identifier(),
+            context_id: args.get_int(0).unwrap_or_default(),
+            request_id: args.get_int(1).unwrap_or_default(),
+        };
+        if name == self.0.config.query_message() {
+            let request = args.get_string(2).unwrap_or_default();
+            let persistent = args.get_bool(3).unwrap_or(false);
+            self.handle_query(browser, frame, key, &request, persistent);
+            true
+        } else if name == self.0.config.cancel_message() {
+            // A request id of -1 cancels every query of the context.
+            self.0.cancel_where(|query| {
+                query.key == key
+                    || (key.request_id == -1
+                        && QueryKey {
+                            request_id: -1,
+                            ..query.key
+                        } == key)
+            });
+            true
+        } else {
+            false
+        }
+    }
+
+    fn handle_query(
+        &self,
+        browser: &Browser,
+        frame: &Frame,
+        key: QueryKey,
+        request: &str,
+        persistent: bool,
+    ) {
+        // The renderer refuses to inject the functions into disallowed origins,
+        // but it can't be trusted to do so.
+        if !self.0.config.is_allowed(&frame.get_url()) {
+            self.0.send_response(
+                &key,
+                frame,
+                Err((ORIGIN_NOT_ALLOWED_ERROR_CODE, "origin is not allowed to send queries")),
+            );
+            return;
+        }
+
+        let query_id = self.0.next_query_id.fetch_add(1, Ordering::Relaxed);
+        self.0.queries.lock().insert(
+            query_id,
+            BrowserQuery {
+                key,
+                browser: browser.clone(),
+                frame: frame.clone(),
+                persistent,
+                handler: None,
+            },
+        );
+
+        let handlers: Vec<_> = self
+            .0
+            .handlers
+            .read()
+            .iter()
+            .map(|(_, handler)| handler.clone())
+            .collect();
+        for handler in handlers {
+            let callback = QueryCallback {
+                router: Arc::downgrade(&self.0),
+                query_id,
+            };
+            if handler.on_query(browser.clone(), frame.clone(), query_id, request, persistent, callback) {
+                // The handler may already have answered the query.
+                if let Some(query) = self.0.queries.lock().get_mut(&query_id) {
+                    query.handler = Some(handler);
+                }
+                return;
+            }
+        }
+
+        self.0.queries.lock().remove(&query_id);
+        self.0
+            .send_response(&key, frame, Err((UNHANDLED_ERROR_CODE, "unhandled query")));
+    }
+}
+
+impl Default for MessageRouterBrowserSide {
+    fn default() -> Self {
+        Self::new(MessageRouterConfig::default())
+    }
+}
+
+/// Used by a [MessageRouterHandler] to answer a query. Can be cloned and sent to
+/// other threads. Answers to queries that already completed or were cancelled are
+/// ignored.
+#[derive(Clone)]
+pub struct QueryCallback {
+    router: Weak<BrowserInner>,
+    query_id: i64,
+}
+
+impl QueryCallback {
+    /// Calls `onSuccess` with `response`. Completes the query unless it is
+    /// persistent.
+    pub fn success(&self, response: &str) {
+        self.respond(Ok(response));
+    }
+    /// Calls `onFailure` with `error_code` and `error_message` and completes the
+    /// query.
+    pub fn failure(&self, error_code: i32, error_message: &str) {
+        self.respond(Err((error_code, error_message)));
+    }
+
+    fn respond(&self, result: Result<&str, (i32, &str)>) {
+        let router = match self.router.upgrade() {
+            Some(router) => router,
+            None => return,
+        };
+        let target = {
+            let mut queries = router.queries.lock();
+            let keep = queries
+                .get(&self.query_id)
+                .map(|query| query.persistent && result.is_ok());
+            match keep {
+                Some(true) => queries
+                    .get(&self.query_id)
+                    .map(|query| (query.key, query.frame.clone())),
+                Some(false) => queries
+                    .remove(&self.query_id)
+                    .map(|query| (query.key, query.frame)),
+                None => None,
+            }
+        };
+        if let Some((key, frame)) = target {
+            router.send_response(&key, &frame, result);
+        }
+    }
+}
+
+struct RendererRequest {
+    context_id: i32,
+    persistent: bool,
+    on_success: Option<V8Value>,
+    on_failure: Option<V8Value>,
+}
+
+struct RendererContext {
+    context: V8Context,
+    frame: Frame,
+}
+
+struct RendererInner {
+    config: MessageRouterConfig,
+    contexts: Mutex<HashMap<i32, RendererContext>>,
+    requests: Mutex<HashMap<(i32, i32), RendererRequest>>,
+    next_context_id: AtomicI32,
+    next_request_id: AtomicI32,
+}
+
+impl RendererInner {
+    fn query(&self, context_id: i32, args: &[V8Value]) -> Result<V8Value, String> {
+        let query_function = &self.config.js_query_function;
+        let options = match args {
+            [options] if options.is_object() => options,
+            _ => return Err(format!("{} expects a single object argument", query_function)),
+        };
+        let request = options
+            .get_value_bykey("request")
+            .filter(V8Value::is_string)
+            .and_then(|request| request.get_string_value())
+            .ok_or_else(|| format!("{}: 'request' must be a string", query_function))?;
+        let persistent = options
+            .get_value_bykey("persistent")
+            .filter(V8Value::is_bool)
+            .and_then(|persistent| persistent.get_bool_value())
+            .unwrap_or(false);
+        let callback = |name: &str| -> Result<Option<V8Value>, String> {
+            match options.get_value_bykey(name) {
+                Some(value) if value.is_function() => Ok(Some(value)),
+                Some(value) if !value.is_undefined() && !value.is_null() => {
+                    Err(format!("{}: '{}' must be a function", query_function, name))
+                }
+                _ => Ok(None),
+            }
+        };
+        let on_success = callback("onSuccess")?;
+        let on_failure = callback("onFailure")?;
+
+        let frame = match self.contexts.lock().get(&context_id) {
+            Some(context) => context.frame.clone(),
+            None => return Err(format!("{}: context was released", query_function)),
+        };
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        self.requests.lock().insert(
+            (context_id, request_id),
+            RendererRequest {
+                context_id,
+                persistent,
+                on_success,
+                on_failure,
+            },
+        );
+
+        let message = ProcessMessage::new(&self.config.query_message());
+        let message_args = message.get_argument_list();
+        message_args.set_int(0, context_id);
+        message_args.set_int(1, request_id);
+        message_args.set_string(2, &request);
+        message_args.set_bool(3, persistent);
+        frame.send_process_message(message);
+
+        Ok(V8Value::from(request_id))
+    }
+
+    fn cancel(&self, context_id: i32, args: &[V8Value]) -> Result<V8Value, String> {
+        let request_id = match args {
+            [id] if id.is_int() => id.get_int_value().unwrap_or_default(),
+            _ => {
+                return Err(format!(
+                    "{} expects a request id",
+                    self.config.js_cancel_function
+                ))
+            }
+        };
+        if self.requests.lock().remove(&(context_id, request_id)).is_none() {
+            return Ok(V8Value::from(false));
+        }
+        if let Some(context) = self.contexts.lock().get(&context_id) {
+            self.send_cancel(&context.frame, context_id, request_id);
+        }
+        Ok(V8Value::from(true))
+    }
+
+    fn send_cancel(&self, frame: &Frame, context_id: i32, request_id: i32) {
+        let message = ProcessMessage::new(&self.config.cancel_message());
+        let args = message.get_argument_list();
+        args.set_int(0, context_id);
+        args.set_int(1, request_id);
+        frame.send_process_message(message);
+    }
+}
+
+/// Render process side of a message router. All functions must be called on the
+/// render process main thread.
+/// Cloning yields another handle to the same contexts and pending requests.
+#[derive(Clone)]
+pub struct MessageRouterRendererSide(Arc<RendererInner>);
+
+impl MessageRouterRendererSide {
+    pub fn new(config: MessageRouterConfig) -> MessageRouterRendererSide {
+        MessageRouterRendererSide(Arc::new(RendererInner {
+            config,
+            contexts: Mutex::new(HashMap::new()),
+            requests: Mutex::new(HashMap::new()),
+            next_context_id: AtomicI32::new(1),
+            next_request_id: AtomicI32::new(1),
+        }))
+    }
+
+    /// Injects the query and cancel functions into the global object of `context`,
+    /// unless the origin of `frame` isn't allowed to send queries.
+    pub fn on_context_created(&self, browser: &Browser, frame: &Frame, context: &V8Context) {
+        if !self.0.config.is_allowed(&frame.get_url()) {
+            return;
+        }
+        let global = match context.get_global() {
+            Some(global) => global,
+            None => return,
+        };
+        let context_id = self.0.next_context_id.fetch_add(1, Ordering::Relaxed);
+        self.0.contexts.lock().insert(
+            context_id,
+            RendererContext {
+                context: context.clone(),
+                frame: frame.clone(),
+            },
+        );
+
+        let attributes = V8PropertyAttribute::READ_ONLY
+            | V8PropertyAttribute::DONT_ENUM
+            | V8PropertyAttribute::DONT_DELETE;
+        let inner = Arc::downgrade(&self.0);
+        let query = V8Value::new_function(&self.0.config.js_query_function, move |_, _, args| {
+            match inner.upgrade() {
+                Some(inner) => inner.query(context_id, args),
+                None => Err("message router was destroyed".to_owned()),
+            }
+        });
+        global.set_value_bykey(&self.0.config.js_query_function, query, attributes);
+        let inner = Arc::downgrade(&self.0);
+        let cancel = V8Value::new_function(&self.0.config.js_cancel_function, move |_, _, args| {
+            match inner.upgrade() {
+                Some(inner) => inner.cancel(context_id, args),
+                None => Err("message router was destroyed".to_owned()),
+            }
+        });
+        global.set_value_bykey(&self.0.config.js_cancel_function, cancel, attributes);
+    }
+
+    /// Drops the pending requests of `context` and cancels them in the browser
+    /// process.
+    pub fn on_context_released(&self, browser: &Browser, frame: &Frame, context: &V8Context) {
+        let context_id = {
+            let mut contexts = self.0.contexts.lock();
+            let context_id = contexts
+                .iter()
+                .find(|(_, known)| known.context.is_same(context))
+                .map(|(id, _)| *id);
+            match context_id {
+                Some(context_id) => {
+                    contexts.remove(&context_id);
+                    context_id
+                }
+                None => return,
+            }
+        };
+        let had_requests = {
+            let mut requests = self.0.requests.lock();
+            let count = requests.len();
+            requests.retain(|_, request| request.context_id != context_id);
+            count != requests.len()
+        };
+        if had_requests {
+            self.0.send_cancel(frame, context_id, -1);
+        }
+    }
+
+    /// Dispatches router messages. Returns true if `message` belonged to this
+    /// router, in which case it shouldn't be processed any further.
+    pub fn on_process_message_received(
+        &self,
+        browser: &Browser,
+        frame: &Frame,
+        message: &ProcessMessage,
+    ) -> bool {
+        if message.get_name() != Some(self.0.config.response_message()) {
+            return false;
+        }
+        let args = message.get_argument_list();
+        let context_id = args.get_int(0).unwrap_or_default();
+        let request_id = args.get_int(1).unwrap_or_default();
+        let success = args.get_bool(2).unwrap_or(false);
+
+        let key = (context_id, request_id);
+        let callback = {
+            let mut requests = self.0.requests.lock();
+            let persistent = match requests.get(&key) {
+                Some(request) => request.persistent,
+                None => return true,
+            };
+            if success && persistent {
+                requests.get(&key).and_then(|request| request.on_success.clone())
+            } else {
+                requests.remove(&key).and_then(|request| {
+                    if success {
+                        request.on_success
+                    } else {
+                        request.on_failure
+                    }
+                })
+            }
+        };
+        let context = self
+            .0
+            .contexts
+            .lock()
+            .get(&context_id)
+            .map(|known| known.context.clone());
+
+        if let (Some(callback), Some(context)) = (callback, context) {
+            if context.is_valid() {
+                let callback_args = if success {
+                    vec![V8Value::from(&*args.get_string(3).unwrap_or_default())]
+                } else {
+                    vec![
+                        V8Value::from(args.get_int(3).unwrap_or_default()),
+                        V8Value::from(&*args.get_string(4).unwrap_or_default()),
+                    ]
+                };
+                callback.execute_function_with_context(context, None, &callback_args);
+            }
+        }
+        true
+    }
+}
+
+impl Default for MessageRouterRendererSide {
+    fn default() -> Self {
+        Self::new(MessageRouterConfig::default())
+    }
+}
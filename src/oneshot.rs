@@ -0,0 +1,77 @@
+//! A minimal single-value channel used to turn CEF callbacks into futures.
+//!
+//! The receiving half resolves to `Err(Canceled)` if the sending half is dropped
+//! without a value being sent, which happens when CEF releases a callback without
+//! ever invoking it.
+
+use parking_lot::Mutex;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+struct Shared<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+pub(crate) struct Sender<T>(Arc<Mutex<Shared<T>>>);
+
+pub(crate) struct Receiver<T>(Arc<Mutex<Shared<T>>>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Canceled;
+
+pub(crate) fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Mutex::new(Shared {
+        value: None,
+        waker: None,
+        closed: false,
+    }));
+    (Sender(shared.clone()), Receiver(shared))
+}
+
+impl<T> Sender<T> {
+    pub(crate) fn send(self, value: T) {
+        let waker = {
+            let mut shared = self.0.lock();
+            shared.value = Some(value);
+            shared.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut shared = self.0.lock();
+            shared.closed = true;
+            shared.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, Canceled>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut shared = self.0.lock();
+        if let Some(value) = shared.value.take() {
+            Poll::Ready(Ok(value))
+        } else if shared.closed {
+            Poll::Ready(Err(Canceled))
+        } else {
+            shared.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
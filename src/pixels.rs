@@ -0,0 +1,797 @@
+//! Converting the pixels delivered to [RenderHandlerCallbacks::on_paint].
+//!
+//! CEF paints BGRA with premultiplied alpha. The functions in this module convert
+//! the dirty rectangles of such a buffer to other formats, leaving the rest of the
+//! destination untouched, so that a persistent destination buffer only has to be
+//! updated where the page changed. Pass `&[Rect::new(0, 0, width, height)]` to
+//! convert a whole buffer.
+//!
+//! The conversions use SSE2, SSSE3 or AVX2 on x86 when the CPU supports them and
+//! NEON on AArch64, with a scalar fallback everywhere else. `benches/pixels.rs`
+//! compares them against a plain per-pixel loop.
+//!
+//! [RenderHandlerCallbacks::on_paint]: crate::client::render_handler::RenderHandlerCallbacks::on_paint
+
+use crate::values::Rect;
+
+/// The instruction set extensions used by the conversions on this machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimdLevel {
+    Scalar,
+    Sse2,
+    Avx2,
+    Neon,
+}
+
+impl SimdLevel {
+    /// Detects the best extensions the current CPU supports.
+    pub fn detect() -> SimdLevel {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                SimdLevel::Avx2
+            } else if is_x86_feature_detected!("sse2") {
+                SimdLevel::Sse2
+            } else {
+                SimdLevel::Scalar
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            SimdLevel::Neon
+        }
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+        {
+            SimdLevel::Scalar
+        }
+    }
+}
+
+/// Converts a row of pixels; the destination holds the same number of pixels.
+type RowKernel = fn(&[u8], &mut [u8]);
+
+/// Computes the chroma of the 2x2 blocks of two rows of pixels into `u` and `v`.
+type ChromaKernel = fn(&[u8], &[u8], &mut [u8], &mut [u8]);
+
+/// Converts the `rects` of the BGRA `src` to RGBA in `dst`. Both buffers are
+/// `width` pixels wide; rectangles are clipped to the smaller of the two.
+pub fn bgra_to_rgba(src: &[u8], dst: &mut [u8], width: i32, rects: &[Rect]) {
+    convert_rects(src, dst, width, 4, rects, swap_rb_kernel());
+}
+
+/// Converts the `rects` of the BGRA `src` to packed RGB in `dst`, dropping alpha.
+/// Both buffers are `width` pixels wide, `dst` with 3 bytes per pixel.
+pub fn bgra_to_rgb(src: &[u8], dst: &mut [u8], width: i32, rects: &[Rect]) {
+    convert_rects(src, dst, width, 3, rects, rgb_kernel());
+}
+
+/// Converts the `rects` of the premultiplied BGRA `src` to RGBA with straight
+/// (not premultiplied) alpha in `dst`. Fully transparent pixels become
+/// transparent black. Both buffers are `width` pixels wide.
+pub fn bgra_to_rgba_unpremultiplied(src: &[u8], dst: &mut [u8], width: i32, rects: &[Rect]) {
+    convert_rects(src, dst, width, 4, rects, unpremultiply_kernel());
+}
+
+/// Returns the sizes of the luma plane and of each chroma plane of an I420 image
+/// of `width` x `height` pixels.
+pub fn i420_plane_sizes(width: i32, height: i32) -> (usize, usize) {
+    let width = width.max(0) as usize;
+    let height = height.max(0) as usize;
+    (width * height, ((width + 1) / 2) * ((height + 1) / 2))
+}
+
+/// Converts the `rects` of the BGRA `src` to I420 (YUV 4:2:0 with separate Y, U
+/// and V planes, BT.601 limited range), as expected by most video encoders. The
+/// luma plane is `width` bytes wide and the chroma planes `(width + 1) / 2`; see
+/// [i420_plane_sizes]. Rectangles are widened to even coordinates, since every
+/// chroma sample covers 2x2 pixels. Alpha is ignored, which amounts to compositing
+/// the premultiplied pixels over black.
+pub fn bgra_to_i420(
+    src: &[u8],
+    width: i32,
+    rects: &[Rect],
+    y_plane: &mut [u8],
+    u_plane: &mut [u8],
+    v_plane: &mut [u8],
+) {
+    if width <= 0 {
+        return;
+    }
+    let w = width as usize;
+    let chroma_width = (w + 1) / 2;
+    let height = (src.len() / (w * 4)).min(y_plane.len() / w) as i32;
+    let chroma_rows = (u_plane.len().min(v_plane.len()) / chroma_width) as i32;
+    let height = height.min(chroma_rows * 2);
+    let bounds = Rect::new(0, 0, width, height);
+    let even: Vec<Rect> = rects
+        .iter()
+        .map(|rect| {
+            let rect = rect.intersect(&bounds);
+            let x = rect.x & !1;
+            let y = rect.y & !1;
+            let right = ((rect.x + rect.width + 1) & !1).min(width);
+            let bottom = ((rect.y + rect.height + 1) & !1).min(height);
+            Rect::new(x, y, right - x, bottom - y)
+        })
+        .filter(|rect| !rect.is_empty())
+        .collect();
+    convert_rects(&src[..w * height as usize * 4], y_plane, width, 1, &even, luma_kernel());
+
+    let chroma = chroma_kernel();
+    for rect in &even {
+        let (x, right) = (rect.x as usize, (rect.x + rect.width) as usize);
+        for cy in (rect.y / 2)..((rect.y + rect.height + 1) / 2) {
+            let row = |y: i32| &src[(y as usize * w + x) * 4..(y as usize * w + right) * 4];
+            let top = row(cy * 2);
+            // The bottom row of an odd height is sampled twice.
+            let bottom = row((cy * 2 + 1).min(height - 1));
+            let first = cy as usize * chroma_width + x / 2;
+            let last = cy as usize * chroma_width + (right + 1) / 2;
+            chroma(top, bottom, &mut u_plane[first..last], &mut v_plane[first..last]);
+        }
+    }
+}
+
+fn convert_rects(src: &[u8], dst: &mut [u8], width: i32, dst_bpp: usize, rects: &[Rect], kernel: RowKernel) {
+    if width <= 0 {
+        return;
+    }
+    let w = width as usize;
+    let height = (src.len() / (w * 4)).min(dst.len() / (w * dst_bpp)) as i32;
+    let bounds = Rect::new(0, 0, width, height);
+    for rect in rects {
+        let rect = rect.intersect(&bounds);
+        if rect.is_empty() {
+            continue;
+        }
+        let count = rect.width as usize;
+        for row in rect.y..rect.y + rect.height {
+            let first = row as usize * w + rect.x as usize;
+            kernel(
+                &src[first * 4..(first + count) * 4],
+                &mut dst[first * dst_bpp..(first + count) * dst_bpp],
+            );
+        }
+    }
+}
+
+fn swap_rb_kernel() -> RowKernel {
+    match SimdLevel::detect() {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Avx2 => x86::swap_rb_avx2,
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Sse2 => x86::swap_rb_sse2,
+        #[cfg(target_arch = "aarch64")]
+        SimdLevel::Neon => neon::swap_rb,
+        _ => scalar::swap_rb,
+    }
+}
+
+fn rgb_kernel() -> RowKernel {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx2") {
+            x86::bgra_to_rgb_avx2
+        } else if is_x86_feature_detected!("ssse3") {
+            x86::bgra_to_rgb_ssse3
+        } else {
+            scalar::bgra_to_rgb
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        neon::bgra_to_rgb
+    }
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        scalar::bgra_to_rgb
+    }
+}
+
+fn unpremultiply_kernel() -> RowKernel {
+    match SimdLevel::detect() {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Avx2 => x86::unpremultiply_avx2,
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Sse2 => x86::unpremultiply_sse2,
+        #[cfg(target_arch = "aarch64")]
+        SimdLevel::Neon => neon::unpremultiply,
+        _ => scalar::unpremultiply,
+    }
+}
+
+fn luma_kernel() -> RowKernel {
+    match SimdLevel::detect() {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Avx2 | SimdLevel::Sse2 => x86::luma_sse2,
+        #[cfg(target_arch = "aarch64")]
+        SimdLevel::Neon => neon::luma,
+        _ => scalar::luma,
+    }
+}
+
+fn chroma_kernel() -> ChromaKernel {
+    match SimdLevel::detect() {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Avx2 | SimdLevel::Sse2 => x86::chroma_sse2,
+        #[cfg(target_arch = "aarch64")]
+        SimdLevel::Neon => neon::chroma,
+        _ => scalar::chroma,
+    }
+}
+
+mod scalar {
+    lazy_static::lazy_static! {
+        /// `(255 << 16) / alpha`, rounded, for unpremultiplying without a division
+        /// per pixel. The SIMD versions use the same table, so that they produce
+        /// exactly the same bytes.
+        pub static ref UNPREMULTIPLY: [u32; 256] = {
+            let mut table = [0; 256];
+            for (alpha, factor) in table.iter_mut().enumerate().skip(1) {
+                *factor = ((255 << 16) + alpha as u32 / 2) / alpha as u32;
+            }
+            table
+        };
+    }
+
+    pub fn swap_rb(src: &[u8], dst: &mut [u8]) {
+        for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+            dst[0] = src[2];
+            dst[1] = src[1];
+            dst[2] = src[0];
+            dst[3] = src[3];
+        }
+    }
+
+    pub fn bgra_to_rgb(src: &[u8], dst: &mut [u8]) {
+        for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(3)) {
+            dst[0] = src[2];
+            dst[1] = src[1];
+            dst[2] = src[0];
+        }
+    }
+
+    pub fn unpremultiply(src: &[u8], dst: &mut [u8]) {
+        for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+            let alpha = src[3];
+            let factor = UNPREMULTIPLY[alpha as usize];
+            let channel = |value: u8| ((value as u32 * factor + (1 << 15)) >> 16).min(255) as u8;
+            dst[0] = channel(src[2]);
+            dst[1] = channel(src[1]);
+            dst[2] = channel(src[0]);
+            dst[3] = alpha;
+        }
+    }
+
+    pub fn luma(src: &[u8], dst: &mut [u8]) {
+        for (src, dst) in src.chunks_exact(4).zip(dst.iter_mut()) {
+            let (b, g, r) = (src[0] as u32, src[1] as u32, src[2] as u32);
+            *dst = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+        }
+    }
+
+    /// Computes the chroma of 2x2 blocks of the pixels in `top` and `bottom`. The
+    /// last column of an odd width is sampled twice.
+    pub fn chroma(top: &[u8], bottom: &[u8], u: &mut [u8], v: &mut [u8]) {
+        let sum = |pair: &[u8], channel: usize| {
+            let second = if pair.len() == 8 { 4 } else { 0 };
+            pair[channel] as i32 + pair[channel + second] as i32
+        };
+        let pairs = top.chunks(8).zip(bottom.chunks(8));
+        for ((top, bottom), (u, v)) in pairs.zip(u.iter_mut().zip(v.iter_mut())) {
+            let b = (sum(top, 0) + sum(bottom, 0) + 2) >> 2;
+            let g = (sum(top, 1) + sum(bottom, 1) + 2) >> 2;
+            let r = (sum(top, 2) + sum(bottom, 2) + 2) >> 2;
+            *u = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            *v = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    //! The safe wrappers in this module may only be called once the CPU features
+    //! they are named after have been detected.
+
+    use super::scalar;
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    pub fn swap_rb_sse2(src: &[u8], dst: &mut [u8]) {
+        unsafe { swap_rb_sse2_impl(src, dst) }
+    }
+    pub fn swap_rb_avx2(src: &[u8], dst: &mut [u8]) {
+        unsafe { swap_rb_avx2_impl(src, dst) }
+    }
+    pub fn bgra_to_rgb_ssse3(src: &[u8], dst: &mut [u8]) {
+        unsafe { bgra_to_rgb_ssse3_impl(src, dst) }
+    }
+    pub fn bgra_to_rgb_avx2(src: &[u8], dst: &mut [u8]) {
+        unsafe { bgra_to_rgb_avx2_impl(src, dst) }
+    }
+    pub fn unpremultiply_sse2(src: &[u8], dst: &mut [u8]) {
+        unsafe { unpremultiply_sse2_impl(src, dst) }
+    }
+    pub fn unpremultiply_avx2(src: &[u8], dst: &mut [u8]) {
+        unsafe { unpremultiply_avx2_impl(src, dst) }
+    }
+    pub fn luma_sse2(src: &[u8], dst: &mut [u8]) {
+        unsafe { luma_sse2_impl(src, dst) }
+    }
+    pub fn chroma_sse2(top: &[u8], bottom: &[u8], u: &mut [u8], v: &mut [u8]) {
+        unsafe { chroma_sse2_impl(top, bottom, u, v) }
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn swap_rb_sse2_impl(src: &[u8], dst: &mut [u8]) {
+        let len = src.len().min(dst.len()) & !3;
+        let alpha_green = _mm_set1_epi32(0xFF00_FF00u32 as i32);
+        let low = _mm_set1_epi32(0xFF);
+        let mut i = 0;
+        while i + 16 <= len {
+            let v = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+            let red = _mm_and_si128(_mm_srli_epi32(v, 16), low);
+            let blue = _mm_slli_epi32(_mm_and_si128(v, low), 16);
+            let v = _mm_or_si128(_mm_and_si128(v, alpha_green), _mm_or_si128(red, blue));
+            _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, v);
+            i += 16;
+        }
+        scalar::swap_rb(&src[i..len], &mut dst[i..len]);
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn swap_rb_avx2_impl(src: &[u8], dst: &mut [u8]) {
+        let len = src.len().min(dst.len()) & !3;
+        let mask = _mm256_setr_epi8(
+            2, 1, 0, 3, 6, 5, 4, 7, 10, 9, 8, 11, 14, 13, 12, 15, 2, 1, 0, 3, 6, 5, 4, 7, 10, 9, 8,
+            11, 14, 13, 12, 15,
+        );
+        let mut i = 0;
+        while i + 32 <= len {
+            let v = _mm256_loadu_si256(src.as_ptr().add(i) as *const __m256i);
+            let v = _mm256_shuffle_epi8(v, mask);
+            _mm256_storeu_si256(dst.as_mut_ptr().add(i) as *mut __m256i, v);
+            i += 32;
+        }
+        swap_rb_sse2_impl(&src[i..len], &mut dst[i..len]);
+    }
+
+    /// Each 16-byte store writes 4 bytes past the 4 converted pixels, which the
+    /// next store overwrites. The loops stop early enough for the last store to
+    /// stay inside `dst`.
+    #[target_feature(enable = "ssse3")]
+    unsafe fn bgra_to_rgb_ssse3_impl(src: &[u8], dst: &mut [u8]) {
+        let pixels = (src.len() / 4).min(dst.len() / 3);
+        let mask = _mm_setr_epi8(2, 1, 0, 6, 5, 4, 10, 9, 8, 14, 13, 12, -1, -1, -1, -1);
+        let mut i = 0;
+        while i + 6 <= pixels {
+            let v = _mm_loadu_si128(src.as_ptr().add(i * 4) as *const __m128i);
+            _mm_storeu_si128(dst.as_mut_ptr().add(i * 3) as *mut __m128i, _mm_shuffle_epi8(v, mask));
+            i += 4;
+        }
+        scalar::bgra_to_rgb(&src[i * 4..pixels * 4], &mut dst[i * 3..pixels * 3]);
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn bgra_to_rgb_avx2_impl(src: &[u8], dst: &mut [u8]) {
+        let pixels = (src.len() / 4).min(dst.len() / 3);
+        let mask = _mm256_setr_epi8(
+            2, 1, 0, 6, 5, 4, 10, 9, 8, 14, 13, 12, -1, -1, -1, -1, 2, 1, 0, 6, 5, 4, 10, 9, 8, 14,
+            13, 12, -1, -1, -1, -1,
+        );
+        let mut i = 0;
+        while i + 10 <= pixels {
+            let v = _mm256_loadu_si256(src.as_ptr().add(i * 4) as *const __m256i);
+            let v = _mm256_shuffle_epi8(v, mask);
+            let out = dst.as_mut_ptr().add(i * 3);
+            _mm_storeu_si128(out as *mut __m128i, _mm256_castsi256_si128(v));
+            _mm_storeu_si128(out.add(12) as *mut __m128i, _mm256_extracti128_si256(v, 1));
+            i += 8;
+        }
+        bgra_to_rgb_ssse3_impl(&src[i * 4..pixels * 4], &mut dst[i * 3..pixels * 3]);
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn unpremultiply_sse2_impl(src: &[u8], dst: &mut [u8]) {
+        let table = &*scalar::UNPREMULTIPLY;
+        let len = src.len().min(dst.len()) & !3;
+        let zero = _mm_setzero_si128();
+        let mut i = 0;
+        while i + 16 <= len {
+            let v = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+            let factor = |pixel: usize| _mm_set1_epi32(table[src[i + pixel * 4 + 3] as usize] as i32);
+            let lo = _mm_unpacklo_epi8(v, zero);
+            let hi = _mm_unpackhi_epi8(v, zero);
+            let p0 = unpremultiply_pixel(_mm_unpacklo_epi16(lo, zero), factor(0));
+            let p1 = unpremultiply_pixel(_mm_unpackhi_epi16(lo, zero), factor(1));
+            let p2 = unpremultiply_pixel(_mm_unpacklo_epi16(hi, zero), factor(2));
+            let p3 = unpremultiply_pixel(_mm_unpackhi_epi16(hi, zero), factor(3));
+            let v = _mm_packus_epi16(_mm_packs_epi32(p0, p1), _mm_packs_epi32(p2, p3));
+            _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, v);
+            i += 16;
+        }
+        scalar::unpremultiply(&src[i..len], &mut dst[i..len]);
+    }
+
+    /// Scales the color channels of one pixel, given as four i32 lanes in BGRA
+    /// order, by the pixel's factor from the scalar table and reorders them to
+    /// RGBA. SSE2 has no 32-bit multiplication, so the even and odd lanes are
+    /// multiplied separately in 64 bits; the products stay below 2^32.
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn unpremultiply_pixel(p: __m128i, factor: __m128i) -> __m128i {
+        let round = _mm_set1_epi64x(1 << 15);
+        let even = _mm_srli_epi64(_mm_add_epi64(_mm_mul_epu32(p, factor), round), 16);
+        let odd = _mm_mul_epu32(_mm_srli_epi64(p, 32), factor);
+        let odd = _mm_srli_epi64(_mm_add_epi64(odd, round), 16);
+        let color = _mm_or_si128(even, _mm_slli_epi64(odd, 32));
+        let alpha_lane = _mm_setr_epi32(0, 0, 0, -1);
+        let p = _mm_or_si128(_mm_and_si128(alpha_lane, p), _mm_andnot_si128(alpha_lane, color));
+        // Values above 255 saturate when the lanes are packed back to bytes.
+        _mm_shuffle_epi32(p, 0xC6)
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn unpremultiply_avx2_impl(src: &[u8], dst: &mut [u8]) {
+        let table = &*scalar::UNPREMULTIPLY;
+        let len = src.len().min(dst.len()) & !3;
+        let round = _mm256_set1_epi32(1 << 15);
+        // Packing interleaves the 128-bit lanes; this puts the pixels back in order.
+        let order = _mm256_setr_epi32(0, 4, 1, 5, 2, 6, 3, 7);
+        let mut i = 0;
+        while i + 32 <= len {
+            let mut pairs = [_mm256_setzero_si256(); 4];
+            for (k, pair) in pairs.iter_mut().enumerate() {
+                let at = i + k * 8;
+                let p = _mm256_cvtepu8_epi32(_mm_loadl_epi64(src.as_ptr().add(at) as *const __m128i));
+                let first = table[src[at + 3] as usize] as i32;
+                let second = table[src[at + 7] as usize] as i32;
+                let factor = _mm256_setr_epi32(first, first, first, first, second, second, second, second);
+                // The products stay below 2^32, so the low halves are exact when
+                // shifted as unsigned.
+                let color = _mm256_srli_epi32(_mm256_add_epi32(_mm256_mullo_epi32(p, factor), round), 16);
+                *pair = _mm256_shuffle_epi32(_mm256_blend_epi32(color, p, 0x88), 0xC6);
+            }
+            let v = _mm256_packus_epi16(
+                _mm256_packs_epi32(pairs[0], pairs[1]),
+                _mm256_packs_epi32(pairs[2], pairs[3]),
+            );
+            let v = _mm256_permutevar8x32_epi32(v, order);
+            _mm256_storeu_si256(dst.as_mut_ptr().add(i) as *mut __m256i, v);
+            i += 32;
+        }
+        unpremultiply_sse2_impl(&src[i..len], &mut dst[i..len]);
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn luma_sse2_impl(src: &[u8], dst: &mut [u8]) {
+        let pixels = (src.len() / 4).min(dst.len());
+        let mut i = 0;
+        while i + 16 <= pixels {
+            let p = src.as_ptr().add(i * 4);
+            let y0 = luma(p);
+            let y1 = luma(p.add(16));
+            let y2 = luma(p.add(32));
+            let y3 = luma(p.add(48));
+            let y = _mm_packus_epi16(_mm_packs_epi32(y0, y1), _mm_packs_epi32(y2, y3));
+            _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, y);
+            i += 16;
+        }
+        scalar::luma(&src[i * 4..pixels * 4], &mut dst[i..pixels]);
+    }
+
+    /// Computes the luma of the four pixels at `p` as i32 lanes. The weighted sums
+    /// stay below 2^16, so 16-bit multiplications are exact.
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn luma(p: *const u8) -> __m128i {
+        let low = _mm_set1_epi32(0xFF);
+        let v = _mm_loadu_si128(p as *const __m128i);
+        let b = _mm_and_si128(v, low);
+        let g = _mm_and_si128(_mm_srli_epi32(v, 8), low);
+        let r = _mm_and_si128(_mm_srli_epi32(v, 16), low);
+        let sum = _mm_add_epi32(
+            _mm_add_epi32(_mm_mullo_epi16(r, _mm_set1_epi32(66)), _mm_mullo_epi16(g, _mm_set1_epi32(129))),
+            _mm_add_epi32(_mm_mullo_epi16(b, _mm_set1_epi32(25)), _mm_set1_epi32(128)),
+        );
+        _mm_add_epi32(_mm_srli_epi32(sum, 8), _mm_set1_epi32(16))
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn chroma_sse2_impl(top: &[u8], bottom: &[u8], u: &mut [u8], v: &mut [u8]) {
+        let pixels = top.len().min(bottom.len()) / 4;
+        let blocks = u.len().min(v.len());
+        let u_weights = _mm_setr_epi16(112, -74, -38, 0, 112, -74, -38, 0);
+        let v_weights = _mm_setr_epi16(-18, -94, 112, 0, -18, -94, 112, 0);
+        let mut i = 0;
+        while i + 8 <= pixels && i / 2 + 4 <= blocks {
+            let load = |row: &[u8], offset: usize| _mm_loadu_si128(row.as_ptr().add(i * 4 + offset) as *const __m128i);
+            let first = block_averages(load(top, 0), load(bottom, 0));
+            let second = block_averages(load(top, 16), load(bottom, 16));
+            let u4 = chroma_samples(first, second, u_weights);
+            let v4 = chroma_samples(first, second, v_weights);
+            u[i / 2..i / 2 + 4].copy_from_slice(&u4.to_ne_bytes());
+            v[i / 2..i / 2 + 4].copy_from_slice(&v4.to_ne_bytes());
+            i += 8;
+        }
+        scalar::chroma(&top[i * 4..], &bottom[i * 4..], &mut u[i / 2..], &mut v[i / 2..]);
+    }
+
+    /// Averages the 2x2 blocks of the four pixels of `top` and `bottom` as 16-bit
+    /// BGRA lanes, rounding like [scalar::chroma].
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn block_averages(top: __m128i, bottom: __m128i) -> __m128i {
+        let zero = _mm_setzero_si128();
+        let lo = _mm_add_epi16(_mm_unpacklo_epi8(top, zero), _mm_unpacklo_epi8(bottom, zero));
+        let hi = _mm_add_epi16(_mm_unpackhi_epi8(top, zero), _mm_unpackhi_epi8(bottom, zero));
+        let sums = _mm_add_epi16(_mm_unpacklo_epi64(lo, hi), _mm_unpackhi_epi64(lo, hi));
+        _mm_srli_epi16(_mm_add_epi16(sums, _mm_set1_epi16(2)), 2)
+    }
+
+    /// Weighs the block averages of `first` and `second` into four chroma bytes.
+    /// The weighted sums fit in 16 bits, and `_mm_madd_epi16` adds them in pairs
+    /// without rounding, so the result matches [scalar::chroma] exactly.
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn chroma_samples(first: __m128i, second: __m128i, weights: __m128i) -> i32 {
+        let weigh = |averages: __m128i| {
+            let sums = _mm_madd_epi16(averages, weights);
+            let sums = _mm_add_epi32(sums, _mm_srli_epi64(sums, 32));
+            let sums = _mm_srai_epi32(_mm_add_epi32(sums, _mm_set1_epi32(128)), 8);
+            // Only lanes 0 and 2 hold a whole block.
+            _mm_shuffle_epi32(_mm_add_epi32(sums, _mm_set1_epi32(128)), 0x08)
+        };
+        let samples = _mm_unpacklo_epi64(weigh(first), weigh(second));
+        let samples = _mm_packs_epi32(samples, samples);
+        _mm_cvtsi128_si32(_mm_packus_epi16(samples, samples))
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use super::scalar;
+    use std::arch::aarch64::*;
+
+    pub fn swap_rb(src: &[u8], dst: &mut [u8]) {
+        let len = src.len().min(dst.len()) & !3;
+        let mut i = 0;
+        unsafe {
+            while i + 64 <= len {
+                let v = vld4q_u8(src.as_ptr().add(i));
+                vst4q_u8(dst.as_mut_ptr().add(i), uint8x16x4_t(v.2, v.1, v.0, v.3));
+                i += 64;
+            }
+        }
+        scalar::swap_rb(&src[i..len], &mut dst[i..len]);
+    }
+
+    pub fn bgra_to_rgb(src: &[u8], dst: &mut [u8]) {
+        let pixels = (src.len() / 4).min(dst.len() / 3);
+        let mut i = 0;
+        unsafe {
+            while i + 16 <= pixels {
+                let v = vld4q_u8(src.as_ptr().add(i * 4));
+                vst3q_u8(dst.as_mut_ptr().add(i * 3), uint8x16x3_t(v.2, v.1, v.0));
+                i += 16;
+            }
+        }
+        scalar::bgra_to_rgb(&src[i * 4..pixels * 4], &mut dst[i * 3..pixels * 3]);
+    }
+
+    pub fn unpremultiply(src: &[u8], dst: &mut [u8]) {
+        let table = &*scalar::UNPREMULTIPLY;
+        let len = src.len().min(dst.len()) & !3;
+        let mut i = 0;
+        unsafe {
+            while i + 64 <= len {
+                let v = vld4q_u8(src.as_ptr().add(i));
+                let mut factors = [0u32; 16];
+                for (k, factor) in factors.iter_mut().enumerate() {
+                    *factor = table[src[i + k * 4 + 3] as usize];
+                }
+                let factors = [
+                    vld1q_u32(factors.as_ptr()),
+                    vld1q_u32(factors.as_ptr().add(4)),
+                    vld1q_u32(factors.as_ptr().add(8)),
+                    vld1q_u32(factors.as_ptr().add(12)),
+                ];
+                let rgba = uint8x16x4_t(
+                    scale(v.2, &factors),
+                    scale(v.1, &factors),
+                    scale(v.0, &factors),
+                    v.3,
+                );
+                vst4q_u8(dst.as_mut_ptr().add(i), rgba);
+                i += 64;
+            }
+        }
+        scalar::unpremultiply(&src[i..len], &mut dst[i..len]);
+    }
+
+    /// Multiplies one channel of 16 pixels by their factors from the scalar table,
+    /// rounding and saturating like [scalar::unpremultiply].
+    #[inline]
+    unsafe fn scale(channel: uint8x16_t, factors: &[uint32x4_t; 4]) -> uint8x16_t {
+        let quarter = |values: uint16x4_t, factor: uint32x4_t| {
+            let product = vmulq_u32(vmovl_u16(values), factor);
+            vqmovn_u32(vshrq_n_u32(vaddq_u32(product, vdupq_n_u32(1 << 15)), 16))
+        };
+        let lo = vmovl_u8(vget_low_u8(channel));
+        let hi = vmovl_u8(vget_high_u8(channel));
+        let lo = vcombine_u16(quarter(vget_low_u16(lo), factors[0]), quarter(vget_high_u16(lo), factors[1]));
+        let hi = vcombine_u16(quarter(vget_low_u16(hi), factors[2]), quarter(vget_high_u16(hi), factors[3]));
+        vcombine_u8(vqmovn_u16(lo), vqmovn_u16(hi))
+    }
+
+    pub fn luma(src: &[u8], dst: &mut [u8]) {
+        let pixels = (src.len() / 4).min(dst.len());
+        let mut i = 0;
+        unsafe {
+            let (kr, kg, kb) = (vdup_n_u8(66), vdup_n_u8(129), vdup_n_u8(25));
+            let round = vdupq_n_u16(128);
+            while i + 16 <= pixels {
+                let v = vld4q_u8(src.as_ptr().add(i * 4));
+                let lo = vmull_u8(vget_low_u8(v.2), kr);
+                let lo = vmlal_u8(lo, vget_low_u8(v.1), kg);
+                let lo = vaddq_u16(vmlal_u8(lo, vget_low_u8(v.0), kb), round);
+                let hi = vmull_u8(vget_high_u8(v.2), kr);
+                let hi = vmlal_u8(hi, vget_high_u8(v.1), kg);
+                let hi = vaddq_u16(vmlal_u8(hi, vget_high_u8(v.0), kb), round);
+                let y = vcombine_u8(vshrn_n_u16(lo, 8), vshrn_n_u16(hi, 8));
+                vst1q_u8(dst.as_mut_ptr().add(i), vaddq_u8(y, vdupq_n_u8(16)));
+                i += 16;
+            }
+        }
+        scalar::luma(&src[i * 4..pixels * 4], &mut dst[i..pixels]);
+    }
+
+    pub fn chroma(top: &[u8], bottom: &[u8], u: &mut [u8], v: &mut [u8]) {
+        let pixels = top.len().min(bottom.len()) / 4;
+        let blocks = u.len().min(v.len());
+        let mut i = 0;
+        unsafe {
+            while i + 16 <= pixels && i / 2 + 8 <= blocks {
+                let t = vld4q_u8(top.as_ptr().add(i * 4));
+                let b = vld4q_u8(bottom.as_ptr().add(i * 4));
+                // Pairwise sums of both rows, rounded to the average of each block.
+                let average = |t: uint8x16_t, b: uint8x16_t| {
+                    vreinterpretq_s16_u16(vrshrq_n_u16(vpadalq_u8(vpaddlq_u8(t), b), 2))
+                };
+                let (blue, green, red) = (average(t.0, b.0), average(t.1, b.1), average(t.2, b.2));
+                vst1_u8(u.as_mut_ptr().add(i / 2), weigh(blue, green, red, [112, -74, -38]));
+                vst1_u8(v.as_mut_ptr().add(i / 2), weigh(blue, green, red, [-18, -94, 112]));
+                i += 16;
+            }
+        }
+        scalar::chroma(&top[i * 4..], &bottom[i * 4..], &mut u[i / 2..], &mut v[i / 2..]);
+    }
+
+    /// Weighs the block averages into chroma bytes. Adding the blue weight first
+    /// keeps every partial sum within 16 bits, so the result matches
+    /// [scalar::chroma] exactly.
+    #[inline]
+    unsafe fn weigh(blue: int16x8_t, green: int16x8_t, red: int16x8_t, weights: [i16; 3]) -> uint8x8_t {
+        let sum = vmulq_n_s16(blue, weights[0]);
+        let sum = vmlaq_n_s16(sum, green, weights[1]);
+        let sum = vmlaq_n_s16(sum, red, weights[2]);
+        let sum = vshrq_n_s16(vaddq_s16(sum, vdupq_n_s16(128)), 8);
+        vqmovun_s16(vaddq_s16(sum, vdupq_n_s16(128)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every color value with every alpha, followed by a few pixels that only the
+    /// scalar tails of the kernels convert.
+    fn pixels() -> Vec<u8> {
+        let mut src = Vec::with_capacity(256 * 256 * 4 + 28);
+        for alpha in 0..=255u8 {
+            for value in 0..=255u8 {
+                src.extend_from_slice(&[value, value.wrapping_mul(7), value ^ alpha, alpha]);
+            }
+        }
+        src.extend((0..28).map(|i| i * 9));
+        src
+    }
+
+    fn simd_row_kernels() -> Vec<(&'static str, RowKernel, RowKernel, usize)> {
+        let mut kernels: Vec<(&'static str, RowKernel, RowKernel, usize)> = Vec::new();
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("sse2") {
+                kernels.push(("swap_rb_sse2", scalar::swap_rb, x86::swap_rb_sse2, 4));
+                kernels.push(("unpremultiply_sse2", scalar::unpremultiply, x86::unpremultiply_sse2, 4));
+                kernels.push(("luma_sse2", scalar::luma, x86::luma_sse2, 1));
+            }
+            if is_x86_feature_detected!("ssse3") {
+                kernels.push(("bgra_to_rgb_ssse3", scalar::bgra_to_rgb, x86::bgra_to_rgb_ssse3, 3));
+            }
+            if is_x86_feature_detected!("avx2") {
+                kernels.push(("swap_rb_avx2", scalar::swap_rb, x86::swap_rb_avx2, 4));
+                kernels.push(("bgra_to_rgb_avx2", scalar::bgra_to_rgb, x86::bgra_to_rgb_avx2, 3));
+                kernels.push(("unpremultiply_avx2", scalar::unpremultiply, x86::unpremultiply_avx2, 4));
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            kernels.push(("swap_rb", scalar::swap_rb, neon::swap_rb, 4));
+            kernels.push(("bgra_to_rgb", scalar::bgra_to_rgb, neon::bgra_to_rgb, 3));
+            kernels.push(("unpremultiply", scalar::unpremultiply, neon::unpremultiply, 4));
+            kernels.push(("luma", scalar::luma, neon::luma, 1));
+        }
+        kernels
+    }
+
+    #[test]
+    fn simd_kernels_match_scalar() {
+        let src = pixels();
+        let total = src.len() / 4;
+        for (name, expected, kernel, dst_bpp) in simd_row_kernels() {
+            for &(first, count) in &[(0, total), (1, total - 1), (3, 37), (5, 15), (2, 1), (0, 0)] {
+                let src = &src[first * 4..(first + count) * 4];
+                let mut want = vec![0; count * dst_bpp];
+                let mut got = vec![0; count * dst_bpp];
+                expected(src, &mut want);
+                kernel(src, &mut got);
+                assert!(want == got, "{} differs for {} pixels at {}", name, count, first);
+            }
+        }
+    }
+
+    #[test]
+    fn unpremultiply_rounds_to_nearest() {
+        let mut dst = [0; 12];
+        scalar::unpremultiply(&[0, 0, 0, 0, 64, 32, 128, 128, 200, 100, 255, 100], &mut dst);
+        assert_eq!(dst, [0, 0, 0, 0, 255, 64, 128, 128, 255, 255, 255, 100]);
+    }
+
+    fn simd_chroma_kernels() -> Vec<(&'static str, ChromaKernel)> {
+        let mut kernels: Vec<(&'static str, ChromaKernel)> = Vec::new();
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("sse2") {
+                kernels.push(("chroma_sse2", x86::chroma_sse2));
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        kernels.push(("chroma", neon::chroma));
+        kernels
+    }
+
+    #[test]
+    fn simd_chroma_matches_scalar() {
+        let src = pixels();
+        let row = 67 * 4;
+        for (name, kernel) in simd_chroma_kernels() {
+            for offset in (0..src.len() - row * 2).step_by(row * 2 + 12) {
+                let (top, bottom) = (&src[offset..offset + row], &src[offset + row..offset + row * 2]);
+                for width in (0..=67).filter(|width| width % 7 < 3 || *width > 60) {
+                    let (top, bottom) = (&top[..width * 4], &bottom[..width * 4]);
+                    let blocks = (width + 1) / 2;
+                    let (mut want_u, mut want_v) = (vec![0; blocks], vec![0; blocks]);
+                    let (mut got_u, mut got_v) = (vec![0; blocks], vec![0; blocks]);
+                    scalar::chroma(top, bottom, &mut want_u, &mut want_v);
+                    kernel(top, bottom, &mut got_u, &mut got_v);
+                    assert!(want_u == got_u && want_v == got_v, "{} differs for width {}", name, width);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn i420_of_gray_is_neutral() {
+        let (width, height) = (5, 3);
+        let src = vec![128; width * height * 4];
+        let (luma, chroma) = i420_plane_sizes(width as i32, height as i32);
+        let (mut y, mut u, mut v) = (vec![0; luma], vec![0; chroma], vec![0; chroma]);
+        let rects = [Rect::new(0, 0, width as i32, height as i32)];
+        bgra_to_i420(&src, width as i32, &rects, &mut y, &mut u, &mut v);
+        assert!(y.iter().all(|&y| y == 126));
+        assert!(u.iter().chain(&v).all(|&c| c == 128));
+    }
+}
@@ -0,0 +1,755 @@
+//! Recording what a windowless browser renders.
+//!
+//! A [Recorder] is a [PaintObserver] that composites the paints of a browser and
+//! samples them at a fixed frame rate, writing either an uncompressed Y4M stream,
+//! which ffmpeg and most players read, or a numbered PNG sequence. When the page
+//! doesn't repaint, the last frame is repeated, so the recording plays back in
+//! real time. Audio can additionally be captured to a WAV file next to it.
+//!
+//! The files are written on a separate thread, so that the thread that paints
+//! isn't blocked by the disk. If the disk can't keep up for a while, painting
+//! waits once a small queue of frames is full.
+//!
+//! ```ignore
+//! let recorder = Recorder::y4m_file("session.y4m", 30)?.with_wav_file("session.wav")?;
+//!
+//! impl ClientCallbacks for MyClient {
+//!     fn get_render_handler(&self) -> Option<RenderHandler> {
+//!         Some(RenderHandler::new(ObservedRenderHandler::new(self.view.clone(), self.recorder.clone())))
+//!     }
+//!     fn get_audio_handler(&self) -> Option<AudioHandler> {
+//!         Some(self.recorder.audio_handler())
+//!     }
+//! }
+//!
+//! recorder.apply_frame_rate(&browser.get_host());
+//! // ...
+//! recorder.finish()?;
+//! ```
+
+use crate::{
+    browser::Browser,
+    browser_host::{BrowserHost, PaintElementType},
+    client::{
+        audio_handler::{AudioHandler, AudioHandlerCallbacks, AudioParameters},
+        render_handler::PaintObserver,
+    },
+    compositor::Compositor,
+    image::{AlphaType, ColorType, Image},
+    pixels,
+    values::Rect,
+};
+use parking_lot::Mutex;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// The number of jobs queued for the writer thread before recording blocks.
+const QUEUE_LEN: usize = 16;
+
+enum Sink {
+    Y4m {
+        /// The frame size, once the header has been written.
+        size: Option<(i32, i32)>,
+        /// The current frame as I420 planes, shared with the writer thread until
+        /// it has written them.
+        planes: Arc<[Vec<u8>; 3]>,
+    },
+    Png {
+        directory: PathBuf,
+        /// The current frame encoded as PNG, or None if it changed since it was
+        /// last encoded.
+        encoded: Option<Arc<Vec<u8>>>,
+    },
+}
+
+/// The I/O done by the writer thread.
+enum Job {
+    Y4mHeader(Vec<u8>),
+    Y4mFrame(Arc<[Vec<u8>; 3]>),
+    Png(PathBuf, Arc<Vec<u8>>),
+    WavFile(BufWriter<File>),
+    WavData(Vec<u8>),
+    /// Flush the files, completing the WAV header with the given format and data
+    /// length.
+    Finish(Option<(u32, u16, u32)>),
+}
+
+/// The handle of the writer thread.
+struct Writer {
+    sender: Option<SyncSender<Job>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+struct Video {
+    sink: Sink,
+    compositor: Compositor,
+    frame_rate: u32,
+    /// When the first frame was painted, as an instant and as wall-clock time.
+    start: Option<(Instant, SystemTime)>,
+    /// The index of the next frame to be written.
+    next_frame: u64,
+    has_frame: bool,
+}
+
+struct Audio {
+    sample_rate: u32,
+    channels: u16,
+    /// The number of sample frames written.
+    frames: u64,
+    /// The presentation time of the first sample frame, which is the start of the
+    /// video, in milliseconds since the Unix epoch.
+    start_pts: Option<i64>,
+    /// Set while a stream whose format differs from the file is playing.
+    ignoring: bool,
+}
+
+struct State {
+    video: Video,
+    audio: Option<Audio>,
+    writer: Writer,
+    finished: bool,
+    error: Option<io::Error>,
+}
+
+/// Records the paints of a windowless browser. Cloning it returns another handle
+/// to the same recording.
+#[derive(Clone)]
+pub struct Recorder(Arc<Mutex<State>>);
+
+impl Recorder {
+    fn new(sink: Sink, y4m: Option<Box<dyn Write + Send>>, frame_rate: u32) -> Recorder {
+        Recorder(Arc::new(Mutex::new(State {
+            video: Video {
+                sink,
+                compositor: Compositor::new(),
+                frame_rate: frame_rate.max(1),
+                start: None,
+                next_frame: 0,
+                has_frame: false,
+            },
+            audio: None,
+            writer: Writer::spawn(y4m),
+            finished: false,
+            error: None,
+        })))
+    }
+    /// Record a Y4M stream (4:2:0, BT.601 limited range) to `writer` at
+    /// `frame_rate` frames per second. The view must not change its size while
+    /// recording, since Y4M frames all have the size given in the header.
+    pub fn y4m(writer: impl Write + Send + 'static, frame_rate: u32) -> Recorder {
+        Recorder::new(
+            Sink::Y4m {
+                size: None,
+                planes: Default::default(),
+            },
+            Some(Box::new(writer)),
+            frame_rate,
+        )
+    }
+    /// Record a Y4M stream to a new file at `path`. See [Recorder::y4m].
+    pub fn y4m_file(path: impl AsRef<Path>, frame_rate: u32) -> io::Result<Recorder> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Recorder::y4m(file, frame_rate))
+    }
+    /// Record numbered PNG files (`000000.png`, `000001.png`, ...) to `directory`
+    /// at `frame_rate` frames per second, creating the directory if needed. Frames
+    /// are encoded with [Image], so the browser must paint on the browser process
+    /// UI thread, as it does by default, and [finish](Recorder::finish) must be
+    /// called there too.
+    pub fn png_sequence(directory: impl Into<PathBuf>, frame_rate: u32) -> io::Result<Recorder> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(Recorder::new(
+            Sink::Png {
+                directory,
+                encoded: None,
+            },
+            None,
+            frame_rate,
+        ))
+    }
+    /// Additionally record the audio received by [audio_handler](Recorder::audio_handler)
+    /// as 16-bit PCM to a WAV file at `path`. The audio is aligned to the start of
+    /// the video: audio that starts later is preceded by silence, and audio played
+    /// before the first paint is dropped.
+    pub fn with_wav_file(self, path: impl AsRef<Path>) -> io::Result<Recorder> {
+        let mut file = BufWriter::new(File::create(path)?);
+        // Written again with the final sizes by finish.
+        write_wav_header(&mut file, 0, 0, 0)?;
+        let mut state = self.0.lock();
+        state.writer.send(Job::WavFile(file))?;
+        state.audio = Some(Audio {
+            sample_rate: 0,
+            channels: 0,
+            frames: 0,
+            start_pts: None,
+            ignoring: false,
+        });
+        drop(state);
+        Ok(self)
+    }
+
+    /// Returns the number of frames per second that are recorded.
+    pub fn frame_rate(&self) -> u32 {
+        self.0.lock().video.frame_rate
+    }
+    /// Make the browser paint at the rate of the recording. CEF doesn't paint more
+    /// than 60 frames per second, so at higher recording rates the browser is set
+    /// to 60 and frames are repeated to keep the recording in real time.
+    pub fn apply_frame_rate(&self, host: &BrowserHost) {
+        let frame_rate = self.frame_rate();
+        if frame_rate > 60 {
+            log::warn!("recording at {} frames per second, but the browser paints at most 60", frame_rate);
+        }
+        host.set_windowless_frame_rate(frame_rate.min(60) as i32);
+    }
+    /// Set the ratio between physical and logical pixels of the view, which is
+    /// needed to place popups correctly.
+    pub fn set_device_scale_factor(&self, device_scale_factor: f32) {
+        self.0
+            .lock()
+            .video
+            .compositor
+            .set_device_scale_factor(device_scale_factor);
+    }
+    /// Returns the number of video frames written so far.
+    pub fn frames_written(&self) -> u64 {
+        self.0.lock().video.next_frame
+    }
+    /// Returns the length of the video written so far.
+    pub fn duration(&self) -> Duration {
+        let state = self.0.lock();
+        Duration::from_secs(state.video.next_frame) / state.video.frame_rate
+    }
+    /// Returns a handler that captures the audio of the browser, if a WAV file was
+    /// set with [with_wav_file](Recorder::with_wav_file). Return it from
+    /// [ClientCallbacks::get_audio_handler].
+    ///
+    /// [ClientCallbacks::get_audio_handler]: crate::client::ClientCallbacks::get_audio_handler
+    pub fn audio_handler(&self) -> AudioHandler {
+        AudioHandler::new(self.clone())
+    }
+
+    /// Stop recording: write the last frame, repeated until now, and complete the
+    /// files. Returns the first error that occurred while recording. A Y4M
+    /// recording fails if nothing was painted, since its header needs the frame
+    /// size.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut guard = self.0.lock();
+        let state = &mut *guard;
+        if state.finished {
+            return Ok(());
+        }
+        state.finished = true;
+        if state.error.is_none() {
+            let wav_format = state.audio.as_ref().map(Audio::format);
+            let video = state.video.finish(&state.writer, Instant::now());
+            // The WAV file is completed even if the video couldn't be.
+            let result = video.and(state.writer.send(Job::Finish(wav_format)));
+            // The writer thread's error explains why sending failed.
+            if let Err(error) = state.writer.join().and(result) {
+                state.error = Some(error);
+            }
+        }
+        match state.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn record(&self, f: impl FnOnce(&mut State) -> io::Result<()>) {
+        let mut state = self.0.lock();
+        if state.finished || state.error.is_some() {
+            return;
+        }
+        if let Err(error) = f(&mut state) {
+            let error = state.writer.join().err().unwrap_or(error);
+            log::error!("recording failed: {}", error);
+            state.error = Some(error);
+        }
+    }
+}
+
+impl Writer {
+    fn spawn(y4m: Option<Box<dyn Write + Send>>) -> Writer {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_LEN);
+        Writer {
+            sender: Some(sender),
+            thread: Some(thread::spawn(move || Writer::run(receiver, y4m))),
+        }
+    }
+
+    /// Queues a job, waiting while the queue is full. Fails if the writer thread
+    /// stopped because of an error, which [join](Writer::join) returns.
+    fn send(&self, job: Job) -> io::Result<()> {
+        let sent = self.sender.as_ref().map_or(false, |sender| sender.send(job).is_ok());
+        if sent {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::Other, "the recording writer stopped"))
+        }
+    }
+
+    /// Waits for the queued jobs to be done and returns the first error.
+    fn join(&mut self) -> io::Result<()> {
+        self.sender = None;
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::new(io::ErrorKind::Other, "the recording writer panicked")),
+            None => Ok(()),
+        }
+    }
+
+    fn run(receiver: Receiver<Job>, mut y4m: Option<Box<dyn Write + Send>>) -> io::Result<()> {
+        let mut wav = None;
+        for job in receiver {
+            match job {
+                Job::Y4mHeader(header) => {
+                    if let Some(writer) = &mut y4m {
+                        writer.write_all(&header)?;
+                    }
+                }
+                Job::Y4mFrame(planes) => {
+                    if let Some(writer) = &mut y4m {
+                        writer.write_all(b"FRAME\n")?;
+                        for plane in planes.iter() {
+                            writer.write_all(plane)?;
+                        }
+                    }
+                }
+                Job::Png(path, png) => fs::write(path, &*png)?,
+                Job::WavFile(file) => wav = Some(file),
+                Job::WavData(data) => {
+                    if let Some(file) = &mut wav {
+                        file.write_all(&data)?;
+                    }
+                }
+                Job::Finish(wav_format) => {
+                    if let Some(writer) = &mut y4m {
+                        writer.flush()?;
+                    }
+                    if let (Some(file), Some((sample_rate, channels, data_len))) = (&mut wav, wav_format) {
+                        file.seek(SeekFrom::Start(0))?;
+                        write_wav_header(file, sample_rate, channels, data_len)?;
+                        file.flush()?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Video {
+    /// Returns the index of the frame shown at `now`.
+    fn frame_at(&self, now: Instant) -> u64 {
+        match self.start {
+            Some((start, _)) => {
+                let elapsed = now.saturating_duration_since(start);
+                (elapsed.as_secs_f64() * self.frame_rate as f64) as u64
+            }
+            None => 0,
+        }
+    }
+
+    /// Writes the frames before `now`, which show the state before a paint at
+    /// `now`.
+    fn write_frames_before(&mut self, writer: &Writer, now: Instant) -> io::Result<()> {
+        if self.has_frame {
+            let frame = self.frame_at(now);
+            while self.next_frame < frame {
+                self.write_frame(writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_paint(
+        &mut self,
+        now: Instant,
+        type_: PaintElementType,
+        dirty_rects: &[Rect],
+        buffer: &[u8],
+        width: i32,
+        height: i32,
+    ) -> io::Result<()> {
+        if self.start.is_none() {
+            self.start = Some((now, SystemTime::now()));
+        }
+        self.compositor.on_paint(type_, dirty_rects, buffer, width, height);
+        let damage = self.compositor.take_damage();
+        if damage.is_empty() {
+            return Ok(());
+        }
+        self.has_frame = true;
+        match &mut self.sink {
+            Sink::Y4m { planes, .. } => {
+                let (width, height) = (self.compositor.width(), self.compositor.height());
+                let (luma, chroma) = pixels::i420_plane_sizes(width, height);
+                if planes[0].len() != luma {
+                    *planes = Arc::new([vec![16; luma], vec![128; chroma], vec![128; chroma]]);
+                }
+                // Copies the planes if the writer thread still holds the last frame.
+                let [y, u, v] = Arc::make_mut(planes);
+                pixels::bgra_to_i420(self.compositor.buffer(), width, &damage, y, u, v);
+            }
+            Sink::Png { encoded, .. } => *encoded = None,
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, writer: &Writer, now: Instant) -> io::Result<()> {
+        if !self.has_frame {
+            return match self.sink {
+                Sink::Y4m { .. } => Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "nothing was painted during the Y4M recording",
+                )),
+                Sink::Png { .. } => Ok(()),
+            };
+        }
+        let frame = self.frame_at(now);
+        while self.next_frame <= frame {
+            self.write_frame(writer)?;
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self, writer: &Writer) -> io::Result<()> {
+        let (width, height) = (self.compositor.width(), self.compositor.height());
+        match &mut self.sink {
+            Sink::Y4m { size, planes } => {
+                match *size {
+                    None => {
+                        let header = format!(
+                            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg XCOLORRANGE=LIMITED\n",
+                            width, height, self.frame_rate
+                        );
+                        writer.send(Job::Y4mHeader(header.into_bytes()))?;
+                        *size = Some((width, height));
+                    }
+                    Some(size) if size != (width, height) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "the view was resized during a Y4M recording",
+                        ));
+                    }
+                    Some(_) => (),
+                }
+                writer.send(Job::Y4mFrame(planes.clone()))?;
+            }
+            Sink::Png { directory, encoded } => {
+                if encoded.is_none() {
+                    let image = Image::new();
+                    let encoded_png = if image.add_bitmap(
+                        1.0,
+                        width,
+                        height,
+                        ColorType::Bgra8888,
+                        AlphaType::Premultiplied,
+                        self.compositor.buffer(),
+                    ) {
+                        image.get_as_png(1.0, true)
+                    } else {
+                        None
+                    };
+                    match encoded_png {
+                        Some(png) => *encoded = Some(Arc::new(png.data)),
+                        None => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::Other,
+                                "encoding a frame as PNG failed",
+                            ))
+                        }
+                    }
+                }
+                let path = directory.join(format!("{:06}.png", self.next_frame));
+                writer.send(Job::Png(path, encoded.clone().unwrap()))?;
+            }
+        }
+        self.next_frame += 1;
+        Ok(())
+    }
+}
+
+impl Audio {
+    fn on_packet(
+        &mut self,
+        writer: &Writer,
+        data: &[&f32],
+        frames: usize,
+        pts: i64,
+        video_start: Option<SystemTime>,
+    ) -> io::Result<()> {
+        let channels = self.channels as usize;
+        if self.ignoring || channels == 0 || data.len() < channels {
+            return Ok(());
+        }
+        let planes: Vec<&[f32]> = data[..channels]
+            .iter()
+            .map(|plane| unsafe { std::slice::from_raw_parts(*plane as *const f32, frames) })
+            .collect();
+        match self.encode(&planes, pts, video_start) {
+            Some(bytes) => writer.send(Job::WavData(bytes)),
+            None => Ok(()),
+        }
+    }
+
+    /// Converts the planes of a packet to interleaved 16-bit PCM aligned to the
+    /// start of the video, or returns None if nothing has been painted yet.
+    fn encode(&mut self, planes: &[&[f32]], pts: i64, video_start: Option<SystemTime>) -> Option<Vec<u8>> {
+        let channels = planes.len();
+        let frames = planes.first().map_or(0, |plane| plane.len());
+        let start_pts = match (self.start_pts, video_start) {
+            (Some(start_pts), _) => start_pts,
+            (None, Some(video_start)) => {
+                let since_epoch = video_start.duration_since(UNIX_EPOCH).unwrap_or_default();
+                *self.start_pts.get_or_insert(since_epoch.as_millis() as i64)
+            }
+            (None, None) => return None,
+        };
+        // Drop the samples played before the video started.
+        let skipped = ((start_pts - pts).max(0) as u64 * self.sample_rate as u64 / 1000).min(frames as u64) as usize;
+        let mut bytes = Vec::with_capacity((frames - skipped) * channels * 2);
+        // Fill gaps, e.g. before the first stream or between two streams, with
+        // silence.
+        let expected = (pts - start_pts).max(0) as u64 * self.sample_rate as u64 / 1000;
+        if expected > self.frames + self.sample_rate as u64 / 10 {
+            bytes.resize((expected - self.frames) as usize * channels * 2, 0);
+            self.frames = expected;
+        }
+        for frame in skipped..frames {
+            for plane in planes {
+                let sample = (plane[frame].max(-1.0).min(1.0) * i16::max_value() as f32) as i16;
+                bytes.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+        self.frames += (frames - skipped) as u64;
+        Some(bytes)
+    }
+
+    /// Returns the sample rate, channel count and data length for the WAV header.
+    fn format(&self) -> (u32, u16, u32) {
+        let data_len = self.frames * self.channels as u64 * 2;
+        (self.sample_rate, self.channels, data_len as u32)
+    }
+}
+
+fn write_wav_header(writer: &mut impl Write, sample_rate: u32, channels: u16, data_len: u32) -> io::Result<()> {
+    let block_align = channels * 2;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_len).to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    // Integer PCM.
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&16u16.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())
+}
+
+impl PaintObserver for Recorder {
+    fn on_paint(
+        &self,
+        _browser: &Browser,
+        type_: PaintElementType,
+        dirty_rects: &[Rect],
+        buffer: &[u8],
+        width: i32,
+        height: i32,
+    ) {
+        let now = Instant::now();
+        self.record(|state| {
+            state.video.write_frames_before(&state.writer, now)?;
+            state.video.on_paint(now, type_, dirty_rects, buffer, width, height)
+        });
+    }
+    fn on_popup_show(&self, _browser: &Browser, show: bool) {
+        self.0.lock().video.compositor.on_popup_show(show);
+    }
+    fn on_popup_size(&self, _browser: &Browser, rect: Rect) {
+        self.0.lock().video.compositor.on_popup_size(rect);
+    }
+}
+
+impl AudioHandlerCallbacks for Recorder {
+    fn get_audio_parameters(&self, _browser: Browser, _params: &mut AudioParameters) -> bool {
+        self.0.lock().audio.is_some()
+    }
+    fn on_audio_stream_started(&self, _browser: Browser, params: &AudioParameters, channels: usize) {
+        self.record(|state| {
+            if let Some(audio) = &mut state.audio {
+                if audio.start_pts.is_none() {
+                    audio.sample_rate = params.sample_rate as u32;
+                    audio.channels = channels as u16;
+                }
+                audio.ignoring = audio.sample_rate != params.sample_rate as u32
+                    || audio.channels as usize != channels;
+                if audio.ignoring {
+                    log::warn!("ignoring an audio stream with a different format than the WAV file");
+                }
+            }
+            Ok(())
+        });
+    }
+    fn on_audio_stream_packet(&self, _browser: Browser, data: &[&f32], frames: usize, pts: i64) {
+        self.record(|state| {
+            let video_start = state.video.start.map(|(_, start)| start);
+            match &mut state.audio {
+                Some(audio) => audio.on_packet(&state.writer, data, frames, pts, video_start),
+                None => Ok(()),
+            }
+        });
+    }
+    fn on_audio_stream_stopped(&self, _browser: Browser) {}
+    fn on_audio_stream_error(&self, _browser: Browser, message: &str) {
+        log::error!("audio capture failed: {}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(frame_rate: u32) -> Video {
+        Video {
+            sink: Sink::Y4m {
+                size: None,
+                planes: Default::default(),
+            },
+            compositor: Compositor::new(),
+            frame_rate,
+            start: None,
+            next_frame: 0,
+            has_frame: false,
+        }
+    }
+
+    fn audio(sample_rate: u32, channels: u16) -> Audio {
+        Audio {
+            sample_rate,
+            channels,
+            frames: 0,
+            start_pts: None,
+            ignoring: false,
+        }
+    }
+
+    /// Returns the samples of interleaved 16-bit PCM.
+    fn samples(bytes: &[u8]) -> Vec<i16> {
+        bytes
+            .chunks(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn wav_header() {
+        let mut header = Vec::new();
+        write_wav_header(&mut header, 48000, 2, 400).unwrap();
+        assert_eq!(header.len(), 44);
+        assert_eq!(&header[0..4], b"RIFF");
+        assert_eq!(&header[4..8], &436u32.to_le_bytes());
+        assert_eq!(&header[8..16], b"WAVEfmt ");
+        assert_eq!(&header[22..24], &2u16.to_le_bytes());
+        assert_eq!(&header[24..28], &48000u32.to_le_bytes());
+        // Bytes per second and per sample frame.
+        assert_eq!(&header[28..32], &192_000u32.to_le_bytes());
+        assert_eq!(&header[32..34], &4u16.to_le_bytes());
+        assert_eq!(&header[36..40], b"data");
+        assert_eq!(&header[40..44], &400u32.to_le_bytes());
+    }
+
+    #[test]
+    fn frame_times() {
+        let mut video = video(30);
+        let start = Instant::now();
+        assert_eq!(video.frame_at(start + Duration::from_secs(1)), 0);
+        video.start = Some((start, SystemTime::now()));
+        assert_eq!(video.frame_at(start), 0);
+        assert_eq!(video.frame_at(start + Duration::from_millis(33)), 0);
+        assert_eq!(video.frame_at(start + Duration::from_millis(34)), 1);
+        assert_eq!(video.frame_at(start + Duration::from_secs(2)), 60);
+        // Instants before the start, e.g. from another thread, show the first frame.
+        assert_eq!(video.frame_at(start - Duration::from_millis(10)), 0);
+    }
+
+    #[test]
+    fn audio_alignment() {
+        let video_start = UNIX_EPOCH + Duration::from_secs(1000);
+        let start_pts = 1_000_000;
+        let mut audio = audio(1000, 2);
+        let left = [0.5; 10];
+        let right = [-1.5; 10];
+
+        // Nothing is written before the first paint.
+        assert_eq!(audio.encode(&[&left, &right], start_pts, None), None);
+        assert_eq!(audio.frames, 0);
+
+        // Samples played before the video started are dropped, and samples are
+        // clamped.
+        let bytes = audio.encode(&[&left, &right], start_pts - 4, Some(video_start)).unwrap();
+        assert_eq!(audio.start_pts, Some(start_pts));
+        assert_eq!(samples(&bytes), [16383, -32767].repeat(6));
+        assert_eq!(audio.frames, 6);
+
+        // Small gaps are ignored, longer ones are filled with silence.
+        let bytes = audio.encode(&[&left[..2], &right[..2]], start_pts + 50, None).unwrap();
+        assert_eq!(bytes.len(), 2 * 4);
+        assert_eq!(audio.frames, 8);
+        let bytes = audio.encode(&[&left[..2], &right[..2]], start_pts + 500, None).unwrap();
+        let samples = samples(&bytes);
+        assert_eq!(samples.len(), 2 * 494);
+        assert!(samples[..2 * 492].iter().all(|&sample| sample == 0));
+        assert_eq!(&samples[2 * 492..], [16383, -32767, 16383, -32767]);
+        assert_eq!(audio.frames, 502);
+        assert_eq!(audio.format(), (1000, 2, 2008));
+
+        // A packet that ended before the video started writes nothing.
+        let mut audio = self::audio(1000, 1);
+        let bytes = audio.encode(&[&left], start_pts - 20, Some(video_start)).unwrap();
+        assert!(bytes.is_empty());
+        assert_eq!(audio.frames, 0);
+    }
+
+    #[test]
+    fn empty_y4m_recordings_fail() {
+        assert!(Recorder::y4m(io::sink(), 30).finish().is_err());
+    }
+
+    #[test]
+    fn y4m_header_uses_the_painted_size() {
+        let recorder = Recorder::y4m(io::sink(), 30);
+        {
+            let mut state = recorder.0.lock();
+            let state = &mut *state;
+            let rect = Rect::new(0, 0, 4, 2);
+            let buffer = [255; 4 * 2 * 4];
+            let now = Instant::now();
+            state
+                .video
+                .on_paint(now, PaintElementType::View, &[rect], &buffer, 4, 2)
+                .unwrap();
+            state.video.finish(&state.writer, now).unwrap();
+            match state.video.sink {
+                Sink::Y4m { size, .. } => assert_eq!(size, Some((4, 2))),
+                Sink::Png { .. } => unreachable!(),
+            }
+        }
+        assert_eq!(recorder.frames_written(), 1);
+        recorder.finish().unwrap();
+    }
+}
@@ -55,7 +55,9 @@ pub trait RenderProcessHandlerCallbacks: 'static + Send {
     /// on the associated thread can be retrieved via the
     /// [V8Context::get_task_runner()] function.
     fn on_context_created(&self, browser: Browser, frame: Frame, context: V8Context) {}
-    /// Called immediately before the V8 context for a frame is released.
+    /// Called immediately before the V8 context for a frame is released. Promises
+    /// created with [V8Value::new_promise] that are still pending afterwards are
+    /// rejected.
     fn on_context_released(&self, browser: Browser, frame: Frame, context: V8Context) {}
     /// Called for global uncaught exceptions in a frame. Execution of this
     /// callback is disabled by default. To enable set
@@ -86,6 +88,12 @@ pub trait RenderProcessHandlerCallbacks: 'static + Send {
     }
 }
 
+/// Used when the application doesn't provide a render process handler, so that
+/// the messages handled internally by the wrapper still get dispatched.
+pub(crate) struct DefaultRenderProcessHandler;
+
+impl RenderProcessHandlerCallbacks for DefaultRenderProcessHandler {}
+
 #[repr(transparent)]
 pub(crate) struct RenderProcessHandlerWrapper(SendProtector<Box<dyn RenderProcessHandlerCallbacks>>);
 
@@ -145,7 +153,9 @@ cef_callback_impl! {
             &self,
             browser: Browser: *mut cef_browser_t,
         ) {
+            let browser_id = browser.get_identifier();
             unsafe{ self.0.get() }.on_browser_destroyed(browser);
+            crate::rpc::cancel_for_browser(browser_id);
         }
 
         fn get_load_handler(
@@ -173,11 +183,14 @@ cef_callback_impl! {
             frame: Frame: *mut cef_frame_t,
             context: V8Context: *mut cef_v8context_t,
         ) {
-            unsafe{ self.0.get() }.on_context_created(
+            unsafe{ self.0.get() }.on_context_released(
                 browser,
-                frame,
-                context,
+                frame.clone(),
+                context.clone(),
             );
+            crate::rpc::cancel_for_frame(&frame);
+            crate::v8context::promise::on_context_released(&context);
+            crate::v8context::de::on_context_released(&context);
         }
 
         fn uncaught_exception(
@@ -218,6 +231,9 @@ cef_callback_impl! {
             message: ProcessMessage: *mut cef_process_message_t,
         ) -> std::os::raw::c_int {
             assert_eq!(ProcessId::Browser, source_process);
+            if crate::eval::on_render_message_received(&frame, &message) {
+                return 1;
+            }
             unsafe{ self.0.get() }.on_process_message_received(
                 browser,
                 frame,
@@ -266,10 +266,10 @@ impl Request {
             })
             .unwrap_or(ReferrerPolicy::Default)
     }
-    /// Get the post data.
-    pub fn get_post_data(&self) -> PostData {
+    /// Get the post data, or None if the request has no body.
+    pub fn get_post_data(&self) -> Option<PostData> {
         let get_post_data = self.0.get_post_data.unwrap();
-        unsafe { PostData::from_ptr_unchecked(get_post_data(self.0.as_ptr())) }
+        unsafe { PostData::from_ptr(get_post_data(self.0.as_ptr())) }
     }
     /// Set the post data.
     pub fn set_post_data(&self, post_data: PostData) {
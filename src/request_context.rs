@@ -1,3 +1,4 @@
+use std::future::Future;
 use std::net::IpAddr;
 use crate::string::CefStringList;
 use parking_lot::Mutex;
@@ -12,6 +13,7 @@ use cef_sys::cef_string_list_t;
 use crate::extension::ExtensionHandler;
 use crate::extension::Extension;
 use crate::callback::CompletionCallback;
+use crate::oneshot;
 use cef_sys::{
     cef_browser_t, cef_create_context_shared, cef_frame_t, cef_plugin_policy_t,
     cef_request_context_create_context, cef_request_context_get_global_context,
@@ -476,6 +478,34 @@ impl RequestContext {
             )
         }
     }
+    /// Like [clear_certificate_exceptions](RequestContext::clear_certificate_exceptions),
+    /// but returns a future that resolves to true on completion, or to false if CEF
+    /// released the callback without completing.
+    pub fn clear_certificate_exceptions_async(&self) -> impl Future<Output = bool> {
+        let (sender, receiver) = oneshot::channel();
+        self.clear_certificate_exceptions(move || sender.send(()));
+        async move { receiver.await.is_ok() }
+    }
+    /// Like [close_all_connections](RequestContext::close_all_connections), but
+    /// returns a future that resolves to true on completion, or to false if CEF
+    /// released the callback without completing.
+    pub fn close_all_connections_async(&self) -> impl Future<Output = bool> {
+        let (sender, receiver) = oneshot::channel();
+        self.close_all_connections(move || sender.send(()));
+        async move { receiver.await.is_ok() }
+    }
+    /// Attempts to resolve `origin` to a list of associated IP addresses. The future
+    /// resolves to [ErrorCode::Aborted] if the resolution was never completed.
+    pub fn resolve_host_async(&self, origin: &str) -> impl Future<Output = Result<Vec<IpAddr>, ErrorCode>> {
+        let (sender, receiver) = oneshot::channel();
+        self.resolve_host(origin, move |result, ips| {
+            sender.send(match result {
+                ErrorCode::None => Ok(ips.to_vec()),
+                error => Err(error),
+            })
+        });
+        async move { receiver.await.unwrap_or(Err(ErrorCode::Aborted)) }
+    }
     /// Load an extension.
     ///
     /// If extension resources will be read from disk using the default load
@@ -0,0 +1,494 @@
+//! Rewriting response bodies as they stream through a [ResponseFilter].
+//!
+//! A [Rewriter] holds a list of rules: literal and regex replacements, and HTML
+//! to inject before `</head>` or `</body>`. Matches are found across the chunk
+//! boundaries of the response by holding back the end of each chunk until the
+//! next one arrives. Return the filter from
+//! [ResourceRequestHandlerCallbacks::get_resource_response_filter]:
+//!
+//! ```ignore
+//! let rewriter = Rewriter::new()
+//!     .replace("https://api.example.com", "http://localhost:8080")
+//!     .replace_regex(&Regex::new(r#"data-version="(\d+)""#).unwrap(), r#"data-version="$1-dev""#)
+//!     .script("app://devtools/overlay.js")
+//!     .stylesheet("app://devtools/overlay.css");
+//!
+//! fn get_resource_response_filter(&self, _browser: Option<Browser>, _frame: Option<Frame>, request: Request, response: Response) -> Option<ResponseFilter> {
+//!     match request.get_resource_type() {
+//!         ResourceType::MainFrame => self.rewriter.response_filter(&response),
+//!         _ => None,
+//!     }
+//! }
+//! ```
+//!
+//! [ResourceRequestHandlerCallbacks::get_resource_response_filter]: crate::resource_request_handler::ResourceRequestHandlerCallbacks::get_resource_response_filter
+
+use crate::{
+    response::Response,
+    url_request::{FilterOutput, ResponseFilter, ResponseFilterCallbacks, ResponseFilterStatus},
+};
+use parking_lot::Mutex;
+use regex::bytes::{CaptureLocations, Regex};
+use std::collections::HashMap;
+
+/// The default for [Rewriter::max_match_len].
+pub const DEFAULT_MAX_MATCH_LEN: usize = 1024;
+
+/// The longest closing tag with whitespace that injections look for.
+const MAX_TAG_LEN: usize = 64;
+
+/// How the text of rules is written into the response body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Utf8,
+    /// A single byte charset that is a superset of ASCII, such as ISO-8859-1 or
+    /// windows-1252. Non-ASCII characters of replacements and injections are
+    /// written as HTML character references, and only match ASCII text.
+    AsciiCompatible,
+}
+
+impl Charset {
+    /// Returns the charset named `name`, as it appears in a `Content-Type`
+    /// header, or None if text can't be rewritten safely in it, e.g. UTF-16 or
+    /// multibyte charsets whose bytes can look like ASCII. A missing charset is
+    /// assumed to be UTF-8.
+    pub fn from_name(name: &str) -> Option<Charset> {
+        let name = name.trim().trim_matches('"').to_ascii_lowercase();
+        match name.as_str() {
+            "" | "utf-8" | "utf8" | "unicode-1-1-utf-8" => Some(Charset::Utf8),
+            "us-ascii" | "ascii" | "latin1" | "l1" => Some(Charset::AsciiCompatible),
+            name if name.starts_with("iso-8859-")
+                || name.starts_with("iso8859-")
+                || name.starts_with("windows-125")
+                || name.starts_with("cp125")
+                || name.starts_with("koi8-") =>
+            {
+                Some(Charset::AsciiCompatible)
+            }
+            _ => None,
+        }
+    }
+
+    fn encode(self, text: &str) -> String {
+        match self {
+            Charset::Utf8 => text.to_string(),
+            Charset::AsciiCompatible => text
+                .chars()
+                .map(|c| if c.is_ascii() { c.to_string() } else { format!("&#{};", c as u32) })
+                .collect(),
+        }
+    }
+}
+
+/// A rule with its patterns compiled, which filters share.
+#[derive(Debug, Clone)]
+enum RuleSpec {
+    /// `utf8` and `ascii` find `find` in the respective charsets.
+    Literal { find: String, utf8: Regex, ascii: Regex, replacement: String },
+    Regex { regex: Regex, replacement: String },
+    Inject { regex: Regex, html: String },
+}
+
+/// A list of rewriting rules, from which a [ResponseFilter] is created for each
+/// response. Rules are applied in a single pass: at each position the rule with
+/// the earliest match wins, and ties go to the rule that was added first.
+/// Replaced text is not matched again.
+#[derive(Debug, Clone)]
+pub struct Rewriter {
+    rules: Vec<RuleSpec>,
+    max_match_len: usize,
+}
+
+impl Default for Rewriter {
+    fn default() -> Rewriter {
+        Rewriter::new()
+    }
+}
+
+impl Rewriter {
+    pub fn new() -> Rewriter {
+        Rewriter {
+            rules: Vec::new(),
+            max_match_len: DEFAULT_MAX_MATCH_LEN,
+        }
+    }
+    /// Replace every occurrence of `find` with `replacement`.
+    pub fn replace(mut self, find: &str, replacement: &str) -> Self {
+        if !find.is_empty() {
+            let literal = |find: &str| Regex::new(&regex::escape(find)).expect("escaped text is a valid regex");
+            self.rules.push(RuleSpec::Literal {
+                find: find.to_string(),
+                utf8: literal(find),
+                ascii: literal(&Charset::AsciiCompatible.encode(find)),
+                replacement: replacement.to_string(),
+            });
+        }
+        self
+    }
+    /// Replace every match of `regex` with `replacement`, in which `$1`, `$name`
+    /// and `${name}` refer to capture groups and `$$` is a literal `$`. Matches
+    /// must not be longer than [max_match_len](Rewriter::max_match_len), and
+    /// empty matches are ignored.
+    pub fn replace_regex(mut self, regex: &regex::Regex, replacement: &str) -> Self {
+        self.rules.push(RuleSpec::Regex {
+            regex: Regex::new(regex.as_str()).expect("a valid regex is a valid bytes regex"),
+            replacement: replacement.to_string(),
+        });
+        self
+    }
+    /// Insert `html` before the first `</head>`, or at the end of the body if
+    /// there is none.
+    pub fn inject_head(self, html: &str) -> Self {
+        self.inject("head", html)
+    }
+    /// Insert `html` before the first `</body>`, or at the end of the body if
+    /// there is none.
+    pub fn inject_body(self, html: &str) -> Self {
+        self.inject("body", html)
+    }
+    fn inject(mut self, tag: &str, html: &str) -> Self {
+        self.rules.push(RuleSpec::Inject {
+            regex: Regex::new(&format!(r"(?i)</{}\s*>", tag)).expect("invalid tag pattern"),
+            html: html.to_string(),
+        });
+        self
+    }
+    /// Load the script at `src` at the end of the document's head.
+    pub fn script(self, src: &str) -> Self {
+        let html = format!("<script src=\"{}\"></script>", escape_attribute(src));
+        self.inject_head(&html)
+    }
+    /// Load the stylesheet at `href` at the end of the document's head.
+    pub fn stylesheet(self, href: &str) -> Self {
+        let html = format!("<link rel=\"stylesheet\" href=\"{}\">", escape_attribute(href));
+        self.inject_head(&html)
+    }
+    /// Set the length of the longest text that regex rules can match. This much
+    /// of the end of each chunk is held back until the next chunk arrives.
+    /// Defaults to [DEFAULT_MAX_MATCH_LEN].
+    pub fn max_match_len(mut self, max_match_len: usize) -> Self {
+        self.max_match_len = max_match_len.max(1);
+        self
+    }
+
+    /// Create a filter for `response`, using the charset it declares. Returns
+    /// None if the charset isn't supported, in which case the response should be
+    /// left alone.
+    pub fn response_filter(&self, response: &Response) -> Option<ResponseFilter> {
+        let charset = Charset::from_name(&response.get_charset())?;
+        Some(ResponseFilter::new(self.filter(charset)))
+    }
+    /// Create the filter callbacks for a body in `charset`.
+    pub fn filter(&self, charset: Charset) -> RewriteFilter {
+        let mut window = 1;
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| {
+                let (regex, action, max_len) = match rule {
+                    RuleSpec::Literal { find, utf8, ascii, replacement } => {
+                        let regex = match charset {
+                            Charset::Utf8 => utf8,
+                            Charset::AsciiCompatible => ascii,
+                        };
+                        let max_len = charset.encode(find).len();
+                        (regex.clone(), Action::Replace(charset.encode(replacement).into_bytes()), max_len)
+                    }
+                    RuleSpec::Regex { regex, replacement } => {
+                        (regex.clone(), Action::Expand(charset.encode(replacement).into_bytes()), self.max_match_len)
+                    }
+                    RuleSpec::Inject { regex, html } => {
+                        (regex.clone(), Action::Inject(charset.encode(html).into_bytes()), MAX_TAG_LEN)
+                    }
+                };
+                window = window.max(max_len);
+                let names = regex
+                    .capture_names()
+                    .enumerate()
+                    .filter_map(|(i, name)| name.map(|name| (name.to_string(), i)))
+                    .collect();
+                Rule {
+                    locations: regex.capture_locations(),
+                    regex,
+                    names,
+                    action,
+                    done: false,
+                }
+            })
+            .collect();
+        RewriteFilter(Mutex::new(State {
+            rules,
+            window,
+            input: Vec::new(),
+            output: FilterOutput::default(),
+        }))
+    }
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+enum Action {
+    Replace(Vec<u8>),
+    /// Expand capture group references in the replacement.
+    Expand(Vec<u8>),
+    /// Insert the text before the match, once.
+    Inject(Vec<u8>),
+}
+
+struct Rule {
+    regex: Regex,
+    locations: CaptureLocations,
+    names: HashMap<String, usize>,
+    action: Action,
+    done: bool,
+}
+
+impl Rule {
+    /// Find the first non-empty match at or after `start`, leaving its capture
+    /// groups in `self.locations`.
+    fn find(&mut self, input: &[u8], mut start: usize) -> Option<(usize, usize)> {
+        if self.done {
+            return None;
+        }
+        while start <= input.len() {
+            let found = self
+                .regex
+                .captures_read_at(&mut self.locations, input, start)
+                .map(|found| (found.start(), found.end()))?;
+            if found.0 < found.1 {
+                return Some(found);
+            }
+            start = found.0 + 1;
+        }
+        None
+    }
+
+    fn write_replacement(&mut self, input: &[u8], found: (usize, usize), output: &mut Vec<u8>) {
+        match &self.action {
+            Action::Replace(replacement) => output.extend_from_slice(replacement),
+            Action::Expand(replacement) => expand(replacement, &self.names, &self.locations, input, output),
+            Action::Inject(html) => {
+                output.extend_from_slice(html);
+                output.extend_from_slice(&input[found.0..found.1]);
+                self.done = true;
+            }
+        }
+    }
+}
+
+/// Expand `$1`, `$name`, `${name}` and `$$` in `replacement` like
+/// [regex::Captures::expand].
+fn expand(replacement: &[u8], names: &HashMap<String, usize>, locations: &CaptureLocations, input: &[u8], output: &mut Vec<u8>) {
+    let is_name = |b: &u8| b.is_ascii_alphanumeric() || *b == b'_';
+    let mut rest = replacement;
+    while let Some(dollar) = rest.iter().position(|&b| b == b'$') {
+        output.extend_from_slice(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+        let (name, len) = match rest.first() {
+            Some(b'$') => {
+                output.push(b'$');
+                rest = &rest[1..];
+                continue;
+            }
+            Some(b'{') => match rest.iter().position(|&b| b == b'}') {
+                Some(end) => (&rest[1..end], end + 1),
+                None => (&rest[..0], 0),
+            },
+            _ => {
+                let len = rest.iter().take_while(|b| is_name(b)).count();
+                (&rest[..len], len)
+            }
+        };
+        if name.is_empty() {
+            output.push(b'$');
+            continue;
+        }
+        let name = String::from_utf8_lossy(name);
+        let group = name.parse::<usize>().ok().or_else(|| names.get(name.as_ref()).copied());
+        if let Some((start, end)) = group.and_then(|group| locations.get(group)) {
+            output.extend_from_slice(&input[start..end]);
+        }
+        rest = &rest[len..];
+    }
+    output.extend_from_slice(rest);
+}
+
+struct State {
+    rules: Vec<Rule>,
+    /// The length of the longest match, which is held back from each chunk.
+    window: usize,
+    /// Input that hasn't been rewritten yet.
+    input: Vec<u8>,
+    /// Rewritten output that hasn't been written yet.
+    output: FilterOutput,
+}
+
+impl State {
+    /// Rewrite the input into the output, keeping back the end of the input that
+    /// could be the start of a match unless `last` is set.
+    fn rewrite(&mut self, last: bool) {
+        let input = std::mem::take(&mut self.input);
+        let mut output = Vec::with_capacity(input.len());
+        // Positions before `limit` can only start matches that end in the input.
+        let limit = if last { input.len() } else { input.len().saturating_sub(self.window - 1) };
+        let mut position = 0;
+        loop {
+            let mut earliest: Option<(usize, (usize, usize))> = None;
+            for (i, rule) in self.rules.iter_mut().enumerate() {
+                let end = earliest.map_or(input.len(), |(_, (start, _))| start);
+                if let Some(found) = rule.find(&input[..], position) {
+                    if found.0 < end || earliest.is_none() {
+                        earliest = Some((i, found));
+                    }
+                }
+            }
+            match earliest {
+                Some((i, found)) if found.0 < limit => {
+                    // The locations of the winning rule are still those of its match,
+                    // since every rule searches once per iteration.
+                    output.extend_from_slice(&input[position..found.0]);
+                    self.rules[i].write_replacement(&input, found, &mut output);
+                    position = found.1;
+                }
+                _ => {
+                    let end = limit.max(position);
+                    output.extend_from_slice(&input[position..end]);
+                    position = end;
+                    break;
+                }
+            }
+        }
+        self.input = input[position..].to_vec();
+        if last {
+            for rule in &mut self.rules {
+                if let (Action::Inject(html), false) = (&rule.action, rule.done) {
+                    output.extend_from_slice(html);
+                    rule.done = true;
+                }
+            }
+        }
+        self.output.push(&output);
+    }
+}
+
+/// The [ResponseFilterCallbacks] created by [Rewriter::filter].
+///
+/// All input is consumed on every call. The end of each chunk that could start a
+/// match is held back until the call with empty input that follows the last
+/// chunk, which CEF makes because the filter returns
+/// [ResponseFilterStatus::NeedMoreData] until its output has been written.
+pub struct RewriteFilter(Mutex<State>);
+
+impl ResponseFilterCallbacks for RewriteFilter {
+    fn init_filter(&self) -> bool {
+        true
+    }
+    fn filter(
+        &self,
+        data_in: &[u8],
+        data_in_read: &mut usize,
+        data_out: &mut [u8],
+        data_out_written: &mut usize,
+    ) -> ResponseFilterStatus {
+        let mut state = self.0.lock();
+        *data_in_read = data_in.len();
+        if state.output.at_end(data_in) {
+            state.rewrite(true);
+        } else if !data_in.is_empty() {
+            state.input.extend_from_slice(data_in);
+            state.rewrite(false);
+        }
+        state.output.write(data_out, data_out_written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pass `chunks` through `filter` like CEF does, with an output slice of
+    /// `out_len` bytes, and return the filtered body.
+    fn run(filter: &RewriteFilter, chunks: &[&[u8]], out_len: usize) -> Vec<u8> {
+        let mut body = Vec::new();
+        let mut data_out = vec![0; out_len];
+        let mut status = ResponseFilterStatus::NeedMoreData;
+        for chunk in chunks.iter().chain(std::iter::repeat(&&[][..])).take(chunks.len() + 1000) {
+            if status == ResponseFilterStatus::Done {
+                break;
+            }
+            let (mut read, mut written) = (0, 0);
+            status = filter.filter(chunk, &mut read, &mut data_out, &mut written);
+            assert_eq!(read, chunk.len());
+            body.extend_from_slice(&data_out[..written]);
+        }
+        assert_eq!(status, ResponseFilterStatus::Done);
+        body
+    }
+
+    fn rewriter() -> Rewriter {
+        let version = regex::Regex::new(r"v(?P<major>\d+)\.(\d+)").unwrap();
+        Rewriter::new()
+            .replace("example.com", "localhost")
+            .replace_regex(&version, "v${major}_$2$$")
+            .script("overlay.js")
+            .inject_body("<i>é</i>")
+    }
+
+    const DOCUMENT: &[u8] = b"<html><HEAD><title>example.com v1.22</title></head >\
+        <body>example.comexample.com example.co</BODY></html>";
+    const REWRITTEN: &str = "<html><HEAD><title>localhost v1_22$</title><script src=\"overlay.js\"></script></head >\
+        <body>localhostlocalhost example.co<i>é</i></BODY></html>";
+
+    #[test]
+    fn split_at_every_offset() {
+        let rewriter = rewriter();
+        for offset in 1..DOCUMENT.len() {
+            for &out_len in &[1, 3, 7, 4096] {
+                let filter = rewriter.filter(Charset::Utf8);
+                let body = run(&filter, &[&DOCUMENT[..offset], &DOCUMENT[offset..]], out_len);
+                assert_eq!(String::from_utf8_lossy(&body), REWRITTEN, "split at {}, output of {}", offset, out_len);
+            }
+        }
+    }
+
+    #[test]
+    fn single_byte_chunks() {
+        let chunks: Vec<_> = DOCUMENT.chunks(1).collect();
+        let body = run(&rewriter().filter(Charset::Utf8), &chunks, 2);
+        assert_eq!(String::from_utf8_lossy(&body), REWRITTEN);
+    }
+
+    #[test]
+    fn missing_tags_inject_at_end() {
+        let body = run(&rewriter().filter(Charset::Utf8), &[b"example.", b"com"], 2);
+        assert_eq!(String::from_utf8_lossy(&body), "localhost<script src=\"overlay.js\"></script><i>é</i>");
+    }
+
+    #[test]
+    fn ascii_compatible_charset() {
+        let rewriter = Rewriter::new().replace("café", "bar").inject_body("<i>é</i>");
+        let body = run(&rewriter.filter(Charset::AsciiCompatible), &[b"caf&#233; caf\xe9</body>"], 5);
+        assert_eq!(body, &b"bar caf\xe9<i>&#233;</i></body>"[..]);
+    }
+
+    #[test]
+    fn empty_body() {
+        assert!(run(&Rewriter::new().filter(Charset::Utf8), &[], 1).is_empty());
+    }
+
+    #[test]
+    fn charsets() {
+        assert_eq!(Charset::from_name(""), Some(Charset::Utf8));
+        assert_eq!(Charset::from_name("\"UTF-8\""), Some(Charset::Utf8));
+        assert_eq!(Charset::from_name("ISO-8859-1"), Some(Charset::AsciiCompatible));
+        assert_eq!(Charset::from_name("windows-1252"), Some(Charset::AsciiCompatible));
+        assert_eq!(Charset::from_name("UTF-16"), None);
+        assert_eq!(Charset::from_name("Shift_JIS"), None);
+    }
+}
@@ -0,0 +1,981 @@
+//! Serving requests from Rust handlers.
+//!
+//! A [Router] maps request methods and URL [patterns](Pattern) to handlers that
+//! produce a [RouteResponse]: a status, headers and a [Body] of bytes, a file or
+//! a stream. Requests that match no route are passed through to the network.
+//! Install it as the request handler of a client, or as a scheme handler factory:
+//!
+//! ```ignore
+//! let router = Router::new()
+//!     .get("https://api.example.com/users/:id", |request: &RouteRequest| {
+//!         RouteResponse::json(format!(r#"{{"id":"{}"}}"#, request.param("id").unwrap()))
+//!     })
+//!     .get("/static/**", |request: &RouteRequest| {
+//!         RouteResponse::file(Path::new("static").join(&request.path()[8..]))
+//!     })
+//!     .route(Some("POST"), Pattern::regex(r"^https://api\.example\.com/upload(\?.*)?$").unwrap(), |request: &RouteRequest| {
+//!         let (sender, body) = Body::channel();
+//!         let len = request.body().len();
+//!         std::thread::spawn(move || sender.send(format!("received {} bytes", len)));
+//!         RouteResponse::new(202).mime_type("text/plain").body(body)
+//!     });
+//!
+//! impl ClientCallbacks for MyClient {
+//!     fn get_request_handler(&self) -> Option<RequestHandler> {
+//!         Some(RequestHandler::new(self.router.clone()))
+//!     }
+//!     // ...
+//! }
+//! ```
+
+use crate::{
+    browser::Browser,
+    callback::Callback,
+    client::request_handler::RequestHandlerCallbacks,
+    frame::Frame,
+    load_handler::ErrorCode,
+    request::{PostDataElementType, Request},
+    resource_request_handler::{ResourceRequestHandler, ResourceRequestHandlerCallbacks},
+    response::Response,
+    scheme::SchemeHandlerFactoryCallbacks,
+    url_request::{
+        RequestCallback, ResourceHandler, ResourceHandlerCallbacks, ResourceReadHandler,
+        ResourceSkipCallback,
+    },
+    ReturnValue,
+};
+use parking_lot::Mutex;
+use regex::Regex;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// A pattern that request URLs are matched against.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    regex: Regex,
+    /// Whether the query and fragment are removed from URLs before matching.
+    strip_query: bool,
+}
+
+impl Pattern {
+    /// Create a pattern from a glob. The glob is matched against the whole URL
+    /// without its query and fragment, and supports these wildcards:
+    ///
+    /// - `*` matches any characters except `/`.
+    /// - `**` matches any characters, including `/`.
+    /// - `:name` at the start of a path segment captures the characters up to
+    ///   the next `/` as the parameter `name`.
+    ///
+    /// A glob that starts with `/` matches the path of URLs with any origin. Fails
+    /// if a parameter name is used twice.
+    pub fn glob(glob: &str) -> Result<Pattern, regex::Error> {
+        let mut regex = String::from("^");
+        if glob.starts_with('/') {
+            regex.push_str("[a-zA-Z][a-zA-Z0-9+.-]*://[^/]*");
+        }
+        let mut chars = glob.chars().peekable();
+        let mut segment_start = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    regex.push_str(".*");
+                }
+                '*' => regex.push_str("[^/]*"),
+                ':' if segment_start && chars.peek().map_or(false, |&c| c.is_ascii_alphabetic() || c == '_') => {
+                    let mut name = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_ascii_alphanumeric() || c == '_' {
+                            name.push(c);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    regex.push_str(&format!("(?P<{}>[^/]+)", name));
+                }
+                c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+            segment_start = c == '/';
+        }
+        regex.push('$');
+        Ok(Pattern {
+            regex: Regex::new(&regex)?,
+            strip_query: true,
+        })
+    }
+    /// Create a pattern from a regular expression, which is matched against the
+    /// whole URL including its query. Named capture groups become parameters,
+    /// other capture groups are available by their index.
+    pub fn regex(regex: &str) -> Result<Pattern, regex::Error> {
+        Regex::new(regex).map(Pattern::from)
+    }
+    /// Match `url` against the pattern. Returns the captured parameters, without
+    /// percent-decoding, or None if it doesn't match.
+    pub fn matches(&self, url: &str) -> Option<HashMap<String, String>> {
+        let url = if self.strip_query {
+            &url[..url.find(|c| c == '?' || c == '#').unwrap_or_else(|| url.len())]
+        } else {
+            url
+        };
+        let captures = self.regex.captures(url)?;
+        let mut params = HashMap::new();
+        for (i, name) in self.regex.capture_names().enumerate().skip(1) {
+            if let Some(capture) = captures.get(i) {
+                let name = name.map(String::from).unwrap_or_else(|| i.to_string());
+                params.insert(name, capture.as_str().to_string());
+            }
+        }
+        Some(params)
+    }
+}
+
+/// Creates a pattern from a glob, so that routes can be given as strings.
+///
+/// # Panics
+///
+/// Panics if the glob is invalid; use [Pattern::glob] to handle the error.
+impl From<&str> for Pattern {
+    fn from(glob: &str) -> Pattern {
+        Pattern::glob(glob).unwrap_or_else(|error| panic!("invalid glob {:?}: {}", glob, error))
+    }
+}
+
+impl From<Regex> for Pattern {
+    fn from(regex: Regex) -> Pattern {
+        Pattern {
+            regex,
+            strip_query: false,
+        }
+    }
+}
+
+/// Produces the response to a routed request. Implemented for closures taking a
+/// [RouteRequest]. Handlers are called on the browser process IO thread, so
+/// they shouldn't block; return a [Body::channel] to produce the body later.
+pub trait RouteHandler: 'static + Send + Sync {
+    fn handle(&self, request: &RouteRequest) -> RouteResponse;
+}
+
+impl<F> RouteHandler for F
+where
+    F: Fn(&RouteRequest) -> RouteResponse + 'static + Send + Sync,
+{
+    fn handle(&self, request: &RouteRequest) -> RouteResponse {
+        self(request)
+    }
+}
+
+/// A request that matched a route.
+pub struct RouteRequest {
+    request: Request,
+    method: String,
+    url: String,
+    params: HashMap<String, String>,
+}
+
+impl RouteRequest {
+    /// Returns the underlying request, which cannot be modified.
+    pub fn request(&self) -> &Request {
+        &self.request
+    }
+    /// Returns the request method, e.g. `GET`.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+    /// Returns the full URL of the request.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+    /// Returns the path of the URL, without the query and fragment.
+    pub fn path(&self) -> &str {
+        url_path(&self.url)
+    }
+    /// Returns the query of the URL, without the leading `?`.
+    pub fn query(&self) -> Option<&str> {
+        let url = &self.url[..self.url.find('#').unwrap_or_else(|| self.url.len())];
+        url.find('?').map(|i| &url[i + 1..])
+    }
+    /// Returns the value of a parameter captured by the route's pattern.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(String::as_str)
+    }
+    /// Returns all parameters captured by the route's pattern.
+    pub fn params(&self) -> &HashMap<String, String> {
+        &self.params
+    }
+    /// Returns the first value of the header `name`.
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.request.get_header_by_name(name)
+    }
+    /// Returns the request body. Elements of the body that refer to files are
+    /// read from disk.
+    pub fn body(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        if let Some(post_data) = self.request.get_post_data() {
+            for element in post_data.get_elements() {
+                match element.get_type() {
+                    PostDataElementType::Bytes => body.extend(element.get_bytes()),
+                    PostDataElementType::File => match std::fs::read(element.get_file()) {
+                        Ok(data) => body.extend(data),
+                        Err(err) => log::warn!("failed to read request body file {}: {}", element.get_file(), err),
+                    },
+                    PostDataElementType::Empty => (),
+                }
+            }
+        }
+        body
+    }
+}
+
+/// The body of a [RouteResponse].
+pub enum Body {
+    Empty,
+    Bytes(Vec<u8>),
+    /// The contents of a file, which is opened when the response is sent.
+    File(PathBuf),
+    /// Data read synchronously on the IO thread, until the reader is exhausted.
+    Reader(Box<dyn Read + Send>),
+    /// Data sent through a [BodySender]. Create it with [Body::channel].
+    Stream(BodyStream),
+}
+
+impl Body {
+    /// Create a body that streams the data passed to the returned sender. The
+    /// body ends when the sender is dropped.
+    pub fn channel() -> (BodySender, Body) {
+        let stream = Arc::new(Mutex::new(StreamState {
+            buffer: VecDeque::new(),
+            end: None,
+            canceled: false,
+            pending_read: None,
+            pending_skip: None,
+        }));
+        (BodySender(stream.clone()), Body::Stream(BodyStream(stream)))
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(data: Vec<u8>) -> Body {
+        Body::Bytes(data)
+    }
+}
+
+impl From<&[u8]> for Body {
+    fn from(data: &[u8]) -> Body {
+        Body::Bytes(data.to_vec())
+    }
+}
+
+impl From<String> for Body {
+    fn from(data: String) -> Body {
+        Body::Bytes(data.into_bytes())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(data: &str) -> Body {
+        Body::Bytes(data.as_bytes().to_vec())
+    }
+}
+
+struct StreamState {
+    buffer: VecDeque<u8>,
+    /// Set once the sender is done. Holds the error that ends the body, if any.
+    end: Option<Option<ErrorCode>>,
+    canceled: bool,
+    pending_read: Option<ResourceReadHandler>,
+    pending_skip: Option<(u64, ResourceSkipCallback)>,
+}
+
+impl StreamState {
+    /// Complete the pending read or skip from the buffer or the end of the
+    /// stream. Returns the callbacks to run once the lock is released.
+    fn complete_pending(&mut self) -> (Option<ResourceReadHandler>, Option<(i64, ResourceSkipCallback)>) {
+        let mut skip = None;
+        if let Some((bytes_to_skip, callback)) = self.pending_skip.take() {
+            if !self.buffer.is_empty() {
+                let skipped = self.buffer.len().min(bytes_to_skip as usize);
+                self.buffer.drain(..skipped);
+                skip = Some((skipped as i64, callback));
+            } else if let Some(error) = self.end {
+                skip = Some((error.unwrap_or(ErrorCode::Failed) as i64, callback));
+            } else {
+                self.pending_skip = Some((bytes_to_skip, callback));
+            }
+        }
+        let mut read = None;
+        if let Some(mut handler) = self.pending_read.take() {
+            if self.fill(&mut handler) {
+                read = Some(handler);
+            } else {
+                self.pending_read = Some(handler);
+            }
+        }
+        (read, skip)
+    }
+    /// Complete `handler` from the buffer or the end of the stream. Returns
+    /// false if there is nothing to read yet.
+    fn fill(&mut self, handler: &mut ResourceReadHandler) -> bool {
+        if !self.buffer.is_empty() {
+            let buffer = handler.as_buffer_ref();
+            let len = buffer.len().min(self.buffer.len());
+            for (dst, src) in buffer.iter_mut().zip(self.buffer.drain(..len)) {
+                *dst = src;
+            }
+            handler.set_bytes_read(len as i32);
+            true
+        } else {
+            match self.end {
+                Some(None) => handler.set_bytes_read(0),
+                Some(Some(error)) => handler.set_error(error),
+                None => return false,
+            }
+            true
+        }
+    }
+}
+
+fn run_callbacks((read, skip): (Option<ResourceReadHandler>, Option<(i64, ResourceSkipCallback)>)) {
+    if let Some((bytes_skipped, callback)) = skip {
+        callback.cont(bytes_skipped);
+    }
+    if let Some(handler) = read {
+        handler.cont();
+    }
+}
+
+/// The receiving end of a [Body::channel].
+pub struct BodyStream(Arc<Mutex<StreamState>>);
+
+/// Sends the data of a streaming [Body]. Dropping it ends the body.
+pub struct BodySender(Arc<Mutex<StreamState>>);
+
+impl BodySender {
+    /// Append `data` to the body. May be called on any thread. Returns false if
+    /// the request was canceled, in which case no more data is needed.
+    pub fn send(&self, data: impl AsRef<[u8]>) -> bool {
+        let data = data.as_ref();
+        let callbacks = {
+            let mut state = self.0.lock();
+            if state.canceled {
+                return false;
+            }
+            if data.is_empty() {
+                return true;
+            }
+            state.buffer.extend(data);
+            state.complete_pending()
+        };
+        run_callbacks(callbacks);
+        true
+    }
+    /// Returns true if the request was canceled.
+    pub fn is_canceled(&self) -> bool {
+        self.0.lock().canceled
+    }
+    /// End the body with an error.
+    pub fn fail(self, error: ErrorCode) {
+        self.end(Some(error));
+    }
+
+    fn end(&self, error: Option<ErrorCode>) {
+        let callbacks = {
+            let mut state = self.0.lock();
+            if state.end.is_some() {
+                return;
+            }
+            state.end = Some(error);
+            state.complete_pending()
+        };
+        run_callbacks(callbacks);
+    }
+}
+
+impl Drop for BodySender {
+    fn drop(&mut self) {
+        self.end(None);
+    }
+}
+
+/// Everything about a [RouteResponse] except its body.
+struct ResponseHead {
+    status: i32,
+    status_text: Option<String>,
+    mime_type: Option<String>,
+    charset: Option<String>,
+    headers: HashMap<String, Vec<String>>,
+    redirect_url: Option<String>,
+    error: Option<ErrorCode>,
+}
+
+/// The response produced by a [RouteHandler].
+pub struct RouteResponse {
+    head: ResponseHead,
+    body: Body,
+}
+
+impl RouteResponse {
+    /// Create a response with the HTTP status code `status` and an empty body.
+    pub fn new(status: i32) -> RouteResponse {
+        RouteResponse {
+            head: ResponseHead {
+                status,
+                status_text: None,
+                mime_type: None,
+                charset: None,
+                headers: HashMap::new(),
+                redirect_url: None,
+                error: None,
+            },
+            body: Body::Empty,
+        }
+    }
+    /// Create a `200 OK` response with a `text/plain` body.
+    pub fn text(text: impl Into<String>) -> RouteResponse {
+        RouteResponse::new(200).mime_type("text/plain").charset("utf-8").body(text.into())
+    }
+    /// Create a `200 OK` response with a `text/html` body.
+    pub fn html(html: impl Into<String>) -> RouteResponse {
+        RouteResponse::new(200).mime_type("text/html").charset("utf-8").body(html.into())
+    }
+    /// Create a `200 OK` response with an `application/json` body.
+    pub fn json(json: impl Into<String>) -> RouteResponse {
+        RouteResponse::new(200).mime_type("application/json").charset("utf-8").body(json.into())
+    }
+    /// Create a `200 OK` response with the contents of the file at `path`. The
+    /// MIME type is guessed from the file extension unless it is set. The
+    /// response is `404 Not Found` if the file doesn't exist.
+    pub fn file(path: impl Into<PathBuf>) -> RouteResponse {
+        RouteResponse::new(200).body(Body::File(path.into()))
+    }
+    /// Create a `404 Not Found` response.
+    pub fn not_found() -> RouteResponse {
+        RouteResponse::new(404).mime_type("text/plain").body("Not Found")
+    }
+    /// Create a `302 Found` response that redirects to `url`, which may be
+    /// relative or fully qualified.
+    pub fn redirect(url: impl Into<String>) -> RouteResponse {
+        let mut response = RouteResponse::new(302);
+        response.head.redirect_url = Some(url.into());
+        response
+    }
+    /// Create a response that fails the request with a network error, as if the
+    /// server couldn't be reached.
+    pub fn error(error: ErrorCode) -> RouteResponse {
+        let mut response = RouteResponse::new(0);
+        response.head.error = Some(error);
+        response
+    }
+    /// Set the status text. Defaults to the reason phrase of the status code.
+    pub fn status_text(mut self, status_text: impl Into<String>) -> Self {
+        self.head.status_text = Some(status_text.into());
+        self
+    }
+    pub fn mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.head.mime_type = Some(mime_type.into());
+        self
+    }
+    pub fn charset(mut self, charset: impl Into<String>) -> Self {
+        self.head.charset = Some(charset.into());
+        self
+    }
+    /// Add a header. Headers with the same name may be added more than once.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.head.headers.entry(name.into()).or_default().push(value.into());
+        self
+    }
+    pub fn body(mut self, body: impl Into<Body>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+/// Returns the MIME type for the extension of `path`, or
+/// `application/octet-stream` if it is unknown.
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => extension.to_ascii_lowercase(),
+        None => return "application/octet-stream",
+    };
+    match extension.as_str() {
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "xml" => "application/xml",
+        "txt" => "text/plain",
+        "csv" => "text/csv",
+        "md" => "text/markdown",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        "svg" => "image/svg+xml",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp3" => "audio/mpeg",
+        "ogg" | "oga" => "audio/ogg",
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "ogv" => "video/ogg",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Returns true if text of `mime_type` should be declared as UTF-8.
+pub(crate) fn is_text(mime_type: &str) -> bool {
+    mime_type.starts_with("text/")
+        || mime_type == "application/json"
+        || mime_type == "application/manifest+json"
+        || mime_type == "application/xml"
+        || mime_type == "image/svg+xml"
+}
+
+/// Returns the path of `url`, without the query and fragment.
+pub(crate) fn url_path(url: &str) -> &str {
+    let start = match url.find("://") {
+        Some(scheme_end) => url[scheme_end + 3..]
+            .find(|c| c == '/' || c == '?' || c == '#')
+            .map_or(url.len(), |i| scheme_end + 3 + i),
+        None => url.find(':').map_or(0, |i| i + 1),
+    };
+    let end = url[start..].find(|c| c == '?' || c == '#').map_or(url.len(), |i| start + i);
+    &url[start..end]
+}
+
+/// Returns the reason phrase of common HTTP status codes.
+pub(crate) fn reason_phrase(status: i32) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        410 => "Gone",
+        416 => "Range Not Satisfiable",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
+
+struct Route {
+    method: Option<String>,
+    pattern: Pattern,
+    handler: Box<dyn RouteHandler>,
+}
+
+/// Maps request methods and URL patterns to [RouteHandler]s. Routes are tried in
+/// the order they were added. Cloning it returns another handle to the same
+/// routes.
+#[derive(Clone, Default)]
+pub struct Router {
+    routes: Vec<Arc<Route>>,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router::default()
+    }
+    /// Add a route for requests with the method `method`, or any method if None,
+    /// whose URL matches `pattern`.
+    pub fn route(mut self, method: Option<&str>, pattern: impl Into<Pattern>, handler: impl RouteHandler) -> Self {
+        self.routes.push(Arc::new(Route {
+            method: method.map(str::to_ascii_uppercase),
+            pattern: pattern.into(),
+            handler: Box::new(handler),
+        }));
+        self
+    }
+    /// Add a route for requests with any method.
+    pub fn any(self, pattern: impl Into<Pattern>, handler: impl RouteHandler) -> Self {
+        self.route(None, pattern, handler)
+    }
+    pub fn get(self, pattern: impl Into<Pattern>, handler: impl RouteHandler) -> Self {
+        self.route(Some("GET"), pattern, handler)
+    }
+    pub fn post(self, pattern: impl Into<Pattern>, handler: impl RouteHandler) -> Self {
+        self.route(Some("POST"), pattern, handler)
+    }
+    pub fn put(self, pattern: impl Into<Pattern>, handler: impl RouteHandler) -> Self {
+        self.route(Some("PUT"), pattern, handler)
+    }
+    pub fn delete(self, pattern: impl Into<Pattern>, handler: impl RouteHandler) -> Self {
+        self.route(Some("DELETE"), pattern, handler)
+    }
+
+    /// Returns a handler for `request` if it matches a route, or None to let it
+    /// through to the network. Use this to route requests from your own
+    /// [RequestHandlerCallbacks::get_resource_request_handler].
+    pub fn resource_request_handler(&self, request: &Request) -> Option<ResourceRequestHandler> {
+        self.resource_handler(request)
+            .map(|handler| ResourceRequestHandler::new(RoutedRequestHandler(Mutex::new(Some(handler)))))
+    }
+    /// Returns a resource handler for `request` if it matches a route.
+    pub fn resource_handler(&self, request: &Request) -> Option<ResourceHandler> {
+        let method = request.get_method();
+        let url = request.get_url();
+        self.routes.iter().find_map(|route| {
+            if let Some(route_method) = &route.method {
+                if !route_method.eq_ignore_ascii_case(&method) {
+                    return None;
+                }
+            }
+            let params = route.pattern.matches(&url)?;
+            Some(ResourceHandler::new(RouteResourceHandler {
+                route: route.clone(),
+                request: RouteRequest {
+                    request: request.clone(),
+                    method: method.clone(),
+                    url: url.clone(),
+                    params,
+                },
+                head: None,
+                reader: BodyReader::Empty,
+            }))
+        })
+    }
+}
+
+impl RequestHandlerCallbacks for Router {
+    fn get_resource_request_handler(
+        &self,
+        _browser: Browser,
+        _frame: Frame,
+        request: Request,
+        _is_navigation: bool,
+        _is_download: bool,
+        _request_initiator: &str,
+        _disable_default_handling: &mut bool,
+    ) -> Option<ResourceRequestHandler> {
+        self.resource_request_handler(&request)
+    }
+}
+
+impl SchemeHandlerFactoryCallbacks for Router {
+    fn create(
+        &self,
+        _browser: Browser,
+        _frame: Frame,
+        _scheme_name: &str,
+        request: Request,
+    ) -> Option<ResourceHandler> {
+        self.resource_handler(&request)
+    }
+}
+
+/// Hands the resource handler of a matched route to CEF.
+struct RoutedRequestHandler(Mutex<Option<ResourceHandler>>);
+
+impl ResourceRequestHandlerCallbacks for RoutedRequestHandler {
+    fn on_before_resource_load(
+        &self,
+        _browser: Option<Browser>,
+        _frame: Option<Frame>,
+        _request: Request,
+        _callback: RequestCallback,
+    ) -> ReturnValue {
+        ReturnValue::Continue
+    }
+    fn get_resource_handler(
+        &self,
+        _browser: Option<Browser>,
+        _frame: Option<Frame>,
+        _request: Request,
+    ) -> Option<ResourceHandler> {
+        self.0.lock().take()
+    }
+}
+
+enum BodyReader {
+    Empty,
+    Bytes(Vec<u8>, usize),
+    Reader(Mutex<Box<dyn Read + Send>>),
+    Stream(Arc<Mutex<StreamState>>),
+}
+
+impl BodyReader {
+    fn skip(&mut self, bytes_to_skip: u64, callback: ResourceSkipCallback) -> Result<u64, ErrorCode> {
+        match self {
+            BodyReader::Empty => Ok(0),
+            BodyReader::Bytes(data, offset) => {
+                let skipped = (data.len() - *offset).min(bytes_to_skip as usize);
+                *offset += skipped;
+                Ok(skipped as u64)
+            }
+            BodyReader::Reader(reader) => {
+                io::copy(&mut reader.get_mut().take(bytes_to_skip), &mut io::sink()).map_err(|_| ErrorCode::Failed)
+            }
+            BodyReader::Stream(stream) => {
+                let mut state = stream.lock();
+                state.pending_skip = Some((bytes_to_skip, callback));
+                let (read, skip) = state.complete_pending();
+                debug_assert!(read.is_none());
+                match skip {
+                    Some((skipped, _)) if skipped > 0 => Ok(skipped as u64),
+                    Some(_) => Err(state.end.and_then(|error| error).unwrap_or(ErrorCode::Failed)),
+                    // The callback is run when data arrives.
+                    None => Ok(0),
+                }
+            }
+        }
+    }
+}
+
+struct RouteResourceHandler {
+    route: Arc<Route>,
+    request: RouteRequest,
+    head: Option<ResponseHead>,
+    reader: BodyReader,
+}
+
+impl ResourceHandlerCallbacks for RouteResourceHandler {
+    fn open(&mut self, _request: Request, handle_request: &mut bool, _callback: Callback) -> bool {
+        let RouteResponse { mut head, body } = self.route.handler.handle(&self.request);
+        self.reader = match body {
+            Body::Empty => BodyReader::Empty,
+            Body::Bytes(data) => BodyReader::Bytes(data, 0),
+            Body::Reader(reader) => BodyReader::Reader(Mutex::new(reader)),
+            Body::Stream(BodyStream(stream)) => BodyReader::Stream(stream),
+            Body::File(path) => match File::open(&path) {
+                Ok(file) => {
+                    if head.mime_type.is_none() {
+                        let mime_type = mime_type(&path);
+                        if is_text(mime_type) && head.charset.is_none() {
+                            head.charset = Some("utf-8".to_string());
+                        }
+                        head.mime_type = Some(mime_type.to_string());
+                    }
+                    if let Ok(metadata) = file.metadata() {
+                        head.headers
+                            .entry("Content-Length".to_string())
+                            .or_insert_with(|| vec![metadata.len().to_string()]);
+                    }
+                    BodyReader::Reader(Mutex::new(Box::new(file)))
+                }
+                Err(err) => {
+                    log::warn!("failed to open {}: {}", path.display(), err);
+                    let error_response = match err.kind() {
+                        io::ErrorKind::PermissionDenied => RouteResponse::new(403).mime_type("text/plain").body("Forbidden"),
+                        _ => RouteResponse::not_found(),
+                    };
+                    head = error_response.head;
+                    match error_response.body {
+                        Body::Bytes(data) => BodyReader::Bytes(data, 0),
+                        _ => BodyReader::Empty,
+                    }
+                }
+            },
+        };
+        self.head = Some(head);
+        *handle_request = true;
+        true
+    }
+    fn get_response_headers(
+        &self,
+        response: Response,
+        response_length: &mut Option<u64>,
+        redirect_url: &mut String,
+    ) {
+        let head = match &self.head {
+            Some(head) => head,
+            None => return,
+        };
+        if let Some(error) = head.error {
+            response.set_error(error);
+            return;
+        }
+        if !head.headers.is_empty() {
+            response.set_header_map(&head.headers);
+        }
+        response.set_status(head.status);
+        response.set_status_text(
+            head.status_text.as_deref().unwrap_or_else(|| reason_phrase(head.status)),
+        );
+        if let Some(mime_type) = &head.mime_type {
+            response.set_mime_type(mime_type);
+        }
+        if let Some(charset) = &head.charset {
+            response.set_charset(charset);
+        }
+        if let Some(url) = &head.redirect_url {
+            *redirect_url = url.clone();
+        }
+        *response_length = match &self.reader {
+            BodyReader::Empty => Some(0),
+            BodyReader::Bytes(data, _) => Some(data.len() as u64),
+            _ => head.headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+                .and_then(|(_, values)| values.first())
+                .and_then(|value| value.parse().ok()),
+        };
+    }
+    fn skip(&mut self, bytes_to_skip: u64, bytes_skipped: &mut u64, callback: ResourceSkipCallback) -> Result<(), ErrorCode> {
+        *bytes_skipped = self.reader.skip(bytes_to_skip, callback)?;
+        Ok(())
+    }
+    fn read(&mut self, mut handler: ResourceReadHandler) -> Option<ResourceReadHandler> {
+        match &mut self.reader {
+            BodyReader::Empty => handler.set_bytes_read(0),
+            BodyReader::Bytes(data, offset) => {
+                let buffer = handler.as_buffer_ref();
+                let len = buffer.len().min(data.len() - *offset);
+                buffer[..len].copy_from_slice(&data[*offset..*offset + len]);
+                *offset += len;
+                handler.set_bytes_read(len as i32);
+            }
+            BodyReader::Reader(reader) => loop {
+                match reader.get_mut().read(handler.as_buffer_ref()) {
+                    Ok(len) => break handler.set_bytes_read(len as i32),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                    Err(err) => {
+                        log::warn!("failed to read response body: {}", err);
+                        break handler.set_error(ErrorCode::Failed);
+                    }
+                }
+            },
+            BodyReader::Stream(stream) => {
+                let mut state = stream.lock();
+                if !state.fill(&mut handler) {
+                    // BodySender::send continues the read when data arrives.
+                    state.pending_read = Some(handler);
+                    return None;
+                }
+            }
+        }
+        Some(handler)
+    }
+    fn cancel(&mut self) {
+        if let BodyReader::Stream(stream) = &self.reader {
+            let mut state = stream.lock();
+            state.canceled = true;
+            state.buffer.clear();
+            state.pending_read = None;
+            state.pending_skip = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pattern: &Pattern, url: &str) -> Option<Vec<(String, String)>> {
+        let mut params: Vec<_> = pattern.matches(url)?.into_iter().collect();
+        params.sort();
+        Some(params)
+    }
+
+    fn param(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        let pattern = Pattern::glob("https://example.com/*/index.html").unwrap();
+        assert!(pattern.matches("https://example.com/docs/index.html").is_some());
+        assert!(pattern.matches("https://example.com/index.html").is_none());
+        assert!(pattern.matches("https://example.com/a/b/index.html").is_none());
+
+        let pattern = Pattern::glob("https://example.com/static/**").unwrap();
+        assert!(pattern.matches("https://example.com/static/").is_some());
+        assert!(pattern.matches("https://example.com/static/a/b.css").is_some());
+        assert!(pattern.matches("https://example.com/statics").is_none());
+    }
+
+    #[test]
+    fn glob_escapes_regex_syntax() {
+        let pattern = Pattern::glob("https://example.com/a.b+(c)").unwrap();
+        assert!(pattern.matches("https://example.com/a.b+(c)").is_some());
+        assert!(pattern.matches("https://example.com/axbb(c)").is_none());
+    }
+
+    #[test]
+    fn glob_parameters() {
+        let pattern = Pattern::glob("https://api.example.com/users/:id/posts/:post_id").unwrap();
+        assert_eq!(
+            params(&pattern, "https://api.example.com/users/42/posts/7"),
+            Some(vec![param("id", "42"), param("post_id", "7")])
+        );
+        assert_eq!(params(&pattern, "https://api.example.com/users//posts/7"), None);
+        assert_eq!(params(&pattern, "https://api.example.com/users/4/2/posts/7"), None);
+
+        // A colon elsewhere is a literal.
+        let pattern = Pattern::glob("https://example.com/a:b").unwrap();
+        assert_eq!(params(&pattern, "https://example.com/a:b"), Some(vec![]));
+    }
+
+    #[test]
+    fn glob_duplicate_parameter_is_an_error() {
+        assert!(Pattern::glob("/users/:id/friends/:id").is_err());
+    }
+
+    #[test]
+    fn glob_paths_match_any_origin() {
+        let pattern = Pattern::glob("/users/:id").unwrap();
+        assert_eq!(params(&pattern, "https://example.com/users/1"), Some(vec![param("id", "1")]));
+        assert_eq!(params(&pattern, "custom://app/users/2"), Some(vec![param("id", "2")]));
+        assert_eq!(params(&pattern, "https://example.com/api/users/1"), None);
+    }
+
+    #[test]
+    fn glob_ignores_query_and_fragment() {
+        let pattern = Pattern::from("/search");
+        assert!(pattern.matches("https://example.com/search?q=a/b").is_some());
+        assert!(pattern.matches("https://example.com/search#results").is_some());
+        assert!(pattern.matches("https://example.com/search/more").is_none());
+    }
+
+    #[test]
+    fn regex_patterns() {
+        let pattern = Pattern::regex(r"^https://example\.com/(?P<name>\w+)\?page=(\d+)$").unwrap();
+        assert_eq!(
+            params(&pattern, "https://example.com/list?page=3"),
+            Some(vec![param("2", "3"), param("name", "list")])
+        );
+        assert_eq!(params(&pattern, "https://example.com/list"), None);
+        assert!(Pattern::regex("(").is_err());
+    }
+
+    #[test]
+    fn request_path() {
+        assert_eq!(url_path("https://example.com/a/b?c=/d#e"), "/a/b");
+        assert_eq!(url_path("https://example.com"), "");
+    }
+}
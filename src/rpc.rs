@@ -0,0 +1,480 @@
+//! Request/response calls on top of [ProcessMessage]s.
+//!
+//! An [RpcRouter] is created once per process (browser and renderer) and fed every
+//! incoming process message by calling [RpcRouter::on_process_message_received]
+//! from [ClientCallbacks::on_process_message_received] or
+//! [RenderProcessHandlerCallbacks::on_process_message_received]. Handlers are
+//! registered by name on the receiving side; calls are made through a [Frame] and
+//! get a correlation id, so that the reply can be routed back to the caller.
+//!
+//! Pending calls fail with [RpcError::Cancelled] when the browser or frame they were
+//! sent to goes away. The wrappers of [LifeSpanHandlerCallbacks::on_before_close] and
+//! [RequestHandlerCallbacks::on_render_process_terminated] in the browser process, and
+//! of [RenderProcessHandlerCallbacks::on_browser_destroyed] and
+//! [RenderProcessHandlerCallbacks::on_context_released] in the render process, cancel
+//! the calls of every router, so nothing needs to be wired up by hand. If the client
+//! has a load handler, the wrapper of [LoadHandlerCallbacks::on_load_start] also
+//! cancels the calls sent to a frame when it navigates to a new document, and those
+//! sent to any frame of the browser when its main frame does. Without one, pass a
+//! timeout to calls that could outlive the document.
+
+use crate::{
+    browser::Browser,
+    frame::Frame,
+    helper_traits::DeepClone,
+    oneshot,
+    process::ProcessMessage,
+    task::{TaskRunner, ThreadId},
+    values::{from_stored_value, to_stored_value, SerdeError, StoredValue, Value},
+};
+use parking_lot::{Mutex, RwLock};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Weak,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+const REQUEST_MESSAGE: &str = "cef.rpc.request";
+const RESPONSE_MESSAGE: &str = "cef.rpc.response";
+
+const STATUS_OK: i32 = 0;
+const STATUS_FAILED: i32 = 1;
+const STATUS_NO_HANDLER: i32 = 2;
+
+/// Errors that can be returned from an RPC call.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcError {
+    /// The handler on the other side reported a failure.
+    Failed(String),
+    /// No handler is registered for the called method on the other side.
+    NoHandler,
+    /// No reply arrived within the timeout passed to the call.
+    TimedOut,
+    /// The browser or frame went away before a reply arrived.
+    Cancelled,
+    /// The request or response payload couldn't be converted.
+    Serde(SerdeError),
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RpcError::Failed(message) => write!(f, "remote handler failed: {}", message),
+            RpcError::NoHandler => f.write_str("no handler registered for method"),
+            RpcError::TimedOut => f.write_str("call timed out"),
+            RpcError::Cancelled => f.write_str("call was cancelled"),
+            RpcError::Serde(error) => write!(f, "invalid payload: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<SerdeError> for RpcError {
+    fn from(error: SerdeError) -> RpcError {
+        RpcError::Serde(error)
+    }
+}
+
+type Handler = dyn Fn(Browser, Frame, StoredValue, RpcResponder) + Send + Sync;
+
+struct PendingCall {
+    browser_id: i32,
+    frame_id: Option<i64>,
+    callback: Box<dyn FnOnce(Result<StoredValue, RpcError>) + Send>,
+}
+
+lazy_static::lazy_static! {
+    /// Every router created in this process, so that their calls can be cancelled
+    /// when browsers and frames go away.
+    static ref ROUTERS: Mutex<Vec<Weak<RouterInner>>> = Mutex::new(Vec::new());
+}
+
+/// The id of the next call. It is shared by all routers, since a reply is handed
+/// to every router that is fed process messages.
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+struct RouterInner {
+    handlers: RwLock<HashMap<String, Arc<Handler>>>,
+    pending: Mutex<HashMap<u32, PendingCall>>,
+}
+
+impl RouterInner {
+    fn complete(&self, id: u32, result: Result<StoredValue, RpcError>) {
+        let call = self.pending.lock().remove(&id);
+        if let Some(call) = call {
+            (call.callback)(result);
+        }
+    }
+
+    fn cancel_where(&self, filter: impl Fn(&PendingCall) -> bool) {
+        let cancelled: Vec<PendingCall> = {
+            let mut pending = self.pending.lock();
+            let ids: Vec<u32> = pending
+                .iter()
+                .filter(|(_, call)| filter(call))
+                .map(|(id, _)| *id)
+                .collect();
+            ids.into_iter().filter_map(|id| pending.remove(&id)).collect()
+        };
+        for call in cancelled {
+            (call.callback)(Err(RpcError::Cancelled));
+        }
+    }
+}
+
+/// Routes RPC requests to registered handlers and replies back to their callers.
+/// Cloning the router yields another handle to the same handlers and pending calls.
+#[derive(Clone)]
+pub struct RpcRouter(Arc<RouterInner>);
+
+impl RpcRouter {
+    pub fn new() -> RpcRouter {
+        let router = Arc::new(RouterInner {
+            handlers: RwLock::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+        });
+        let mut routers = ROUTERS.lock();
+        routers.retain(|router| router.strong_count() > 0);
+        routers.push(Arc::downgrade(&router));
+        RpcRouter(router)
+    }
+
+    /// Registers a handler that answers through an [RpcResponder]. The responder may
+    /// be moved to another thread and answered later. Replaces any handler previously
+    /// registered for `method`.
+    pub fn register_async(
+        &self,
+        method: &str,
+        handler: impl Fn(Browser, Frame, StoredValue, RpcResponder) + Send + Sync + 'static,
+    ) {
+        self.0.handlers.write().insert(method.to_owned(), Arc::new(handler));
+    }
+
+    /// Registers a handler that answers synchronously. Returning `Err` fails the call
+    /// with [RpcError::Failed] on the calling side.
+    pub fn register(
+        &self,
+        method: &str,
+        handler: impl Fn(&Browser, &Frame, StoredValue) -> Result<StoredValue, String> + Send + Sync + 'static,
+    ) {
+        self.register_async(method, move |browser, frame, payload, responder| {
+            match handler(&browser, &frame, payload) {
+                Ok(response) => responder.success(response),
+                Err(message) => responder.failure(&message),
+            }
+        });
+    }
+
+    /// Registers a handler whose request and response are converted with serde.
+    pub fn register_typed<Req, Resp, F>(&self, method: &str, handler: F)
+    where
+        Req: DeserializeOwned,
+        Resp: Serialize,
+        F: Fn(&Browser, &Frame, Req) -> Result<Resp, String> + Send + Sync + 'static,
+    {
+        self.register(method, move |browser, frame, payload| {
+            let request = from_stored_value(payload).map_err(|error| error.to_string())?;
+            let response = handler(browser, frame, request)?;
+            to_stored_value(&response).map_err(|error| error.to_string())
+        });
+    }
+
+    /// Removes the handler for `method`. Returns true if a handler was registered.
+    pub fn unregister(&self, method: &str) -> bool {
+        self.0.handlers.write().remove(method).is_some()
+    }
+
+    /// Calls `method` in the process on the other side of `frame`. `callback` is
+    /// called exactly once, with either the reply or the reason the call failed. It
+    /// runs on the thread that receives process messages.
+    pub fn call(
+        &self,
+        frame: &Frame,
+        method: &str,
+        payload: StoredValue,
+        timeout: Option<Duration>,
+        callback: impl FnOnce(Result<StoredValue, RpcError>) + Send + 'static,
+    ) {
+        let payload = match Value::try_from(payload) {
+            Ok(payload) => payload,
+            Err(_) => return callback(Err(RpcError::Serde(SerdeError::SetFailed))),
+        };
+        if !frame.is_valid() {
+            return callback(Err(RpcError::Cancelled));
+        }
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let message = ProcessMessage::new(REQUEST_MESSAGE);
+        let args = message.get_argument_list();
+        args.set_int(0, id as i32);
+        args.set_string(1, method);
+        args.set_value_inner(2, payload);
+
+        self.0.pending.lock().insert(
+            id,
+            PendingCall {
+                browser_id: frame.get_browser().get_identifier(),
+                frame_id: frame.get_identifier(),
+                callback: Box::new(callback),
+            },
+        );
+        if let Some(timeout) = timeout {
+            let router = Arc::downgrade(&self.0);
+            schedule_timeout(router, id, timeout);
+        }
+        frame.send_process_message(message);
+    }
+
+    /// Like [RpcRouter::call], but returns a future that resolves to the reply.
+    pub fn call_async(
+        &self,
+        frame: &Frame,
+        method: &str,
+        payload: StoredValue,
+        timeout: Option<Duration>,
+    ) -> RpcFuture {
+        let (sender, receiver) = oneshot::channel();
+        self.call(frame, method, payload, timeout, move |result| sender.send(result));
+        RpcFuture(receiver)
+    }
+
+    /// Like [RpcRouter::call_async], but converts the request and reply with serde.
+    pub fn call_typed<Req, Resp>(
+        &self,
+        frame: &Frame,
+        method: &str,
+        request: &Req,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = Result<Resp, RpcError>>
+    where
+        Req: Serialize + ?Sized,
+        Resp: DeserializeOwned,
+    {
+        let call = to_stored_value(request)
+            .map(|payload| self.call_async(frame, method, payload, timeout));
+        async move {
+            let response = call?.await?;
+            from_stored_value(response).map_err(RpcError::from)
+        }
+    }
+
+    /// Dispatches RPC messages. Returns true if `message` was an RPC message, in
+    /// which case it shouldn't be processed any further.
+    pub fn on_process_message_received(
+        &self,
+        browser: &Browser,
+        frame: &Frame,
+        message: &ProcessMessage,
+    ) -> bool {
+        match message.get_name().as_ref().map(String::as_str) {
+            Some(REQUEST_MESSAGE) => {
+                self.handle_request(browser, frame, message);
+                true
+            }
+            Some(RESPONSE_MESSAGE) => {
+                self.handle_response(message);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Fails all pending calls sent to `browser` with [RpcError::Cancelled]. This
+    /// is done automatically when the browser is destroyed.
+    pub fn on_browser_destroyed(&self, browser: &Browser) {
+        let browser_id = browser.get_identifier();
+        self.0.cancel_where(|call| call.browser_id == browser_id);
+    }
+
+    /// Fails all pending calls sent to `frame` with [RpcError::Cancelled]. This is
+    /// done automatically when the frame's V8 context is released.
+    pub fn on_frame_released(&self, frame: &Frame) {
+        let frame_id = frame.get_identifier();
+        self.0.cancel_where(|call| frame_id.is_some() && call.frame_id == frame_id);
+    }
+
+    fn handle_request(&self, browser: &Browser, frame: &Frame, message: &ProcessMessage) {
+        let args = message.get_argument_list();
+        let id = args.get_int(0).unwrap_or_default() as u32;
+        let method = args.get_string(1).unwrap_or_default();
+        // The payload is owned by the message, which CEF destroys once this
+        // callback returns, so it has to be copied for asynchronous handlers.
+        let payload = args
+            .get(2)
+            .map(|payload| payload.deep_clone())
+            .unwrap_or(StoredValue::Null);
+        let mut responder = RpcResponder {
+            frame: frame.clone(),
+            id,
+            runner: TaskRunner::get_for_current_thread(),
+            sent: false,
+        };
+        let handler = self.0.handlers.read().get(&method).cloned();
+        match handler {
+            Some(handler) => handler(browser.clone(), frame.clone(), payload, responder),
+            None => responder.respond(Err(RpcError::NoHandler)),
+        }
+    }
+
+    fn handle_response(&self, message: &ProcessMessage) {
+        let args = message.get_argument_list();
+        let id = args.get_int(0).unwrap_or_default() as u32;
+        let result = match args.get_int(1).unwrap_or(STATUS_FAILED) {
+            STATUS_OK => Ok(args
+                .get(2)
+                .map(|payload| payload.deep_clone())
+                .unwrap_or(StoredValue::Null)),
+            STATUS_NO_HANDLER => Err(RpcError::NoHandler),
+            _ => Err(RpcError::Failed(args.get_string(2).unwrap_or_default())),
+        };
+        self.0.complete(id, result);
+    }
+}
+
+impl Default for RpcRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn live_routers() -> Vec<Arc<RouterInner>> {
+    ROUTERS.lock().iter().filter_map(Weak::upgrade).collect()
+}
+
+/// Fails the calls of every router that were sent to the browser with
+/// `browser_id`.
+pub(crate) fn cancel_for_browser(browser_id: i32) {
+    for router in live_routers() {
+        router.cancel_where(|call| call.browser_id == browser_id);
+    }
+}
+
+/// Fails the calls of every router that were sent to `frame`.
+pub(crate) fn cancel_for_frame(frame: &Frame) {
+    if let Some(frame_id) = frame.get_identifier() {
+        for router in live_routers() {
+            router.cancel_where(|call| call.frame_id == Some(frame_id));
+        }
+    }
+}
+
+fn schedule_timeout(router: Weak<RouterInner>, id: u32, timeout: Duration) {
+    let task = move || {
+        if let Some(router) = router.upgrade() {
+            router.complete(id, Err(RpcError::TimedOut));
+        }
+    };
+    // Calls made from threads without a task runner time out on the thread that
+    // receives process messages, which is the UI thread in the browser process and
+    // the renderer thread in render processes.
+    let runner = TaskRunner::get_for_current_thread()
+        .or_else(|| TaskRunner::get_for_thread(ThreadId::UI))
+        .or_else(|| TaskRunner::get_for_thread(ThreadId::Renderer));
+    match runner {
+        Some(runner) => {
+            runner.post_delayed_task(task, timeout.as_millis() as i64);
+        }
+        None => log::warn!("no task runner to time out RPC call {} on", id),
+    }
+}
+
+/// Used by handlers to answer a request. Can be sent to other threads; the reply is
+/// always delivered from the thread the request arrived on. Dropping the responder
+/// without answering fails the call.
+pub struct RpcResponder {
+    frame: Frame,
+    id: u32,
+    runner: Option<TaskRunner>,
+    sent: bool,
+}
+
+impl RpcResponder {
+    /// Answers the request with `payload`.
+    pub fn success(mut self, payload: StoredValue) {
+        self.respond(Ok(payload));
+    }
+    /// Fails the request with [RpcError::Failed].
+    pub fn failure(mut self, message: &str) {
+        self.respond(Err(RpcError::Failed(message.to_owned())));
+    }
+    /// Answers the request with a value converted with serde.
+    pub fn success_typed<T: Serialize + ?Sized>(self, payload: &T) {
+        match to_stored_value(payload) {
+            Ok(payload) => self.success(payload),
+            Err(error) => self.failure(&error.to_string()),
+        }
+    }
+
+    fn respond(&mut self, result: Result<StoredValue, RpcError>) {
+        self.sent = true;
+        let frame = self.frame.clone();
+        let id = self.id;
+        let send = move || send_response(&frame, id, result);
+        match &self.runner {
+            Some(runner) if !runner.belongs_to_current_thread() => {
+                runner.post_task(send);
+            }
+            _ => send(),
+        }
+    }
+}
+
+impl Drop for RpcResponder {
+    fn drop(&mut self) {
+        if !self.sent {
+            self.respond(Err(RpcError::Failed("request was dropped without a response".to_owned())));
+        }
+    }
+}
+
+fn send_response(frame: &Frame, id: u32, result: Result<StoredValue, RpcError>) {
+    if !frame.is_valid() {
+        return;
+    }
+    let message = ProcessMessage::new(RESPONSE_MESSAGE);
+    let args = message.get_argument_list();
+    args.set_int(0, id as i32);
+    match result {
+        Ok(payload) => match Value::try_from(payload) {
+            Ok(payload) => {
+                args.set_int(1, STATUS_OK);
+                args.set_value_inner(2, payload);
+            }
+            Err(error) => {
+                args.set_int(1, STATUS_FAILED);
+                args.set_string(2, error);
+            }
+        },
+        Err(RpcError::NoHandler) => {
+            args.set_int(1, STATUS_NO_HANDLER);
+        }
+        Err(error) => {
+            args.set_int(1, STATUS_FAILED);
+            args.set_string(2, &error.to_string());
+        }
+    }
+    frame.send_process_message(message);
+}
+
+/// A future resolving to the reply of an [RpcRouter::call_async] call.
+pub struct RpcFuture(oneshot::Receiver<Result<StoredValue, RpcError>>);
+
+impl Future for RpcFuture {
+    type Output = Result<StoredValue, RpcError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|result| result.unwrap_or_else(|_| Err(RpcError::Cancelled)))
+    }
+}
@@ -0,0 +1,539 @@
+//! Serving a directory tree from disk.
+//!
+//! [StaticFiles] maps request URLs to the files below a root directory. It
+//! guesses MIME types from file extensions, serves `index.html` for directories,
+//! can fall back to a single page application's entry point, answers conditional
+//! requests with `304 Not Modified` and honours `Range` headers. Files are read
+//! in chunks as CEF asks for them, so large files are never loaded into memory.
+//!
+//! ```ignore
+//! let ui = StaticFiles::new("ui/dist").spa_fallback("index.html");
+//! SchemeHandlerFactory::new(ui).register(SchemeName::Custom { name: "app".into() });
+//! // app://ui/settings/profile now serves ui/dist/index.html.
+//! ```
+
+use crate::{
+    browser::Browser,
+    callback::Callback,
+    frame::Frame,
+    load_handler::ErrorCode,
+    request::Request,
+    response::Response,
+    router::{reason_phrase, url_path},
+    scheme::SchemeHandlerFactoryCallbacks,
+    url_request::{ResourceHandler, ResourceHandlerCallbacks, ResourceReadHandler, ResourceSkipCallback},
+};
+use chrono::{DateTime, Utc};
+use std::{
+    collections::HashMap,
+    fs::{File, Metadata},
+    io::{self, Read, Seek, SeekFrom},
+    path::PathBuf,
+    time::UNIX_EPOCH,
+};
+
+pub use crate::router::mime_type;
+pub(crate) use crate::router::is_text;
+
+/// Formats `time` as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Decodes `%XX` escapes in a URL path.
+pub(crate) fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        match (bytes[i], bytes.get(i + 1).copied().and_then(hex), bytes.get(i + 2).copied().and_then(hex)) {
+            (b'%', Some(high), Some(low)) => {
+                decoded.push((high * 16 + low) as u8);
+                i += 3;
+            }
+            (b, _, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Splits a decoded URL path into its segments, leaving out empty and `.`
+/// segments. Returns None if the path would leave the directory it is relative
+/// to, or if a segment could be interpreted as a path on its own.
+pub(crate) fn path_segments(path: &str) -> Option<Vec<&str>> {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => (),
+            ".." => return None,
+            segment if segment.contains(|c| c == '\\' || c == ':' || c == '\0') => return None,
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments)
+}
+
+/// Removes `prefix` from the URL path `path`. Returns None if `path` doesn't
+/// start with the whole prefix.
+pub(crate) fn strip_path_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    if !path.starts_with(prefix) {
+        return None;
+    }
+    let path = &path[prefix.len()..];
+    if path.is_empty() || path.starts_with('/') {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// Returns `url` with a `/` appended to its path, to redirect a directory to its
+/// index so that relative URLs in the index resolve against the directory.
+pub(crate) fn directory_url(url: &str) -> String {
+    match url.find(|c| c == '?' || c == '#') {
+        Some(i) => format!("{}/{}", &url[..i], &url[i..]),
+        None => format!("{}/", url),
+    }
+}
+
+/// Serves the files below a root directory. Create a [ResourceHandler] per
+/// request with [resource_handler](StaticFiles::resource_handler), or register it
+/// as a scheme handler factory.
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+    prefix: String,
+    index_file: Option<String>,
+    spa_fallback: Option<String>,
+    cache_control: String,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> StaticFiles {
+        StaticFiles {
+            root: root.into(),
+            prefix: String::new(),
+            index_file: Some("index.html".to_string()),
+            spa_fallback: None,
+            cache_control: "no-cache".to_string(),
+        }
+    }
+    /// Only serve URLs whose path starts with `prefix`, which is removed before
+    /// the path is mapped to a file, e.g. `/ui` to serve `/ui/app.js` from
+    /// `root/app.js`.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_end_matches('/').to_string();
+        self
+    }
+    /// Set the file served for directories, or None to answer them with `404 Not
+    /// Found`. Defaults to `index.html`.
+    pub fn index_file(mut self, index_file: Option<&str>) -> Self {
+        self.index_file = index_file.map(String::from);
+        self
+    }
+    /// Serve the file at `path`, relative to the root, for paths that don't
+    /// exist and have no file extension, so that a single page application can
+    /// route them on the client.
+    pub fn spa_fallback(mut self, path: &str) -> Self {
+        self.spa_fallback = Some(path.trim_start_matches('/').to_string());
+        self
+    }
+    /// Set the `Cache-Control` header of served files. Defaults to `no-cache`,
+    /// which makes the browser revalidate them with the ETag on every use.
+    pub fn cache_control(mut self, cache_control: &str) -> Self {
+        self.cache_control = cache_control.to_string();
+        self
+    }
+    /// Create the handler for a single request.
+    pub fn handler(&self) -> StaticFileHandler {
+        StaticFileHandler {
+            config: self.clone(),
+            response: None,
+        }
+    }
+    pub fn resource_handler(&self) -> ResourceHandler {
+        ResourceHandler::new(self.handler())
+    }
+
+    /// Map the path of a URL to a file, or to the response if there is none.
+    fn resolve(&self, url: &str) -> Result<PathBuf, FileResponse> {
+        let path = url_path(url);
+        let path = match strip_path_prefix(path, &self.prefix) {
+            Some(path) => path,
+            None => return Err(FileResponse::error(404)),
+        };
+        let path = percent_decode(path);
+        let mut file_path = self.root.clone();
+        match path_segments(&path) {
+            Some(segments) => file_path.extend(segments),
+            None => return Err(FileResponse::error(404)),
+        }
+        if file_path.is_dir() {
+            if !path.ends_with('/') {
+                let mut response = FileResponse::error(301);
+                response.headers.insert("Location".to_string(), vec![directory_url(url)]);
+                return Err(response);
+            }
+            match &self.index_file {
+                Some(index_file) => file_path.push(index_file),
+                None => return Err(FileResponse::error(404)),
+            }
+        }
+        if file_path.is_file() {
+            return Ok(file_path);
+        }
+        let has_extension = path.rsplit('/').next().map_or(false, |name| name.contains('.'));
+        match &self.spa_fallback {
+            Some(spa_fallback) if !has_extension => Ok(self.root.join(spa_fallback)),
+            _ => Err(FileResponse::error(404)),
+        }
+    }
+}
+
+impl SchemeHandlerFactoryCallbacks for StaticFiles {
+    fn create(
+        &self,
+        _browser: Browser,
+        _frame: Frame,
+        _scheme_name: &str,
+        _request: Request,
+    ) -> Option<ResourceHandler> {
+        Some(self.resource_handler())
+    }
+}
+
+struct FileResponse {
+    status: i32,
+    mime_type: &'static str,
+    headers: HashMap<String, Vec<String>>,
+    file: Option<File>,
+    /// The offset in the file of the first byte of the body.
+    start: u64,
+    /// The offset in the file after the last byte of the body.
+    end: u64,
+    /// The offset in the file of the next byte to send.
+    position: u64,
+}
+
+impl FileResponse {
+    fn error(status: i32) -> FileResponse {
+        FileResponse {
+            status,
+            mime_type: "text/plain",
+            headers: HashMap::new(),
+            file: None,
+            start: 0,
+            end: 0,
+            position: 0,
+        }
+    }
+}
+
+/// The [ResourceHandlerCallbacks] that serves a single request for
+/// [StaticFiles].
+pub struct StaticFileHandler {
+    config: StaticFiles,
+    response: Option<FileResponse>,
+}
+
+impl StaticFileHandler {
+    fn respond(&self, request: &Request) -> FileResponse {
+        let method = request.get_method();
+        let head = method.eq_ignore_ascii_case("HEAD");
+        if !head && !method.eq_ignore_ascii_case("GET") {
+            let mut response = FileResponse::error(405);
+            response.headers.insert("Allow".to_string(), vec!["GET, HEAD".to_string()]);
+            return response;
+        }
+        let path = match self.config.resolve(&request.get_url()) {
+            Ok(path) => path,
+            Err(response) => return response,
+        };
+        let (mut file, metadata) = match File::open(&path).and_then(|file| Ok((file.metadata()?, file))) {
+            Ok((metadata, file)) => (file, metadata),
+            Err(err) => {
+                log::warn!("failed to open {}: {}", path.display(), err);
+                return FileResponse::error(match err.kind() {
+                    io::ErrorKind::PermissionDenied => 403,
+                    _ => 404,
+                });
+            }
+        };
+        let length = metadata.len();
+        let (etag, last_modified) = validators(&metadata);
+
+        let mut response = FileResponse {
+            status: 200,
+            mime_type: mime_type(&path),
+            headers: HashMap::new(),
+            file: None,
+            start: 0,
+            end: length,
+            position: 0,
+        };
+        response.headers.insert("Accept-Ranges".to_string(), vec!["bytes".to_string()]);
+        response.headers.insert("Cache-Control".to_string(), vec![self.config.cache_control.clone()]);
+        response.headers.insert("ETag".to_string(), vec![etag.clone()]);
+        if let Some(last_modified) = last_modified {
+            response.headers.insert("Last-Modified".to_string(), vec![http_date(last_modified)]);
+        }
+
+        if not_modified(request, &etag, last_modified) {
+            response.status = 304;
+            response.end = 0;
+            return response;
+        }
+        if let Some(range) = request.get_header_by_name("Range") {
+            match parse_range(&range, length) {
+                Some(Ok((first, last))) => {
+                    if let Err(err) = file.seek(SeekFrom::Start(first)) {
+                        log::warn!("failed to seek in {}: {}", path.display(), err);
+                        return FileResponse::error(500);
+                    }
+                    response.status = 206;
+                    response.start = first;
+                    response.end = last + 1;
+                    response.position = first;
+                    response.headers.insert(
+                        "Content-Range".to_string(),
+                        vec![format!("bytes {}-{}/{}", first, last, length)],
+                    );
+                }
+                Some(Err(())) => {
+                    let mut response = FileResponse::error(416);
+                    response.headers.insert("Content-Range".to_string(), vec![format!("bytes */{}", length)]);
+                    return response;
+                }
+                // Multiple or malformed ranges are answered with the whole file.
+                None => (),
+            }
+        }
+        if head {
+            response.position = response.end;
+        } else {
+            response.file = Some(file);
+        }
+        response
+    }
+}
+
+/// Returns the ETag and modification time of a file.
+fn validators(metadata: &Metadata) -> (String, Option<DateTime<Utc>>) {
+    let modified = metadata.modified().ok();
+    let nanos = modified
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since_epoch| since_epoch.as_nanos());
+    let etag = format!("\"{:x}-{:x}\"", metadata.len(), nanos);
+    (etag, modified.map(DateTime::from))
+}
+
+/// Returns true if the conditional headers of `request` show that the browser's
+/// cached copy is still current.
+pub(crate) fn not_modified(request: &Request, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    if let Some(if_none_match) = request.get_header_by_name("If-None-Match") {
+        // If-None-Match takes precedence over If-Modified-Since and compares weakly.
+        return if_none_match
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == etag);
+    }
+    match (request.get_header_by_name("If-Modified-Since"), last_modified) {
+        (Some(since), Some(last_modified)) => DateTime::parse_from_rfc2822(&since)
+            .map(|since| last_modified.timestamp() <= since.timestamp())
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Returns the position to read from after CEF asked to skip `bytes_to_skip`
+/// bytes of a body that is read up to `end`. CEF skips to the first byte of a
+/// `Range` header, which the response already starts at, so `bytes_to_skip` is
+/// taken as an offset in the file and skipping to a position that has been passed
+/// does nothing.
+pub(crate) fn skip_position(position: u64, end: u64, bytes_to_skip: u64) -> Result<u64, ErrorCode> {
+    if bytes_to_skip >= end {
+        Err(ErrorCode::RequestRangeNotSatisfiable)
+    } else {
+        Ok(position.max(bytes_to_skip))
+    }
+}
+
+/// Parses a `Range` header with a single byte range into the first and last
+/// byte positions. Returns None for headers that should be ignored and
+/// `Some(Err(()))` for ranges that can't be satisfied.
+pub(crate) fn parse_range(range: &str, length: u64) -> Option<Result<(u64, u64), ()>> {
+    let range = range.trim();
+    if !range.starts_with("bytes=") {
+        return None;
+    }
+    let spec = &range["bytes=".len()..];
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_at(spec.find('-')?);
+    let (first, last) = (first.trim(), last[1..].trim());
+    let range = if first.is_empty() {
+        // A suffix range: the last `last` bytes.
+        let suffix: u64 = last.parse().ok()?;
+        if suffix == 0 || length == 0 {
+            return Some(Err(()));
+        }
+        (length.saturating_sub(suffix), length - 1)
+    } else {
+        let first: u64 = first.parse().ok()?;
+        let last = match last {
+            "" => std::u64::MAX,
+            last => last.parse().ok()?,
+        };
+        if last < first {
+            return None;
+        }
+        if first >= length {
+            return Some(Err(()));
+        }
+        (first, last.min(length - 1))
+    };
+    Some(Ok(range))
+}
+
+impl ResourceHandlerCallbacks for StaticFileHandler {
+    fn open(&mut self, request: Request, handle_request: &mut bool, _callback: Callback) -> bool {
+        self.response = Some(self.respond(&request));
+        *handle_request = true;
+        true
+    }
+    fn get_response_headers(
+        &self,
+        response: Response,
+        response_length: &mut Option<u64>,
+        _redirect_url: &mut String,
+    ) {
+        let file_response = match &self.response {
+            Some(file_response) => file_response,
+            None => return,
+        };
+        response.set_header_map(&file_response.headers);
+        response.set_status(file_response.status);
+        response.set_status_text(reason_phrase(file_response.status));
+        response.set_mime_type(file_response.mime_type);
+        if is_text(file_response.mime_type) {
+            response.set_charset("utf-8");
+        }
+        *response_length = Some(file_response.end - file_response.start);
+    }
+    /// See [skip_position].
+    fn skip(&mut self, bytes_to_skip: u64, bytes_skipped: &mut u64, _callback: ResourceSkipCallback) -> Result<(), ErrorCode> {
+        let file_response = match &mut self.response {
+            Some(file_response) => file_response,
+            None => return Err(ErrorCode::Failed),
+        };
+        if let Some(file) = &mut file_response.file {
+            let position = skip_position(file_response.position, file_response.end, bytes_to_skip)?;
+            if position != file_response.position {
+                file.seek(SeekFrom::Start(position)).map_err(|_| ErrorCode::Failed)?;
+                file_response.position = position;
+            }
+        }
+        *bytes_skipped = bytes_to_skip;
+        Ok(())
+    }
+    fn read(&mut self, mut handler: ResourceReadHandler) -> Option<ResourceReadHandler> {
+        let file_response = match &mut self.response {
+            Some(file_response) => file_response,
+            None => {
+                handler.set_error(ErrorCode::Failed);
+                return Some(handler);
+            }
+        };
+        let file = match &mut file_response.file {
+            Some(file) if file_response.position < file_response.end => file,
+            _ => {
+                handler.set_bytes_read(0);
+                return Some(handler);
+            }
+        };
+        let buffer = handler.as_buffer_ref();
+        let len = (buffer.len() as u64).min(file_response.end - file_response.position) as usize;
+        loop {
+            match file.read(&mut buffer[..len]) {
+                Ok(read) => {
+                    file_response.position += read as u64;
+                    handler.set_bytes_read(read as i32);
+                    break;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => {
+                    log::warn!("failed to read file: {}", err);
+                    handler.set_error(ErrorCode::Failed);
+                    break;
+                }
+            }
+        }
+        Some(handler)
+    }
+    fn cancel(&mut self) {
+        self.response = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("bytes=0-0", 10), Some(Ok((0, 0))));
+        assert_eq!(parse_range("bytes=2-5", 10), Some(Ok((2, 5))));
+        assert_eq!(parse_range(" bytes= 2 - 5 ", 10), Some(Ok((2, 5))));
+        assert_eq!(parse_range("bytes=4-", 10), Some(Ok((4, 9))));
+        assert_eq!(parse_range("bytes=4-100", 10), Some(Ok((4, 9))));
+        assert_eq!(parse_range("bytes=-3", 10), Some(Ok((7, 9))));
+        assert_eq!(parse_range("bytes=-30", 10), Some(Ok((0, 9))));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(parse_range("bytes=10-", 10), Some(Err(())));
+        assert_eq!(parse_range("bytes=20-30", 10), Some(Err(())));
+        assert_eq!(parse_range("bytes=-0", 10), Some(Err(())));
+        assert_eq!(parse_range("bytes=-5", 0), Some(Err(())));
+        assert_eq!(parse_range("bytes=0-", 0), Some(Err(())));
+    }
+
+    #[test]
+    fn ignored_ranges() {
+        assert_eq!(parse_range("items=0-5", 10), None);
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), None);
+        assert_eq!(parse_range("bytes=5-2", 10), None);
+        assert_eq!(parse_range("bytes=a-b", 10), None);
+        assert_eq!(parse_range("bytes=5", 10), None);
+        assert_eq!(parse_range("bytes=-", 10), None);
+    }
+
+    #[test]
+    fn skipping() {
+        // CEF skips to the start of the range the response already starts at.
+        assert_eq!(skip_position(4, 10, 4), Ok(4));
+        assert_eq!(skip_position(4, 10, 0), Ok(4));
+        assert_eq!(skip_position(4, 10, 7), Ok(7));
+        assert_eq!(skip_position(0, 10, 9), Ok(9));
+        assert_eq!(skip_position(0, 10, 10), Err(ErrorCode::RequestRangeNotSatisfiable));
+    }
+
+    #[test]
+    fn segments() {
+        assert_eq!(path_segments("/"), Some(vec![]));
+        assert_eq!(path_segments("/a/b.js"), Some(vec!["a", "b.js"]));
+        assert_eq!(path_segments("//a/./b//"), Some(vec!["a", "b"]));
+        assert_eq!(path_segments("/a/.../b"), Some(vec!["a", "...", "b"]));
+        assert_eq!(path_segments("/a/../b"), None);
+        assert_eq!(path_segments(".."), None);
+        assert_eq!(path_segments("/a\\..\\b"), None);
+        assert_eq!(path_segments("/C:/Windows"), None);
+        assert_eq!(path_segments("/a\0b"), None);
+    }
+}
@@ -1,8 +1,15 @@
 use cef_sys::{
     _cef_stream_reader_t,
     _cef_stream_writer_t,
+    cef_stream_reader_create_for_data,
+    cef_stream_reader_create_for_file,
 };
-use std::io::{self, SeekFrom};
+use std::{
+    io::{self, SeekFrom},
+    os::raw::c_void,
+    path::Path,
+};
+use crate::string::CefString;
 
 ref_counted_ptr!{
     pub struct StreamReader(*mut _cef_stream_reader_t);
@@ -15,6 +22,16 @@ ref_counted_ptr!{
 // TODO: CREATE CUSTOM READERS AND WRITERS
 
 impl StreamReader {
+    /// Create a reader for the file at `path`. Returns None if it can't be opened.
+    pub fn from_file(path: &Path) -> Option<StreamReader> {
+        unsafe{ StreamReader::from_ptr(cef_stream_reader_create_for_file(CefString::new(&path.to_string_lossy()).as_ptr())) }
+    }
+
+    /// Create a reader for a copy of `data`.
+    pub fn from_data(data: &[u8]) -> Option<StreamReader> {
+        unsafe{ StreamReader::from_ptr(cef_stream_reader_create_for_data(data.as_ptr() as *mut c_void, data.len())) }
+    }
+
     /// Read raw binary data, returning how many bytes were read.
     pub fn read(&self, data: &mut [u8]) -> usize {
         unsafe{ (self.0.read.unwrap())(self.as_ptr(), data.as_mut_ptr() as *mut _, 1, data.len()) }
@@ -5,6 +5,9 @@ use cef_sys::{
 };
 use parking_lot::Mutex;
 
+pub mod executor;
+pub use self::executor::{Delay, DelayError, JoinError, JoinHandle};
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ThreadId {
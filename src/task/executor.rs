@@ -0,0 +1,278 @@
+//! Running futures on CEF threads.
+//!
+//! A spawned future is polled by tasks posted to its [TaskRunner], and waking it
+//! posts another task to the same runner, so the future only ever runs on the
+//! thread CEF requires:
+//!
+//! ```ignore
+//! let handle = TaskRunner::spawn_on(ThreadId::UI, async {
+//!     Delay::new(Duration::from_secs(1)).await.ok()?;
+//!     Some(cookie_manager.flush_store_async().await)
+//! });
+//! ```
+
+use super::{TaskRunner, ThreadId};
+use crate::oneshot;
+use parking_lot::Mutex;
+use std::{
+    future::Future,
+    mem::ManuallyDrop,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+    time::Duration,
+};
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Where the tasks of a spawned future are posted.
+#[derive(Clone)]
+enum Target {
+    Thread(ThreadId),
+    Runner(TaskRunner),
+}
+
+impl Target {
+    fn post(&self, task: impl FnOnce() + Send + 'static) -> bool {
+        match self {
+            Target::Thread(thread_id) => TaskRunner::post_task_on(*thread_id, task),
+            Target::Runner(runner) => runner.post_task(task),
+        }
+    }
+    fn post_delayed(&self, task: impl FnOnce() + Send + 'static, delay_ms: i64) -> bool {
+        match self {
+            Target::Thread(thread_id) => TaskRunner::post_delayed_task_on(*thread_id, task, delay_ms),
+            Target::Runner(runner) => runner.post_delayed_task(task, delay_ms),
+        }
+    }
+}
+
+struct Task {
+    future: Mutex<Option<BoxFuture>>,
+    target: Target,
+    /// Set while a task polling the future is posted, so that repeated wakes don't
+    /// post more than one.
+    scheduled: AtomicBool,
+    /// Set if a task couldn't be posted while the future was being polled. The
+    /// future is dropped once the poll returns.
+    orphaned: AtomicBool,
+}
+
+impl Task {
+    fn schedule(self: &Arc<Self>) {
+        if self.scheduled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let task = self.clone();
+        if !self.target.post(move || task.run()) {
+            // The thread is gone, so the future can never complete. Dropping it
+            // resolves its join handle.
+            match self.future.try_lock() {
+                Some(mut future) => *future = None,
+                None => self.orphaned.store(true, Ordering::Release),
+            }
+        }
+    }
+
+    fn run(self: &Arc<Self>) {
+        self.scheduled.store(false, Ordering::Release);
+        let mut slot = self.future.lock();
+        if let Some(future) = slot.as_mut() {
+            let waker = new_waker(self.clone());
+            let mut cx = Context::from_waker(&waker);
+            let ready = future.as_mut().poll(&mut cx).is_ready();
+            if ready || self.orphaned.load(Ordering::Acquire) {
+                *slot = None;
+            }
+        }
+    }
+}
+
+static WAKER_VTABLE: RawWakerVTable =
+    RawWakerVTable::new(clone_waker, wake, wake_by_ref, drop_waker);
+
+fn new_waker(task: Arc<Task>) -> Waker {
+    unsafe { Waker::from_raw(RawWaker::new(Arc::into_raw(task) as *const (), &WAKER_VTABLE)) }
+}
+
+unsafe fn clone_waker(ptr: *const ()) -> RawWaker {
+    let task = ManuallyDrop::new(Arc::from_raw(ptr as *const Task));
+    let task: Arc<Task> = Arc::clone(&task);
+    RawWaker::new(Arc::into_raw(task) as *const (), &WAKER_VTABLE)
+}
+
+unsafe fn wake(ptr: *const ()) {
+    let task = Arc::from_raw(ptr as *const Task);
+    task.schedule();
+}
+
+unsafe fn wake_by_ref(ptr: *const ()) {
+    let task = ManuallyDrop::new(Arc::from_raw(ptr as *const Task));
+    task.schedule();
+}
+
+unsafe fn drop_waker(ptr: *const ()) {
+    drop(Arc::from_raw(ptr as *const Task));
+}
+
+fn spawn_with<F>(target: Target, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    let task = Arc::new(Task {
+        future: Mutex::new(Some(Box::pin(async move {
+            sender.send(future.await);
+        }))),
+        target,
+        scheduled: AtomicBool::new(false),
+        orphaned: AtomicBool::new(false),
+    });
+    task.schedule();
+    JoinHandle(receiver)
+}
+
+impl TaskRunner {
+    /// Run `future` on the thread associated with this task runner. The future is
+    /// first polled asynchronously, and it keeps running if the returned handle is
+    /// dropped.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        spawn_with(Target::Runner(self.clone()), future)
+    }
+    /// Run `future` on the specified thread. Equivalent to using
+    /// `TaskRunner::get_for_thread(thread_id).spawn(future)`.
+    pub fn spawn_on<F>(thread_id: ThreadId, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        spawn_with(Target::Thread(thread_id), future)
+    }
+}
+
+/// The error returned by a [JoinHandle] whose future was dropped before it
+/// completed, because the thread it was spawned on no longer accepts tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinError;
+
+impl std::fmt::Display for JoinError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("the task was dropped before it completed")
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// Resolves to the output of a future spawned with [TaskRunner::spawn] or
+/// [TaskRunner::spawn_on]. It can be awaited on any thread and with any executor.
+pub struct JoinHandle<T>(oneshot::Receiver<T>);
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx).map(|result| result.map_err(|_| JoinError))
+    }
+}
+
+/// The error returned by a [Delay] whose timer task couldn't be posted, because
+/// the thread it was posted to no longer accepts tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DelayError;
+
+impl std::fmt::Display for DelayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("the timer task couldn't be posted")
+    }
+}
+
+impl std::error::Error for DelayError {}
+
+struct DelayState {
+    fired: bool,
+    waker: Option<Waker>,
+}
+
+/// A future that completes after a duration, built on
+/// [TaskRunner::post_delayed_task]. The timer task is posted to the thread that
+/// first polls the delay, or to the UI thread if that isn't a CEF thread. If it
+/// can't be posted, the delay completes right away with a [DelayError].
+pub struct Delay {
+    delay_ms: i64,
+    runner: Option<TaskRunner>,
+    posted: bool,
+    state: Arc<Mutex<DelayState>>,
+}
+
+impl Delay {
+    /// Creates a delay that completes `duration` after it is first polled.
+    pub fn new(duration: Duration) -> Delay {
+        let mut delay_ms = duration.as_millis();
+        if duration.subsec_nanos() % 1_000_000 != 0 {
+            delay_ms += 1;
+        }
+        Delay {
+            delay_ms: delay_ms.min(i64::max_value() as u128) as i64,
+            runner: None,
+            posted: false,
+            state: Arc::new(Mutex::new(DelayState {
+                fired: false,
+                waker: None,
+            })),
+        }
+    }
+    /// Creates a delay whose timer task is posted to `runner`.
+    pub fn new_on(runner: &TaskRunner, duration: Duration) -> Delay {
+        Delay {
+            runner: Some(runner.clone()),
+            ..Delay::new(duration)
+        }
+    }
+}
+
+impl Future for Delay {
+    type Output = Result<(), DelayError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        {
+            let mut state = self.state.lock();
+            if state.fired {
+                return Poll::Ready(Ok(()));
+            }
+            state.waker = Some(cx.waker().clone());
+        }
+        if !self.posted {
+            let target = self
+                .runner
+                .clone()
+                .or_else(TaskRunner::get_for_current_thread)
+                .map(Target::Runner)
+                .unwrap_or(Target::Thread(ThreadId::UI));
+            let state = self.state.clone();
+            let fire = move || {
+                let waker = {
+                    let mut state = state.lock();
+                    state.fired = true;
+                    state.waker.take()
+                };
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            };
+            if !target.post_delayed(fire, self.delay_ms) {
+                // Nothing could ever fire the timer; fail instead of hanging.
+                return Poll::Ready(Err(DelayError));
+            }
+            self.posted = true;
+        }
+        Poll::Pending
+    }
+}
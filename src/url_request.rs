@@ -7,6 +7,7 @@ use cef_sys::{
     cef_urlrequest_create, cef_urlrequest_status_t, cef_urlrequest_t,
 };
 use std::{
+    collections::VecDeque,
     convert::TryInto,
     ptr::null_mut,
     os::raw::{c_int, c_void},
@@ -479,7 +480,10 @@ pub trait ResponseFilterCallbacks: 'static + Send + Sync {
     /// then be called an additional time with an zero-length input slice if the user
     /// filled the output slice (set `data_out_written` = `data_out.len()`) and
     /// returned [ResponseFilterStatus::NeedMoreData] to indicate that output data is
-    /// still pending.
+    /// still pending. CEF's filter wrapper makes that call whenever the last call
+    /// returned [ResponseFilterStatus::NeedMoreData], even if the output wasn't
+    /// filled, so it is the only reliable signal that the response is complete.
+    /// [FilterOutput] implements this contract.
     ///
     /// Calls to this function will stop when one of the following conditions is
     /// met:
@@ -499,6 +503,51 @@ pub trait ResponseFilterCallbacks: 'static + Send + Sync {
     }
 }
 
+/// The output of a [ResponseFilterCallbacks] implementation that reads all of
+/// its input on every call, queued until it fits into `data_out`.
+///
+/// Such a filter returns [ResponseFilterStatus::NeedMoreData] for every chunk of
+/// the response, so that CEF calls it once more with empty input after the last
+/// one. [at_end](FilterOutput::at_end) recognizes that call, after which the
+/// filter queues whatever it held back, and CEF keeps calling it until the queue
+/// is written and [write](FilterOutput::write) returns
+/// [ResponseFilterStatus::Done].
+#[derive(Debug, Default)]
+pub(crate) struct FilterOutput {
+    queue: VecDeque<u8>,
+    finished: bool,
+}
+
+impl FilterOutput {
+    /// Returns true for the first call with empty input, which means that the
+    /// response is complete.
+    pub(crate) fn at_end(&mut self, data_in: &[u8]) -> bool {
+        if data_in.is_empty() && !self.finished {
+            self.finished = true;
+            true
+        } else {
+            false
+        }
+    }
+    pub(crate) fn push(&mut self, data: &[u8]) {
+        self.queue.extend(data);
+    }
+    /// Write as much of the queue as fits into `data_out` and return the status
+    /// for CEF.
+    pub(crate) fn write(&mut self, data_out: &mut [u8], data_out_written: &mut usize) -> ResponseFilterStatus {
+        let written = data_out.len().min(self.queue.len());
+        for (out, byte) in data_out.iter_mut().zip(self.queue.drain(..written)) {
+            *out = byte;
+        }
+        *data_out_written = written;
+        if self.finished && self.queue.is_empty() {
+            ResponseFilterStatus::Done
+        } else {
+            ResponseFilterStatus::NeedMoreData
+        }
+    }
+}
+
 pub(crate) struct ResponseFilterWrapper {
     delegate: Box<dyn ResponseFilterCallbacks>,
 }
@@ -1,3 +1,11 @@
+pub mod ser;
+pub mod de;
+pub(crate) mod promise;
+pub use self::{
+    ser::{to_v8_value, V8Date},
+    de::from_v8_value,
+    promise::V8PromiseResolver,
+};
 use cef_sys::{
     cef_base_ref_counted_t, cef_register_extension, cef_string_t, cef_time_from_doublet,
     cef_time_to_doublet, cef_v8_accesscontrol_t, cef_v8_propertyattribute_t, cef_v8accessor_t,
@@ -17,7 +25,7 @@ use std::{
     convert::TryFrom,
     ptr::null_mut,
     mem::ManuallyDrop,
-    time::{Duration, SystemTime, SystemTimeError},
+    time::{SystemTime, SystemTimeError},
 };
 
 use crate::{
@@ -27,8 +35,10 @@ use crate::{
     send_protector::SendProtector,
     string::{CefString, CefStringList},
     task::TaskRunner,
+    values::{to_stored_value, SerdeError, StoredValue},
 };
 use bitflags::bitflags;
+use serde::Serialize;
 
 ref_counted_ptr! {
     /// Structure representing a V8 context handle. V8 handles can only be accessed
@@ -109,10 +119,10 @@ impl V8Context {
     ///   // Call another function.
     ///   example.test.increment();
     /// ```
-    pub fn register_extension(
+    pub fn register_extension<E: Into<JsError>>(
         extension_name: &str,
         javascript_code: &str,
-        handler: impl Fn(&str, V8Value, &[V8Value]) -> Result<V8Value, String> + Sync + Send + 'static,
+        handler: impl Fn(&str, V8Value, &[V8Value]) -> Result<V8Value, E> + Sync + Send + 'static,
     ) {
         let name = CefString::new(extension_name);
         let js = CefString::new(javascript_code);
@@ -120,7 +130,7 @@ impl V8Context {
             cef_register_extension(
                 name.as_ptr(),
                 js.as_ptr(),
-                V8HandlerWrapper::new(Box::new(handler)).wrap().into_raw(),
+                V8HandlerWrapper::new(handler).wrap().into_raw(),
             );
         }
     }
@@ -316,6 +326,379 @@ impl V8Exception {
     }
 }
 
+/// The class of a [JsError] when it is thrown into JavaScript.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsErrorKind {
+    Error,
+    TypeError,
+    RangeError,
+    ReferenceError,
+    SyntaxError,
+    EvalError,
+    URIError,
+    /// A custom error class. If the global object has a constructor of this name
+    /// it is called with the message, otherwise an `Error` whose `name` is set to
+    /// the class name is thrown.
+    Custom(String),
+}
+
+impl JsErrorKind {
+    /// Returns the name of the constructor used to create the error.
+    pub fn class_name(&self) -> &str {
+        match self {
+            JsErrorKind::Error => "Error",
+            JsErrorKind::TypeError => "TypeError",
+            JsErrorKind::RangeError => "RangeError",
+            JsErrorKind::ReferenceError => "ReferenceError",
+            JsErrorKind::SyntaxError => "SyntaxError",
+            JsErrorKind::EvalError => "EvalError",
+            JsErrorKind::URIError => "URIError",
+            JsErrorKind::Custom(name) => name,
+        }
+    }
+    /// Returns the kind whose [class_name](JsErrorKind::class_name) is `name`.
+    pub fn from_class_name(name: &str) -> JsErrorKind {
+        match name {
+            "Error" => JsErrorKind::Error,
+            "TypeError" => JsErrorKind::TypeError,
+            "RangeError" => JsErrorKind::RangeError,
+            "ReferenceError" => JsErrorKind::ReferenceError,
+            "SyntaxError" => JsErrorKind::SyntaxError,
+            "EvalError" => JsErrorKind::EvalError,
+            "URIError" => JsErrorKind::URIError,
+            name => JsErrorKind::Custom(name.to_owned()),
+        }
+    }
+}
+
+impl Default for JsErrorKind {
+    fn default() -> JsErrorKind {
+        JsErrorKind::Error
+    }
+}
+
+impl std::fmt::Display for JsErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.class_name())
+    }
+}
+
+/// A frame of the stack captured in a [JsError]. Unlike [V8StackFrame] this is
+/// plain data that can be used on any thread.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JsStackFrame {
+    /// The name of the resource that contains the function, or the sourceURL
+    /// value if the script name is undefined and its source ends with a
+    /// `//@ sourceURL=...` string.
+    pub script_name: String,
+    /// The name of the function, empty for anonymous functions.
+    pub function_name: String,
+    /// The 1-based line number of the function call or 0 if unknown.
+    pub line_number: i32,
+    /// The 1-based column offset on the line of the function call or 0 if
+    /// unknown.
+    pub column: i32,
+    /// True if the function was compiled using eval().
+    pub is_eval: bool,
+    /// True if the function was called as a constructor via "new".
+    pub is_constructor: bool,
+}
+
+impl From<&V8StackFrame> for JsStackFrame {
+    fn from(frame: &V8StackFrame) -> JsStackFrame {
+        JsStackFrame {
+            script_name: frame.get_script_name_or_source_url(),
+            function_name: frame.get_function_name(),
+            line_number: frame.get_line_number(),
+            column: frame.get_column(),
+            is_eval: frame.is_eval(),
+            is_constructor: frame.is_constructor(),
+        }
+    }
+}
+
+impl std::fmt::Display for JsStackFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let function_name = if self.function_name.is_empty() {
+            "<anonymous>"
+        } else {
+            &self.function_name
+        };
+        if self.is_constructor {
+            f.write_str("new ")?;
+        }
+        write!(f, "{}", function_name)?;
+        if !self.script_name.is_empty() || self.line_number > 0 {
+            write!(f, " ({}:{}:{})", self.script_name, self.line_number, self.column)?;
+        }
+        Ok(())
+    }
+}
+
+/// A frame limit for [JsError::capture_stack] that keeps errors reasonably small.
+pub const JS_ERROR_STACK_LIMIT: i32 = 32;
+
+const NEW_ERROR: &str = "(function(name, message) {\
+    var C = globalThis[name];\
+    if (typeof C === 'function') { return new C(message); }\
+    var e = new Error(message);\
+    e.name = name;\
+    return e;\
+})";
+
+const THROW: &str = "(function(e) { throw e; })";
+
+/// A JavaScript error. It is either captured from a [V8Exception], or created in
+/// Rust to be thrown into JavaScript by returning it from a function created with
+/// [V8Value::new_function] or registered with [register_extension]. Unlike
+/// [V8Exception] this is plain data, so it can be kept around and sent between
+/// threads and processes.
+///
+/// ```ignore
+/// let function = V8Value::new_function("setVolume", |_, _, args| {
+///     let volume = args.get(0).and_then(|arg| arg.get_double_value());
+///     match volume {
+///         Some(volume) if volume >= 0.0 && volume <= 1.0 => Ok(V8Value::undefined()),
+///         Some(_) => Err(JsError::range_error("volume must be between 0 and 1")),
+///         None => Err(JsError::custom("AudioError", "no volume given").with_property("code", &22)?),
+///     }
+/// });
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsError {
+    /// The class of the error.
+    pub kind: JsErrorKind,
+    /// The exception message.
+    pub message: String,
+    /// The resource name for the script from where the function causing the
+    /// error originates.
+    pub script_resource_name: String,
+    /// The line of source code that the exception occurred within.
+    pub source_line: String,
+    /// The 1-based number of the line where the error occurred or 0 if the line
+    /// number is unknown.
+    pub line_number: i32,
+    /// The index within the line of the first character where the error occurred.
+    pub start_column: i32,
+    /// The index within the line of the last character where the error occurred.
+    pub end_column: i32,
+    /// The JavaScript stack at the time the error was captured, innermost frame
+    /// first. Empty if it wasn't captured inside a V8 context.
+    pub stack: Vec<JsStackFrame>,
+    /// Additional properties set on the error object when it is thrown.
+    pub properties: Vec<(String, StoredValue)>,
+}
+
+impl JsError {
+    /// Creates an `Error` that only carries a message, with no source position.
+    pub fn new(message: &str) -> JsError {
+        JsError::with_kind(JsErrorKind::Error, message)
+    }
+    /// Creates an error of the given class.
+    pub fn with_kind(kind: JsErrorKind, message: &str) -> JsError {
+        JsError {
+            kind,
+            message: message.to_owned(),
+            ..JsError::default()
+        }
+    }
+    /// Creates a `TypeError`.
+    pub fn type_error(message: &str) -> JsError {
+        JsError::with_kind(JsErrorKind::TypeError, message)
+    }
+    /// Creates a `RangeError`.
+    pub fn range_error(message: &str) -> JsError {
+        JsError::with_kind(JsErrorKind::RangeError, message)
+    }
+    /// Creates a `ReferenceError`.
+    pub fn reference_error(message: &str) -> JsError {
+        JsError::with_kind(JsErrorKind::ReferenceError, message)
+    }
+    /// Creates a `SyntaxError`.
+    pub fn syntax_error(message: &str) -> JsError {
+        JsError::with_kind(JsErrorKind::SyntaxError, message)
+    }
+    /// Creates an error of the custom class `class_name`. See [JsErrorKind::Custom].
+    pub fn custom(class_name: &str, message: &str) -> JsError {
+        JsError::with_kind(JsErrorKind::Custom(class_name.to_owned()), message)
+    }
+    /// Adds a property that is set on the error object when it is thrown. The value
+    /// is converted with serde.
+    pub fn with_property<T: Serialize + ?Sized>(
+        mut self,
+        name: &str,
+        value: &T,
+    ) -> Result<JsError, SerdeError> {
+        self.properties
+            .push((name.to_owned(), to_stored_value(value)?));
+        Ok(self)
+    }
+    /// Replaces [stack](JsError::stack) with the current JavaScript stack, at most
+    /// `frame_limit` frames deep. Leaves it empty when called outside of a V8
+    /// context.
+    pub fn capture_stack(mut self, frame_limit: i32) -> JsError {
+        self.stack = if V8Context::in_context() {
+            V8Context::get_current_stacktrace(frame_limit)
+                .iter()
+                .map(JsStackFrame::from)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self
+    }
+    /// Replaces [stack](JsError::stack) with `frames`, such as the stack passed to
+    /// [RenderProcessHandlerCallbacks::on_uncaught_exception].
+    pub fn with_stack(mut self, frames: &[V8StackFrame]) -> JsError {
+        self.stack = frames.iter().map(JsStackFrame::from).collect();
+        self
+    }
+    /// Creates the error object in the current context, or returns None if there is
+    /// no current context.
+    pub fn to_v8_value(&self) -> Option<V8Value> {
+        let context = V8Context::get_current()?;
+        let constructor = context.eval(NEW_ERROR, "", 0).ok()?;
+        let error = constructor.execute_function(
+            None,
+            &[
+                V8Value::from(self.kind.class_name()),
+                V8Value::from(self.message.as_str()),
+            ],
+        )?;
+        for (name, value) in &self.properties {
+            if let Ok(value) = to_v8_value(value) {
+                error.set_value_bykey(name, value, V8PropertyAttribute::empty());
+            }
+        }
+        Some(error)
+    }
+    /// Throws the error in the current context. This only has an effect from within
+    /// a [V8Handler] or [V8AccessorCallbacks] callback, where the exception
+    /// propagates to the calling script once the callback returns. Returns false if
+    /// the error couldn't be thrown.
+    pub fn throw(&self) -> bool {
+        let thrower = V8Context::get_current().and_then(|context| context.eval(THROW, "", 0).ok());
+        let thrower = match thrower {
+            Some(thrower) => thrower,
+            None => return false,
+        };
+        let error = match self.to_v8_value() {
+            Some(error) => error,
+            None => return false,
+        };
+        thrower.set_rethrow_exceptions(true);
+        thrower.execute_function(None, &[error]);
+        thrower.has_exception()
+    }
+    /// Returns true if this is a plain `Error` without properties, which can be
+    /// thrown through CEF's exception string.
+    fn is_plain(&self) -> bool {
+        self.kind == JsErrorKind::Error && self.properties.is_empty()
+    }
+}
+
+impl From<&V8Exception> for JsError {
+    /// Captures the exception data. This version of CEF doesn't expose the stack of
+    /// an exception, so [stack](JsError::stack) only holds the frame the exception
+    /// was thrown in, or is empty if its position is unknown. The stack of the code
+    /// that caught the exception is unrelated, so it isn't captured; use
+    /// [JsError::with_stack] where the exception's stack is known. The
+    /// [kind](JsError::kind) is read from the class name V8 puts in front of the
+    /// message.
+    fn from(exception: &V8Exception) -> JsError {
+        let (kind, message) = split_kind(&exception.get_message());
+        let script_resource_name = exception.get_script_resource_name();
+        let line_number = exception.get_line_number();
+        let start_column = exception.get_start_column();
+        let stack = if line_number > 0 {
+            vec![JsStackFrame {
+                script_name: script_resource_name.clone(),
+                line_number,
+                column: start_column + 1,
+                ..JsStackFrame::default()
+            }]
+        } else {
+            Vec::new()
+        };
+        JsError {
+            kind,
+            message,
+            script_resource_name,
+            source_line: exception.get_source_line(),
+            line_number,
+            start_column,
+            end_column: exception.get_end_column(),
+            stack,
+            properties: Vec::new(),
+        }
+    }
+}
+
+/// Splits an exception message like `Uncaught TypeError: x is not a function`
+/// into the class and the rest of the message. Custom classes can't be told apart
+/// from other prefixes, so those messages are kept whole as an `Error`.
+fn split_kind(message: &str) -> (JsErrorKind, String) {
+    let unprefixed = message.trim_start_matches("Uncaught ");
+    let mut parts = unprefixed.splitn(2, ": ");
+    match (JsErrorKind::from_class_name(parts.next().unwrap_or("")), parts.next()) {
+        (JsErrorKind::Custom(_), _) | (_, None) => (JsErrorKind::Error, message.to_owned()),
+        (kind, Some(rest)) => (kind, rest.to_owned()),
+    }
+}
+
+impl From<V8Exception> for JsError {
+    fn from(exception: V8Exception) -> JsError {
+        JsError::from(&exception)
+    }
+}
+
+impl From<String> for JsError {
+    fn from(message: String) -> JsError {
+        JsError {
+            message,
+            ..JsError::default()
+        }
+    }
+}
+
+impl From<&str> for JsError {
+    fn from(message: &str) -> JsError {
+        JsError::new(message)
+    }
+}
+
+impl From<SerdeError> for JsError {
+    fn from(error: SerdeError) -> JsError {
+        JsError::type_error(&error.to_string())
+    }
+}
+
+impl std::fmt::Display for JsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.kind != JsErrorKind::Error {
+            write!(f, "{}: ", self.kind)?;
+        }
+        f.write_str(&self.message)?;
+        if self.line_number > 0 {
+            if self.script_resource_name.is_empty() {
+                write!(f, " (line {}, column {})", self.line_number, self.start_column)?;
+            } else {
+                write!(
+                    f,
+                    " ({}:{}:{})",
+                    self.script_resource_name, self.line_number, self.start_column
+                )?;
+            }
+        }
+        for frame in &self.stack {
+            write!(f, "\n    at {}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for JsError {}
+
 ref_counted_ptr! {
     /// Structure representing a V8 stack frame handle. V8 handles can only be
     /// accessed from the thread on which they are created. Valid threads for
@@ -503,7 +886,7 @@ impl V8Value {
             V8Value::from_ptr_unchecked(cef_v8value_create_array_buffer(
                 ptr as *mut _,
                 length,
-                V8ArrayBufferReleaseCallbackWrapper::new(move |_| {
+                V8ArrayBufferReleaseCallbackWrapper::new(ptr, length, move |_| {
                     ManuallyDrop::drop(&mut buffer_md);
                 })
                 .wrap()
@@ -516,15 +899,18 @@ impl V8Value {
     /// [RenderProcessHandlerCallbacks], [V8Handler] or [V8AccessorCallbacks] callback,
     /// or in combination with calling [V8Context::enter] and [V8Context::exit] on a stored [V8Context]
     /// reference.
-    pub fn new_function(
+    ///
+    /// An error returned by `handler` is thrown in the calling script; return a
+    /// [JsError] to throw a typed error or an error with extra properties.
+    pub fn new_function<E: Into<JsError>>(
         name: &str,
-        handler: impl Fn(&str, V8Value, &[V8Value]) -> Result<V8Value, String> + Send + 'static,
+        handler: impl Fn(&str, V8Value, &[V8Value]) -> Result<V8Value, E> + Send + 'static,
     ) -> Self {
         let name = CefString::new(name);
         unsafe {
             V8Value::from_ptr_unchecked(cef_v8value_create_function(
                 name.as_ptr(),
-                V8HandlerWrapper::new(Box::new(handler)).wrap().into_raw(),
+                V8HandlerWrapper::new(handler).wrap().into_raw(),
             ))
         }
     }
@@ -670,16 +1056,17 @@ impl V8Value {
             None
         }
     }
-    /// Return a Date value.
+    /// Return a Date value. Returns None for invalid dates and dates that
+    /// `SystemTime` can't represent.
     pub fn get_date_value(&self) -> Option<SystemTime> {
         if self.is_date() {
-            self.0.get_date_value.map(|get_date_value| {
+            self.0.get_date_value.and_then(|get_date_value| {
                 let value = unsafe { get_date_value(self.as_ptr()) };
                 let mut fvalue = 0.0;
                 unsafe {
                     cef_time_to_doublet(&value, &mut fvalue);
                 }
-                SystemTime::UNIX_EPOCH + Duration::from_secs_f64(fvalue)
+                V8Date::from_millis(fvalue * 1000.0).map(SystemTime::from)
             })
         } else {
             None
@@ -985,7 +1372,33 @@ impl V8Value {
             .map(|get_array_length| unsafe { get_array_length(self.as_ptr()) })
             .unwrap_or(0)
     }
-    // pub fn get_array_buffer_release_callback
+    /// Returns a copy of the contents of an ArrayBuffer created with
+    /// [V8Value::new_array_buffer], read directly from its memory. This version of
+    /// CEF has no accessor for the memory of other ArrayBuffers, so None is
+    /// returned for those. The copy ignores [V8Value::neuter_array_buffer], as the
+    /// memory stays allocated until V8 releases the buffer.
+    ///
+    /// This function is only available on ArrayBuffers.
+    pub fn get_array_buffer_contents(&self) -> Option<Vec<u8>> {
+        if !self.is_array_buffer() {
+            return None;
+        }
+        let get_release_callback = self.0.get_array_buffer_release_callback?;
+        let callback = unsafe { RefCountedPtr::from_ptr(get_release_callback(self.as_ptr())) }?;
+        let ours = V8ArrayBufferReleaseCallbackWrapper::release_buffer as usize;
+        if callback.release_buffer.map(|release| release as usize) != Some(ours) {
+            return None;
+        }
+        let wrapper = unsafe {
+            crate::refcounted::RefCounted::<V8ArrayBufferReleaseCallbackWrapper>::wrapper(callback.as_ptr())
+        };
+        let release = wrapper.release.lock();
+        if release.is_none() {
+            return None;
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(wrapper.data as *const u8, wrapper.length) };
+        Some(bytes.to_vec())
+    }
     /// Prevent the ArrayBuffer from using it's memory block by setting the length
     /// to zero. This operation cannot be undone.
     ///
@@ -1119,12 +1532,13 @@ impl TryFrom<SystemTime> for V8Value {
     /// called from within the scope of a [RenderProcessHandlerCallbacks],
     /// [V8Handler] or [V8AccessorHandler] callback, or in combination with calling
     /// [V8Context::enter] and [V8Context::exit] on a stored [V8Context] reference.
+    /// Times before the Unix epoch are supported, so this doesn't fail.
     fn try_from(value: SystemTime) -> Result<Self, Self::Error> {
-        let duration = value.duration_since(SystemTime::UNIX_EPOCH)?;
+        let seconds = V8Date(value).to_millis() / 1000.0;
         let mut result = unsafe { std::mem::zeroed() };
 
         unsafe {
-            cef_time_from_doublet(duration.as_secs_f64(), &mut result);
+            cef_time_from_doublet(seconds, &mut result);
         } // This could fail in theory, but the actual implementation only returns 0 if the result is NULL
         Ok(unsafe { V8Value::from_ptr(cef_v8value_create_date(&result)) }.unwrap())
     }
@@ -1373,13 +1787,21 @@ cef_callback_impl! {
     }
 }
 
-struct V8ArrayBufferReleaseCallbackWrapper(
-    Mutex<Option<Box<dyn FnOnce(*mut u8) + Send + 'static>>>,
-);
+struct V8ArrayBufferReleaseCallbackWrapper {
+    /// The address and length of the buffer, so that buffers created by
+    /// [V8Value::new_array_buffer] can be read without running a script.
+    data: usize,
+    length: usize,
+    release: Mutex<Option<Box<dyn FnOnce(*mut u8) + Send + 'static>>>,
+}
 
 impl V8ArrayBufferReleaseCallbackWrapper {
-    fn new(delegate: impl FnOnce(*mut u8) + Send + 'static) -> Self {
-        Self(Mutex::new(Some(Box::new(delegate))))
+    fn new(data: *mut u8, length: usize, delegate: impl FnOnce(*mut u8) + Send + 'static) -> Self {
+        Self {
+            data: data as usize,
+            length,
+            release: Mutex::new(Some(Box::new(delegate))),
+        }
     }
 }
 
@@ -1400,7 +1822,7 @@ impl Wrapper for V8ArrayBufferReleaseCallbackWrapper {
 cef_callback_impl! {
     impl for V8ArrayBufferReleaseCallbackWrapper: cef_v8array_buffer_release_callback_t {
         fn release_buffer(&self, buffer: *mut std::os::raw::c_void: *mut std::os::raw::c_void) {
-            if let Some(release) = self.0.lock().take() {
+            if let Some(release) = self.release.lock().take() {
                 release(buffer as *mut u8);
             }
         }
@@ -1408,16 +1830,16 @@ cef_callback_impl! {
 }
 
 struct V8HandlerWrapper(
-    SendProtector<Box<dyn Fn(&str, V8Value, &[V8Value]) -> Result<V8Value, String> + Send + 'static>>,
+    SendProtector<Box<dyn Fn(&str, V8Value, &[V8Value]) -> Result<V8Value, JsError> + Send + 'static>>,
 );
 
 impl V8HandlerWrapper {
-    fn new(
-        delegate: Box<
-            dyn Fn(&str, V8Value, &[V8Value]) -> Result<V8Value, String> + Send + 'static,
-        >,
+    fn new<E: Into<JsError>>(
+        delegate: impl Fn(&str, V8Value, &[V8Value]) -> Result<V8Value, E> + Send + 'static,
     ) -> Self {
-        Self(SendProtector::new(delegate))
+        Self(SendProtector::new(Box::new(move |name: &str, object: V8Value, args: &[V8Value]| {
+            delegate(name, object, args).map_err(Into::into)
+        })))
     }
 }
 
@@ -1452,9 +1874,13 @@ cef_callback_impl! {
                     *retval = value.into_raw();
                     1
                 }
+                // Typed errors and errors with properties are thrown as objects;
+                // CEF can only throw a plain Error from the exception string.
                 Err(err) => {
-                    exception.set_string(&err);
-                    0
+                    if err.is_plain() || !err.throw() {
+                        exception.set_string(&err.message);
+                    }
+                    1
                 }
             }
         }
@@ -0,0 +1,465 @@
+//! Deserialization of Rust types from V8 values.
+//!
+//! This is the inverse of the mapping described in the [ser](super::ser) module.
+//! Like `JSON.stringify`, object properties holding `undefined` or functions are
+//! skipped. Dates are read as milliseconds since the Unix epoch, or as a
+//! [V8Date](super::V8Date), and `ArrayBuffer`s are read as bytes. Objects that
+//! contain themselves fail with [SerdeError::CyclicValue].
+//!
+//! `ArrayBuffer`s created with [V8Value::new_array_buffer] are read from their
+//! memory. This version of CEF has no accessor for the memory of other buffers, so
+//! those are read by a small function that is compiled once per context. Like
+//! serialization, this must happen while a context is entered.
+
+use super::{ser::DATE_NEWTYPE, V8Context, V8Date, V8Value};
+use crate::values::{
+    de::{visit_integer, KeyDeserializer},
+    ser::MAX_SAFE_INTEGER,
+    SerdeError,
+};
+use serde::de::{
+    self, Deserialize, DeserializeOwned, DeserializeSeed, Deserializer as _, IntoDeserializer,
+    Unexpected, Visitor,
+};
+use parking_lot::Mutex;
+use serde::forward_to_deserialize_any;
+use std::{cell::RefCell, rc::Rc};
+
+/// Returns a function turning an `ArrayBuffer` into a string with one character per
+/// byte. The builtins it uses are captured when it is compiled, so that later
+/// changes to them by page scripts don't affect it.
+const ARRAY_BUFFER_TO_STRING: &str = "(function(Uint8Array, apply, fromCharCode) {\
+    var subarray = Uint8Array.prototype.subarray;\
+    var length = Object.getOwnPropertyDescriptor(\
+        Object.getPrototypeOf(Uint8Array.prototype), 'length').get;\
+    return function(buffer) {\
+        var bytes = new Uint8Array(buffer), count = apply(length, bytes, []), result = '';\
+        for (var i = 0; i < count; i += 8192) {\
+            result += apply(fromCharCode, null, apply(subarray, bytes, [i, i + 8192]));\
+        }\
+        return result;\
+    };\
+})(Uint8Array, Reflect.apply, String.fromCharCode)";
+
+lazy_static::lazy_static! {
+    /// The compiled [ARRAY_BUFFER_TO_STRING] function of each context it was used in.
+    static ref READERS: Mutex<Vec<(V8Context, V8Value)>> = Mutex::new(Vec::new());
+}
+
+/// Deserializes an instance of `T` from a [V8Value].
+pub fn from_v8_value<T: DeserializeOwned>(value: &V8Value) -> Result<T, SerdeError> {
+    T::deserialize(Deserializer::new(value.clone()))
+}
+
+/// Forgets the compiled functions of `context`.
+pub(crate) fn on_context_released(context: &V8Context) {
+    READERS.lock().retain(|(reader_context, _)| !reader_context.is_same(context));
+}
+
+fn array_buffer_reader(context: &V8Context) -> Result<V8Value, SerdeError> {
+    let cached = READERS
+        .lock()
+        .iter()
+        .find(|(reader_context, _)| reader_context.is_same(context))
+        .map(|(_, reader)| reader.clone());
+    if let Some(reader) = cached {
+        return Ok(reader);
+    }
+    let reader = context
+        .eval(ARRAY_BUFFER_TO_STRING, "", 0)
+        .map_err(|exception| SerdeError::Message(exception.get_message()))?;
+    READERS.lock().push((context.clone(), reader.clone()));
+    Ok(reader)
+}
+
+fn array_buffer_bytes(buffer: &V8Value) -> Result<Vec<u8>, SerdeError> {
+    if let Some(bytes) = buffer.get_array_buffer_contents() {
+        return Ok(bytes);
+    }
+    let error = || SerdeError::Message("unable to read ArrayBuffer".to_owned());
+    let context = V8Context::get_current().ok_or_else(error)?;
+    let string = array_buffer_reader(&context)?
+        .execute_function(None, &[buffer.clone()])
+        .and_then(|string| string.get_string_value())
+        .ok_or_else(error)?;
+    Ok(string.chars().map(|c| c as u32 as u8).collect())
+}
+
+fn unexpected(value: &V8Value) -> Unexpected<'static> {
+    if value.is_undefined() || value.is_null() {
+        Unexpected::Unit
+    } else if let Some(b) = value.get_bool_value().filter(|_| value.is_bool()) {
+        Unexpected::Bool(b)
+    } else if value.is_int() || value.is_uint() || value.is_double() {
+        Unexpected::Float(value.get_double_value().unwrap_or_default())
+    } else if value.is_string() {
+        Unexpected::Other("string")
+    } else if value.is_date() {
+        Unexpected::Other("date")
+    } else if value.is_array() {
+        Unexpected::Seq
+    } else if value.is_array_buffer() {
+        Unexpected::Other("ArrayBuffer")
+    } else if value.is_function() {
+        Unexpected::Other("function")
+    } else {
+        Unexpected::Map
+    }
+}
+
+fn date_millis(value: &V8Value) -> f64 {
+    value
+        .get_date_value()
+        .map(|date| V8Date(date).to_millis())
+        .unwrap_or(std::f64::NAN)
+}
+
+/// A serde `Deserializer` that reads from a [V8Value].
+pub struct Deserializer {
+    value: V8Value,
+    /// The objects and arrays currently being read, used to detect cycles.
+    parents: Rc<RefCell<Vec<V8Value>>>,
+}
+
+impl Deserializer {
+    pub fn new(value: V8Value) -> Self {
+        Self {
+            value,
+            parents: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    fn child(&self, value: V8Value) -> Self {
+        Self {
+            value,
+            parents: self.parents.clone(),
+        }
+    }
+
+    /// Runs `read` with `self.value` registered as a parent, failing if it already
+    /// is one.
+    fn nested<T>(&self, read: impl FnOnce() -> Result<T, SerdeError>) -> Result<T, SerdeError> {
+        if self
+            .parents
+            .borrow()
+            .iter()
+            .any(|parent| parent.is_same(&self.value))
+        {
+            return Err(SerdeError::CyclicValue);
+        }
+        self.parents.borrow_mut().push(self.value.clone());
+        let result = read();
+        self.parents.borrow_mut().pop();
+        result
+    }
+
+    fn deserialize_integer<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        let value = &self.value;
+        if value.is_int() {
+            visitor.visit_i32(value.get_int_value().unwrap_or_default())
+        } else if value.is_uint() {
+            visitor.visit_u32(value.get_uint_value().unwrap_or_default())
+        } else if value.is_double() {
+            let f = value.get_double_value().unwrap_or_default();
+            if f.fract() == 0.0 && f.abs() <= MAX_SAFE_INTEGER as f64 {
+                visit_integer(f as i128, visitor)
+            } else {
+                visitor.visit_f64(f)
+            }
+        } else if value.is_string() {
+            let s = value.get_string_value().unwrap_or_default();
+            if let Ok(integer) = s.parse::<i128>() {
+                visit_integer(integer, visitor)
+            } else if let Ok(integer) = s.parse::<u128>() {
+                visitor.visit_u128(integer)
+            } else {
+                Err(de::Error::invalid_type(Unexpected::Str(&s), &visitor))
+            }
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        let value = &self.value;
+        if value.is_undefined() || value.is_null() {
+            visitor.visit_unit()
+        } else if value.is_bool() {
+            visitor.visit_bool(value.get_bool_value().unwrap_or_default())
+        } else if value.is_int() {
+            visitor.visit_i32(value.get_int_value().unwrap_or_default())
+        } else if value.is_uint() {
+            visitor.visit_u32(value.get_uint_value().unwrap_or_default())
+        } else if value.is_double() {
+            visitor.visit_f64(value.get_double_value().unwrap_or_default())
+        } else if value.is_string() {
+            visitor.visit_string(value.get_string_value().unwrap_or_default())
+        } else if value.is_date() {
+            visitor.visit_f64(date_millis(value))
+        } else if value.is_array_buffer() {
+            visitor.visit_byte_buf(array_buffer_bytes(value)?)
+        } else if value.is_array() {
+            self.nested(|| visitor.visit_seq(ArrayAccess::new(&self)))
+        } else if value.is_function() {
+            Err(de::Error::invalid_type(unexpected(value), &visitor))
+        } else {
+            self.nested(|| visitor.visit_map(ObjectAccess::new(&self)))
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        if self.value.is_undefined() || self.value.is_null() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        if name == DATE_NEWTYPE && self.value.is_date() {
+            let millis: de::value::F64Deserializer<SerdeError> =
+                date_millis(&self.value).into_deserializer();
+            visitor.visit_newtype_struct(millis)
+        } else {
+            visitor.visit_newtype_struct(self)
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        let value = &self.value;
+        if value.is_string() {
+            visitor.visit_enum(EnumAccess {
+                variant: value.get_string_value().unwrap_or_default(),
+                value: None,
+            })
+        } else if value.is_object() && !value.is_array() && !value.is_function() {
+            let keys = value.get_keys().unwrap_or_default();
+            let variant = match keys.as_slice() {
+                [variant] => variant.clone(),
+                _ => {
+                    return Err(de::Error::invalid_value(
+                        Unexpected::Map,
+                        &"an object with a single key",
+                    ))
+                }
+            };
+            let inner = value.get_value_bykey(&variant).unwrap_or_else(V8Value::undefined);
+            let inner = self.child(inner);
+            self.nested(|| {
+                visitor.visit_enum(EnumAccess {
+                    variant,
+                    value: Some(inner),
+                })
+            })
+        } else {
+            Err(de::Error::invalid_type(unexpected(value), &"enum"))
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool f32 f64 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct ArrayAccess {
+    range: std::ops::Range<i32>,
+    array: Deserializer,
+}
+
+impl ArrayAccess {
+    fn new(array: &Deserializer) -> Self {
+        Self {
+            range: 0..array.value.get_array_length().max(0),
+            array: array.child(array.value.clone()),
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for ArrayAccess {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, SerdeError> {
+        match self.range.next() {
+            Some(index) => {
+                let value = self
+                    .array
+                    .value
+                    .get_value_byindex(index)
+                    .unwrap_or_else(V8Value::undefined);
+                seed.deserialize(self.array.child(value)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.range.len())
+    }
+}
+
+struct ObjectAccess {
+    keys: std::vec::IntoIter<String>,
+    object: Deserializer,
+    next_value: Option<V8Value>,
+}
+
+impl ObjectAccess {
+    fn new(object: &Deserializer) -> Self {
+        Self {
+            keys: object.value.get_keys().unwrap_or_default().into_iter(),
+            object: object.child(object.value.clone()),
+            next_value: None,
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for ObjectAccess {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, SerdeError> {
+        while let Some(key) = self.keys.next() {
+            let value = match self.object.value.get_value_bykey(&key) {
+                Some(value) if !value.is_undefined() && !value.is_function() => value,
+                _ => continue,
+            };
+            self.next_value = Some(value);
+            return seed.deserialize(KeyDeserializer(key)).map(Some);
+        }
+        Ok(None)
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, SerdeError> {
+        let value = self
+            .next_value
+            .take()
+            .expect("next_value_seed called before next_key_seed");
+        seed.deserialize(self.object.child(value))
+    }
+}
+
+struct EnumAccess {
+    variant: String,
+    value: Option<Deserializer>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = SerdeError;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, VariantAccess), SerdeError> {
+        let variant = seed.deserialize(KeyDeserializer(self.variant))?;
+        Ok((variant, VariantAccess { value: self.value }))
+    }
+}
+
+struct VariantAccess {
+    value: Option<Deserializer>,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        match self.value {
+            Some(ref value) if !value.value.is_undefined() && !value.value.is_null() => {
+                Err(de::Error::invalid_type(unexpected(&value.value), &"unit variant"))
+            }
+            _ => Ok(()),
+        }
+    }
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, SerdeError> {
+        match self.value {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"newtype variant")),
+        }
+    }
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Some(value) => value.deserialize_seq(visitor),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"tuple variant")),
+        }
+    }
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Some(value) => value.deserialize_map(visitor),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"struct variant")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for V8Date {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<V8Date, D::Error> {
+        struct DateVisitor;
+
+        impl<'de> Visitor<'de> for DateVisitor {
+            type Value = V8Date;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a date or a number of milliseconds since the Unix epoch")
+            }
+            fn visit_newtype_struct<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<V8Date, D::Error> {
+                let millis = f64::deserialize(deserializer)?;
+                self.visit_f64(millis)
+            }
+            fn visit_f64<E: de::Error>(self, millis: f64) -> Result<V8Date, E> {
+                V8Date::from_millis(millis)
+                    .ok_or_else(|| de::Error::invalid_value(Unexpected::Float(millis), &self))
+            }
+            fn visit_i64<E: de::Error>(self, millis: i64) -> Result<V8Date, E> {
+                self.visit_f64(millis as f64)
+            }
+            fn visit_u64<E: de::Error>(self, millis: u64) -> Result<V8Date, E> {
+                self.visit_f64(millis as f64)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(DATE_NEWTYPE, DateVisitor)
+    }
+}
@@ -0,0 +1,174 @@
+//! JavaScript Promises that are settled from Rust.
+//!
+//! This version of CEF has no API for creating promises, so they are created by a
+//! small script and settled by calling the captured `resolve` and `reject`
+//! functions. Those functions are kept in a registry on the Rust side; a
+//! [V8PromiseResolver] only holds an id into it, which makes it safe to send to
+//! other threads.
+
+use super::{to_v8_value, JsError, V8Context, V8Value};
+use crate::task::TaskRunner;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+const NEW_PROMISE: &str = "(function() {\
+    var result = {};\
+    result.promise = new Promise(function(resolve, reject) {\
+        result.resolve = resolve;\
+        result.reject = reject;\
+    });\
+    return result;\
+})()";
+
+struct PendingPromise {
+    context: V8Context,
+    resolve: V8Value,
+    reject: V8Value,
+}
+
+lazy_static::lazy_static! {
+    static ref PENDING: Mutex<HashMap<u64, PendingPromise>> = Mutex::new(HashMap::new());
+}
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+impl V8Value {
+    /// Create a new Promise in the current context. Returns the promise, which can
+    /// be returned to JavaScript, and a [V8PromiseResolver] used to settle it later,
+    /// possibly from another thread. This function should only be called from within
+    /// the scope of a [RenderProcessHandlerCallbacks], [V8Handler] or
+    /// [V8AccessorCallbacks] callback, or in combination with calling
+    /// [V8Context::enter] and [V8Context::exit] on a stored [V8Context] reference.
+    pub fn new_promise() -> Option<(V8Value, V8PromiseResolver)> {
+        let context = V8Context::get_current()?;
+        let parts = context.eval(NEW_PROMISE, "", 0).ok()?;
+        let promise = parts.get_value_bykey("promise")?;
+        let resolve = parts.get_value_bykey("resolve")?;
+        let reject = parts.get_value_bykey("reject")?;
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let runner = context.get_task_runner();
+        PENDING.lock().insert(
+            id,
+            PendingPromise {
+                context,
+                resolve,
+                reject,
+            },
+        );
+        Some((promise, V8PromiseResolver { id, runner, settled: false }))
+    }
+}
+
+/// Settles a promise created with [V8Value::new_promise]. Can be sent to other
+/// threads; the promise is always settled on the thread of its context. Dropping
+/// the resolver without settling the promise rejects it.
+///
+/// Promises that are still pending when their context is released are rejected
+/// while the context is torn down, and settling them afterwards does nothing.
+pub struct V8PromiseResolver {
+    id: u64,
+    runner: TaskRunner,
+    settled: bool,
+}
+
+impl V8PromiseResolver {
+    /// Resolve the promise with `value`, converted with serde. If the conversion
+    /// fails the promise is rejected with the conversion error instead.
+    pub fn resolve<T: Serialize + Send + 'static>(self, value: T) {
+        self.settle(move |pending| match to_v8_value(&value) {
+            Ok(value) => pending.resolve.execute_function(None, &[value]),
+            Err(error) => {
+                let error = new_error(&JsError::from(error));
+                pending.reject.execute_function(None, &[error])
+            }
+        });
+    }
+    /// Resolve the promise with the value returned by `value`, which is called on
+    /// the thread of the promise's context while the context is entered.
+    pub fn resolve_with(self, value: impl FnOnce() -> V8Value + Send + 'static) {
+        self.settle(move |pending| pending.resolve.execute_function(None, &[value()]));
+    }
+    /// Reject the promise with an `Error` carrying `message`.
+    pub fn reject(self, message: &str) {
+        self.reject_error(JsError::new(message));
+    }
+    /// Reject the promise with `error`, which may be a typed error or carry extra
+    /// properties.
+    pub fn reject_error(self, error: JsError) {
+        self.settle(move |pending| {
+            pending
+                .reject
+                .execute_function(None, &[new_error(&error)])
+        });
+    }
+    /// Reject the promise with the value returned by `reason`, which is called on
+    /// the thread of the promise's context while the context is entered.
+    pub fn reject_with(self, reason: impl FnOnce() -> V8Value + Send + 'static) {
+        self.settle(move |pending| pending.reject.execute_function(None, &[reason()]));
+    }
+
+    fn settle(mut self, settle: impl FnOnce(&PendingPromise) -> Option<V8Value> + Send + 'static) {
+        self.settled = true;
+        let id = self.id;
+        let task = move || {
+            let pending = PENDING.lock().remove(&id);
+            if let Some(pending) = pending {
+                if pending.context.is_valid() {
+                    pending.context.execute_in_context(|| settle(&pending));
+                }
+            }
+        };
+        if self.runner.belongs_to_current_thread() {
+            task();
+        } else {
+            self.runner.post_task(task);
+        }
+    }
+}
+
+impl Drop for V8PromiseResolver {
+    fn drop(&mut self) {
+        if !self.settled {
+            let resolver = V8PromiseResolver {
+                id: self.id,
+                runner: self.runner.clone(),
+                settled: false,
+            };
+            resolver.reject("promise was dropped without being settled");
+        }
+    }
+}
+
+fn new_error(error: &JsError) -> V8Value {
+    error
+        .to_v8_value()
+        .unwrap_or_else(|| V8Value::from(error.message.as_str()))
+}
+
+/// Rejects the promises that are still pending in `context`. Called while the
+/// context is being released, when it can still be entered.
+pub(crate) fn on_context_released(context: &V8Context) {
+    let released: Vec<PendingPromise> = {
+        let mut pending = PENDING.lock();
+        let ids: Vec<u64> = pending
+            .iter()
+            .filter(|(_, promise)| promise.context.is_same(context))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.into_iter().filter_map(|id| pending.remove(&id)).collect()
+    };
+    if released.is_empty() {
+        return;
+    }
+    context.execute_in_context(|| {
+        for promise in released {
+            promise
+                .reject
+                .execute_function(None, &[new_error(&JsError::new("context was released"))]);
+        }
+    });
+}
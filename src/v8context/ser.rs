@@ -0,0 +1,412 @@
+//! Serialization of Rust types into V8 values.
+//!
+//! Values map onto [V8Value] like `JSON.parse` would produce them:
+//!
+//! - `()`, unit structs and `None` become `null`, `Some(v)` becomes `v`.
+//! - Integers that fit into an `i32` or `u32` become V8 integers, integers whose
+//!   magnitude is at most 2^53 become numbers and anything larger becomes a
+//!   string holding the decimal representation.
+//! - Byte slices serialized through `serialize_bytes` (e.g. with `serde_bytes`)
+//!   become `ArrayBuffer`s.
+//! - [V8Date] becomes a `Date`.
+//! - Sequences and tuples become arrays, maps and structs become objects.
+//! - Enum variants are represented the same way as in the [values](crate::values::ser)
+//!   serializer.
+//!
+//! Objects, arrays and dates can only be created while a context is entered, so
+//! these functions must be called from within a V8 callback or between
+//! [V8Context::enter] and [V8Context::exit].
+
+use super::{V8PropertyAttribute, V8Value};
+use crate::values::{ser::{KeySerializer, MAX_SAFE_INTEGER}, SerdeError};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq};
+use std::{
+    convert::TryFrom,
+    time::{Duration, SystemTime},
+};
+
+/// The newtype struct name used to pass [V8Date]s through serde.
+pub(crate) const DATE_NEWTYPE: &str = "$cef::V8Date";
+
+/// Serializes `value` into a [V8Value].
+pub fn to_v8_value<T: Serialize + ?Sized>(value: &T) -> Result<V8Value, SerdeError> {
+    value.serialize(Serializer)
+}
+
+/// A point in time that is represented as a JavaScript `Date` in V8 values and as
+/// milliseconds since the Unix epoch in other formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct V8Date(pub SystemTime);
+
+impl V8Date {
+    pub(crate) fn to_millis(self) -> f64 {
+        match self.0.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(after) => after.as_secs_f64() * 1000.0,
+            Err(before) => -before.duration().as_secs_f64() * 1000.0,
+        }
+    }
+    /// Returns None for NaN (an invalid `Date`) and times that `SystemTime` can't
+    /// represent.
+    pub(crate) fn from_millis(millis: f64) -> Option<V8Date> {
+        let seconds = millis.abs() / 1000.0;
+        if !seconds.is_finite() || seconds >= u64::max_value() as f64 {
+            return None;
+        }
+        let offset = Duration::from_secs_f64(seconds);
+        if millis < 0.0 {
+            SystemTime::UNIX_EPOCH.checked_sub(offset).map(V8Date)
+        } else {
+            SystemTime::UNIX_EPOCH.checked_add(offset).map(V8Date)
+        }
+    }
+}
+
+impl From<SystemTime> for V8Date {
+    fn from(time: SystemTime) -> V8Date {
+        V8Date(time)
+    }
+}
+
+impl From<V8Date> for SystemTime {
+    fn from(date: V8Date) -> SystemTime {
+        date.0
+    }
+}
+
+impl Serialize for V8Date {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(DATE_NEWTYPE, &self.to_millis())
+    }
+}
+
+fn integer(value: i128) -> V8Value {
+    if let Ok(value) = i32::try_from(value) {
+        V8Value::from(value)
+    } else if let Ok(value) = u32::try_from(value) {
+        V8Value::from(value)
+    } else if -MAX_SAFE_INTEGER <= value && value <= MAX_SAFE_INTEGER {
+        V8Value::from(value as f64)
+    } else {
+        V8Value::from(&*value.to_string())
+    }
+}
+
+fn set_key(object: &V8Value, key: &str, value: V8Value) -> Result<(), SerdeError> {
+    if object.set_value_bykey(key, value, V8PropertyAttribute::empty()) {
+        Ok(())
+    } else {
+        Err(SerdeError::SetFailed)
+    }
+}
+
+fn single_entry(key: &str, value: V8Value) -> Result<V8Value, SerdeError> {
+    let object = V8Value::new_object(None, None);
+    set_key(&object, key, value)?;
+    Ok(object)
+}
+
+/// A serde `Serializer` that produces [V8Value]s.
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = V8Value;
+    type Error = SerdeError;
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeTupleVariant;
+    type SerializeMap = SerializeObject;
+    type SerializeStruct = SerializeObject;
+    type SerializeStructVariant = SerializeStructVariant;
+
+    fn serialize_bool(self, v: bool) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::from(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::from(v as i32))
+    }
+    fn serialize_i16(self, v: i16) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::from(v as i32))
+    }
+    fn serialize_i32(self, v: i32) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::from(v))
+    }
+    fn serialize_i64(self, v: i64) -> Result<V8Value, SerdeError> {
+        Ok(integer(v as i128))
+    }
+    fn serialize_i128(self, v: i128) -> Result<V8Value, SerdeError> {
+        Ok(integer(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::from(v as i32))
+    }
+    fn serialize_u16(self, v: u16) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::from(v as i32))
+    }
+    fn serialize_u32(self, v: u32) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::from(v))
+    }
+    fn serialize_u64(self, v: u64) -> Result<V8Value, SerdeError> {
+        Ok(integer(v as i128))
+    }
+    fn serialize_u128(self, v: u128) -> Result<V8Value, SerdeError> {
+        match i128::try_from(v) {
+            Ok(v) => Ok(integer(v)),
+            Err(_) => Ok(V8Value::from(&*v.to_string())),
+        }
+    }
+    fn serialize_f32(self, v: f32) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::from(v as f64))
+    }
+    fn serialize_f64(self, v: f64) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::from(v))
+    }
+    fn serialize_char(self, v: char) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::from(&*v.to_string()))
+    }
+    fn serialize_str(self, v: &str) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::from(v))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::new_array_buffer(v.to_vec().into_boxed_slice()))
+    }
+    fn serialize_none(self) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::null())
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<V8Value, SerdeError> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::null())
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::null())
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<V8Value, SerdeError> {
+        Ok(V8Value::from(variant))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<V8Value, SerdeError> {
+        let value = value.serialize(self)?;
+        if name != DATE_NEWTYPE {
+            return Ok(value);
+        }
+        let millis = value
+            .get_double_value()
+            .or_else(|| value.get_int_value().map(f64::from))
+            .ok_or_else(|| SerdeError::Message("date must be a number of milliseconds".to_owned()))?;
+        let date = V8Date::from_millis(millis)
+            .ok_or_else(|| SerdeError::Message("date is out of range".to_owned()))?;
+        V8Value::try_from(date.0).map_err(|error| SerdeError::Message(error.to_string()))
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<V8Value, SerdeError> {
+        single_entry(variant, value.serialize(Serializer)?)
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray, SerdeError> {
+        Ok(SerializeArray::new(len))
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeTupleVariant, SerdeError> {
+        Ok(SerializeTupleVariant {
+            variant,
+            array: SerializeArray::new(Some(len)),
+        })
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeObject, SerdeError> {
+        Ok(SerializeObject::new())
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeObject, SerdeError> {
+        self.serialize_map(Some(len))
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeStructVariant, SerdeError> {
+        Ok(SerializeStructVariant {
+            variant,
+            object: SerializeObject::new(),
+        })
+    }
+}
+
+/// Collects the elements first, as V8 arrays are created with a fixed length.
+pub struct SerializeArray {
+    elements: Vec<V8Value>,
+}
+
+impl SerializeArray {
+    fn new(len: Option<usize>) -> Self {
+        Self {
+            elements: Vec::with_capacity(len.unwrap_or(0)),
+        }
+    }
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = V8Value;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.elements.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+    fn end(self) -> Result<V8Value, SerdeError> {
+        let length = i32::try_from(self.elements.len())
+            .map_err(|_| SerdeError::Message("sequence is too long for a V8 array".to_owned()))?;
+        let array = V8Value::new_array(length);
+        for (index, element) in self.elements.into_iter().enumerate() {
+            if !array.set_value_byindex(index as i32, element) {
+                return Err(SerdeError::SetFailed);
+            }
+        }
+        Ok(array)
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = V8Value;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<V8Value, SerdeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = V8Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<V8Value, SerdeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+pub struct SerializeTupleVariant {
+    variant: &'static str,
+    array: SerializeArray,
+}
+
+impl ser::SerializeTupleVariant for SerializeTupleVariant {
+    type Ok = V8Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(&mut self.array, value)
+    }
+    fn end(self) -> Result<V8Value, SerdeError> {
+        single_entry(self.variant, SerializeSeq::end(self.array)?)
+    }
+}
+
+pub struct SerializeObject {
+    object: V8Value,
+    next_key: Option<String>,
+}
+
+impl SerializeObject {
+    fn new() -> Self {
+        Self {
+            object: V8Value::new_object(None, None),
+            next_key: None,
+        }
+    }
+}
+
+impl ser::SerializeMap for SerializeObject {
+    type Ok = V8Value;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        self.next_key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self
+            .next_key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        set_key(&self.object, &key, value.serialize(Serializer)?)
+    }
+    fn end(self) -> Result<V8Value, SerdeError> {
+        Ok(self.object)
+    }
+}
+
+impl ser::SerializeStruct for SerializeObject {
+    type Ok = V8Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        set_key(&self.object, key, value.serialize(Serializer)?)
+    }
+    fn end(self) -> Result<V8Value, SerdeError> {
+        SerializeMap::end(self)
+    }
+}
+
+pub struct SerializeStructVariant {
+    variant: &'static str,
+    object: SerializeObject,
+}
+
+impl ser::SerializeStructVariant for SerializeStructVariant {
+    type Ok = V8Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        ser::SerializeStruct::serialize_field(&mut self.object, key, value)
+    }
+    fn end(self) -> Result<V8Value, SerdeError> {
+        single_entry(self.variant, SerializeMap::end(self.object)?)
+    }
+}
@@ -1,5 +1,13 @@
 pub mod iter;
+mod error;
+pub mod ser;
+pub mod de;
 use self::iter::DictionaryValueKeysIter;
+pub use self::{
+    error::SerdeError,
+    ser::{to_stored_value, to_dictionary_value, to_list_value},
+    de::{from_stored_value, from_dictionary_value, from_list_value},
+};
 use cef_sys::{
     cef_binary_value_create, cef_binary_value_t, cef_dictionary_value_create,
     cef_dictionary_value_t, cef_list_value_create, cef_list_value_t, cef_point_t, cef_range_t,
@@ -29,7 +37,8 @@ pub(crate) enum ValueType {
     List = cef_value_type_t::VTYPE_LIST as isize,
 }
 
-#[derive(Debug, Clone)]
+/// Doubles compare by value, and binary, dictionary and list values by content.
+#[derive(Debug, Clone, PartialEq)]
 pub enum StoredValue {
     Invalid,
     Null,
@@ -42,6 +51,20 @@ pub enum StoredValue {
     List(ListValue),
 }
 
+impl crate::helper_traits::DeepClone for StoredValue {
+    /// Returns a copy of this value. Binary, dictionary and list data is copied as
+    /// well, so the result stays valid after the original owner is destroyed.
+    fn deep_clone(&self) -> Self {
+        use crate::helper_traits::DeepClone;
+        match self {
+            StoredValue::Binary(binary) => StoredValue::Binary(binary.deep_clone()),
+            StoredValue::Dictionary(dictionary) => StoredValue::Dictionary(dictionary.deep_clone()),
+            StoredValue::List(list) => StoredValue::List(list.deep_clone()),
+            value => value.clone(),
+        }
+    }
+}
+
 ref_counted_ptr! {
     pub(crate) struct Value(*mut cef_value_t);
 }
@@ -1195,6 +1218,58 @@ pub struct Rect {
     pub height: i32,
 }
 
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect{ x, y, width, height }
+    }
+    /// Returns true if the rectangle covers no pixels.
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+    /// Returns the area covered by both rectangles, which is empty if they don't
+    /// overlap.
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        Rect {
+            x,
+            y,
+            width: (right - x).max(0),
+            height: (bottom - y).max(0),
+        }
+    }
+    /// Returns the smallest rectangle containing both rectangles. Empty rectangles
+    /// are ignored.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if other.is_empty() {
+            return *self;
+        }
+        if self.is_empty() {
+            return *other;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Rect {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        }
+    }
+    /// Returns the rectangle moved by `dx` and `dy`.
+    pub fn offset(&self, dx: i32, dy: i32) -> Rect {
+        Rect {
+            x: self.x + dx,
+            y: self.y + dy,
+            ..*self
+        }
+    }
+}
+
 impl From<cef_rect_t> for Rect {
     fn from(rect: cef_rect_t) -> Rect {
         Rect {
@@ -0,0 +1,481 @@
+//! Deserialization of Rust types from CEF values.
+//!
+//! This is the inverse of the mapping described in the [ser](super::ser) module.
+//! Integers can be read from `Int`, from integral `Double`s and from `String`s
+//! holding a decimal number, so values written by the serializer always round-trip.
+
+use super::{
+    ser::{self, MAX_SAFE_INTEGER},
+    BinaryValue, DictionaryValue, ListValue, SerdeError, StoredValue,
+};
+use serde::de::{
+    self, value::StringDeserializer, Deserialize, DeserializeOwned, DeserializeSeed,
+    Deserializer as _, IntoDeserializer, MapAccess, SeqAccess, Unexpected, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use std::convert::TryFrom;
+
+/// Deserializes an instance of `T` from a [StoredValue].
+pub fn from_stored_value<T: DeserializeOwned>(value: StoredValue) -> Result<T, SerdeError> {
+    T::deserialize(Deserializer::new(value))
+}
+
+/// Deserializes an instance of `T` from a [DictionaryValue].
+pub fn from_dictionary_value<T: DeserializeOwned>(dictionary: &DictionaryValue) -> Result<T, SerdeError> {
+    from_stored_value(StoredValue::Dictionary(dictionary.clone()))
+}
+
+/// Deserializes an instance of `T` from a [ListValue].
+pub fn from_list_value<T: DeserializeOwned>(list: &ListValue) -> Result<T, SerdeError> {
+    from_stored_value(StoredValue::List(list.clone()))
+}
+
+fn unexpected(value: &StoredValue) -> Unexpected<'_> {
+    match value {
+        StoredValue::Invalid | StoredValue::Null => Unexpected::Unit,
+        StoredValue::Bool(b) => Unexpected::Bool(*b),
+        StoredValue::Int(i) => Unexpected::Signed(*i as i64),
+        StoredValue::Double(f) => Unexpected::Float(*f),
+        StoredValue::String(s) => Unexpected::Str(s),
+        StoredValue::Binary(_) => Unexpected::Other("binary value"),
+        StoredValue::Dictionary(_) => Unexpected::Map,
+        StoredValue::List(_) => Unexpected::Seq,
+    }
+}
+
+pub(crate) fn visit_integer<'de, V: Visitor<'de>>(value: i128, visitor: V) -> Result<V::Value, SerdeError> {
+    if let Ok(value) = i64::try_from(value) {
+        visitor.visit_i64(value)
+    } else if let Ok(value) = u64::try_from(value) {
+        visitor.visit_u64(value)
+    } else {
+        visitor.visit_i128(value)
+    }
+}
+
+/// A serde `Deserializer` that reads from a [StoredValue].
+pub struct Deserializer {
+    value: StoredValue,
+}
+
+impl Deserializer {
+    pub fn new(value: StoredValue) -> Self {
+        Self { value }
+    }
+
+    fn deserialize_integer<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            StoredValue::Double(f) if f.fract() == 0.0 && f.abs() <= MAX_SAFE_INTEGER as f64 => {
+                visit_integer(f as i128, visitor)
+            }
+            StoredValue::String(s) => {
+                if let Ok(integer) = s.parse::<i128>() {
+                    visit_integer(integer, visitor)
+                } else if let Ok(integer) = s.parse::<u128>() {
+                    visitor.visit_u128(integer)
+                } else {
+                    Err(de::Error::invalid_type(Unexpected::Str(&s), &visitor))
+                }
+            }
+            value => Deserializer::new(value).deserialize_any(visitor),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            StoredValue::Invalid | StoredValue::Null => visitor.visit_unit(),
+            StoredValue::Bool(b) => visitor.visit_bool(b),
+            StoredValue::Int(i) => visitor.visit_i32(i),
+            StoredValue::Double(f) => visitor.visit_f64(f),
+            StoredValue::String(s) => visitor.visit_string(s),
+            StoredValue::Binary(b) => visitor.visit_byte_buf(b.to_vec()),
+            StoredValue::Dictionary(d) => visitor.visit_map(DictionaryAccess::new(d)),
+            StoredValue::List(l) => visitor.visit_seq(ListAccess::new(l)),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            StoredValue::Invalid | StoredValue::Null => visitor.visit_none(),
+            value => visitor.visit_some(Deserializer::new(value)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.value {
+            StoredValue::String(variant) => visitor.visit_enum(EnumAccess {
+                variant,
+                value: None,
+                _parent: None,
+            }),
+            StoredValue::Dictionary(dictionary) => {
+                let mut entries = (&dictionary).into_iter();
+                let (variant, value) = match (entries.next(), entries.next()) {
+                    (Some(entry), None) => entry,
+                    _ => {
+                        return Err(de::Error::invalid_value(
+                            Unexpected::Map,
+                            &"a dictionary with a single key",
+                        ))
+                    }
+                };
+                drop(entries);
+                visitor.visit_enum(EnumAccess {
+                    variant,
+                    value: Some(value),
+                    _parent: Some(dictionary),
+                })
+            }
+            value => Err(de::Error::invalid_type(unexpected(&value), &"enum")),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool f32 f64 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// Reads the entries of a dictionary. The dictionary is kept alive while its
+/// entries are read, since CEF invalidates child values once their owner is
+/// destroyed.
+struct DictionaryAccess {
+    entries: std::vec::IntoIter<(String, StoredValue)>,
+    value: Option<StoredValue>,
+    _dictionary: DictionaryValue,
+}
+
+impl DictionaryAccess {
+    fn new(dictionary: DictionaryValue) -> Self {
+        let entries: Vec<(String, StoredValue)> = (&dictionary).into_iter().collect();
+        Self {
+            entries: entries.into_iter(),
+            value: None,
+            _dictionary: dictionary,
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for DictionaryAccess {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, SerdeError> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(KeyDeserializer(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, SerdeError> {
+        let value = self
+            .value
+            .take()
+            .expect("next_value_seed called before next_key_seed");
+        seed.deserialize(Deserializer::new(value))
+    }
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct ListAccess {
+    range: std::ops::Range<usize>,
+    list: ListValue,
+}
+
+impl ListAccess {
+    fn new(list: ListValue) -> Self {
+        Self {
+            range: 0..list.len(),
+            list,
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for ListAccess {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, SerdeError> {
+        match self.range.next() {
+            Some(index) => {
+                let value = self.list.get(index).unwrap_or(StoredValue::Null);
+                seed.deserialize(Deserializer::new(value)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.range.len())
+    }
+}
+
+struct EnumAccess {
+    variant: String,
+    value: Option<StoredValue>,
+    _parent: Option<DictionaryValue>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = SerdeError;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, VariantAccess), SerdeError> {
+        let variant = seed.deserialize(KeyDeserializer(self.variant))?;
+        Ok((
+            variant,
+            VariantAccess {
+                value: self.value,
+                _parent: self._parent,
+            },
+        ))
+    }
+}
+
+struct VariantAccess {
+    value: Option<StoredValue>,
+    _parent: Option<DictionaryValue>,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        match self.value {
+            None | Some(StoredValue::Null) | Some(StoredValue::Invalid) => Ok(()),
+            Some(value) => Err(de::Error::invalid_type(unexpected(&value), &"unit variant")),
+        }
+    }
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, SerdeError> {
+        match self.value {
+            Some(value) => seed.deserialize(Deserializer::new(value)),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"newtype variant")),
+        }
+    }
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Some(value) => Deserializer::new(value).deserialize_seq(visitor),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"tuple variant")),
+        }
+    }
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Some(value) => Deserializer::new(value).deserialize_map(visitor),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"struct variant")),
+        }
+    }
+}
+
+/// Reads dictionary keys. Keys are always strings in CEF, so numeric and boolean
+/// map keys are parsed back from their string form.
+pub(crate) struct KeyDeserializer(pub(crate) String);
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_string(self.0)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0.parse::<bool>() {
+            Ok(b) => visitor.visit_bool(b),
+            Err(_) => visitor.visit_string(self.0),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_integer(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        let variant: StringDeserializer<SerdeError> = self.0.into_deserializer();
+        visitor.visit_enum(variant)
+    }
+
+    forward_to_deserialize_any! {
+        f32 f64 char str string bytes byte_buf option unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl KeyDeserializer {
+    fn deserialize_integer<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        if let Ok(integer) = self.0.parse::<i128>() {
+            visit_integer(integer, visitor)
+        } else if let Ok(integer) = self.0.parse::<u128>() {
+            visitor.visit_u128(integer)
+        } else {
+            visitor.visit_string(self.0)
+        }
+    }
+}
+
+/// Reads any self-describing value, mapping integers the same way the
+/// [Serializer](super::ser::Serializer) does. This is what converts values read by
+/// other deserializers, such as V8 values, into [StoredValue]s.
+impl<'de> Deserialize<'de> for StoredValue {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<StoredValue, D::Error> {
+        struct StoredValueVisitor;
+
+        impl<'de> Visitor<'de> for StoredValueVisitor {
+            type Value = StoredValue;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("any value")
+            }
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<StoredValue, E> {
+                Ok(StoredValue::Bool(v))
+            }
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<StoredValue, E> {
+                Ok(ser::integer(v as i128))
+            }
+            fn visit_i128<E: de::Error>(self, v: i128) -> Result<StoredValue, E> {
+                Ok(ser::integer(v))
+            }
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<StoredValue, E> {
+                Ok(ser::integer(v as i128))
+            }
+            fn visit_u128<E: de::Error>(self, v: u128) -> Result<StoredValue, E> {
+                Ok(i128::try_from(v)
+                    .map(ser::integer)
+                    .unwrap_or_else(|_| StoredValue::String(v.to_string())))
+            }
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<StoredValue, E> {
+                Ok(StoredValue::Double(v))
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<StoredValue, E> {
+                Ok(StoredValue::String(v.to_owned()))
+            }
+            fn visit_string<E: de::Error>(self, v: String) -> Result<StoredValue, E> {
+                Ok(StoredValue::String(v))
+            }
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<StoredValue, E> {
+                Ok(StoredValue::Binary(BinaryValue::new(v)))
+            }
+            fn visit_none<E: de::Error>(self) -> Result<StoredValue, E> {
+                Ok(StoredValue::Null)
+            }
+            fn visit_some<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<StoredValue, D::Error> {
+                StoredValue::deserialize(deserializer)
+            }
+            fn visit_unit<E: de::Error>(self) -> Result<StoredValue, E> {
+                Ok(StoredValue::Null)
+            }
+            fn visit_newtype_struct<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<StoredValue, D::Error> {
+                StoredValue::deserialize(deserializer)
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<StoredValue, A::Error> {
+                let list = ListValue::new();
+                let mut index = 0;
+                while let Some(value) = seq.next_element::<StoredValue>()? {
+                    ser::set(&list, index, value).map_err(de::Error::custom)?;
+                    index += 1;
+                }
+                Ok(StoredValue::List(list))
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<StoredValue, A::Error> {
+                let dictionary = DictionaryValue::new();
+                while let Some((key, value)) = map.next_entry::<String, StoredValue>()? {
+                    ser::insert(&dictionary, &key, value).map_err(de::Error::custom)?;
+                }
+                Ok(StoredValue::Dictionary(dictionary))
+            }
+        }
+
+        deserializer.deserialize_any(StoredValueVisitor)
+    }
+}
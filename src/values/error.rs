@@ -0,0 +1,46 @@
+use std::fmt;
+
+/// Errors that can occur while converting between Rust types and CEF values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SerdeError {
+    /// A custom error raised by a `Serialize` or `Deserialize` implementation.
+    Message(String),
+    /// A map key could not be represented as a string. CEF dictionaries only
+    /// support string keys.
+    KeyMustBeAString,
+    /// The value was expected to serialize into a [DictionaryValue](super::DictionaryValue).
+    ExpectedDictionary,
+    /// The value was expected to serialize into a [ListValue](super::ListValue).
+    ExpectedList,
+    /// CEF refused to store a value in a dictionary or list.
+    SetFailed,
+    /// A JavaScript object refers back to itself, directly or through its children.
+    CyclicValue,
+}
+
+impl fmt::Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerdeError::Message(message) => f.write_str(message),
+            SerdeError::KeyMustBeAString => f.write_str("dictionary key must be a string"),
+            SerdeError::ExpectedDictionary => f.write_str("value did not serialize into a dictionary"),
+            SerdeError::ExpectedList => f.write_str("value did not serialize into a list"),
+            SerdeError::SetFailed => f.write_str("unable to store value"),
+            SerdeError::CyclicValue => f.write_str("value contains a cycle"),
+        }
+    }
+}
+
+impl std::error::Error for SerdeError {}
+
+impl serde::ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError::Message(msg.to_string())
+    }
+}
+
+impl serde::de::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError::Message(msg.to_string())
+    }
+}
@@ -0,0 +1,551 @@
+//! Serialization of Rust types into CEF values.
+//!
+//! Values map onto [StoredValue] as follows:
+//!
+//! - `()`, unit structs and `None` become `Null`, `Some(v)` becomes `v`.
+//! - `bool`, `f32` and `f64` become `Bool` and `Double`.
+//! - `char` and strings become `String`.
+//! - Byte slices serialized through `serialize_bytes` (e.g. with `serde_bytes`)
+//!   become `Binary`.
+//! - Sequences, tuples and tuple structs become `List`.
+//! - Maps and structs become `Dictionary`. Map keys must be strings, chars, bools
+//!   or integers; non-string keys are stored in their `Display` form.
+//! - Unit variants become a `String` holding the variant name. All other enum
+//!   variants become a `Dictionary` with a single entry keyed by the variant name.
+//!
+//! CEF only stores 32-bit integers, so wider integers use the following encoding:
+//! integers that fit into an `i32` are stored as `Int`, integers whose magnitude is
+//! at most 2^53 are stored as `Double` (which represents them exactly and matches
+//! what JavaScript can represent as a number), and anything larger is stored as a
+//! `String` holding the decimal representation. The deserializer accepts all three
+//! forms when reading an integer.
+
+use super::{DictionaryValue, ListValue, SerdeError, StoredValue, Value, BinaryValue};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq};
+use std::convert::TryFrom;
+
+/// The largest integer magnitude that a double can represent exactly (2^53).
+pub(crate) const MAX_SAFE_INTEGER: i128 = 9_007_199_254_740_992;
+
+/// Serializes `value` into a [StoredValue].
+pub fn to_stored_value<T: Serialize + ?Sized>(value: &T) -> Result<StoredValue, SerdeError> {
+    value.serialize(Serializer)
+}
+
+/// Serializes `value` into a [DictionaryValue]. Fails if `value` doesn't serialize
+/// into a map or struct.
+pub fn to_dictionary_value<T: Serialize + ?Sized>(value: &T) -> Result<DictionaryValue, SerdeError> {
+    match to_stored_value(value)? {
+        StoredValue::Dictionary(dictionary) => Ok(dictionary),
+        _ => Err(SerdeError::ExpectedDictionary),
+    }
+}
+
+/// Serializes `value` into a [ListValue]. Fails if `value` doesn't serialize
+/// into a sequence or tuple.
+pub fn to_list_value<T: Serialize + ?Sized>(value: &T) -> Result<ListValue, SerdeError> {
+    match to_stored_value(value)? {
+        StoredValue::List(list) => Ok(list),
+        _ => Err(SerdeError::ExpectedList),
+    }
+}
+
+pub(super) fn integer(value: i128) -> StoredValue {
+    if let Ok(value) = i32::try_from(value) {
+        StoredValue::Int(value)
+    } else if -MAX_SAFE_INTEGER <= value && value <= MAX_SAFE_INTEGER {
+        StoredValue::Double(value as f64)
+    } else {
+        StoredValue::String(value.to_string())
+    }
+}
+
+pub(super) fn insert(dictionary: &DictionaryValue, key: &str, value: StoredValue) -> Result<(), SerdeError> {
+    let value = Value::try_from(value).map_err(|_| SerdeError::SetFailed)?;
+    if dictionary.insert_inner(key, value) {
+        Ok(())
+    } else {
+        Err(SerdeError::SetFailed)
+    }
+}
+
+pub(super) fn set(list: &ListValue, index: usize, value: StoredValue) -> Result<(), SerdeError> {
+    let value = Value::try_from(value).map_err(|_| SerdeError::SetFailed)?;
+    if list.set_value_inner(index, value) {
+        Ok(())
+    } else {
+        Err(SerdeError::SetFailed)
+    }
+}
+
+fn single_entry(key: &str, value: StoredValue) -> Result<StoredValue, SerdeError> {
+    let dictionary = DictionaryValue::new();
+    insert(&dictionary, key, value)?;
+    Ok(StoredValue::Dictionary(dictionary))
+}
+
+/// A serde `Serializer` that produces [StoredValue]s.
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = StoredValue;
+    type Error = SerdeError;
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeTupleVariant;
+    type SerializeMap = SerializeDictionary;
+    type SerializeStruct = SerializeDictionary;
+    type SerializeStructVariant = SerializeStructVariant;
+
+    fn serialize_bool(self, v: bool) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Bool(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Int(v as i32))
+    }
+    fn serialize_i16(self, v: i16) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Int(v as i32))
+    }
+    fn serialize_i32(self, v: i32) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Int(v))
+    }
+    fn serialize_i64(self, v: i64) -> Result<StoredValue, SerdeError> {
+        Ok(integer(v as i128))
+    }
+    fn serialize_i128(self, v: i128) -> Result<StoredValue, SerdeError> {
+        Ok(integer(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Int(v as i32))
+    }
+    fn serialize_u16(self, v: u16) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Int(v as i32))
+    }
+    fn serialize_u32(self, v: u32) -> Result<StoredValue, SerdeError> {
+        Ok(integer(v as i128))
+    }
+    fn serialize_u64(self, v: u64) -> Result<StoredValue, SerdeError> {
+        Ok(integer(v as i128))
+    }
+    fn serialize_u128(self, v: u128) -> Result<StoredValue, SerdeError> {
+        match i128::try_from(v) {
+            Ok(v) => Ok(integer(v)),
+            Err(_) => Ok(StoredValue::String(v.to_string())),
+        }
+    }
+    fn serialize_f32(self, v: f32) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Double(v as f64))
+    }
+    fn serialize_f64(self, v: f64) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Double(v))
+    }
+    fn serialize_char(self, v: char) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::String(v.to_string()))
+    }
+    fn serialize_str(self, v: &str) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::String(v.to_owned()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Binary(BinaryValue::new(v)))
+    }
+    fn serialize_none(self) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Null)
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<StoredValue, SerdeError> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Null)
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Null)
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::String(variant.to_owned()))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<StoredValue, SerdeError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<StoredValue, SerdeError> {
+        single_entry(variant, value.serialize(Serializer)?)
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SerializeList, SerdeError> {
+        Ok(SerializeList::new())
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeList, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeTupleVariant, SerdeError> {
+        Ok(SerializeTupleVariant {
+            variant,
+            list: SerializeList::new(),
+        })
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeDictionary, SerdeError> {
+        Ok(SerializeDictionary::new())
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeDictionary, SerdeError> {
+        self.serialize_map(Some(len))
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeStructVariant, SerdeError> {
+        Ok(SerializeStructVariant {
+            variant,
+            dictionary: SerializeDictionary::new(),
+        })
+    }
+}
+
+pub struct SerializeList {
+    list: ListValue,
+    index: usize,
+}
+
+impl SerializeList {
+    fn new() -> Self {
+        Self {
+            list: ListValue::new(),
+            index: 0,
+        }
+    }
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = StoredValue;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        set(&self.list, self.index, value.serialize(Serializer)?)?;
+        self.index += 1;
+        Ok(())
+    }
+    fn end(self) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::List(self.list))
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = StoredValue;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<StoredValue, SerdeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = StoredValue;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<StoredValue, SerdeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+pub struct SerializeTupleVariant {
+    variant: &'static str,
+    list: SerializeList,
+}
+
+impl ser::SerializeTupleVariant for SerializeTupleVariant {
+    type Ok = StoredValue;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(&mut self.list, value)
+    }
+    fn end(self) -> Result<StoredValue, SerdeError> {
+        single_entry(self.variant, SerializeSeq::end(self.list)?)
+    }
+}
+
+pub struct SerializeDictionary {
+    dictionary: DictionaryValue,
+    next_key: Option<String>,
+}
+
+impl SerializeDictionary {
+    fn new() -> Self {
+        Self {
+            dictionary: DictionaryValue::new(),
+            next_key: None,
+        }
+    }
+}
+
+impl ser::SerializeMap for SerializeDictionary {
+    type Ok = StoredValue;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        self.next_key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self
+            .next_key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        insert(&self.dictionary, &key, value.serialize(Serializer)?)
+    }
+    fn end(self) -> Result<StoredValue, SerdeError> {
+        Ok(StoredValue::Dictionary(self.dictionary))
+    }
+}
+
+impl ser::SerializeStruct for SerializeDictionary {
+    type Ok = StoredValue;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        insert(&self.dictionary, key, value.serialize(Serializer)?)
+    }
+    fn end(self) -> Result<StoredValue, SerdeError> {
+        SerializeMap::end(self)
+    }
+}
+
+pub struct SerializeStructVariant {
+    variant: &'static str,
+    dictionary: SerializeDictionary,
+}
+
+impl ser::SerializeStructVariant for SerializeStructVariant {
+    type Ok = StoredValue;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        ser::SerializeStruct::serialize_field(&mut self.dictionary, key, value)
+    }
+    fn end(self) -> Result<StoredValue, SerdeError> {
+        single_entry(self.variant, SerializeMap::end(self.dictionary)?)
+    }
+}
+
+/// Turns map keys into dictionary keys.
+pub(crate) struct KeySerializer;
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = SerdeError;
+    type SerializeSeq = ser::Impossible<String, SerdeError>;
+    type SerializeTuple = ser::Impossible<String, SerdeError>;
+    type SerializeTupleStruct = ser::Impossible<String, SerdeError>;
+    type SerializeTupleVariant = ser::Impossible<String, SerdeError>;
+    type SerializeMap = ser::Impossible<String, SerdeError>;
+    type SerializeStruct = ser::Impossible<String, SerdeError>;
+    type SerializeStructVariant = ser::Impossible<String, SerdeError>;
+
+    fn serialize_bool(self, v: bool) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+    fn serialize_i8(self, v: i8) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+    fn serialize_i16(self, v: i16) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+    fn serialize_i32(self, v: i32) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+    fn serialize_i64(self, v: i64) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+    fn serialize_i128(self, v: i128) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+    fn serialize_u8(self, v: u8) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+    fn serialize_u16(self, v: u16) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+    fn serialize_u32(self, v: u32) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+    fn serialize_u64(self, v: u64) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+    fn serialize_u128(self, v: u128) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+    fn serialize_f32(self, _v: f32) -> Result<String, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_f64(self, _v: f64) -> Result<String, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_char(self, v: char) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+    fn serialize_str(self, v: &str) -> Result<String, SerdeError> {
+        Ok(v.to_owned())
+    }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_none(self) -> Result<String, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<String, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_unit(self) -> Result<String, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String, SerdeError> {
+        Ok(variant.to_owned())
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, SerdeError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerdeError> {
+        Err(SerdeError::KeyMustBeAString)
+    }
+}
+
+impl Serialize for StoredValue {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            StoredValue::Invalid | StoredValue::Null => serializer.serialize_unit(),
+            StoredValue::Bool(b) => serializer.serialize_bool(*b),
+            StoredValue::Int(i) => serializer.serialize_i32(*i),
+            StoredValue::Double(f) => serializer.serialize_f64(*f),
+            StoredValue::String(s) => serializer.serialize_str(s),
+            StoredValue::Binary(b) => serializer.serialize_bytes(&b.to_vec()),
+            StoredValue::Dictionary(d) => d.serialize(serializer),
+            StoredValue::List(l) => l.serialize(serializer),
+        }
+    }
+}
+
+impl Serialize for DictionaryValue {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (key, value) in self {
+            map.serialize_entry(&key, &value)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for ListValue {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for value in self {
+            seq.serialize_element(&value)?;
+        }
+        seq.end()
+    }
+}
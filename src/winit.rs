@@ -0,0 +1,851 @@
+//! Translating [winit](::winit) input events for windowless browsers.
+//!
+//! This module is only available with the `winit` feature. An [InputTranslator]
+//! keeps the state that CEF expects with every event but winit reports separately,
+//! like the held modifier keys and mouse buttons and the position of the mouse, and
+//! forwards the window events of the window that shows the browser:
+//!
+//! ```ignore
+//! let mut input = InputTranslator::new(window.scale_factor());
+//! event_loop.run(move |event, _, control_flow| match event {
+//!     Event::WindowEvent { event, .. } => {
+//!         input.handle_event(&browser.get_host(), &event);
+//!     }
+//!     // ...
+//! });
+//!
+//! impl RenderHandlerCallbacks for MyView {
+//!     fn on_cursor_change(&self, _browser: Browser, _cursor: CursorHandle, type_: CursorType) {
+//!         cef::winit::set_cursor(&self.window, type_);
+//!     }
+//!     // ...
+//! }
+//! ```
+//!
+//! Mouse and touch positions are converted from the physical pixels winit reports
+//! to the logical view coordinates CEF expects, using the scale factor of the
+//! window. Report the same scale factor as [ScreenInfo::device_scale_factor].
+//!
+//! [ScreenInfo::device_scale_factor]: crate::client::render_handler::ScreenInfo::device_scale_factor
+
+use crate::{
+    browser_host::BrowserHost,
+    client::render_handler::{CursorType, CustomCursorInfo},
+    drag::{DragData, DragOperation},
+    events::{
+        EventFlags, KeyEvent, MouseButtonType, MouseEvent, PointerType, TouchEvent,
+        TouchEventType, WindowsKeyCode,
+    },
+    keyboard::{self, PhysicalKey},
+};
+use ::winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{
+        ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, Touch,
+        TouchPhase, VirtualKeyCode, WindowEvent,
+    },
+    window::{CursorIcon, Window},
+};
+use std::{
+    convert::TryFrom,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+/// The number of pixels scrolled per line of a mouse wheel that scrolls by lines.
+pub const PIXELS_PER_LINE: f32 = 20.0;
+/// The longest time between two clicks that still counts as a double click.
+pub const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(500);
+/// The farthest distance in logical pixels between two clicks that still counts as
+/// a double click.
+pub const DOUBLE_CLICK_DISTANCE: i32 = 4;
+
+/// Returns the flags of the modifier keys in `modifiers`. The logo key is reported
+/// as [EventFlags::COMMAND_DOWN] on macOS and ignored elsewhere.
+pub fn event_flags(modifiers: ModifiersState) -> EventFlags {
+    let mut flags = EventFlags::empty();
+    flags.set(EventFlags::SHIFT_DOWN, modifiers.shift());
+    flags.set(EventFlags::CONTROL_DOWN, modifiers.ctrl());
+    flags.set(EventFlags::ALT_DOWN, modifiers.alt());
+    if cfg!(target_os = "macos") {
+        flags.set(EventFlags::COMMAND_DOWN, modifiers.logo());
+    }
+    flags
+}
+
+/// Returns the mouse button CEF knows as `button`, if any.
+pub fn mouse_button(button: MouseButton) -> Option<MouseButtonType> {
+    match button {
+        MouseButton::Left => Some(MouseButtonType::Left),
+        MouseButton::Middle => Some(MouseButtonType::Middle),
+        MouseButton::Right => Some(MouseButtonType::Right),
+        MouseButton::Other(_) => None,
+    }
+}
+
+fn button_flag(button: MouseButtonType) -> EventFlags {
+    match button {
+        MouseButtonType::Left => EventFlags::LEFT_MOUSE_BUTTON,
+        MouseButtonType::Middle => EventFlags::MIDDLE_MOUSE_BUTTON,
+        MouseButtonType::Right => EventFlags::RIGHT_MOUSE_BUTTON,
+    }
+}
+
+/// Returns the Windows virtual key code that key events of `key` carry, if it has
+/// one. Like on Windows, the left and right modifier keys share the codes of
+/// [WindowsKeyCode::Shift], [WindowsKeyCode::Control] and [WindowsKeyCode::Menu];
+/// [physical_key] tells them apart.
+///
+/// The punctuation keys map to the codes they have on a US keyboard layout, which
+/// is how winit names them.
+pub fn windows_key_code(key: VirtualKeyCode) -> Option<WindowsKeyCode> {
+    virtual_key_code(key).map(WindowsKeyCode::located)
+}
+
+/// Returns the Windows virtual key code of `key`, with the left and right variants
+/// of the modifier keys.
+fn virtual_key_code(key: VirtualKeyCode) -> Option<WindowsKeyCode> {
+    match key {
+        VirtualKeyCode::Key1 => Some(WindowsKeyCode::Key1),
+        VirtualKeyCode::Key2 => Some(WindowsKeyCode::Key2),
+        VirtualKeyCode::Key3 => Some(WindowsKeyCode::Key3),
+        VirtualKeyCode::Key4 => Some(WindowsKeyCode::Key4),
+        VirtualKeyCode::Key5 => Some(WindowsKeyCode::Key5),
+        VirtualKeyCode::Key6 => Some(WindowsKeyCode::Key6),
+        VirtualKeyCode::Key7 => Some(WindowsKeyCode::Key7),
+        VirtualKeyCode::Key8 => Some(WindowsKeyCode::Key8),
+        VirtualKeyCode::Key9 => Some(WindowsKeyCode::Key9),
+        VirtualKeyCode::Key0 => Some(WindowsKeyCode::Key0),
+        VirtualKeyCode::A => Some(WindowsKeyCode::A),
+        VirtualKeyCode::B => Some(WindowsKeyCode::B),
+        VirtualKeyCode::C => Some(WindowsKeyCode::C),
+        VirtualKeyCode::D => Some(WindowsKeyCode::D),
+        VirtualKeyCode::E => Some(WindowsKeyCode::E),
+        VirtualKeyCode::F => Some(WindowsKeyCode::F),
+        VirtualKeyCode::G => Some(WindowsKeyCode::G),
+        VirtualKeyCode::H => Some(WindowsKeyCode::H),
+        VirtualKeyCode::I => Some(WindowsKeyCode::I),
+        VirtualKeyCode::J => Some(WindowsKeyCode::J),
+        VirtualKeyCode::K => Some(WindowsKeyCode::K),
+        VirtualKeyCode::L => Some(WindowsKeyCode::L),
+        VirtualKeyCode::M => Some(WindowsKeyCode::M),
+        VirtualKeyCode::N => Some(WindowsKeyCode::N),
+        VirtualKeyCode::O => Some(WindowsKeyCode::O),
+        VirtualKeyCode::P => Some(WindowsKeyCode::P),
+        VirtualKeyCode::Q => Some(WindowsKeyCode::Q),
+        VirtualKeyCode::R => Some(WindowsKeyCode::R),
+        VirtualKeyCode::S => Some(WindowsKeyCode::S),
+        VirtualKeyCode::T => Some(WindowsKeyCode::T),
+        VirtualKeyCode::U => Some(WindowsKeyCode::U),
+        VirtualKeyCode::V => Some(WindowsKeyCode::V),
+        VirtualKeyCode::W => Some(WindowsKeyCode::W),
+        VirtualKeyCode::X => Some(WindowsKeyCode::X),
+        VirtualKeyCode::Y => Some(WindowsKeyCode::Y),
+        VirtualKeyCode::Z => Some(WindowsKeyCode::Z),
+        VirtualKeyCode::Escape => Some(WindowsKeyCode::Escape),
+        VirtualKeyCode::F1 => Some(WindowsKeyCode::F1),
+        VirtualKeyCode::F2 => Some(WindowsKeyCode::F2),
+        VirtualKeyCode::F3 => Some(WindowsKeyCode::F3),
+        VirtualKeyCode::F4 => Some(WindowsKeyCode::F4),
+        VirtualKeyCode::F5 => Some(WindowsKeyCode::F5),
+        VirtualKeyCode::F6 => Some(WindowsKeyCode::F6),
+        VirtualKeyCode::F7 => Some(WindowsKeyCode::F7),
+        VirtualKeyCode::F8 => Some(WindowsKeyCode::F8),
+        VirtualKeyCode::F9 => Some(WindowsKeyCode::F9),
+        VirtualKeyCode::F10 => Some(WindowsKeyCode::F10),
+        VirtualKeyCode::F11 => Some(WindowsKeyCode::F11),
+        VirtualKeyCode::F12 => Some(WindowsKeyCode::F12),
+        VirtualKeyCode::F13 => Some(WindowsKeyCode::F13),
+        VirtualKeyCode::F14 => Some(WindowsKeyCode::F14),
+        VirtualKeyCode::F15 => Some(WindowsKeyCode::F15),
+        VirtualKeyCode::F16 => Some(WindowsKeyCode::F16),
+        VirtualKeyCode::F17 => Some(WindowsKeyCode::F17),
+        VirtualKeyCode::F18 => Some(WindowsKeyCode::F18),
+        VirtualKeyCode::F19 => Some(WindowsKeyCode::F19),
+        VirtualKeyCode::F20 => Some(WindowsKeyCode::F20),
+        VirtualKeyCode::F21 => Some(WindowsKeyCode::F21),
+        VirtualKeyCode::F22 => Some(WindowsKeyCode::F22),
+        VirtualKeyCode::F23 => Some(WindowsKeyCode::F23),
+        VirtualKeyCode::F24 => Some(WindowsKeyCode::F24),
+        VirtualKeyCode::Snapshot => Some(WindowsKeyCode::Snapshot),
+        VirtualKeyCode::Scroll => Some(WindowsKeyCode::Scroll),
+        VirtualKeyCode::Pause => Some(WindowsKeyCode::Pause),
+        VirtualKeyCode::Insert => Some(WindowsKeyCode::Insert),
+        VirtualKeyCode::Home => Some(WindowsKeyCode::Home),
+        VirtualKeyCode::Delete => Some(WindowsKeyCode::Delete),
+        VirtualKeyCode::End => Some(WindowsKeyCode::End),
+        VirtualKeyCode::PageDown => Some(WindowsKeyCode::Next),
+        VirtualKeyCode::PageUp => Some(WindowsKeyCode::Prior),
+        VirtualKeyCode::Left => Some(WindowsKeyCode::Left),
+        VirtualKeyCode::Up => Some(WindowsKeyCode::Up),
+        VirtualKeyCode::Right => Some(WindowsKeyCode::Right),
+        VirtualKeyCode::Down => Some(WindowsKeyCode::Down),
+        VirtualKeyCode::Back => Some(WindowsKeyCode::Back),
+        VirtualKeyCode::Return => Some(WindowsKeyCode::Return),
+        VirtualKeyCode::Space => Some(WindowsKeyCode::Space),
+        VirtualKeyCode::Numlock => Some(WindowsKeyCode::Numlock),
+        VirtualKeyCode::Numpad0 => Some(WindowsKeyCode::Numpad0),
+        VirtualKeyCode::Numpad1 => Some(WindowsKeyCode::Numpad1),
+        VirtualKeyCode::Numpad2 => Some(WindowsKeyCode::Numpad2),
+        VirtualKeyCode::Numpad3 => Some(WindowsKeyCode::Numpad3),
+        VirtualKeyCode::Numpad4 => Some(WindowsKeyCode::Numpad4),
+        VirtualKeyCode::Numpad5 => Some(WindowsKeyCode::Numpad5),
+        VirtualKeyCode::Numpad6 => Some(WindowsKeyCode::Numpad6),
+        VirtualKeyCode::Numpad7 => Some(WindowsKeyCode::Numpad7),
+        VirtualKeyCode::Numpad8 => Some(WindowsKeyCode::Numpad8),
+        VirtualKeyCode::Numpad9 => Some(WindowsKeyCode::Numpad9),
+        VirtualKeyCode::NumpadComma => Some(WindowsKeyCode::Separator),
+        VirtualKeyCode::NumpadEnter => Some(WindowsKeyCode::Return),
+        VirtualKeyCode::Add => Some(WindowsKeyCode::Add),
+        VirtualKeyCode::Apps => Some(WindowsKeyCode::Apps),
+        VirtualKeyCode::Calculator => Some(WindowsKeyCode::LaunchApp2),
+        VirtualKeyCode::Capital => Some(WindowsKeyCode::Capital),
+        VirtualKeyCode::Comma => Some(WindowsKeyCode::OemComma),
+        VirtualKeyCode::Convert => Some(WindowsKeyCode::Convert),
+        VirtualKeyCode::Decimal => Some(WindowsKeyCode::Decimal),
+        VirtualKeyCode::Divide => Some(WindowsKeyCode::Divide),
+        VirtualKeyCode::Equals => Some(WindowsKeyCode::OemPlus),
+        VirtualKeyCode::Kana => Some(WindowsKeyCode::Kana),
+        VirtualKeyCode::Kanji => Some(WindowsKeyCode::Kanji),
+        VirtualKeyCode::LAlt => Some(WindowsKeyCode::LMenu),
+        VirtualKeyCode::LControl => Some(WindowsKeyCode::LControl),
+        VirtualKeyCode::LShift => Some(WindowsKeyCode::LShift),
+        VirtualKeyCode::LWin => Some(WindowsKeyCode::LWin),
+        VirtualKeyCode::Mail => Some(WindowsKeyCode::LaunchMail),
+        VirtualKeyCode::MediaSelect => Some(WindowsKeyCode::LaunchMediaSelect),
+        VirtualKeyCode::MediaStop => Some(WindowsKeyCode::MediaStop),
+        VirtualKeyCode::Minus => Some(WindowsKeyCode::OemMinus),
+        VirtualKeyCode::Multiply => Some(WindowsKeyCode::Multiply),
+        VirtualKeyCode::Mute => Some(WindowsKeyCode::VolumeMute),
+        VirtualKeyCode::MyComputer => Some(WindowsKeyCode::LaunchApp1),
+        VirtualKeyCode::NavigateForward => Some(WindowsKeyCode::BrowserForward),
+        VirtualKeyCode::NavigateBackward => Some(WindowsKeyCode::BrowserBack),
+        VirtualKeyCode::NextTrack => Some(WindowsKeyCode::MediaNextTrack),
+        VirtualKeyCode::NoConvert => Some(WindowsKeyCode::NonConvert),
+        VirtualKeyCode::OEM102 => Some(WindowsKeyCode::Oem102),
+        VirtualKeyCode::Period => Some(WindowsKeyCode::OemPeriod),
+        VirtualKeyCode::PlayPause => Some(WindowsKeyCode::MediaPlayPause),
+        VirtualKeyCode::PrevTrack => Some(WindowsKeyCode::MediaPrevTrack),
+        VirtualKeyCode::RAlt => Some(WindowsKeyCode::RMenu),
+        VirtualKeyCode::RControl => Some(WindowsKeyCode::RControl),
+        VirtualKeyCode::RShift => Some(WindowsKeyCode::RShift),
+        VirtualKeyCode::RWin => Some(WindowsKeyCode::RWin),
+        VirtualKeyCode::Sleep => Some(WindowsKeyCode::Sleep),
+        VirtualKeyCode::Subtract => Some(WindowsKeyCode::Subtract),
+        VirtualKeyCode::Tab => Some(WindowsKeyCode::Tab),
+        VirtualKeyCode::VolumeDown => Some(WindowsKeyCode::VolumeDown),
+        VirtualKeyCode::VolumeUp => Some(WindowsKeyCode::VolumeUp),
+        VirtualKeyCode::WebBack => Some(WindowsKeyCode::BrowserBack),
+        VirtualKeyCode::WebFavorites => Some(WindowsKeyCode::BrowserFavorites),
+        VirtualKeyCode::WebForward => Some(WindowsKeyCode::BrowserForward),
+        VirtualKeyCode::WebHome => Some(WindowsKeyCode::BrowserHome),
+        VirtualKeyCode::WebRefresh => Some(WindowsKeyCode::BrowserRefresh),
+        VirtualKeyCode::WebSearch => Some(WindowsKeyCode::BrowserSearch),
+        VirtualKeyCode::WebStop => Some(WindowsKeyCode::BrowserStop),
+        VirtualKeyCode::Semicolon => Some(WindowsKeyCode::Oem1),
+        VirtualKeyCode::Slash => Some(WindowsKeyCode::Oem2),
+        VirtualKeyCode::Grave => Some(WindowsKeyCode::Oem3),
+        VirtualKeyCode::LBracket => Some(WindowsKeyCode::Oem4),
+        VirtualKeyCode::Backslash => Some(WindowsKeyCode::Oem5),
+        VirtualKeyCode::RBracket => Some(WindowsKeyCode::Oem6),
+        VirtualKeyCode::Apostrophe => Some(WindowsKeyCode::Oem7),
+        _ => None,
+    }
+}
+
+/// Returns the physical key of `input`, if it can be identified.
+///
+/// winit reports the evdev scancode on Linux and the BSDs and the virtual key code
+/// on macOS. On Windows it drops the extended key flag of the scancode, so the
+/// virtual key decides between the keys that share a scancode, e.g. between the
+/// arrow keys and the keypad digits. The keypad Enter and, without Num Lock, the
+/// keypad navigation keys can't be told apart from the main keys there.
+pub fn physical_key(input: &KeyboardInput) -> Option<PhysicalKey> {
+    let key_code = input.virtual_keycode.and_then(virtual_key_code);
+    let scancode = u16::try_from(input.scancode).ok();
+    let key = if cfg!(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    )) {
+        scancode.and_then(PhysicalKey::from_evdev)
+    } else if cfg!(target_os = "macos") {
+        scancode.and_then(PhysicalKey::from_mac_keycode)
+    } else if cfg!(windows) {
+        match (scancode, key_code) {
+            // The left and right modifier keys have virtual keys of their own.
+            (_, Some(key_code)) if key_code.located() != key_code => {
+                PhysicalKey::from_windows_key_code(key_code)
+            }
+            (Some(scancode), _) => {
+                let matches = |key: &PhysicalKey| {
+                    key_code.map_or(true, |key_code| key.windows_key_code() == key_code)
+                };
+                PhysicalKey::from_windows_scancode(scancode)
+                    .filter(matches)
+                    .or_else(|| PhysicalKey::from_windows_scancode(0xE000 | scancode).filter(matches))
+            }
+            (None, _) => None,
+        }
+    } else {
+        None
+    };
+    key.or_else(|| key_code.and_then(PhysicalKey::from_windows_key_code))
+}
+
+/// A mouse cursor of a browser, as winit shows it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cursor {
+    /// The cursor is hidden.
+    Hidden,
+    /// One of the standard cursors.
+    Icon(CursorIcon),
+    /// An image supplied by the page, e.g. by the CSS `cursor: url(...)` property.
+    Custom(CustomCursor),
+}
+
+/// Returns the winit cursor for `type_`. Panning and drag-and-drop cursors, which
+/// winit has no equivalent of, become the default cursor.
+pub fn cursor(type_: CursorType) -> Cursor {
+    let icon = match type_ {
+        CursorType::MiddlePanning
+        | CursorType::EastPanning
+        | CursorType::NorthPanning
+        | CursorType::NorthEastPanning
+        | CursorType::NorthWestPanning
+        | CursorType::SouthPanning
+        | CursorType::SouthEastPanning
+        | CursorType::SouthWestPanning
+        | CursorType::WestPanning
+        | CursorType::MiddlePanningVertical
+        | CursorType::MiddlePanningHorizontal
+        | CursorType::DndNone
+        | CursorType::DndMove
+        | CursorType::DndCopy
+        | CursorType::DndLink
+        | CursorType::Pointer => CursorIcon::Default,
+        CursorType::Cross => CursorIcon::Crosshair,
+        CursorType::Hand => CursorIcon::Hand,
+        CursorType::IBeam => CursorIcon::Text,
+        CursorType::Wait => CursorIcon::Wait,
+        CursorType::Help => CursorIcon::Help,
+        CursorType::EastResize => CursorIcon::EResize,
+        CursorType::NorthResize => CursorIcon::NResize,
+        CursorType::NorthEastResize => CursorIcon::NeResize,
+        CursorType::NorthWestResize => CursorIcon::NwResize,
+        CursorType::SouthResize => CursorIcon::SResize,
+        CursorType::SouthEastResize => CursorIcon::SeResize,
+        CursorType::SouthWestResize => CursorIcon::SwResize,
+        CursorType::WestResize => CursorIcon::WResize,
+        CursorType::NorthSouthResize => CursorIcon::NsResize,
+        CursorType::EastWestResize => CursorIcon::EwResize,
+        CursorType::NorthEastSouthWestResize => CursorIcon::NeswResize,
+        CursorType::NorthWestSouthEastResize => CursorIcon::NwseResize,
+        CursorType::ColumnResize => CursorIcon::ColResize,
+        CursorType::RowResize => CursorIcon::RowResize,
+        CursorType::Move => CursorIcon::Move,
+        CursorType::VerticalText => CursorIcon::VerticalText,
+        CursorType::Cell => CursorIcon::Cell,
+        CursorType::ContextMenu => CursorIcon::ContextMenu,
+        CursorType::Alias => CursorIcon::Alias,
+        CursorType::Progress => CursorIcon::Progress,
+        CursorType::NoDrop => CursorIcon::NoDrop,
+        CursorType::Copy => CursorIcon::Copy,
+        CursorType::NotAllowed => CursorIcon::NotAllowed,
+        CursorType::ZoomIn => CursorIcon::ZoomIn,
+        CursorType::ZoomOut => CursorIcon::ZoomOut,
+        CursorType::Grab => CursorIcon::Grab,
+        CursorType::Grabbing => CursorIcon::Grabbing,
+        CursorType::None => return Cursor::Hidden,
+        CursorType::Custom(info) => return Cursor::Custom(CustomCursor::new(&info)),
+    };
+    Cursor::Icon(icon)
+}
+
+/// Show the cursor for `type_` over `window`. Forward the argument of
+/// [RenderHandlerCallbacks::on_cursor_change] to this.
+///
+/// winit can't show custom cursor images, so these show the default cursor; use
+/// [cursor] and [CustomCursor] to show them in another way.
+///
+/// [RenderHandlerCallbacks::on_cursor_change]: crate::client::render_handler::RenderHandlerCallbacks::on_cursor_change
+pub fn set_cursor(window: &Window, type_: CursorType) {
+    match cursor(type_) {
+        Cursor::Hidden => window.set_cursor_visible(false),
+        Cursor::Icon(icon) => {
+            window.set_cursor_icon(icon);
+            window.set_cursor_visible(true);
+        }
+        Cursor::Custom(_) => {
+            window.set_cursor_icon(CursorIcon::Default);
+            window.set_cursor_visible(true);
+        }
+    }
+}
+
+/// A cursor image supplied by the page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomCursor {
+    rgba: Vec<u8>,
+    size: PhysicalSize<u32>,
+    hotspot: PhysicalPosition<u32>,
+    scale_factor: f64,
+}
+
+impl CustomCursor {
+    /// Copies the cursor image of `info`, converting it from BGRA to RGBA.
+    pub fn new(info: &CustomCursorInfo) -> CustomCursor {
+        let width = info.size.width.max(0) as u32;
+        let height = info.size.height.max(0) as u32;
+        let mut rgba = info.buffer[..(width * height * 4) as usize].to_vec();
+        for pixel in rgba.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        CustomCursor {
+            rgba,
+            size: PhysicalSize::new(width, height),
+            hotspot: PhysicalPosition::new(info.hotspot.x.max(0) as u32, info.hotspot.y.max(0) as u32),
+            scale_factor: info.image_scale_factor as f64,
+        }
+    }
+    /// Returns the RGBA pixels of the image.
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+    /// Returns the size of the image.
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+    /// Returns the point of the image that is the position of the mouse.
+    pub fn hotspot(&self) -> PhysicalPosition<u32> {
+        self.hotspot
+    }
+    /// Returns the scale factor the image was drawn for.
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+    /// Returns the image resampled for a display with `scale_factor`.
+    pub fn scaled(&self, scale_factor: f64) -> CustomCursor {
+        if scale_factor == self.scale_factor || self.size.width == 0 || self.size.height == 0 {
+            return self.clone();
+        }
+        let size: PhysicalSize<u32> = self
+            .size
+            .to_logical::<f64>(self.scale_factor)
+            .to_physical(scale_factor);
+        let size = PhysicalSize::new(size.width.max(1), size.height.max(1));
+        let hotspot = self
+            .hotspot
+            .to_logical::<f64>(self.scale_factor)
+            .to_physical(scale_factor);
+        CustomCursor {
+            rgba: resample(&self.rgba, self.size, size),
+            size,
+            hotspot: PhysicalPosition::new(
+                u32::min(hotspot.x, size.width - 1),
+                u32::min(hotspot.y, size.height - 1),
+            ),
+            scale_factor,
+        }
+    }
+}
+
+/// Resamples an RGBA image with bilinear filtering.
+fn resample(src: &[u8], from: PhysicalSize<u32>, to: PhysicalSize<u32>) -> Vec<u8> {
+    let mut dst = vec![0; (to.width * to.height * 4) as usize];
+    let scale_x = from.width as f32 / to.width as f32;
+    let scale_y = from.height as f32 / to.height as f32;
+    let max_x = from.width as usize - 1;
+    let max_y = from.height as usize - 1;
+    for y in 0..to.height as usize {
+        let src_y = ((y as f32 + 0.5) * scale_y - 0.5).max(0.0);
+        let y0 = (src_y as usize).min(max_y);
+        let y1 = (y0 + 1).min(max_y);
+        let fy = src_y - y0 as f32;
+        for x in 0..to.width as usize {
+            let src_x = ((x as f32 + 0.5) * scale_x - 0.5).max(0.0);
+            let x0 = (src_x as usize).min(max_x);
+            let x1 = (x0 + 1).min(max_x);
+            let fx = src_x - x0 as f32;
+            let at = |x: usize, y: usize, c: usize| src[(y * from.width as usize + x) * 4 + c] as f32;
+            for c in 0..4 {
+                let top = at(x0, y0, c) * (1.0 - fx) + at(x1, y0, c) * fx;
+                let bottom = at(x0, y1, c) * (1.0 - fx) + at(x1, y1, c) * fx;
+                dst[(y * to.width as usize + x) * 4 + c] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+            }
+        }
+    }
+    dst
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Click {
+    button: MouseButtonType,
+    time: Instant,
+    x: i32,
+    y: i32,
+    count: i32,
+}
+
+#[derive(Debug, Default)]
+struct FileDrag {
+    files: Vec<PathBuf>,
+    entered: bool,
+    dropped: bool,
+}
+
+/// Translates the winit events of a window into the input events of the browser
+/// shown in it.
+#[derive(Debug)]
+pub struct InputTranslator {
+    scale_factor: f64,
+    modifiers: EventFlags,
+    buttons: EventFlags,
+    position: PhysicalPosition<f64>,
+    last_click: Option<Click>,
+    drag: Option<FileDrag>,
+    /// The key pressed last, which produces the characters that follow.
+    pressed_key: Option<PhysicalKey>,
+}
+
+impl InputTranslator {
+    /// Create a translator for a window with `scale_factor`, as returned by
+    /// [Window::scale_factor].
+    pub fn new(scale_factor: f64) -> InputTranslator {
+        InputTranslator {
+            scale_factor,
+            modifiers: EventFlags::empty(),
+            buttons: EventFlags::empty(),
+            position: PhysicalPosition::new(0.0, 0.0),
+            last_click: None,
+            drag: None,
+            pressed_key: None,
+        }
+    }
+    /// Returns the scale factor used to convert positions to view coordinates.
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+    /// Returns the held modifier keys and mouse buttons.
+    pub fn modifiers(&self) -> EventFlags {
+        self.modifiers | self.buttons
+    }
+    /// Returns a mouse event at the last known mouse position.
+    pub fn mouse_event(&self) -> MouseEvent {
+        let position = self.position.to_logical::<f64>(self.scale_factor);
+        MouseEvent {
+            x: position.x.round() as i32,
+            y: position.y.round() as i32,
+            modifiers: self.modifiers(),
+        }
+    }
+
+    /// Forward `event` to the browser of `host`. Returns false if the event has
+    /// nothing to do with the browser.
+    ///
+    /// Dead keys, which winit reports without a virtual key code, produce no key
+    /// events; the characters they compose arrive as
+    /// [WindowEvent::ReceivedCharacter] and are sent as [KeyEvent::Char] of the key
+    /// that completed them.
+    /// Losing the focus while a mouse button is held cancels the mouse capture of
+    /// the browser, and files dragged over the window are forwarded as a drag and
+    /// drop operation.
+    pub fn handle_event(&mut self, host: &BrowserHost, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = event_flags(*modifiers);
+            }
+            WindowEvent::Resized(_) => host.was_resized(),
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.scale_factor = *scale_factor;
+                host.notify_screen_info_changed();
+                host.was_resized();
+            }
+            WindowEvent::Focused(focused) => {
+                if !focused {
+                    if !self.buttons.is_empty() {
+                        host.send_capture_lost_event();
+                    }
+                    // Releases that happen elsewhere aren't reported.
+                    self.buttons = EventFlags::empty();
+                    self.modifiers = EventFlags::empty();
+                    self.last_click = None;
+                    self.pressed_key = None;
+                }
+                host.send_focus_event(*focused);
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.position = *position;
+                let event = self.mouse_event();
+                match &self.drag {
+                    Some(drag) if drag.entered => {
+                        host.drag_target_drag_over(&event, DragOperation::COPY)
+                    }
+                    _ => host.send_mouse_move_event(&event, false),
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                host.send_mouse_move_event(&self.mouse_event(), true);
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match mouse_button(*button) {
+                    Some(button) => button,
+                    None => return false,
+                };
+                let mouse_up = *state == ElementState::Released;
+                self.buttons.set(button_flag(button), !mouse_up);
+                let event = self.mouse_event();
+                let click_count = if mouse_up {
+                    self.last_click.map(|click| click.count).unwrap_or(1)
+                } else {
+                    self.click(button, &event, Instant::now())
+                };
+                host.send_mouse_click_event(&event, button, mouse_up, click_count);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (delta_x, delta_y) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => {
+                        ((x * PIXELS_PER_LINE) as i32, (y * PIXELS_PER_LINE) as i32)
+                    }
+                    MouseScrollDelta::PixelDelta(delta) => (delta.x as i32, delta.y as i32),
+                };
+                host.send_mouse_wheel_event(&self.mouse_event(), delta_x, delta_y);
+            }
+            WindowEvent::Touch(touch) => host.send_touch_event(&self.touch_event(touch)),
+            WindowEvent::KeyboardInput { input, .. } => {
+                if input.state == ElementState::Pressed {
+                    self.pressed_key = physical_key(input);
+                }
+                match self.key_event(input) {
+                    Some(event) => host.send_key_event(event),
+                    // A dead key, which only composes the next character.
+                    None if input.virtual_keycode.is_none() && self.pressed_key.is_some() => (),
+                    None => return false,
+                }
+            }
+            WindowEvent::ReceivedCharacter(char) => {
+                // macOS reports function keys as characters in the private use area.
+                if ('\u{f700}'..='\u{f8ff}').contains(char) {
+                    return false;
+                }
+                host.send_key_event(self.char_event(*char));
+            }
+            WindowEvent::HoveredFile(path) => {
+                let drag = self.drag.get_or_insert_with(FileDrag::default);
+                if drag.entered {
+                    // winit reports each file separately, so restart the drag with
+                    // all of them.
+                    host.drag_target_drag_leave();
+                }
+                drag.files.push(path.clone());
+                drag.entered = true;
+                let event = self.mouse_event();
+                let data = drag_data(&self.drag.as_ref().unwrap().files);
+                host.drag_target_drag_enter(data, &event, DragOperation::COPY);
+                host.drag_target_drag_over(&event, DragOperation::COPY);
+            }
+            WindowEvent::HoveredFileCancelled => {
+                if let Some(drag) = self.drag.take() {
+                    if drag.entered && !drag.dropped {
+                        host.drag_target_drag_leave();
+                    }
+                }
+            }
+            WindowEvent::DroppedFile(path) => {
+                let event = self.mouse_event();
+                let drag = self.drag.get_or_insert_with(FileDrag::default);
+                if !drag.entered {
+                    // The drop wasn't preceded by a hover.
+                    drag.files.push(path.clone());
+                    drag.entered = true;
+                    host.drag_target_drag_enter(drag_data(&drag.files), &event, DragOperation::COPY);
+                    host.drag_target_drag_over(&event, DragOperation::COPY);
+                }
+                if !drag.dropped {
+                    drag.dropped = true;
+                    host.drag_target_drop(&event);
+                }
+                // The remaining files of the drop were part of the dropped data.
+                drag.files.retain(|file| file != path);
+                if drag.files.is_empty() {
+                    self.drag = None;
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Returns the key event for `input`, or None if winit couldn't identify the
+    /// key. The native key code and the location flags come from the
+    /// [physical key](physical_key), as [KeyEventBuilder] builds them.
+    ///
+    /// [KeyEventBuilder]: crate::keyboard::KeyEventBuilder
+    pub fn key_event(&self, input: &KeyboardInput) -> Option<KeyEvent> {
+        let windows_key_code = windows_key_code(input.virtual_keycode?)?;
+        let key = physical_key(input);
+        let key_up = input.state == ElementState::Released;
+        let modifiers = self.modifiers() | key.map(PhysicalKey::location).unwrap_or_default();
+        let native_key_code = key.map_or(0, |key| key.native_key_code(key_up));
+        let is_system_key = keyboard::is_system_key(modifiers);
+        Some(match input.state {
+            ElementState::Pressed => KeyEvent::KeyDown {
+                modifiers,
+                windows_key_code,
+                native_key_code,
+                is_system_key,
+                focus_on_editable_field: false,
+            },
+            ElementState::Released => KeyEvent::KeyUp {
+                modifiers,
+                windows_key_code,
+                native_key_code,
+                is_system_key,
+                focus_on_editable_field: false,
+            },
+        })
+    }
+
+    /// Returns the event of a character typed or composed with the key pressed
+    /// last.
+    pub fn char_event(&self, char: char) -> KeyEvent {
+        let key = self.pressed_key;
+        let modifiers = self.modifiers() | key.map(PhysicalKey::location).unwrap_or_default();
+        KeyEvent::Char {
+            modifiers,
+            char,
+            native_key_code: key.map_or(0, |key| key.native_key_code(false)),
+            is_system_key: keyboard::is_system_key(modifiers),
+        }
+    }
+
+    /// Returns the touch event for `touch`.
+    pub fn touch_event(&self, touch: &Touch) -> TouchEvent {
+        let location = touch.location.to_logical::<f64>(self.scale_factor);
+        TouchEvent {
+            touch_id: touch.id as i32,
+            x: location.x as f32,
+            y: location.y as f32,
+            radius_x: 1.0,
+            radius_y: 1.0,
+            rotation_angle: 0.0,
+            pressure: touch.force.map(|force| force.normalized() as f32).unwrap_or(1.0),
+            event_type: match touch.phase {
+                TouchPhase::Started => TouchEventType::Pressed,
+                TouchPhase::Moved => TouchEventType::Moved,
+                TouchPhase::Ended => TouchEventType::Released,
+                TouchPhase::Cancelled => TouchEventType::Cancelled,
+            },
+            modifiers: self.modifiers(),
+            pointer_type: PointerType::Touch,
+        }
+    }
+
+    /// Records a button press at `now` and returns its click count.
+    fn click(&mut self, button: MouseButtonType, event: &MouseEvent, now: Instant) -> i32 {
+        let count = match self.last_click {
+            Some(click)
+                if click.button == button
+                    && now.duration_since(click.time) <= DOUBLE_CLICK_TIME
+                    && (click.x - event.x).abs() <= DOUBLE_CLICK_DISTANCE
+                    && (click.y - event.y).abs() <= DOUBLE_CLICK_DISTANCE =>
+            {
+                click.count + 1
+            }
+            _ => 1,
+        };
+        self.last_click = Some(Click {
+            button,
+            time: now,
+            x: event.x,
+            y: event.y,
+            count,
+        });
+        count
+    }
+}
+
+fn drag_data(files: &[PathBuf]) -> DragData {
+    let data = DragData::new();
+    for file in files {
+        let display_name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        data.add_file(&file.to_string_lossy(), &display_name);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::values::{Point, Size};
+
+    fn press(translator: &mut InputTranslator, button: MouseButtonType, x: i32, y: i32, now: Instant) -> i32 {
+        let event = MouseEvent {
+            x,
+            y,
+            modifiers: EventFlags::empty(),
+        };
+        translator.click(button, &event, now)
+    }
+
+    #[test]
+    fn cursors() {
+        assert_eq!(cursor(CursorType::Pointer), Cursor::Icon(CursorIcon::Default));
+        assert_eq!(cursor(CursorType::IBeam), Cursor::Icon(CursorIcon::Text));
+        assert_eq!(cursor(CursorType::NorthWestSouthEastResize), Cursor::Icon(CursorIcon::NwseResize));
+        assert_eq!(cursor(CursorType::Grabbing), Cursor::Icon(CursorIcon::Grabbing));
+        // winit has no panning or drag-and-drop cursors.
+        assert_eq!(cursor(CursorType::MiddlePanning), Cursor::Icon(CursorIcon::Default));
+        assert_eq!(cursor(CursorType::DndCopy), Cursor::Icon(CursorIcon::Default));
+        assert_eq!(cursor(CursorType::None), Cursor::Hidden);
+    }
+
+    #[test]
+    fn custom_cursors() {
+        // Two BGRA pixels, followed by padding that isn't part of the image.
+        let buffer = [1, 2, 3, 4, 5, 6, 7, 8, 0, 0, 0, 0];
+        let info = CustomCursorInfo {
+            hotspot: Point { x: 1, y: -1 },
+            image_scale_factor: 2.0,
+            buffer: &buffer,
+            size: Size { width: 2, height: 1 },
+        };
+        let custom = match cursor(CursorType::Custom(info)) {
+            Cursor::Custom(custom) => custom,
+            cursor => panic!("unexpected cursor {:?}", cursor),
+        };
+        assert_eq!(custom.rgba(), &[3, 2, 1, 4, 7, 6, 5, 8]);
+        assert_eq!(custom.size(), PhysicalSize::new(2, 1));
+        assert_eq!(custom.hotspot(), PhysicalPosition::new(1, 0));
+        assert_eq!(custom.scale_factor(), 2.0);
+
+        let scaled = custom.scaled(1.0);
+        assert_eq!(scaled.size(), PhysicalSize::new(1, 1));
+        // The hotspot stays inside the image.
+        assert_eq!(scaled.hotspot(), PhysicalPosition::new(0, 0));
+        assert_eq!(scaled.rgba(), &[5, 4, 3, 6]);
+        assert_eq!(custom.scaled(2.0), custom);
+    }
+
+    #[test]
+    fn click_counts() {
+        let mut translator = InputTranslator::new(1.0);
+        let start = Instant::now();
+        let left = MouseButtonType::Left;
+        assert_eq!(press(&mut translator, left, 10, 10, start), 1);
+        assert_eq!(press(&mut translator, left, 12, 8, start + Duration::from_millis(200)), 2);
+        assert_eq!(press(&mut translator, left, 14, 10, start + Duration::from_millis(400)), 3);
+        // The time and distance are measured from the previous click.
+        assert_eq!(press(&mut translator, left, 14, 10, start + Duration::from_millis(1000)), 1);
+        assert_eq!(press(&mut translator, left, 19, 10, start + Duration::from_millis(1100)), 1);
+        assert_eq!(press(&mut translator, left, 19, 10, start + Duration::from_millis(1600)), 2);
+        // Another button starts a new series.
+        assert_eq!(press(&mut translator, MouseButtonType::Right, 19, 10, start + Duration::from_millis(1700)), 1);
+        assert_eq!(press(&mut translator, left, 19, 10, start + Duration::from_millis(1800)), 1);
+    }
+}
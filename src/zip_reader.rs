@@ -0,0 +1,164 @@
+//! Reading zip archives with CEF's zip reader.
+
+use crate::{
+    stream::StreamReader,
+    string::CefString,
+    values::cef_time_to_date_time,
+};
+use cef_sys::{cef_zip_reader_create, cef_zip_reader_t};
+use chrono::{DateTime, Utc};
+use std::{
+    io::{self, Read},
+    marker::PhantomData,
+    os::raw::{c_int, c_void},
+    path::Path,
+    ptr::null,
+};
+
+ref_counted_ptr!{
+    struct ZipReaderPtr(*mut cef_zip_reader_t);
+}
+
+/// Reads the files of a zip archive. The reader points at one file at a time,
+/// which is selected with [move_to_first_file](ZipReader::move_to_first_file),
+/// [move_to_next_file](ZipReader::move_to_next_file) or
+/// [move_to_file](ZipReader::move_to_file) and read with
+/// [open_file](ZipReader::open_file).
+///
+/// CEF only allows a zip reader to be used on the thread that created it, so
+/// `ZipReader` is neither `Send` nor `Sync`.
+pub struct ZipReader {
+    reader: ZipReaderPtr,
+    _thread_bound: PhantomData<*const ()>,
+}
+
+impl ZipReader {
+    /// Create a reader for the zip archive in `stream`. Returns None if the
+    /// stream isn't a zip archive.
+    pub fn new(stream: StreamReader) -> Option<ZipReader> {
+        unsafe{ ZipReaderPtr::from_ptr(cef_zip_reader_create(stream.into_raw())) }.map(|reader| ZipReader {
+            reader,
+            _thread_bound: PhantomData,
+        })
+    }
+    /// Create a reader for a zip archive in memory. The data is copied.
+    pub fn from_data(data: &[u8]) -> Option<ZipReader> {
+        StreamReader::from_data(data).and_then(ZipReader::new)
+    }
+    /// Create a reader for the zip archive at `path`.
+    pub fn from_file(path: &Path) -> Option<ZipReader> {
+        StreamReader::from_file(path).and_then(ZipReader::new)
+    }
+
+    /// Moves the cursor to the first file in the archive. Returns true if the
+    /// cursor position was set successfully.
+    pub fn move_to_first_file(&mut self) -> bool {
+        unsafe{ self.reader.0.move_to_first_file.unwrap()(self.reader.as_ptr()) != 0 }
+    }
+    /// Moves the cursor to the next file in the archive. Returns true if the
+    /// cursor position was set successfully.
+    pub fn move_to_next_file(&mut self) -> bool {
+        unsafe{ self.reader.0.move_to_next_file.unwrap()(self.reader.as_ptr()) != 0 }
+    }
+    /// Moves the cursor to the file named `file_name` in the archive. Returns true
+    /// if the cursor position was set successfully.
+    pub fn move_to_file(&mut self, file_name: &str, case_sensitive: bool) -> bool {
+        unsafe{ self.reader.0.move_to_file.unwrap()(self.reader.as_ptr(), CefString::new(file_name).as_ptr(), case_sensitive as _) != 0 }
+    }
+    /// Returns the name of the file at the cursor. Directories end with `/`.
+    pub fn file_name(&self) -> String {
+        unsafe{ CefString::from_userfree(self.reader.0.get_file_name.unwrap()(self.reader.as_ptr())) }
+            .map(String::from)
+            .unwrap_or_default()
+    }
+    /// Returns the uncompressed size of the file at the cursor.
+    pub fn file_size(&self) -> u64 {
+        unsafe{ self.reader.0.get_file_size.unwrap()(self.reader.as_ptr()) }.max(0) as u64
+    }
+    /// Returns the last modified time of the file at the cursor.
+    pub fn file_last_modified(&self) -> DateTime<Utc> {
+        cef_time_to_date_time(unsafe{ self.reader.0.get_file_last_modified.unwrap()(self.reader.as_ptr()) })
+    }
+    /// Open the file at the cursor for reading, decrypting it with `password` if
+    /// it is encrypted. The file is closed when the returned [ZipFile] is dropped.
+    pub fn open_file(&mut self, password: Option<&str>) -> io::Result<ZipFile<'_>> {
+        let password = password.map(CefString::new);
+        let opened = unsafe{
+            self.reader.0.open_file.unwrap()(
+                self.reader.as_ptr(),
+                password.as_ref().map(|password| password.as_ptr()).unwrap_or_else(null),
+            )
+        };
+        if opened != 0 {
+            Ok(ZipFile { reader: self })
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData, format!("failed to open {} in zip archive", self.file_name())))
+        }
+    }
+    /// Returns the names of all files in the archive, without directories. This
+    /// moves the cursor.
+    pub fn file_names(&mut self) -> Vec<String> {
+        let mut file_names = Vec::new();
+        let mut more = self.move_to_first_file();
+        while more {
+            let file_name = self.file_name();
+            if !file_name.ends_with('/') {
+                file_names.push(file_name);
+            }
+            more = self.move_to_next_file();
+        }
+        file_names
+    }
+    /// Read the whole file named `file_name`. This moves the cursor.
+    pub fn read_file(&mut self, file_name: &str) -> io::Result<Vec<u8>> {
+        if !self.move_to_file(file_name, true) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found in zip archive", file_name)));
+        }
+        let mut data = Vec::with_capacity(self.file_size() as usize);
+        self.open_file(None)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl Drop for ZipReader {
+    fn drop(&mut self) {
+        unsafe{ self.reader.0.close.unwrap()(self.reader.as_ptr()); }
+    }
+}
+
+/// A file of a zip archive that is open for reading.
+pub struct ZipFile<'a> {
+    reader: &'a mut ZipReader,
+}
+
+impl ZipFile<'_> {
+    /// Returns the current offset in the uncompressed file contents.
+    pub fn tell(&self) -> u64 {
+        unsafe{ self.reader.reader.0.tell.unwrap()(self.reader.reader.as_ptr()) }.max(0) as u64
+    }
+    /// Returns true if the end of the file has been reached.
+    pub fn eof(&self) -> bool {
+        unsafe{ self.reader.reader.0.eof.unwrap()(self.reader.reader.as_ptr()) != 0 }
+    }
+}
+
+impl Read for ZipFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // CEF returns the number of bytes read as an int.
+        let len = buf.len().min(c_int::max_value() as usize);
+        let read = unsafe{
+            self.reader.reader.0.read_file.unwrap()(self.reader.reader.as_ptr(), buf.as_mut_ptr() as *mut c_void, len)
+        };
+        if read < 0 {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "failed to read file in zip archive"))
+        } else {
+            Ok(read as usize)
+        }
+    }
+}
+
+impl Drop for ZipFile<'_> {
+    fn drop(&mut self) {
+        unsafe{ self.reader.reader.0.close_file.unwrap()(self.reader.reader.as_ptr()); }
+    }
+}